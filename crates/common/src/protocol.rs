@@ -3,6 +3,8 @@
 //! These types are serialised as JSON over both the public HTTPS API and any
 //! internal vsock channels.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 // ---------------------------------------------------------------------------
@@ -18,6 +20,16 @@ use serde::{Deserialize, Serialize};
 pub struct EncryptRequest {
     /// Arbitrary JSON object to encrypt PII fields within.
     pub payload: serde_json::Value,
+    /// Dot-notation PII paths to encrypt instead of a registered schema's.
+    /// Only accepted when the service enables inline schemas; the schema
+    /// header is then not required.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pii_paths: Option<Vec<String>>,
+    /// Encrypt every field that can be encrypted and report the others in
+    /// [`EncryptResponse::error`], instead of failing the whole request on
+    /// the first bad field.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub collect_errors: bool,
 }
 
 /// Successful response body for `POST /encrypt`.
@@ -28,29 +40,197 @@ pub struct EncryptRequest {
 pub struct EncryptResponse {
     /// Transformed JSON object with PII fields encrypted.
     pub payload: serde_json::Value,
+    /// With [`EncryptRequest::collect_errors`], the fields that could not be
+    /// encrypted, listed in [`ErrorResponse::details`]; those fields are
+    /// `null` in `payload`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+/// Request body for `POST /encrypt/value`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptValueRequest {
+    /// The string to encrypt.
+    pub value: String,
+    /// Caller-chosen context bound into the ciphertext's associated data; the
+    /// same context is needed to authenticate it again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// Response body for `POST /encrypt/value`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptValueResponse {
+    /// The `v1.<nonce>.<ciphertext>` encrypted value.
+    pub value: String,
+}
+
+/// Request body for `POST /decrypt/value`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptValueRequest {
+    /// A value returned by `POST /encrypt/value`.
+    pub value: String,
+    /// The context the value was encrypted with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// Response body for `POST /decrypt/value`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptValueResponse {
+    /// The decrypted string.
+    pub value: String,
+}
+
+/// Request body for `POST /encrypt/batch`.
+///
+/// Every item is encrypted with the schema named by the `X-Schema-Name`
+/// request header, exactly as a single `/encrypt` payload would be.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchEncryptRequest {
+    /// JSON payloads to encrypt PII fields within.
+    pub items: Vec<serde_json::Value>,
+}
+
+/// Successful response body for `POST /encrypt/batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchEncryptResponse {
+    /// One result per request item, in request order.
+    pub results: Vec<BatchItemResult>,
+}
+
+/// Outcome of one item in a batch: exactly one of `payload` and `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    /// Zero-based position of the item in [`BatchEncryptRequest::items`].
+    pub index: usize,
+    /// The item with PII fields encrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// Why the item could not be encrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
 }
 
 // ---------------------------------------------------------------------------
 // Error response
 // ---------------------------------------------------------------------------
 
+/// Machine-readable error code carried in [`ErrorResponse::code`].
+///
+/// Serialises to the snake_case strings callers already match on
+/// (e.g. `"bad_request"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request was malformed (missing header, unknown schema, bad payload).
+    BadRequest,
+    /// The client is not permitted to perform the request.
+    Forbidden,
+    /// No route matches the request path.
+    NotFound,
+    /// The requested schema was removed by a recent refresh.
+    SchemaRemoved,
+    /// A required resource (DEK, schema) is not ready; retry later.
+    ServiceUnavailable,
+    /// An unexpected server-side failure.
+    InternalError,
+    /// The schema cache has not refreshed successfully for too long; retry later.
+    SchemasStale,
+    /// The payload holds more PII fields than one request may encrypt.
+    TooManyPiiFields,
+    /// The named schema is unknown while no schemas are loaded yet; retry later.
+    SchemasLoading,
+    /// The caller-supplied deadline passed before the request completed.
+    DeadlineExceeded,
+    /// An array on a PII path holds more items than one request may traverse.
+    ArrayTooLarge,
+    /// The ciphertext does not authenticate for the request's `X-Tenant-Id`.
+    TenantMismatch,
+    /// A schema reload would cache more schemas than the configured limit.
+    TooManySchemas,
+}
+
+impl ErrorCode {
+    /// Every code, in declaration order.
+    pub const ALL: [ErrorCode; 13] = [
+        ErrorCode::BadRequest,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::SchemaRemoved,
+        ErrorCode::ServiceUnavailable,
+        ErrorCode::InternalError,
+        ErrorCode::SchemasStale,
+        ErrorCode::TooManyPiiFields,
+        ErrorCode::SchemasLoading,
+        ErrorCode::DeadlineExceeded,
+        ErrorCode::ArrayTooLarge,
+        ErrorCode::TenantMismatch,
+        ErrorCode::TooManySchemas,
+    ];
+
+    /// The wire string for this code.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::SchemaRemoved => "schema_removed",
+            ErrorCode::ServiceUnavailable => "service_unavailable",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::SchemasStale => "schemas_stale",
+            ErrorCode::TooManyPiiFields => "too_many_pii_fields",
+            ErrorCode::SchemasLoading => "schemas_loading",
+            ErrorCode::DeadlineExceeded => "deadline_exceeded",
+            ErrorCode::ArrayTooLarge => "array_too_large",
+            ErrorCode::TenantMismatch => "tenant_mismatch",
+            ErrorCode::TooManySchemas => "too_many_schemas",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Standard error response body returned on any non-2xx status.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorResponse {
     /// Short machine-readable error code (e.g. `"bad_request"`).
-    pub code: String,
+    pub code: ErrorCode,
     /// Human-readable description safe to expose to callers.
     pub message: String,
+    /// Per-field failures, when the error concerns individual fields.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
 }
 
 impl ErrorResponse {
     /// Construct an [`ErrorResponse`] from a code and message.
-    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
         Self {
-            code: code.into(),
+            code,
             message: message.into(),
+            details: Vec::new(),
         }
     }
+
+    /// Attach per-field failures.
+    pub fn with_details(mut self, details: Vec<FieldError>) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Why one field of a payload could not be processed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// Dot-notation schema path of the field.
+    pub path: String,
+    /// Human-readable reason; never contains the field value.
+    pub message: String,
 }
 
 // ---------------------------------------------------------------------------
@@ -78,6 +258,53 @@ pub struct DecryptResponse {
     pub payload: serde_json::Value,
 }
 
+// ---------------------------------------------------------------------------
+// Redact endpoint
+// ---------------------------------------------------------------------------
+
+/// Request body for `POST /redact`.
+///
+/// The `payload` field contains an arbitrary JSON object whose PII fields
+/// (as identified by the OpenAPI schema in the `X-Schema-Name` header) will
+/// be replaced by the redaction marker, whether plaintext or encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactRequest {
+    /// Arbitrary JSON object containing PII fields to redact.
+    pub payload: serde_json::Value,
+}
+
+/// Successful response body for `POST /redact`.
+///
+/// The `payload` field mirrors the input structure with every PII field
+/// replaced by the redaction marker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactResponse {
+    /// Transformed JSON object with PII fields redacted.
+    pub payload: serde_json::Value,
+}
+
+// ---------------------------------------------------------------------------
+// Verify endpoint
+// ---------------------------------------------------------------------------
+
+/// Request body for `POST /verify`.
+///
+/// The `payload` field contains a JSON object that should already be
+/// encrypted according to the OpenAPI schema in the `X-Schema-Name` header.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyRequest {
+    /// JSON object whose PII fields are checked.
+    pub payload: serde_json::Value,
+}
+
+/// Successful response body for `POST /verify`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    /// Schema PII paths holding at least one value that is not in encrypted
+    /// (or hashed) form, sorted. Empty when the payload is fully encrypted.
+    pub plaintext_paths: Vec<String>,
+}
+
 // ---------------------------------------------------------------------------
 // Health check
 // ---------------------------------------------------------------------------
@@ -85,12 +312,134 @@ pub struct DecryptResponse {
 /// Response body for `GET /health`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthResponse {
-    /// Overall service status: `"ok"` or `"degraded"`.
+    /// Overall service status: `"ok"` or `"degraded"`. Degraded comes with
+    /// `200 OK` when only [`stalled_tasks`](Self::stalled_tasks) is set.
     pub status: String,
     /// Whether the DEK is currently loaded and ready.
     pub dek_ready: bool,
     /// Number of OpenAPI schemas currently cached.
     pub schemas_loaded: usize,
+    /// Whether an operator has put the instance into drain mode.
+    #[serde(default)]
+    pub draining: bool,
+    /// Whether the last successful schema refresh is older than the
+    /// configured staleness limit.
+    #[serde(default)]
+    pub schemas_stale: bool,
+    /// Whether the latest vsock connectivity probe reached the AWS proxy
+    /// (always `true` when probing is disabled).
+    #[serde(default = "default_true")]
+    pub proxy_reachable: bool,
+    /// Background tasks (`dek_rotation`, `schema_refresh`) that have missed
+    /// their heartbeat and have probably stopped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stalled_tasks: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// ---------------------------------------------------------------------------
+// Admin endpoints
+// ---------------------------------------------------------------------------
+
+/// Query parameters for `GET /admin/schemas/by-path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaPathQuery {
+    /// Dot-notation PII field path to look up (e.g. `"account.iban"`).
+    pub path: String,
+}
+
+/// Response body for `GET /admin/schemas/by-path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaPathResponse {
+    /// The path that was looked up.
+    pub path: String,
+    /// Names of the cached schemas that mark `path` as PII, sorted.
+    pub schemas: Vec<String>,
+}
+
+/// A schema object that failed to parse on several consecutive loads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedSchema {
+    /// `s3://bucket/key` of the object.
+    pub location: String,
+    /// Consecutive loads on which the object failed to parse.
+    pub failures: u32,
+    /// The parse error from the latest load.
+    pub error: String,
+}
+
+/// Response body for `GET /admin/schemas`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaListResponse {
+    /// Cached schema names and their fingerprints.
+    pub schemas: BTreeMap<String, String>,
+    /// Quarantined schema objects, sorted by location.
+    pub quarantined: Vec<QuarantinedSchema>,
+}
+
+/// Query parameters for `POST /admin/reload-schemas`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadSchemasQuery {
+    /// Name of the schema source to reload, `bucket/prefix`.
+    pub source: String,
+}
+
+/// Response body for `POST /admin/reload-schemas`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadSchemasResponse {
+    /// The source that was reloaded.
+    pub source: String,
+    /// Number of schemas the source now defines.
+    pub schemas: usize,
+}
+
+/// Response body for `GET /admin/stats`.
+///
+/// Runtime counters for diagnosing memory growth. Contains no payload data
+/// and no key material.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsResponse {
+    /// Number of OpenAPI schemas currently cached.
+    pub schemas_cached: usize,
+    /// Summed byte length of every cached PII path string.
+    pub pii_path_bytes: usize,
+    /// Encrypt/decrypt requests currently being processed.
+    pub active_requests: usize,
+    /// Number of times a DEK has been loaded (initial load and rotations).
+    pub dek_generation: u64,
+}
+
+/// How encrypted fields are written into payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CiphertextEncoding {
+    /// A `v1.<nonce>.<ciphertext>` string.
+    #[default]
+    CompactString,
+    /// An `{"alg", "nonce", "ct"}` object.
+    JsonObject,
+}
+
+/// Encryption settings applied to newly encrypted data; the body of
+/// `GET /admin/encryption` and `PUT /admin/encryption`.
+///
+/// Changing them never affects existing ciphertext: `/decrypt` accepts every
+/// format regardless of the current settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionSettings {
+    /// How new ciphertext is written.
+    #[serde(default)]
+    pub encoding: CiphertextEncoding,
+}
+
+/// Response body for `POST /admin/drain` and `DELETE /admin/drain`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrainResponse {
+    /// Whether the instance is draining after the call.
+    pub draining: bool,
 }
 
 #[cfg(test)]
@@ -102,17 +451,66 @@ mod tests {
     fn encrypt_request_round_trip() {
         let req = EncryptRequest {
             payload: json!({"ssn": "123-45-6789", "name": "Alice"}),
+            pii_paths: None,
+            collect_errors: false,
         };
         let json = serde_json::to_string(&req).unwrap();
+        assert!(!json.contains("pii_paths"));
+        assert!(!json.contains("collect_errors"));
         let decoded: EncryptRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(decoded.payload["ssn"], "123-45-6789");
+        assert_eq!(decoded.pii_paths, None);
     }
 
     #[test]
     fn error_response_new() {
-        let e = ErrorResponse::new("bad_request", "missing schema header");
-        assert_eq!(e.code, "bad_request");
+        let e = ErrorResponse::new(ErrorCode::BadRequest, "missing schema header");
+        assert_eq!(e.code, ErrorCode::BadRequest);
         assert!(e.message.contains("missing schema header"));
+        assert!(!serde_json::to_string(&e).unwrap().contains("details"));
+    }
+
+    #[test]
+    fn error_response_details_round_trip() {
+        let e = ErrorResponse::new(ErrorCode::BadRequest, "1 field failed").with_details(vec![
+            FieldError {
+                path: "user.ssn".into(),
+                message: "too long".into(),
+            },
+        ]);
+        let json = serde_json::to_value(&e).unwrap();
+        assert_eq!(json["details"][0]["path"], "user.ssn");
+        let back: ErrorResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(back.details, e.details);
+    }
+
+    #[test]
+    fn error_codes_serialise_to_wire_strings() {
+        let expected = [
+            (ErrorCode::BadRequest, "bad_request"),
+            (ErrorCode::Forbidden, "forbidden"),
+            (ErrorCode::NotFound, "not_found"),
+            (ErrorCode::SchemaRemoved, "schema_removed"),
+            (ErrorCode::ServiceUnavailable, "service_unavailable"),
+            (ErrorCode::InternalError, "internal_error"),
+            (ErrorCode::SchemasStale, "schemas_stale"),
+            (ErrorCode::TooManyPiiFields, "too_many_pii_fields"),
+            (ErrorCode::SchemasLoading, "schemas_loading"),
+            (ErrorCode::DeadlineExceeded, "deadline_exceeded"),
+            (ErrorCode::ArrayTooLarge, "array_too_large"),
+            (ErrorCode::TenantMismatch, "tenant_mismatch"),
+            (ErrorCode::TooManySchemas, "too_many_schemas"),
+        ];
+        assert_eq!(ErrorCode::ALL.len(), expected.len());
+        for (code, wire) in expected {
+            assert_eq!(code.as_str(), wire);
+            assert_eq!(code.to_string(), wire);
+            assert_eq!(serde_json::to_value(code).unwrap(), json!(wire));
+            let back: ErrorCode = serde_json::from_value(json!(wire)).unwrap();
+            assert_eq!(back, code);
+        }
+        let body = serde_json::to_value(ErrorResponse::new(ErrorCode::NotFound, "x")).unwrap();
+        assert_eq!(body, json!({"code": "not_found", "message": "x"}));
     }
 
     #[test]
@@ -135,12 +533,28 @@ mod tests {
         assert_eq!(decoded.payload["ssn"], "123-45-6789");
     }
 
+    #[test]
+    fn encryption_settings_serde() {
+        let s: EncryptionSettings = serde_json::from_str(r#"{"encoding":"json_object"}"#).unwrap();
+        assert_eq!(s.encoding, CiphertextEncoding::JsonObject);
+        let s: EncryptionSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(s, EncryptionSettings::default());
+        assert_eq!(
+            serde_json::to_value(&s).unwrap(),
+            json!({"encoding": "compact_string"})
+        );
+    }
+
     #[test]
     fn health_response_serde() {
         let h = HealthResponse {
             status: "ok".into(),
             dek_ready: true,
             schemas_loaded: 3,
+            draining: false,
+            schemas_stale: false,
+            proxy_reachable: true,
+            stalled_tasks: Vec::new(),
         };
         let json = serde_json::to_string(&h).unwrap();
         let decoded: HealthResponse = serde_json::from_str(&json).unwrap();
@@ -10,11 +10,16 @@
 //! enclave entrypoint starts on 127.0.0.1:8004 → vsock(3,8004).
 //! `AWS_EC2_METADATA_SERVICE_ENDPOINT=http://127.0.0.1:8004` (baked into the
 //! EIF) redirects the SDK's IMDS client to that bridge.
+//!
+//! Test and staging environments may point individual services at other
+//! endpoints (e.g. localstack) with [`EndpointOverrides`]; the connector then
+//! routes each override's host to that service's proxy port.
 
 use std::fmt;
+use std::sync::Arc;
 
 use anyhow::Result;
-use aws_config::BehaviorVersion;
+use aws_config::{BehaviorVersion, SdkConfig};
 use aws_smithy_runtime_api::client::http::{
     HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpClient,
     SharedHttpConnector,
@@ -28,7 +33,8 @@ use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
 use tower::ServiceExt;
 
-use super::vsock_connector::VsockRawConnector;
+use super::recipient::KmsRecipient;
+use super::vsock_connector::{authority, VsockBuffers, VsockRawConnector};
 
 // ---------------------------------------------------------------------------
 // VsockAdapter — HttpConnector backed by a vsock-aware hyper client
@@ -101,6 +107,36 @@ impl HttpClient for VsockHttpClient {
     }
 }
 
+// ---------------------------------------------------------------------------
+// EndpointOverrides
+// ---------------------------------------------------------------------------
+
+/// Per-service endpoint URLs used instead of the SDK's regional defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EndpointOverrides {
+    /// KMS endpoint URL.
+    pub kms: Option<String>,
+    /// Secrets Manager endpoint URL.
+    pub secretsmanager: Option<String>,
+    /// S3 endpoint URL. Requests use path-style addressing, so the bucket
+    /// name never becomes part of the host.
+    pub s3: Option<String>,
+}
+
+impl EndpointOverrides {
+    /// Authority (`host:port`) of each override and the offset from the base
+    /// vsock port of the proxy serving it (KMS 1, Secrets Manager 2, S3 3).
+    pub(crate) fn vsock_routes(&self) -> Vec<(String, u32)> {
+        [(&self.kms, 1), (&self.secretsmanager, 2), (&self.s3, 3)]
+            .into_iter()
+            .filter_map(|(url, offset)| {
+                let uri = url.as_deref()?.parse().ok()?;
+                Some((authority(&uri)?, offset))
+            })
+            .collect()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // AwsClients
 // ---------------------------------------------------------------------------
@@ -114,6 +150,9 @@ pub struct AwsClients {
     pub secretsmanager: aws_sdk_secretsmanager::Client,
     /// S3 client used to fetch OpenAPI schema files.
     pub s3: aws_sdk_s3::Client,
+    /// Recipient whose attestation document accompanies KMS decrypts when
+    /// `KMS_RECIPIENT_ATTESTATION` is set; see [`super::recipient`].
+    pub kms_recipient: Option<Arc<dyn KmsRecipient>>,
 }
 
 impl AwsClients {
@@ -121,14 +160,23 @@ impl AwsClients {
     ///
     /// The connector routes HTTPS connections to AWS service endpoints through
     /// vsock to the corresponding `vsock-proxy` on the parent EC2, negotiating
-    /// TLS end-to-end with the real AWS endpoint.
+    /// TLS end-to-end with the real AWS endpoint. Services listed in
+    /// `endpoints` use the given URL instead, still through their proxy.
+    /// Vsock streams are buffered as `buffers` configures.
     ///
     /// # Errors
     ///
     /// Returns an error if the SDK config cannot be built.
-    pub async fn init(vsock_proxy_cid: u32, vsock_proxy_port: u32) -> Result<Self> {
+    pub async fn init(
+        vsock_proxy_cid: u32,
+        vsock_proxy_port: u32,
+        endpoints: &EndpointOverrides,
+        buffers: VsockBuffers,
+    ) -> Result<Self> {
         // Build the vsock raw connector (handles vsock vs. TCP routing).
-        let raw = VsockRawConnector::new(vsock_proxy_cid, vsock_proxy_port);
+        let raw = VsockRawConnector::new(vsock_proxy_cid, vsock_proxy_port)
+            .with_routes(endpoints.vsock_routes())
+            .with_buffers(buffers);
 
         // Wrap with hyper-rustls to add TLS for HTTPS URIs.
         let https_connector = HttpsConnectorBuilder::new()
@@ -155,10 +203,119 @@ impl AwsClients {
             .load()
             .await;
 
-        Ok(Self {
-            kms: aws_sdk_kms::Client::new(&config),
-            secretsmanager: aws_sdk_secretsmanager::Client::new(&config),
-            s3: aws_sdk_s3::Client::new(&config),
-        })
+        Ok(Self::from_sdk_config(&config, endpoints))
+    }
+
+    /// Build the service clients from a loaded SDK config, applying any
+    /// endpoint overrides.
+    fn from_sdk_config(config: &SdkConfig, endpoints: &EndpointOverrides) -> Self {
+        let mut kms = aws_sdk_kms::config::Builder::from(config);
+        if let Some(url) = &endpoints.kms {
+            kms = kms.endpoint_url(url);
+        }
+        let mut secretsmanager = aws_sdk_secretsmanager::config::Builder::from(config);
+        if let Some(url) = &endpoints.secretsmanager {
+            secretsmanager = secretsmanager.endpoint_url(url);
+        }
+        let mut s3 = aws_sdk_s3::config::Builder::from(config);
+        if let Some(url) = &endpoints.s3 {
+            s3 = s3.endpoint_url(url).force_path_style(true);
+        }
+        Self {
+            kms: aws_sdk_kms::Client::from_conf(kms.build()),
+            secretsmanager: aws_sdk_secretsmanager::Client::from_conf(secretsmanager.build()),
+            s3: aws_sdk_s3::Client::from_conf(s3.build()),
+            kms_recipient: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records the URI of every request and fails it without retrying.
+    #[derive(Clone, Debug, Default)]
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl HttpConnector for Capture {
+        fn call(
+            &self,
+            request: aws_smithy_runtime_api::client::orchestrator::HttpRequest,
+        ) -> HttpConnectorFuture {
+            self.0.lock().unwrap().push(request.uri().to_owned());
+            HttpConnectorFuture::ready(Err(ConnectorError::other("captured".into(), None)))
+        }
+    }
+
+    impl HttpClient for Capture {
+        fn http_connector(
+            &self,
+            _settings: &HttpConnectorSettings,
+            _components: &RuntimeComponents,
+        ) -> SharedHttpConnector {
+            SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn endpoint_overrides_apply_per_service() {
+        let capture = Capture::default();
+        let config = aws_config::defaults(BehaviorVersion::latest())
+            .region(aws_config::Region::new("us-east-2"))
+            .credentials_provider(aws_sdk_kms::config::Credentials::new(
+                "AKID", "secret", None, None, "test",
+            ))
+            .retry_config(aws_config::retry::RetryConfig::disabled())
+            .http_client(SharedHttpClient::new(capture.clone()))
+            .load()
+            .await;
+        let clients = AwsClients::from_sdk_config(
+            &config,
+            &EndpointOverrides {
+                kms: Some("https://kms.staging.internal:4566".into()),
+                secretsmanager: None,
+                s3: Some("https://s3.staging.internal".into()),
+            },
+        );
+
+        let _ = clients.kms.list_keys().send().await;
+        let _ = clients.secretsmanager.list_secrets().send().await;
+        let _ = clients.s3.list_objects_v2().bucket("schemas").send().await;
+
+        let uris = capture.0.lock().unwrap().clone();
+        assert_eq!(uris.len(), 3, "{uris:?}");
+        assert!(
+            uris[0].starts_with("https://kms.staging.internal:4566/"),
+            "{uris:?}"
+        );
+        assert!(
+            uris[1].starts_with("https://secretsmanager.us-east-2.amazonaws.com/"),
+            "{uris:?}"
+        );
+        assert!(
+            uris[2].starts_with("https://s3.staging.internal/schemas"),
+            "{uris:?}"
+        );
+    }
+
+    #[test]
+    fn override_hosts_route_to_their_service_proxy() {
+        let routes = EndpointOverrides {
+            kms: Some("https://kms.staging.internal:4566".into()),
+            secretsmanager: Some("http://localstack".into()),
+            s3: Some("https://localstack".into()),
+        }
+        .vsock_routes();
+        assert_eq!(
+            routes,
+            [
+                ("kms.staging.internal:4566".to_string(), 1),
+                ("localstack:80".to_string(), 2),
+                ("localstack:443".to_string(), 3),
+            ]
+        );
+        assert!(EndpointOverrides::default().vsock_routes().is_empty());
     }
 }
@@ -0,0 +1,302 @@
+//! Opening the CMS `EnvelopedData` that KMS returns as `CiphertextForRecipient`.
+//!
+//! KMS encrypts the plaintext under a fresh AES-256-CBC key and wraps that key
+//! with RSAES-OAEP-SHA-256 under the public key from the attestation document
+//! (RFC 5652 §6). The envelope may be BER-encoded, with indefinite lengths and
+//! chunked OCTET STRINGs, so the reader accepts both forms. Only the fields
+//! needed to decrypt are read; anything else in the envelope is skipped.
+
+use anyhow::{Context, Result};
+use aws_lc_rs::cipher::{DecryptionContext, PaddedBlockDecryptingKey, UnboundCipherKey, AES_256};
+use aws_lc_rs::iv::FixedLength;
+use aws_lc_rs::rsa::{OaepPrivateDecryptingKey, OAEP_SHA256_MGF1SHA256};
+use zeroize::Zeroize;
+
+// Universal and context-specific tags.
+const INTEGER: u8 = 0x02;
+const OID: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const CONTEXT_0: u8 = 0xA0;
+const CONSTRUCTED: u8 = 0x20;
+
+/// `id-envelopedData` (1.2.840.113549.1.7.3).
+const ENVELOPED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x03];
+/// `id-RSAES-OAEP` (1.2.840.113549.1.1.7).
+const RSAES_OAEP: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x07];
+/// `id-aes256-CBC` (2.16.840.1.101.3.4.1.42).
+const AES_256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2A];
+
+/// Decrypt the content of the `EnvelopedData` in `envelope` with `key`.
+///
+/// # Errors
+///
+/// Returns an error if the envelope is malformed, uses algorithms other than
+/// RSAES-OAEP and AES-256-CBC, or was not encrypted to `key`.
+pub fn open(envelope: &[u8], key: &OaepPrivateDecryptingKey) -> Result<Vec<u8>> {
+    let (content_info, _) = expect(envelope, SEQUENCE)?;
+    let (content_type, rest) = expect(content_info, OID)?;
+    if content_type != ENVELOPED_DATA {
+        anyhow::bail!("CMS content is not EnvelopedData");
+    }
+    let (explicit, _) = expect(rest, CONTEXT_0)?;
+    let (enveloped, _) = expect(explicit, SEQUENCE)?;
+    let (_version, rest) = expect(enveloped, INTEGER)?;
+    let rest = match element(rest)? {
+        // originatorInfo
+        (CONTEXT_0, _, rest) => rest,
+        _ => rest,
+    };
+    let (recipients, rest) = expect(rest, SET)?;
+    let (encrypted_content_info, _) = expect(rest, SEQUENCE)?;
+
+    // The first recipient must be a KeyTransRecipientInfo for our key.
+    let (recipient, _) = expect(recipients, SEQUENCE)?;
+    let (_version, rest) = expect(recipient, INTEGER)?;
+    let (_, _, rest) = element(rest)?; // rid
+    let (algorithm, rest) = expect(rest, SEQUENCE)?;
+    if expect(algorithm, OID)?.0 != RSAES_OAEP {
+        anyhow::bail!("CMS key encryption algorithm is not RSAES-OAEP");
+    }
+    let encrypted_key = octets(rest)?;
+
+    let (_content_type, rest) = expect(encrypted_content_info, OID)?;
+    let (algorithm, rest) = expect(rest, SEQUENCE)?;
+    let (oid, parameters) = expect(algorithm, OID)?;
+    if oid != AES_256_CBC {
+        anyhow::bail!("CMS content encryption algorithm is not AES-256-CBC");
+    }
+    let iv = octets(parameters)?;
+    let mut content = octets(rest)?;
+
+    let mut cek = vec![0u8; key.min_output_size()];
+    let unwrapped = key
+        .decrypt(&OAEP_SHA256_MGF1SHA256, &encrypted_key, &mut cek, None)
+        .map(|k| k.len())
+        .map_err(|_| anyhow::anyhow!("failed to unwrap CMS content key"));
+    let plaintext = unwrapped.and_then(|len| decrypt_content(&cek[..len], &iv, &mut content));
+    cek.zeroize();
+    content.zeroize();
+    plaintext
+}
+
+/// AES-256-CBC decrypt and unpad `content` in place, returning a copy of the
+/// plaintext.
+fn decrypt_content(cek: &[u8], iv: &[u8], content: &mut [u8]) -> Result<Vec<u8>> {
+    let iv = FixedLength::try_from(iv)
+        .map_err(|_| anyhow::anyhow!("CMS AES-CBC IV is {} bytes", iv.len()))?;
+    let cipher = UnboundCipherKey::new(&AES_256, cek)
+        .and_then(PaddedBlockDecryptingKey::cbc_pkcs7)
+        .map_err(|_| anyhow::anyhow!("CMS content key is {} bytes", cek.len()))?;
+    cipher
+        .decrypt(content, DecryptionContext::Iv128(iv))
+        .map(|plaintext| plaintext.to_vec())
+        .map_err(|_| anyhow::anyhow!("failed to decrypt CMS content"))
+}
+
+/// The contents of the element at the start of `input`, which must have
+/// `tag`, and the bytes after it.
+fn expect(input: &[u8], tag: u8) -> Result<(&[u8], &[u8])> {
+    match element(input)? {
+        (found, contents, rest) if found == tag => Ok((contents, rest)),
+        (found, _, _) => anyhow::bail!("expected CMS tag {tag:#04x}, found {found:#04x}"),
+    }
+}
+
+/// The OCTET STRING (or implicitly tagged equivalent) at the start of
+/// `input`, joining the chunks of a constructed encoding.
+fn octets(input: &[u8]) -> Result<Vec<u8>> {
+    let (tag, contents, _) = element(input)?;
+    if tag & CONSTRUCTED == 0 {
+        return Ok(contents.to_vec());
+    }
+    let mut out = Vec::new();
+    let mut rest = contents;
+    while !rest.is_empty() {
+        out.extend(octets(rest)?);
+        rest = element(rest)?.2;
+    }
+    Ok(out)
+}
+
+/// Split the BER element at the start of `input` into its tag, its contents
+/// and the bytes after it. Indefinite lengths are resolved by walking the
+/// nested elements up to the end-of-contents marker.
+fn element(input: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first().context("truncated CMS envelope")?;
+    if tag & 0x1f == 0x1f {
+        anyhow::bail!("unsupported high-number CMS tag");
+    }
+    let (&first, rest) = rest.split_first().context("truncated CMS envelope")?;
+    if first == 0x80 {
+        if tag & CONSTRUCTED == 0 {
+            anyhow::bail!("indefinite length on a primitive CMS element");
+        }
+        let mut inner = rest;
+        while !inner.starts_with(&[0, 0]) {
+            inner = element(inner)?.2;
+        }
+        let contents = &rest[..rest.len() - inner.len()];
+        return Ok((tag, contents, &inner[2..]));
+    }
+    let (len, rest) = if first & 0x80 == 0 {
+        (usize::from(first), rest)
+    } else {
+        let width = usize::from(first & 0x7f);
+        if width > 4 || rest.len() < width {
+            anyhow::bail!("unsupported CMS length encoding");
+        }
+        let (bytes, rest) = rest.split_at(width);
+        let len = bytes
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | usize::from(b));
+        (len, rest)
+    };
+    if rest.len() < len {
+        anyhow::bail!("truncated CMS envelope");
+    }
+    let (contents, rest) = rest.split_at(len);
+    Ok((tag, contents, rest))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use aws_lc_rs::cipher::PaddedBlockEncryptingKey;
+    use aws_lc_rs::rsa::{KeySize, OaepPublicEncryptingKey, PrivateDecryptingKey};
+
+    const OCTET_STRING: u8 = 0x04;
+
+    /// `id-data` (1.2.840.113549.1.7.1).
+    const DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x01];
+
+    /// DER element with `tag` and `contents`.
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match contents.len() {
+            len @ 0..=0x7f => out.push(len as u8),
+            len @ 0x80..=0xff => out.extend([0x81, len as u8]),
+            len => {
+                out.push(0x82);
+                out.extend((len as u16).to_be_bytes());
+            }
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    /// BER element with `tag` and an indefinite length.
+    fn ber(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag, 0x80];
+        out.extend_from_slice(contents);
+        out.extend([0, 0]);
+        out
+    }
+
+    /// A freshly generated recipient key pair.
+    pub(crate) fn recipient_key() -> (OaepPrivateDecryptingKey, OaepPublicEncryptingKey) {
+        let private = PrivateDecryptingKey::generate(KeySize::Rsa2048).unwrap();
+        let public = OaepPublicEncryptingKey::new(private.public_key()).unwrap();
+        (OaepPrivateDecryptingKey::new(private).unwrap(), public)
+    }
+
+    /// An `EnvelopedData` carrying `plaintext` for `public`, shaped like the
+    /// one KMS returns. With `indefinite` the envelope uses BER indefinite
+    /// lengths and the content is split into chunks.
+    pub(crate) fn envelope(
+        plaintext: &[u8],
+        public: &OaepPublicEncryptingKey,
+        indefinite: bool,
+    ) -> Vec<u8> {
+        let cek = [0x5Au8; 32];
+        let mut wrapped = vec![0u8; public.ciphertext_size()];
+        let wrapped = public
+            .encrypt(&OAEP_SHA256_MGF1SHA256, &cek, &mut wrapped, None)
+            .unwrap()
+            .to_vec();
+        let cipher =
+            PaddedBlockEncryptingKey::cbc_pkcs7(UnboundCipherKey::new(&AES_256, &cek).unwrap())
+                .unwrap();
+        let mut content = plaintext.to_vec();
+        let context = cipher.encrypt(&mut content).unwrap();
+        let iv: &[u8] = (&context).try_into().unwrap();
+
+        let wrap = |tag, contents: &[u8]| {
+            if indefinite && tag & CONSTRUCTED != 0 {
+                ber(tag, contents)
+            } else {
+                der(tag, contents)
+            }
+        };
+        let recipient = wrap(
+            SEQUENCE,
+            &[
+                der(INTEGER, &[2]),
+                der(0x80, b"subject-key-id"),
+                wrap(SEQUENCE, &der(OID, RSAES_OAEP)),
+                der(OCTET_STRING, &wrapped),
+            ]
+            .concat(),
+        );
+        let encrypted_content = if indefinite {
+            let chunks: Vec<u8> = content
+                .chunks(7)
+                .flat_map(|c| der(OCTET_STRING, c))
+                .collect();
+            ber(CONTEXT_0, &chunks)
+        } else {
+            der(0x80, &content)
+        };
+        let encrypted_content_info = wrap(
+            SEQUENCE,
+            &[
+                der(OID, DATA),
+                wrap(
+                    SEQUENCE,
+                    &[der(OID, AES_256_CBC), der(OCTET_STRING, iv)].concat(),
+                ),
+                encrypted_content,
+            ]
+            .concat(),
+        );
+        let enveloped = wrap(
+            SEQUENCE,
+            &[
+                der(INTEGER, &[2]),
+                wrap(SET, &recipient),
+                encrypted_content_info,
+            ]
+            .concat(),
+        );
+        wrap(
+            SEQUENCE,
+            &[der(OID, ENVELOPED_DATA), wrap(CONTEXT_0, &enveloped)].concat(),
+        )
+    }
+
+    #[test]
+    fn der_and_ber_envelopes_open_to_the_plaintext() {
+        let (private, public) = recipient_key();
+        let dek = [0x42u8; 32];
+        assert_eq!(
+            open(&envelope(&dek, &public, false), &private).unwrap(),
+            dek
+        );
+        assert_eq!(open(&envelope(&dek, &public, true), &private).unwrap(), dek);
+    }
+
+    #[test]
+    fn envelopes_for_another_key_or_malformed_are_rejected() {
+        let (private, public) = recipient_key();
+        let (other, _) = recipient_key();
+        let sealed = envelope(b"secret", &public, false);
+        assert!(open(&sealed, &other).is_err());
+        assert!(open(&sealed[..sealed.len() - 5], &private).is_err());
+        assert!(open(&[], &private).is_err());
+
+        let mut tampered = sealed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert_ne!(open(&tampered, &private).ok(), Some(b"secret".to_vec()));
+    }
+}
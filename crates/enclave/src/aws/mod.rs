@@ -5,6 +5,11 @@
 //! configures each SDK client to target the correct vsock endpoint.
 
 pub mod clients;
+pub mod cms;
+pub mod nsm;
+pub mod probe;
+pub mod recipient;
 pub mod vsock_connector;
 
-pub use clients::AwsClients;
+pub use clients::{AwsClients, EndpointOverrides};
+pub use vsock_connector::VsockBuffers;
@@ -0,0 +1,286 @@
+//! Attestation documents from the Nitro Secure Module (NSM).
+//!
+//! Inside an enclave the NSM is the `/dev/nsm` device. A request is a CBOR
+//! message handed to the driver in a single ioctl, which writes the CBOR
+//! response into a caller-supplied buffer. Only the `Attestation` request is
+//! issued here, so the CBOR code covers just the shapes that request and its
+//! response take rather than the general format.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+
+use anyhow::{Context, Result};
+
+/// Path of the NSM device inside the enclave.
+const NSM_DEVICE: &str = "/dev/nsm";
+
+/// Largest request and response the NSM driver accepts.
+const NSM_REQUEST_MAX: usize = 0x1000;
+const NSM_RESPONSE_MAX: usize = 0x3000;
+
+/// Argument of the NSM ioctl: the request and response buffers.
+#[repr(C)]
+struct NsmMessage {
+    request: libc::iovec,
+    response: libc::iovec,
+}
+
+/// `_IOWR(0x0A, 0, struct nsm_message)` from the NSM driver.
+const NSM_IOCTL: u64 = (3 << 30) | ((std::mem::size_of::<NsmMessage>() as u64) << 16) | (0x0A << 8);
+
+/// Request an attestation document from the NSM that embeds `public_key`
+/// (a DER `SubjectPublicKeyInfo`).
+///
+/// # Errors
+///
+/// Returns an error if the device cannot be opened (outside an enclave), if
+/// the ioctl fails, or if the NSM answers with an error.
+pub fn attestation_document(public_key: &[u8]) -> Result<Vec<u8>> {
+    let response = call(&attestation_request(public_key))?;
+    attestation_response(&response)
+}
+
+/// Send one CBOR `request` to the NSM and return its CBOR response.
+fn call(request: &[u8]) -> Result<Vec<u8>> {
+    if request.len() > NSM_REQUEST_MAX {
+        anyhow::bail!(
+            "NSM request is {} bytes, limit {NSM_REQUEST_MAX}",
+            request.len()
+        );
+    }
+    let device = File::options()
+        .read(true)
+        .write(true)
+        .open(NSM_DEVICE)
+        .with_context(|| format!("failed to open {NSM_DEVICE}"))?;
+
+    let mut response = vec![0u8; NSM_RESPONSE_MAX];
+    let mut message = NsmMessage {
+        request: libc::iovec {
+            iov_base: request.as_ptr().cast_mut().cast(),
+            iov_len: request.len(),
+        },
+        response: libc::iovec {
+            iov_base: response.as_mut_ptr().cast(),
+            iov_len: response.len(),
+        },
+    };
+    // SAFETY: both iovecs point at live buffers of the stated lengths for the
+    // duration of the call. The driver only reads the request and writes at
+    // most `response.len()` bytes, then stores the written length back into
+    // `message.response.iov_len`.
+    let rc = unsafe { libc::ioctl(device.as_raw_fd(), NSM_IOCTL as _, &mut message) };
+    if rc < 0 {
+        return Err(io::Error::last_os_error()).context("NSM ioctl failed");
+    }
+    response.truncate(message.response.iov_len.min(NSM_RESPONSE_MAX));
+    Ok(response)
+}
+
+/// `{"Attestation": {"user_data": null, "nonce": null, "public_key": <bytes>}}`
+fn attestation_request(public_key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(public_key.len() + 64);
+    header(&mut out, MAP, 1);
+    text(&mut out, "Attestation");
+    header(&mut out, MAP, 3);
+    text(&mut out, "user_data");
+    out.push(NULL);
+    text(&mut out, "nonce");
+    out.push(NULL);
+    text(&mut out, "public_key");
+    header(&mut out, BYTES, public_key.len() as u64);
+    out.extend_from_slice(public_key);
+    out
+}
+
+/// The document from `{"Attestation": {"document": <bytes>}}`, or the error
+/// from `{"Error": "<code>"}`.
+fn attestation_response(response: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader(response);
+    if reader.header(MAP)? != 1 {
+        anyhow::bail!("unexpected NSM response shape");
+    }
+    match reader.text()? {
+        "Attestation" => {
+            for _ in 0..reader.header(MAP)? {
+                if reader.text()? == "document" {
+                    return reader.bytes();
+                }
+                reader.skip()?;
+            }
+            anyhow::bail!("NSM attestation response has no document")
+        }
+        "Error" => anyhow::bail!("NSM returned error {}", reader.text()?),
+        other => anyhow::bail!("unexpected NSM response {other}"),
+    }
+}
+
+// CBOR major types and the one simple value used.
+const UINT: u8 = 0;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const NULL: u8 = 0xf6;
+
+/// Append the head of a CBOR item of `major` type with argument `len`.
+fn header(out: &mut Vec<u8>, major: u8, len: u64) {
+    let major = major << 5;
+    match len {
+        0..=23 => out.push(major | len as u8),
+        24..=0xff => out.extend([major | 24, len as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((len as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((len as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(len.to_be_bytes());
+        }
+    }
+}
+
+fn text(out: &mut Vec<u8>, s: &str) {
+    header(out, TEXT, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Cursor over definite-length CBOR.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.0.len() < n {
+            anyhow::bail!("truncated NSM response");
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    /// The major type and argument of the next item.
+    fn next(&mut self) -> Result<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let width = match initial & 0x1f {
+            n @ 0..=23 => return Ok((initial >> 5, u64::from(n))),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => anyhow::bail!("unsupported CBOR item in NSM response"),
+        };
+        let arg = self
+            .take(width)?
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+        Ok((initial >> 5, arg))
+    }
+
+    /// The argument of the next item, which must be of `major` type.
+    fn header(&mut self, major: u8) -> Result<u64> {
+        match self.next()? {
+            (m, arg) if m == major => Ok(arg),
+            (m, _) => anyhow::bail!("expected CBOR major type {major}, found {m}"),
+        }
+    }
+
+    fn text(&mut self) -> Result<&'a str> {
+        let len = self.header(TEXT)?;
+        let raw = self.take(usize::try_from(len)?)?;
+        std::str::from_utf8(raw).context("NSM response text is not UTF-8")
+    }
+
+    /// A byte string, also accepted as an array of small integers.
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        match self.next()? {
+            (BYTES, len) => Ok(self.take(usize::try_from(len)?)?.to_vec()),
+            (ARRAY, len) => (0..len)
+                .map(|_| Ok(u8::try_from(self.header(UINT)?)?))
+                .collect(),
+            (m, _) => anyhow::bail!("expected CBOR bytes, found major type {m}"),
+        }
+    }
+
+    /// Skip over one complete item.
+    fn skip(&mut self) -> Result<()> {
+        let (major, arg) = self.next()?;
+        match major {
+            BYTES | TEXT => {
+                self.take(usize::try_from(arg)?)?;
+            }
+            ARRAY => (0..arg).try_for_each(|_| self.skip())?,
+            MAP => (0..arg * 2).try_for_each(|_| self.skip())?,
+            6 => self.skip()?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attestation_request_is_externally_tagged_cbor() {
+        let request = attestation_request(&[0xAB; 300]);
+        let mut reader = Reader(&request);
+        assert_eq!(reader.header(MAP).unwrap(), 1);
+        assert_eq!(reader.text().unwrap(), "Attestation");
+        assert_eq!(reader.header(MAP).unwrap(), 3);
+        assert_eq!(reader.text().unwrap(), "user_data");
+        assert_eq!(reader.next().unwrap(), (7, 22));
+        assert_eq!(reader.text().unwrap(), "nonce");
+        assert_eq!(reader.next().unwrap(), (7, 22));
+        assert_eq!(reader.text().unwrap(), "public_key");
+        assert_eq!(reader.bytes().unwrap(), vec![0xAB; 300]);
+        assert!(reader.0.is_empty());
+    }
+
+    #[test]
+    fn document_is_read_from_attestation_response() {
+        let mut response = Vec::new();
+        header(&mut response, MAP, 1);
+        text(&mut response, "Attestation");
+        header(&mut response, MAP, 2);
+        text(&mut response, "extra");
+        header(&mut response, ARRAY, 2);
+        header(&mut response, UINT, 1000);
+        text(&mut response, "x");
+        text(&mut response, "document");
+        header(&mut response, BYTES, 3);
+        response.extend([1, 2, 3]);
+        assert_eq!(attestation_response(&response).unwrap(), [1, 2, 3]);
+
+        // The same document encoded as an array of integers.
+        let mut response = Vec::new();
+        header(&mut response, MAP, 1);
+        text(&mut response, "Attestation");
+        header(&mut response, MAP, 1);
+        text(&mut response, "document");
+        header(&mut response, ARRAY, 3);
+        [1u64, 2, 3]
+            .iter()
+            .for_each(|&b| header(&mut response, UINT, b));
+        assert_eq!(attestation_response(&response).unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn nsm_errors_and_malformed_responses_are_rejected() {
+        let mut response = Vec::new();
+        header(&mut response, MAP, 1);
+        text(&mut response, "Error");
+        text(&mut response, "InvalidArgument");
+        let err = attestation_response(&response).unwrap_err();
+        assert!(err.to_string().contains("InvalidArgument"), "{err}");
+
+        let truncated = &attestation_request(&[0; 40])[..20];
+        assert!(attestation_response(truncated).is_err());
+        assert!(attestation_response(&[]).is_err());
+    }
+}
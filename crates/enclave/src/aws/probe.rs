@@ -0,0 +1,81 @@
+//! Connectivity probe for the vsock path to the parent's AWS proxy.
+//!
+//! DEK rotation and schema refresh only notice a dead `vsock-proxy` on their
+//! next run, which may be an hour away. The probe opens (and immediately
+//! closes) a bare vsock connection to the KMS proxy port on a short interval,
+//! without sending a request, and publishes the result for readiness.
+
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+use tokio_vsock::{VsockAddr, VsockStream};
+use tracing::{info, warn};
+
+/// Upper bound on one connection attempt, so a wedged proxy cannot stall the probe.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether `connect` establishes a connection within `timeout`.
+///
+/// The connection is dropped as soon as it is established.
+pub async fn reachable<F, Fut, S>(connect: F, timeout: Duration) -> bool
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = io::Result<S>>,
+{
+    matches!(time::timeout(timeout, connect()).await, Ok(Ok(_)))
+}
+
+/// Spawn a background task that probes vsock(`cid`, `port`) every `interval`
+/// and stores the outcome in `flag`. Transitions are logged once each.
+pub fn probe_task(
+    cid: u32,
+    port: u32,
+    interval: Duration,
+    flag: Arc<AtomicBool>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let ok = reachable(
+                || VsockStream::connect(VsockAddr::new(cid, port)),
+                PROBE_TIMEOUT.min(interval),
+            )
+            .await;
+            match (flag.swap(ok, Ordering::Relaxed), ok) {
+                (true, false) => warn!(cid, port, "vsock proxy unreachable; readiness degraded"),
+                (false, true) => info!(cid, port, "vsock proxy reachable again"),
+                _ => {}
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn probe_detects_reachable_and_unreachable_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+        assert!(reachable(|| TcpStream::connect(open), PROBE_TIMEOUT).await);
+
+        let closed = {
+            let gone = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            gone.local_addr().unwrap()
+        };
+        assert!(!reachable(|| TcpStream::connect(closed), PROBE_TIMEOUT).await);
+    }
+
+    #[tokio::test]
+    async fn hanging_connect_times_out() {
+        let hang = || std::future::pending::<io::Result<()>>();
+        assert!(!reachable(hang, Duration::from_millis(20)).await);
+    }
+}
@@ -0,0 +1,224 @@
+//! KMS recipient attestation for DEK decryption.
+//!
+//! With `KMS_RECIPIENT_ATTESTATION` set, every `kms:Decrypt` of a DEK share
+//! carries the enclave's attestation document as its `Recipient` parameter.
+//! KMS then withholds the plaintext and returns it re-encrypted to the public
+//! key bound in the document (`CiphertextForRecipient`), which only the
+//! enclave can open. A response that still carries `Plaintext` means the
+//! attestation was not enforced, and the share is rejected.
+//!
+//! The attestation document and the recipient key come from a
+//! [`KmsRecipient`]; [`AwsClients`](super::AwsClients) holds the one in use.
+//! In the enclave that is an [`NsmRecipient`].
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use aws_lc_rs::encoding::AsDer;
+use aws_lc_rs::rsa::{KeySize, OaepPrivateDecryptingKey, PrivateDecryptingKey};
+use aws_sdk_kms::operation::decrypt::builders::DecryptFluentBuilder;
+use aws_sdk_kms::primitives::Blob;
+use aws_sdk_kms::types::{KeyEncryptionMechanism, RecipientInfo};
+
+use super::{cms, nsm};
+
+/// Source of the attestation document sent to KMS and holder of the private
+/// key it binds.
+pub trait KmsRecipient: Send + Sync {
+    /// A fresh attestation document embedding the recipient public key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document cannot be produced.
+    fn attestation_document(&self) -> Result<Vec<u8>>;
+
+    /// Decrypt a `CiphertextForRecipient` returned by KMS.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ciphertext is not addressed to this recipient.
+    fn open(&self, ciphertext_for_recipient: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A recipient backed by the Nitro Secure Module.
+///
+/// Holds an RSA-2048 key pair generated at startup that never leaves enclave
+/// memory. Each attestation document is requested from the NSM with the
+/// public key embedded, so KMS can only encrypt to this enclave.
+pub struct NsmRecipient {
+    key: OaepPrivateDecryptingKey,
+    public_key: Vec<u8>,
+}
+
+impl NsmRecipient {
+    /// Generate the recipient key pair.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if key generation fails.
+    pub fn generate() -> Result<Self> {
+        let private = PrivateDecryptingKey::generate(KeySize::Rsa2048)
+            .map_err(|_| anyhow::anyhow!("failed to generate KMS recipient key"))?;
+        let public_key = private
+            .public_key()
+            .as_der()
+            .map_err(|_| anyhow::anyhow!("failed to encode KMS recipient public key"))?
+            .as_ref()
+            .to_vec();
+        let key = OaepPrivateDecryptingKey::new(private)
+            .map_err(|_| anyhow::anyhow!("KMS recipient key is unusable for RSA-OAEP"))?;
+        Ok(Self { key, public_key })
+    }
+}
+
+impl KmsRecipient for NsmRecipient {
+    fn attestation_document(&self) -> Result<Vec<u8>> {
+        nsm::attestation_document(&self.public_key)
+    }
+
+    fn open(&self, ciphertext_for_recipient: &[u8]) -> Result<Vec<u8>> {
+        cms::open(ciphertext_for_recipient, &self.key)
+    }
+}
+
+/// The recipient used for DEK decryption when `enabled`.
+///
+/// # Errors
+///
+/// Returns an error when attestation is enabled but no recipient is available.
+pub fn required(
+    enabled: bool,
+    recipient: Option<&Arc<dyn KmsRecipient>>,
+) -> Result<Option<&dyn KmsRecipient>> {
+    if !enabled {
+        return Ok(None);
+    }
+    recipient
+        .map(|r| Some(r.as_ref()))
+        .context("KMS_RECIPIENT_ATTESTATION is set but no KMS recipient was configured")
+}
+
+/// Attach `recipient`'s attestation document to a KMS decrypt request.
+///
+/// # Errors
+///
+/// Returns an error if the attestation document cannot be produced.
+pub fn attach(
+    request: DecryptFluentBuilder,
+    recipient: Option<&dyn KmsRecipient>,
+) -> Result<DecryptFluentBuilder> {
+    let Some(recipient) = recipient else {
+        return Ok(request);
+    };
+    let document = recipient
+        .attestation_document()
+        .context("failed to produce attestation document for KMS")?;
+    Ok(request.recipient(
+        RecipientInfo::builder()
+            .key_encryption_algorithm(KeyEncryptionMechanism::RsaesOaepSha256)
+            .attestation_document(Blob::new(document))
+            .build(),
+    ))
+}
+
+/// The decrypted bytes of a KMS decrypt response.
+///
+/// Without a recipient the response must carry `plaintext`. With one it must
+/// carry `ciphertext_for_recipient` and no `plaintext`; the former is opened
+/// with the recipient key.
+///
+/// # Errors
+///
+/// Returns an error if the expected field is missing, if KMS returned
+/// plaintext despite the recipient, or if the ciphertext cannot be opened.
+pub fn plaintext(
+    plaintext: Option<&[u8]>,
+    ciphertext_for_recipient: Option<&[u8]>,
+    recipient: Option<&dyn KmsRecipient>,
+) -> Result<Vec<u8>> {
+    let Some(recipient) = recipient else {
+        return plaintext
+            .map(<[u8]>::to_vec)
+            .context("KMS decrypt response contained no plaintext");
+    };
+    if plaintext.is_some() {
+        anyhow::bail!("KMS returned plaintext despite recipient attestation; refusing the DEK");
+    }
+    let ciphertext = ciphertext_for_recipient
+        .context("KMS decrypt response contained no CiphertextForRecipient")?;
+    recipient
+        .open(ciphertext)
+        .context("failed to open KMS CiphertextForRecipient")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a fixed document and "opens" ciphertext by reversing it.
+    struct Fixed;
+
+    impl KmsRecipient for Fixed {
+        fn attestation_document(&self) -> Result<Vec<u8>> {
+            Ok(b"attestation-document".to_vec())
+        }
+
+        fn open(&self, ciphertext_for_recipient: &[u8]) -> Result<Vec<u8>> {
+            Ok(ciphertext_for_recipient.iter().rev().copied().collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn attestation_document_is_attached_to_decrypt_request() {
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_config::Region::new("us-east-2"))
+            .load()
+            .await;
+        let kms = aws_sdk_kms::Client::new(&config);
+
+        let plain = attach(kms.decrypt(), None).unwrap();
+        assert!(plain.get_recipient().is_none());
+
+        let recipient: Arc<dyn KmsRecipient> = Arc::new(Fixed);
+        let used = required(true, Some(&recipient)).unwrap();
+        let request = attach(kms.decrypt(), used).unwrap();
+        let info = request.get_recipient().as_ref().unwrap();
+        assert_eq!(
+            info.attestation_document().map(|b| b.as_ref()),
+            Some(&b"attestation-document"[..])
+        );
+        assert_eq!(
+            info.key_encryption_algorithm(),
+            Some(&KeyEncryptionMechanism::RsaesOaepSha256)
+        );
+
+        assert!(required(false, Some(&recipient)).unwrap().is_none());
+        assert!(required(true, None).is_err());
+    }
+
+    #[test]
+    fn nsm_recipient_opens_envelopes_for_its_public_key() {
+        use aws_lc_rs::rsa::{OaepPublicEncryptingKey, PublicEncryptingKey};
+
+        let recipient = NsmRecipient::generate().unwrap();
+        let public = PublicEncryptingKey::from_der(&recipient.public_key).unwrap();
+        let public = OaepPublicEncryptingKey::new(public).unwrap();
+        let envelope = cms::tests::envelope(&[0x42; 32], &public, true);
+        assert_eq!(recipient.open(&envelope).unwrap(), [0x42; 32]);
+
+        let (_, other) = cms::tests::recipient_key();
+        let envelope = cms::tests::envelope(&[0x42; 32], &other, false);
+        assert!(recipient.open(&envelope).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_refused_when_recipient_was_sent() {
+        assert_eq!(plaintext(Some(b"dek"), None, None).unwrap(), b"dek");
+        assert!(plaintext(None, Some(b"keb"), None).is_err());
+
+        let err = plaintext(Some(b"dek"), Some(b"ked"), Some(&Fixed)).unwrap_err();
+        assert!(err.to_string().contains("despite recipient"), "{err}");
+        assert!(plaintext(None, None, Some(&Fixed)).is_err());
+        assert_eq!(plaintext(None, Some(b"ked"), Some(&Fixed)).unwrap(), b"dek");
+    }
+}
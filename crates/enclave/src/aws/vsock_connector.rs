@@ -14,6 +14,14 @@
 //! - base+2 (8002): Secrets Manager
 //! - base+3 (8003): S3
 //!
+//! Hosts of configured endpoint overrides are routed to their service's port
+//! by authority (`host:port`), before the hostname rules above apply.
+//!
+//! Vsock streams can be wrapped in a read/write buffering layer (see
+//! [`VsockBuffers`]) so large responses, such as big S3 schema objects, are
+//! read in fewer, larger socket reads. `tokio-vsock` exposes no socket
+//! options, so buffering is the only tuning available.
+//!
 //! IMDS (for credential resolution) is not handled here. The enclave
 //! entrypoint starts a socat bridge on 127.0.0.1:8004 → vsock(3, 8004),
 //! and `AWS_EC2_METADATA_SERVICE_ENDPOINT=http://127.0.0.1:8004` redirects
@@ -23,13 +31,14 @@
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use anyhow::{Context as _, Result};
 use hyper::rt::{ReadBufCursor, Write};
 use hyper::Uri;
 use hyper_util::client::legacy::connect::{Connected, Connection};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader, BufWriter, ReadBuf};
 use tokio::net::TcpStream;
 use tokio_vsock::{VsockAddr, VsockStream};
 use tower::Service;
@@ -51,6 +60,36 @@ fn vsock_port(host: &str, base_port: u32) -> u32 {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Buffering
+// ---------------------------------------------------------------------------
+
+/// A stream with a read buffer in front of a write buffer.
+pub type Buffered<S> = BufReader<BufWriter<S>>;
+
+/// Read and write buffer capacities (bytes) for vsock streams.
+///
+/// Both zero (the default) leaves streams unbuffered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VsockBuffers {
+    /// Read buffer capacity; `0` reads straight from the socket.
+    pub read: usize,
+    /// Write buffer capacity; `0` writes straight to the socket.
+    pub write: usize,
+}
+
+impl VsockBuffers {
+    /// Whether any buffering is configured.
+    pub fn is_enabled(self) -> bool {
+        self.read > 0 || self.write > 0
+    }
+
+    /// Wrap `stream` in buffers of the configured capacities.
+    pub fn wrap<S: AsyncRead + AsyncWrite>(self, stream: S) -> Buffered<S> {
+        BufReader::with_capacity(self.read, BufWriter::with_capacity(self.write, stream))
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Raw stream type returned by VsockRawConnector
 // ---------------------------------------------------------------------------
@@ -62,9 +101,21 @@ fn vsock_port(host: &str, base_port: u32) -> u32 {
 /// `HttpsConnector` can wrap it with TLS.
 pub enum RawStream {
     Vsock(VsockStream),
+    BufferedVsock(Buffered<VsockStream>),
     Tcp(TcpStream),
 }
 
+impl RawStream {
+    /// A vsock stream, buffered when `buffers` asks for it.
+    fn vsock(stream: VsockStream, buffers: VsockBuffers) -> Self {
+        if buffers.is_enabled() {
+            RawStream::BufferedVsock(buffers.wrap(stream))
+        } else {
+            RawStream::Vsock(stream)
+        }
+    }
+}
+
 impl Connection for RawStream {
     fn connected(&self) -> Connected {
         Connected::new()
@@ -89,6 +140,7 @@ impl hyper::rt::Read for RawStream {
             let mut tbuf = ReadBuf::uninit(buf.as_mut());
             let poll = match self.get_mut() {
                 RawStream::Vsock(s) => Pin::new(s).poll_read(cx, &mut tbuf),
+                RawStream::BufferedVsock(s) => Pin::new(s).poll_read(cx, &mut tbuf),
                 RawStream::Tcp(s) => Pin::new(s).poll_read(cx, &mut tbuf),
             };
             match poll {
@@ -109,6 +161,7 @@ impl Write for RawStream {
     ) -> Poll<io::Result<usize>> {
         match self.get_mut() {
             RawStream::Vsock(s) => Pin::new(s).poll_write(cx, buf),
+            RawStream::BufferedVsock(s) => Pin::new(s).poll_write(cx, buf),
             RawStream::Tcp(s) => Pin::new(s).poll_write(cx, buf),
         }
     }
@@ -116,6 +169,7 @@ impl Write for RawStream {
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
             RawStream::Vsock(s) => Pin::new(s).poll_flush(cx),
+            RawStream::BufferedVsock(s) => Pin::new(s).poll_flush(cx),
             RawStream::Tcp(s) => Pin::new(s).poll_flush(cx),
         }
     }
@@ -123,6 +177,7 @@ impl Write for RawStream {
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         match self.get_mut() {
             RawStream::Vsock(s) => Pin::new(s).poll_shutdown(cx),
+            RawStream::BufferedVsock(s) => Pin::new(s).poll_shutdown(cx),
             RawStream::Tcp(s) => Pin::new(s).poll_shutdown(cx),
         }
     }
@@ -142,13 +197,45 @@ pub struct VsockRawConnector {
     cid: u32,
     /// Base vsock port. KMS = base+1, SM = base+2, S3 = base+3.
     base_port: u32,
+    /// Authorities (`host:port`) of endpoint overrides and their port offset.
+    routes: Arc<[(String, u32)]>,
+    /// Buffering applied to every vsock stream.
+    buffers: VsockBuffers,
 }
 
 impl VsockRawConnector {
     /// Create a new connector.
     pub fn new(cid: u32, base_port: u32) -> Self {
-        Self { cid, base_port }
+        Self {
+            cid,
+            base_port,
+            routes: Arc::new([]),
+            buffers: VsockBuffers::default(),
+        }
     }
+
+    /// Wrap every vsock stream in buffers of the given capacities.
+    pub fn with_buffers(mut self, buffers: VsockBuffers) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    /// Route connections to each `(authority, offset)` to vsock port
+    /// `base + offset`, whatever the hostname.
+    pub fn with_routes(mut self, routes: Vec<(String, u32)>) -> Self {
+        self.routes = routes.into();
+        self
+    }
+}
+
+/// `host:port` of `uri`, with the scheme's default port when none is given.
+pub(crate) fn authority(uri: &Uri) -> Option<String> {
+    let host = uri.host()?;
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("http") => 80,
+        _ => 443,
+    });
+    Some(format!("{host}:{port}"))
 }
 
 impl Service<Uri> for VsockRawConnector {
@@ -163,10 +250,27 @@ impl Service<Uri> for VsockRawConnector {
     fn call(&mut self, uri: Uri) -> Self::Future {
         let cid = self.cid;
         let base_port = self.base_port;
+        let buffers = self.buffers;
+        let routed = authority(&uri).and_then(|authority| {
+            self.routes
+                .iter()
+                .find(|(route, _)| *route == authority)
+                .map(|&(_, offset)| base_port + offset)
+        });
 
         Box::pin(async move {
             let host = uri.host().unwrap_or("").to_owned();
 
+            // Endpoint override: its service's proxy, whatever the host.
+            if let Some(port) = routed {
+                let stream = VsockStream::connect(VsockAddr::new(cid, port))
+                    .await
+                    .with_context(|| {
+                        format!("vsock connect to CID={cid} port={port} for {host}")
+                    })?;
+                return Ok(RawStream::vsock(stream, buffers));
+            }
+
             // Local addresses (e.g. IMDS redirect on 127.0.0.1:8004): plain TCP.
             if host == "127.0.0.1" || host == "localhost" {
                 let port = uri.port_u16().unwrap_or(80);
@@ -182,7 +286,7 @@ impl Service<Uri> for VsockRawConnector {
             let stream = VsockStream::connect(addr)
                 .await
                 .with_context(|| format!("vsock connect to CID={cid} port={port} for {host}"))?;
-            Ok(RawStream::Vsock(stream))
+            Ok(RawStream::vsock(stream, buffers))
         })
     }
 }
@@ -195,6 +299,35 @@ impl Service<Uri> for VsockRawConnector {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn buffers_wrap_stream_with_configured_capacities() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (local, mut peer) = tokio::io::duplex(1024);
+        let buffers = VsockBuffers {
+            read: 16,
+            write: 32,
+        };
+        assert!(buffers.is_enabled());
+        assert!(!VsockBuffers::default().is_enabled());
+        let mut stream = buffers.wrap(local);
+
+        // Small writes stay in the write buffer until flushed.
+        stream.write_all(b"hello").await.unwrap();
+        assert_eq!(stream.get_ref().buffer(), b"hello");
+        stream.flush().await.unwrap();
+        assert!(stream.get_ref().buffer().is_empty());
+        let mut received = [0u8; 5];
+        peer.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"hello");
+
+        // A one-byte read fills the read buffer up to its capacity.
+        peer.write_all(&[7u8; 100]).await.unwrap();
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await.unwrap();
+        assert_eq!(stream.buffer().len(), 15);
+    }
+
     #[test]
     fn port_mapping_kms() {
         assert_eq!(vsock_port("kms.us-east-2.amazonaws.com", 8000), 8001);
@@ -2,9 +2,22 @@
 //!
 //! All values are read from environment variables at startup. The process will
 //! exit with a clear error message if any required variable is missing or invalid.
+//!
+//! With `EXPAND_ENV_VARS=true`, `${VAR}` references inside configuration
+//! values are replaced with the value of the process environment variable
+//! `VAR` (`$$` is a literal `$`), so deployment templates can write e.g.
+//! `KMS_ENDPOINT_URL=https://kms.${AWS_REGION}.staging.internal`.
+
+use std::collections::{HashMap, HashSet};
 
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use serde::de::{self, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::aws::{EndpointOverrides, VsockBuffers};
+use crate::crypto::cipher::CiphertextEncoding;
+use crate::server::identity::SchemaAllowlist;
+use crate::server::mask::MaskPolicy;
 
 /// Validated enclave service configuration.
 #[derive(Debug, Clone, Deserialize)]
@@ -12,9 +25,28 @@ pub struct Config {
     /// Secrets Manager ARN of the envelope-encrypted DEK. **Required.**
     pub secret_arn: String,
 
+    /// Comma-separated Secrets Manager ARNs of further DEK shares for split
+    /// knowledge. When set, each share is KMS-decrypted like `secret_arn`'s
+    /// and the DEK is the XOR of all of them, so no single secret reveals it.
+    #[serde(default)]
+    pub secret_share_arns: Option<String>,
+
+    /// Secrets Manager ARN of the envelope-encrypted token key, which hash,
+    /// lookup and format-preserving tokens are derived from. Unlike the DEK it
+    /// is fetched once at startup and never rotated, so tokens stay stable and
+    /// reversible. Without it, `x-pii-mode: hash`, `lookup` and `fpe` fields
+    /// are refused.
+    #[serde(default)]
+    pub token_key_secret_arn: Option<String>,
+
     /// KMS key ID used to decrypt the DEK. **Required.**
     pub kms_key_id: String,
 
+    /// Send the enclave's attestation document as the `Recipient` of each
+    /// KMS decrypt and reject responses that carry plaintext anyway.
+    #[serde(default)]
+    pub kms_recipient_attestation: bool,
+
     /// S3 bucket containing OpenAPI spec files. **Required.**
     pub s3_bucket: String,
 
@@ -22,6 +54,49 @@ pub struct Config {
     #[serde(default = "default_s3_prefix")]
     pub s3_prefix: String,
 
+    /// Additional schema sources beyond `s3_bucket`/`s3_prefix`, as a
+    /// comma-separated list of `bucket[/prefix]` entries
+    /// (e.g. `"team-a-schemas/schemas/,team-b-schemas/pii/"`).
+    #[serde(default)]
+    pub s3_extra_sources: Option<String>,
+
+    /// Skip schema objects that fail to parse (with a warning) instead of
+    /// failing the whole load.
+    #[serde(default)]
+    pub schema_load_lenient: bool,
+
+    /// Comma-separated field patterns that must be PII wherever a schema
+    /// declares them (e.g. `"*.ssn,*.card_number"`), checked at startup.
+    #[serde(default)]
+    pub required_pii_paths: Option<String>,
+
+    /// Log schemas that declare a required PII field without marking it PII
+    /// instead of failing startup.
+    #[serde(default)]
+    pub required_pii_lenient: bool,
+
+    /// Consecutive refreshes on which a skipped object must fail to parse
+    /// before it is reported as quarantined; `0` disables quarantine.
+    #[serde(default = "default_schema_quarantine_threshold")]
+    pub schema_quarantine_threshold: u32,
+
+    /// Most schemas the cache may hold; `0` (the default) sets no limit. A
+    /// load over the limit fails, or with `schema_load_lenient` keeps the
+    /// first schemas by name and skips the rest with a warning.
+    #[serde(default)]
+    pub max_cached_schemas: usize,
+
+    /// Keep each parsed OpenAPI document in the cache alongside the PII paths
+    /// derived from it. Disable to save memory when many schemas are loaded.
+    #[serde(default = "default_retain_schema_documents")]
+    pub retain_schema_documents: bool,
+
+    /// Comma-separated schema names resolved into PII paths at load. Other
+    /// schemas are parsed at load but resolved on their first request. Unset,
+    /// every schema is resolved at load.
+    #[serde(default)]
+    pub eager_schemas: Option<String>,
+
     /// HTTP header used to identify which schema to apply.
     #[serde(default = "default_schema_header")]
     pub schema_header_name: String,
@@ -34,6 +109,11 @@ pub struct Config {
     #[serde(default = "default_schema_refresh_interval")]
     pub schema_refresh_interval_secs: u64,
 
+    /// How long (seconds) schema names removed by a refresh are reported as
+    /// `schema_removed` (410) instead of unknown (400). `0` disables tombstones.
+    #[serde(default)]
+    pub schema_tombstone_grace_secs: u64,
+
     /// Vsock CID of the parent EC2 aws-vsock-proxy. **Required.**
     pub vsock_proxy_cid: u32,
 
@@ -41,10 +121,44 @@ pub struct Config {
     #[serde(default = "default_vsock_proxy_port")]
     pub vsock_proxy_port: u32,
 
+    /// Read buffer (bytes) wrapped around each vsock stream to the AWS
+    /// proxies. `0` (default) reads straight from the socket.
+    #[serde(default)]
+    pub vsock_read_buffer_bytes: usize,
+
+    /// Write buffer (bytes) wrapped around each vsock stream to the AWS
+    /// proxies. `0` (default) writes straight to the socket.
+    #[serde(default)]
+    pub vsock_write_buffer_bytes: usize,
+
+    /// KMS endpoint URL replacing the regional default (test/staging only).
+    #[serde(default)]
+    pub kms_endpoint_url: Option<String>,
+
+    /// Secrets Manager endpoint URL replacing the regional default.
+    #[serde(default)]
+    pub secretsmanager_endpoint_url: Option<String>,
+
+    /// S3 endpoint URL replacing the regional default; implies path-style
+    /// addressing.
+    #[serde(default)]
+    pub s3_endpoint_url: Option<String>,
+
+    /// Expand `${VAR}` references in configuration values from the process
+    /// environment; a reference to an unset variable fails startup.
+    #[serde(default)]
+    pub expand_env_vars: bool,
+
     /// Port the enclave HTTPS server listens on.
     #[serde(default = "default_tls_port")]
     pub tls_port: u16,
 
+    /// When set, read-only routes (health, previews, stats, schema lookups)
+    /// move to this second TLS port and `tls_port` serves only the mutating
+    /// and data-plane routes. Unset serves everything on `tls_port`.
+    #[serde(default)]
+    pub read_only_port: Option<u16>,
+
     /// Filesystem path to the PEM-encoded TLS certificate chain delivered by
     /// ACM for Nitro Enclaves. **Required.**
     pub tls_cert_path: String,
@@ -53,12 +167,271 @@ pub struct Config {
     /// ACM for Nitro Enclaves. **Required.**
     pub tls_key_path: String,
 
+    /// Filesystem path to a PEM bundle of CAs trusted to issue client
+    /// certificates. When set, clients must present a certificate (mTLS).
+    #[serde(default)]
+    pub tls_client_ca_path: Option<String>,
+
+    /// Certificate chain served on `read_only_port`; defaults to
+    /// `tls_cert_path`. Requires `read_only_port`.
+    #[serde(default)]
+    pub read_only_tls_cert_path: Option<String>,
+
+    /// Private key for `read_only_tls_cert_path`; defaults to `tls_key_path`.
+    /// Requires `read_only_port`.
+    #[serde(default)]
+    pub read_only_tls_key_path: Option<String>,
+
+    /// Client CA bundle for `read_only_port`; defaults to
+    /// `tls_client_ca_path`. Set it alone to require mTLS on the read-only
+    /// port while `tls_port` stays server-authenticated only. Requires
+    /// `read_only_port`.
+    #[serde(default)]
+    pub read_only_tls_client_ca_path: Option<String>,
+
+    /// Passphrase for the TLS private key when ACM delivers it as an
+    /// `ENCRYPTED PRIVATE KEY` (PBES2, PBKDF2-HMAC-SHA256, AES-CBC).
+    #[serde(default)]
+    pub tls_key_passphrase: Option<Secret>,
+
+    /// Capacity of the TLS session-ID resumption cache; `0` disables it.
+    #[serde(default = "default_tls_session_cache_size")]
+    pub tls_session_cache_size: usize,
+
+    /// Issue TLS session tickets (keys are ephemeral, generated per boot).
+    #[serde(default)]
+    pub tls_session_tickets: bool,
+
+    /// Client certificate CN → schema-prefix grants, in the form
+    /// `cn=prefix[,prefix...][;cn=...]`. When set, each client may only use
+    /// schemas whose names start with a prefix granted to its CN.
+    /// Requires `tls_client_ca_path`.
+    #[serde(default)]
+    pub client_schema_allowlist: Option<String>,
+
+    /// Comma-separated client certificate CNs holding the admin role, which
+    /// grants access to `POST /admin/decrypt/preview`. Requires
+    /// `tls_client_ca_path`.
+    #[serde(default)]
+    pub admin_client_cns: Option<String>,
+
+    /// Masking rules for the decrypt preview, keyed by `x-pii-category`:
+    /// `CATEGORY=rule[;...]` with rules `full`, `first:N`, or `last:N`, and
+    /// `default=rule` for everything else. Defaults to `last:4` throughout.
+    #[serde(default)]
+    pub mask_rules: Option<String>,
+
+    /// Comma-separated header names whose values must never reach spans or
+    /// logs, in addition to `Authorization`, `Proxy-Authorization`, `Cookie`,
+    /// `Set-Cookie` and `X-Tenant-Id`.
+    #[serde(default)]
+    pub redacted_headers: Option<String>,
+
     /// OTLP endpoint (vsock address to OTEL collector). **Required.**
     pub otel_exporter_otlp_endpoint: String,
 
     /// Tracing log level (e.g. `"info"`, `"debug"`).
     #[serde(default = "default_log_level")]
     pub log_level: String,
+
+    /// Also export log records over OTLP to the collector, alongside the
+    /// stdout and tcplog JSON streams.
+    #[serde(default)]
+    pub otel_logs_enabled: bool,
+
+    /// Upper bound (seconds) on the initial DEK fetch + decrypt at startup.
+    #[serde(default = "default_startup_dek_timeout")]
+    pub startup_dek_timeout_secs: u64,
+
+    /// Upper bound (seconds) on the initial schema load from S3 at startup.
+    #[serde(default = "default_startup_schema_timeout")]
+    pub startup_schema_timeout_secs: u64,
+
+    /// Minimum number of cached schemas required before `/health` reports ready.
+    /// Set to `0` for deployments that push schemas after startup.
+    #[serde(default = "default_min_schemas_for_ready")]
+    pub min_schemas_for_ready: usize,
+
+    /// Whether `/health` requires a loaded DEK before reporting ready.
+    #[serde(default = "default_require_dek_for_ready")]
+    pub require_dek_for_ready: bool,
+
+    /// Consecutive DEK rotation failures before the KMS circuit breaker opens.
+    #[serde(default = "default_kms_breaker_failure_threshold")]
+    pub kms_breaker_failure_threshold: u32,
+
+    /// While the KMS circuit breaker is open, the next rotation attempt is
+    /// deferred by this multiple of `dek_rotation_interval_secs`.
+    #[serde(default = "default_kms_breaker_backoff_multiplier")]
+    pub kms_breaker_backoff_multiplier: u32,
+
+    /// `Retry-After` value (seconds) sent with 503 responses while the DEK or
+    /// schemas are not yet loaded.
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+
+    /// How long (milliseconds) `/encrypt` waits for the DEK and the first
+    /// schema load before answering `503`; `0` answers at once.
+    #[serde(default)]
+    pub encrypt_ready_wait_ms: u64,
+
+    /// HTTP status for a request naming a schema that is not loaded: `400`
+    /// (the default) or `404`. Until the first schema load completes, the
+    /// request gets a retryable `503` `schemas_loading` instead.
+    #[serde(default = "default_unknown_schema_status")]
+    pub unknown_schema_status: u16,
+
+    /// Reject `/encrypt` and `/decrypt` requests without an `X-Tenant-Id` header.
+    #[serde(default)]
+    pub require_tenant: bool,
+
+    /// Reject payloads whose root is not the kind (object/array) the schema
+    /// describes, instead of returning them unchanged.
+    #[serde(default = "default_enforce_payload_root")]
+    pub enforce_payload_root: bool,
+
+    /// Reject `/encrypt` payloads with top-level keys the schema does not
+    /// declare, so fields missing from the schema cannot slip through
+    /// unannotated. Free-form schemas (no declared properties) are exempt.
+    #[serde(default)]
+    pub reject_unknown_top_level_keys: bool,
+
+    /// Audit the nonces of the ciphertext in each `/decrypt` payload and flag
+    /// (log and count) reuse that honest encryption never produces, a sign the
+    /// stored ciphertext was tampered with. Decryption itself is unchanged.
+    #[serde(default)]
+    pub audit_nonce_reuse: bool,
+
+    /// Maximum size in bytes of a PII string value accepted by `/encrypt` when
+    /// the schema declares no `maxLength` for the field.
+    #[serde(default = "default_max_field_bytes")]
+    pub max_field_bytes: usize,
+
+    /// Marker that `/redact` puts in place of every PII value.
+    #[serde(default = "default_redaction_marker")]
+    pub redaction_marker: String,
+
+    /// Responses whose body exceeds this many bytes are sent uncompressed even
+    /// when the caller accepts compression, bounding the memory compression
+    /// takes; `0` sets no limit.
+    #[serde(default = "default_compression_max_response_bytes")]
+    pub compression_max_response_bytes: u64,
+
+    /// Verify the `Content-MD5` or `X-Content-SHA256` checksum of a request
+    /// body that carries one, answering `400` on a mismatch. Off by default,
+    /// as verification buffers the body before the handler sees it.
+    #[serde(default)]
+    pub verify_body_checksums: bool,
+
+    /// Maximum number of PII leaves one `/encrypt` payload (or batch item)
+    /// may carry; larger payloads are rejected before any is encrypted.
+    /// `0` (the default) sets no limit.
+    #[serde(default)]
+    pub max_encrypted_fields: usize,
+
+    /// Maximum number of items an array on a PII path may hold; a payload
+    /// with a larger one is rejected with `400 array_too_large` instead of
+    /// traversed. `0` sets no limit.
+    #[serde(default = "default_max_array_items")]
+    pub max_array_items: usize,
+
+    /// Accept `/encrypt` requests that list their PII paths inline
+    /// (`pii_paths`) instead of naming a schema registered in S3.
+    #[serde(default)]
+    pub allow_inline_schema: bool,
+
+    /// Serve `POST /encrypt/value`, which encrypts a single string with the
+    /// current DEK and no schema, and `POST /decrypt/value`, which reverses it.
+    #[serde(default)]
+    pub allow_value_endpoint: bool,
+
+    /// When the last successful schema refresh is older than this (seconds),
+    /// `/encrypt` returns `503 schemas_stale` and readiness degrades. `0`
+    /// disables the check. Must exceed `schema_refresh_interval_secs`.
+    #[serde(default)]
+    pub max_schema_staleness_secs: u64,
+
+    /// Embed a short prefix of the applied schema's fingerprint in every
+    /// ciphertext (`v1.<schema_tag>.<nonce>.<ciphertext>`) for forensic
+    /// traceability.
+    #[serde(default)]
+    pub schema_tag_ciphertext: bool,
+
+    /// How new ciphertext is initially written: `compact_string` (`v1.`
+    /// strings) or `json_object` (`{"alg", "nonce", "ct"}` objects).
+    /// Adjustable at runtime through `PUT /admin/encryption`; `/decrypt`
+    /// accepts both regardless.
+    #[serde(default)]
+    pub ciphertext_encoding: CiphertextEncoding,
+
+    /// PII strings with fewer characters than this are passed through
+    /// unencrypted (e.g. `1` leaves empty strings as-is). `0` (default)
+    /// encrypts every string.
+    #[serde(default)]
+    pub min_encrypt_len: usize,
+
+    /// Reject (`400`) a payload whose PII path holds an object, array or
+    /// boolean, or a number where the schema expects a string, instead of
+    /// leaving that value unencrypted.
+    #[serde(default)]
+    pub strict_pii_leaf_types: bool,
+
+    /// Reserved schema name that encrypts nothing: requests naming it pass
+    /// through unchanged, whatever the cache holds. For measuring the
+    /// service's overhead during phased rollouts.
+    pub noop_schema_name: Option<String>,
+
+    /// Interval (seconds) between vsock connectivity probes of the KMS proxy
+    /// port; an unreachable proxy degrades readiness. `0` disables the probe.
+    #[serde(default)]
+    pub proxy_probe_interval_secs: u64,
+
+    /// Capacity of the queue of audit events awaiting the audit writer; a
+    /// full queue drops events. `0` (default) disables audit events.
+    #[serde(default)]
+    pub audit_queue_capacity: usize,
+}
+
+/// A configuration value that must never appear in logs; `Debug` prints
+/// `[REDACTED]`.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wrap a secret value.
+    #[cfg(test)]
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    /// The secret value itself.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+/// One S3 location from which OpenAPI schemas are loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaSource {
+    /// S3 bucket name.
+    pub bucket: String,
+    /// Key prefix within the bucket (may be empty).
+    pub prefix: String,
+}
+
+impl SchemaSource {
+    /// The source's name, `bucket/prefix`, as accepted by
+    /// `POST /admin/reload-schemas?source=`.
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.bucket, self.prefix)
+    }
 }
 
 fn default_s3_prefix() -> String {
@@ -73,15 +446,63 @@ fn default_dek_rotation_interval() -> u64 {
 fn default_schema_refresh_interval() -> u64 {
     300
 }
+fn default_schema_quarantine_threshold() -> u32 {
+    3
+}
 fn default_vsock_proxy_port() -> u32 {
     8000
 }
 fn default_tls_port() -> u16 {
     443
 }
+fn default_tls_session_cache_size() -> usize {
+    256
+}
 fn default_log_level() -> String {
     "info".into()
 }
+fn default_startup_dek_timeout() -> u64 {
+    30
+}
+fn default_startup_schema_timeout() -> u64 {
+    60
+}
+fn default_min_schemas_for_ready() -> usize {
+    1
+}
+fn default_require_dek_for_ready() -> bool {
+    true
+}
+fn default_retry_after_secs() -> u64 {
+    5
+}
+fn default_unknown_schema_status() -> u16 {
+    400
+}
+fn default_retain_schema_documents() -> bool {
+    true
+}
+fn default_redaction_marker() -> String {
+    "[REDACTED]".into()
+}
+fn default_enforce_payload_root() -> bool {
+    true
+}
+fn default_max_field_bytes() -> usize {
+    64 * 1024
+}
+fn default_max_array_items() -> usize {
+    100_000
+}
+fn default_compression_max_response_bytes() -> u64 {
+    8 * 1024 * 1024
+}
+fn default_kms_breaker_failure_threshold() -> u32 {
+    3
+}
+fn default_kms_breaker_backoff_multiplier() -> u32 {
+    4
+}
 
 impl Config {
     /// Load and validate configuration from environment variables.
@@ -90,10 +511,19 @@ impl Config {
     ///
     /// Returns an error if any required variable is absent or cannot be parsed.
     pub fn from_env() -> Result<Self> {
-        let cfg = config::Config::builder()
-            .add_source(config::Environment::default())
-            .build()
-            .context("failed to build configuration from environment")?;
+        let mut cfg = Self::builder(config::Environment::default())?;
+        // Read the flag before deserialising, so `${VAR}` in a numeric field
+        // does not fail the first pass.
+        let expand = match cfg.get_bool("expand_env_vars") {
+            Ok(expand) => expand,
+            Err(config::ConfigError::NotFound(_)) => false,
+            Err(e) => return Err(e).context("invalid EXPAND_ENV_VARS"),
+        };
+        if expand {
+            let vars: HashMap<String, String> = std::env::vars().collect();
+            let env = config::Environment::default().source(Some(expand_config_vars(&vars)?));
+            cfg = Self::builder(env)?;
+        }
 
         let c: Config = cfg
             .try_deserialize()
@@ -103,9 +533,97 @@ impl Config {
         Ok(c)
     }
 
+    fn builder(env: config::Environment) -> Result<config::Config> {
+        config::Config::builder()
+            .add_source(env)
+            .build()
+            .context("failed to build configuration from environment")
+    }
+
+    /// The configured AWS endpoint overrides.
+    pub fn endpoint_overrides(&self) -> EndpointOverrides {
+        EndpointOverrides {
+            kms: self.kms_endpoint_url.clone(),
+            secretsmanager: self.secretsmanager_endpoint_url.clone(),
+            s3: self.s3_endpoint_url.clone(),
+        }
+    }
+
+    /// The configured vsock stream buffering.
+    pub fn vsock_buffers(&self) -> VsockBuffers {
+        VsockBuffers {
+            read: self.vsock_read_buffer_bytes,
+            write: self.vsock_write_buffer_bytes,
+        }
+    }
+
+    /// ARNs of every DEK share: `secret_arn` followed by any
+    /// `secret_share_arns`, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an ARN is listed twice, since equal shares cancel
+    /// out under XOR.
+    pub fn secret_arns(&self) -> Result<Vec<&str>> {
+        let mut arns = vec![self.secret_arn.trim()];
+        let shares = self.secret_share_arns.as_deref().unwrap_or_default();
+        for arn in shares.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            if arns.contains(&arn) {
+                anyhow::bail!("DEK share secret {arn} is listed more than once");
+            }
+            arns.push(arn);
+        }
+        Ok(arns)
+    }
+
+    /// Names of the schemas resolved at load, or `None` when every schema is.
+    pub fn eager_schema_names(&self) -> Option<HashSet<String>> {
+        let names = self.eager_schemas.as_deref()?;
+        Some(
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    /// All schema sources: the primary `s3_bucket`/`s3_prefix` followed by any
+    /// `s3_extra_sources`, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an extra source entry has an empty bucket name.
+    pub fn schema_sources(&self) -> Result<Vec<SchemaSource>> {
+        let mut sources = vec![SchemaSource {
+            bucket: self.s3_bucket.clone(),
+            prefix: self.s3_prefix.clone(),
+        }];
+        let extra = self.s3_extra_sources.as_deref().unwrap_or_default();
+        for entry in extra.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (bucket, prefix) = entry.split_once('/').unwrap_or((entry, ""));
+            if bucket.is_empty() {
+                anyhow::bail!("S3_EXTRA_SOURCES entry {entry:?} has an empty bucket name");
+            }
+            sources.push(SchemaSource {
+                bucket: bucket.to_owned(),
+                prefix: prefix.to_owned(),
+            });
+        }
+        Ok(sources)
+    }
+
     /// Validate all fields, returning a descriptive error on the first failure.
     fn validate(&self) -> Result<()> {
         ensure_non_empty(&self.secret_arn, "SECRET_ARN")?;
+        let arns = self.secret_arns()?;
+        if let Some(arn) = &self.token_key_secret_arn {
+            ensure_non_empty(arn, "TOKEN_KEY_SECRET_ARN")?;
+            if arns.contains(&arn.trim()) {
+                anyhow::bail!("TOKEN_KEY_SECRET_ARN must not be a DEK secret");
+            }
+        }
         ensure_non_empty(&self.kms_key_id, "KMS_KEY_ID")?;
         ensure_non_empty(&self.s3_bucket, "S3_BUCKET")?;
         ensure_non_empty(
@@ -114,20 +632,192 @@ impl Config {
         )?;
         ensure_non_empty(&self.tls_cert_path, "TLS_CERT_PATH")?;
         ensure_non_empty(&self.tls_key_path, "TLS_KEY_PATH")?;
+        if self.read_only_port == Some(self.tls_port) {
+            anyhow::bail!("READ_ONLY_PORT must differ from TLS_PORT");
+        }
+        let read_only_tls = self.read_only_tls_cert_path.is_some()
+            || self.read_only_tls_key_path.is_some()
+            || self.read_only_tls_client_ca_path.is_some();
+        if read_only_tls && self.read_only_port.is_none() {
+            anyhow::bail!("READ_ONLY_TLS_* settings require READ_ONLY_PORT");
+        }
+        if self.read_only_tls_cert_path.is_some() != self.read_only_tls_key_path.is_some() {
+            anyhow::bail!(
+                "READ_ONLY_TLS_CERT_PATH and READ_ONLY_TLS_KEY_PATH must be set together"
+            );
+        }
 
         if self.vsock_proxy_cid == 0 {
             anyhow::bail!("VSOCK_PROXY_CID must be a non-zero vsock CID");
         }
+        for (url, name) in [
+            (&self.kms_endpoint_url, "KMS_ENDPOINT_URL"),
+            (
+                &self.secretsmanager_endpoint_url,
+                "SECRETSMANAGER_ENDPOINT_URL",
+            ),
+            (&self.s3_endpoint_url, "S3_ENDPOINT_URL"),
+        ] {
+            if let Some(url) = url {
+                ensure_endpoint_url(url, name)?;
+            }
+        }
         if self.dek_rotation_interval_secs == 0 {
             anyhow::bail!("DEK_ROTATION_INTERVAL_SECS must be > 0");
         }
         if self.schema_refresh_interval_secs == 0 {
             anyhow::bail!("SCHEMA_REFRESH_INTERVAL_SECS must be > 0");
         }
+        if self.startup_dek_timeout_secs == 0 {
+            anyhow::bail!("STARTUP_DEK_TIMEOUT_SECS must be > 0");
+        }
+        if self.startup_schema_timeout_secs == 0 {
+            anyhow::bail!("STARTUP_SCHEMA_TIMEOUT_SECS must be > 0");
+        }
+        if self.kms_breaker_failure_threshold == 0 {
+            anyhow::bail!("KMS_BREAKER_FAILURE_THRESHOLD must be > 0");
+        }
+        if self.kms_breaker_backoff_multiplier == 0 {
+            anyhow::bail!("KMS_BREAKER_BACKOFF_MULTIPLIER must be > 0");
+        }
+        if self.retry_after_secs == 0 {
+            anyhow::bail!("RETRY_AFTER_SECS must be > 0");
+        }
+        if !matches!(self.unknown_schema_status, 400 | 404) {
+            anyhow::bail!("UNKNOWN_SCHEMA_STATUS must be 400 or 404");
+        }
+        if self.max_schema_staleness_secs != 0
+            && self.max_schema_staleness_secs <= self.schema_refresh_interval_secs
+        {
+            anyhow::bail!(
+                "MAX_SCHEMA_STALENESS_SECS must be 0 or greater than SCHEMA_REFRESH_INTERVAL_SECS"
+            );
+        }
+        if self.max_field_bytes == 0 {
+            anyhow::bail!("MAX_FIELD_BYTES must be > 0");
+        }
+        if self.redaction_marker.is_empty() {
+            anyhow::bail!("REDACTION_MARKER must not be empty");
+        }
+        self.schema_sources()?;
+        if let Some(spec) = &self.client_schema_allowlist {
+            if self.tls_client_ca_path.is_none() {
+                anyhow::bail!("CLIENT_SCHEMA_ALLOWLIST requires TLS_CLIENT_CA_PATH (mTLS)");
+            }
+            SchemaAllowlist::parse(spec).context("CLIENT_SCHEMA_ALLOWLIST is invalid")?;
+        }
+        if self.admin_client_cns.is_some()
+            && self.tls_client_ca_path.is_none()
+            && self.read_only_tls_client_ca_path.is_none()
+        {
+            anyhow::bail!(
+                "ADMIN_CLIENT_CNS requires TLS_CLIENT_CA_PATH or READ_ONLY_TLS_CLIENT_CA_PATH (mTLS)"
+            );
+        }
+        if let Some(spec) = &self.mask_rules {
+            MaskPolicy::parse(spec).context("MASK_RULES is invalid")?;
+        }
+        crate::server::middleware::redacted_headers(self.redacted_headers.as_deref())?;
         Ok(())
     }
 }
 
+fn ensure_endpoint_url(value: &str, name: &str) -> Result<()> {
+    let uri: axum::http::Uri = value
+        .parse()
+        .with_context(|| format!("{name} is not a valid URL"))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+        anyhow::bail!("{name} must be an http:// or https:// URL with a host");
+    }
+    Ok(())
+}
+
+/// `vars` with `${VAR}` references expanded in the values of the variables
+/// [`Config`] reads. Other variables are passed through untouched, so unrelated
+/// environment entries never fail startup.
+fn expand_config_vars(vars: &HashMap<String, String>) -> Result<HashMap<String, String>> {
+    let fields = config_fields();
+    vars.iter()
+        .map(|(key, value)| {
+            if !fields.contains(&key.to_lowercase().as_str()) {
+                return Ok((key.clone(), value.clone()));
+            }
+            let expanded = expand_vars(value, |name| vars.get(name).cloned())
+                .with_context(|| format!("failed to expand {key}"))?;
+            Ok((key.clone(), expanded))
+        })
+        .collect()
+}
+
+/// Replace each `${NAME}` in `value` with `lookup(NAME)`; `$$` is a literal
+/// `$`, and any other `$` is kept as is. Substituted text is not expanded again.
+fn expand_vars(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            out.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .with_context(|| format!("unterminated ${{ in {value:?}"))?;
+            let name = &after[..end];
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                anyhow::bail!("invalid variable name {name:?} in ${{...}}");
+            }
+            let resolved =
+                lookup(name).with_context(|| format!("undefined variable ${{{name}}}"))?;
+            out.push_str(&resolved);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The (lower-case) names of the fields [`Config`] deserialises, which are the
+/// environment variables it reads.
+fn config_fields() -> &'static [&'static str] {
+    /// A deserializer that only records the field list of the struct asked for.
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("only struct field names are recorded"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only struct field names are recorded"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = Config::deserialize(FieldNames(&mut fields));
+    fields
+}
+
 fn ensure_non_empty(value: &str, name: &str) -> Result<()> {
     if value.trim().is_empty() {
         anyhow::bail!("{name} is required and must not be empty");
@@ -136,9 +826,88 @@ fn ensure_non_empty(value: &str, name: &str) -> Result<()> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    pub(crate) fn valid_config() -> Config {
+        Config {
+            secret_arn: "arn".into(),
+            secret_share_arns: None,
+            token_key_secret_arn: None,
+            kms_key_id: "key".into(),
+            kms_recipient_attestation: false,
+            s3_bucket: "bucket".into(),
+            s3_prefix: default_s3_prefix(),
+            s3_extra_sources: None,
+            schema_load_lenient: false,
+            required_pii_paths: None,
+            required_pii_lenient: false,
+            schema_quarantine_threshold: default_schema_quarantine_threshold(),
+            max_cached_schemas: 0,
+            retain_schema_documents: default_retain_schema_documents(),
+            eager_schemas: None,
+            expand_env_vars: false,
+            schema_header_name: default_schema_header(),
+            dek_rotation_interval_secs: default_dek_rotation_interval(),
+            schema_refresh_interval_secs: default_schema_refresh_interval(),
+            schema_tombstone_grace_secs: 0,
+            vsock_proxy_cid: 3,
+            vsock_proxy_port: default_vsock_proxy_port(),
+            vsock_read_buffer_bytes: 0,
+            vsock_write_buffer_bytes: 0,
+            kms_endpoint_url: None,
+            secretsmanager_endpoint_url: None,
+            s3_endpoint_url: None,
+            tls_port: default_tls_port(),
+            read_only_port: None,
+            tls_cert_path: "/run/acm/tls.crt".into(),
+            tls_key_path: "/run/acm/tls.key".into(),
+            tls_client_ca_path: None,
+            read_only_tls_cert_path: None,
+            read_only_tls_key_path: None,
+            read_only_tls_client_ca_path: None,
+            tls_key_passphrase: None,
+            tls_session_cache_size: default_tls_session_cache_size(),
+            tls_session_tickets: false,
+            client_schema_allowlist: None,
+            admin_client_cns: None,
+            mask_rules: None,
+            redacted_headers: None,
+            otel_exporter_otlp_endpoint: "vsock://3:4317".into(),
+            log_level: default_log_level(),
+            otel_logs_enabled: false,
+            startup_dek_timeout_secs: default_startup_dek_timeout(),
+            startup_schema_timeout_secs: default_startup_schema_timeout(),
+            min_schemas_for_ready: default_min_schemas_for_ready(),
+            require_dek_for_ready: default_require_dek_for_ready(),
+            kms_breaker_failure_threshold: default_kms_breaker_failure_threshold(),
+            kms_breaker_backoff_multiplier: default_kms_breaker_backoff_multiplier(),
+            retry_after_secs: default_retry_after_secs(),
+            encrypt_ready_wait_ms: 0,
+            unknown_schema_status: default_unknown_schema_status(),
+            require_tenant: false,
+            enforce_payload_root: default_enforce_payload_root(),
+            reject_unknown_top_level_keys: false,
+            audit_nonce_reuse: false,
+            max_field_bytes: default_max_field_bytes(),
+            compression_max_response_bytes: default_compression_max_response_bytes(),
+            verify_body_checksums: false,
+            redaction_marker: default_redaction_marker(),
+            max_encrypted_fields: 0,
+            max_array_items: default_max_array_items(),
+            allow_inline_schema: false,
+            allow_value_endpoint: false,
+            max_schema_staleness_secs: 0,
+            schema_tag_ciphertext: false,
+            ciphertext_encoding: CiphertextEncoding::CompactString,
+            min_encrypt_len: 0,
+            strict_pii_leaf_types: false,
+            noop_schema_name: None,
+            proxy_probe_interval_secs: 0,
+            audit_queue_capacity: 0,
+        }
+    }
+
     #[test]
     fn defaults_are_correct() {
         assert_eq!(default_s3_prefix(), "schemas/");
@@ -148,25 +917,110 @@ mod tests {
         assert_eq!(default_vsock_proxy_port(), 8000);
         assert_eq!(default_tls_port(), 443);
         assert_eq!(default_log_level(), "info");
+        assert_eq!(default_startup_dek_timeout(), 30);
+        assert_eq!(default_startup_schema_timeout(), 60);
+        assert_eq!(default_min_schemas_for_ready(), 1);
+        assert!(default_require_dek_for_ready());
+        assert_eq!(default_kms_breaker_failure_threshold(), 3);
+        assert_eq!(default_kms_breaker_backoff_multiplier(), 4);
+        assert_eq!(default_retry_after_secs(), 5);
+        assert_eq!(default_unknown_schema_status(), 400);
+        assert!(default_enforce_payload_root());
+        assert_eq!(default_max_field_bytes(), 65536);
+        assert_eq!(default_max_array_items(), 100_000);
+        assert_eq!(default_compression_max_response_bytes(), 8_388_608);
+        assert_eq!(default_redaction_marker(), "[REDACTED]");
+        assert!(default_retain_schema_documents());
+        assert_eq!(default_tls_session_cache_size(), 256);
+        assert_eq!(default_schema_quarantine_threshold(), 3);
+    }
+
+    #[test]
+    fn validate_accepts_valid_config() {
+        assert!(valid_config().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_read_only_port_equal_to_tls_port() {
+        let cfg = Config {
+            read_only_port: Some(default_tls_port()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        let cfg = Config {
+            read_only_port: Some(8444),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_read_only_tls_settings() {
+        let cfg = Config {
+            read_only_tls_client_ca_path: Some("/run/acm/admin-ca.pem".into()),
+            ..valid_config()
+        };
+        assert!(
+            cfg.validate().is_err(),
+            "read-only TLS without READ_ONLY_PORT"
+        );
+        let cfg = Config {
+            read_only_port: Some(8444),
+            read_only_tls_cert_path: Some("/run/acm/admin.crt".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err(), "cert without key");
+        // mTLS on the read-only port alone is enough for the admin role.
+        let cfg = Config {
+            read_only_port: Some(8444),
+            read_only_tls_cert_path: Some("/run/acm/admin.crt".into()),
+            read_only_tls_key_path: Some("/run/acm/admin.key".into()),
+            read_only_tls_client_ca_path: Some("/run/acm/admin-ca.pem".into()),
+            admin_client_cns: Some("support".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn secret_arns_lists_the_primary_then_the_shares() {
+        assert_eq!(valid_config().secret_arns().unwrap(), ["arn"]);
+        let cfg = Config {
+            secret_share_arns: Some(" arn-b, ,arn-c ".into()),
+            ..valid_config()
+        };
+        assert_eq!(cfg.secret_arns().unwrap(), ["arn", "arn-b", "arn-c"]);
+        assert!(cfg.validate().is_ok());
+
+        let cfg = Config {
+            secret_share_arns: Some("arn-b,arn".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn token_key_secret_must_differ_from_the_dek_secrets() {
+        let cfg = Config {
+            token_key_secret_arn: Some("token-arn".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+        for arn in ["arn", "arn-b", " "] {
+            let cfg = Config {
+                secret_share_arns: Some("arn-b".into()),
+                token_key_secret_arn: Some(arn.into()),
+                ..valid_config()
+            };
+            assert!(cfg.validate().is_err(), "{arn:?}");
+        }
     }
 
     #[test]
     fn validate_rejects_empty_secret_arn() {
         let cfg = Config {
             secret_arn: "".into(),
-            kms_key_id: "key".into(),
-            s3_bucket: "bucket".into(),
-            s3_prefix: default_s3_prefix(),
-            schema_header_name: default_schema_header(),
-            dek_rotation_interval_secs: default_dek_rotation_interval(),
-            schema_refresh_interval_secs: default_schema_refresh_interval(),
-            vsock_proxy_cid: 3,
-            vsock_proxy_port: default_vsock_proxy_port(),
-            tls_port: default_tls_port(),
-            tls_cert_path: "/run/acm/tls.crt".into(),
-            tls_key_path: "/run/acm/tls.key".into(),
-            otel_exporter_otlp_endpoint: "vsock://3:4317".into(),
-            log_level: default_log_level(),
+            ..valid_config()
         };
         assert!(cfg.validate().is_err());
     }
@@ -174,20 +1028,236 @@ mod tests {
     #[test]
     fn validate_rejects_zero_cid() {
         let cfg = Config {
-            secret_arn: "arn".into(),
-            kms_key_id: "key".into(),
-            s3_bucket: "bucket".into(),
-            s3_prefix: default_s3_prefix(),
-            schema_header_name: default_schema_header(),
-            dek_rotation_interval_secs: default_dek_rotation_interval(),
-            schema_refresh_interval_secs: default_schema_refresh_interval(),
             vsock_proxy_cid: 0,
-            vsock_proxy_port: default_vsock_proxy_port(),
-            tls_port: default_tls_port(),
-            tls_cert_path: "/run/acm/tls.crt".into(),
-            tls_key_path: "/run/acm/tls.key".into(),
-            otel_exporter_otlp_endpoint: "vsock://3:4317".into(),
-            log_level: default_log_level(),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_zero_startup_timeouts() {
+        let cfg = Config {
+            startup_dek_timeout_secs: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        let cfg = Config {
+            startup_schema_timeout_secs: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_zero_breaker_settings() {
+        let cfg = Config {
+            kms_breaker_failure_threshold: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        let cfg = Config {
+            kms_breaker_backoff_multiplier: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_checks_endpoint_overrides() {
+        let cfg = Config {
+            kms_endpoint_url: Some("https://localstack:4566".into()),
+            s3_endpoint_url: Some("http://localstack".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+        assert_eq!(
+            cfg.endpoint_overrides().s3.as_deref(),
+            Some("http://localstack")
+        );
+        for bad in ["localstack:4566", "ftp://localstack", "not a url"] {
+            let cfg = Config {
+                secretsmanager_endpoint_url: Some(bad.into()),
+                ..valid_config()
+            };
+            assert!(cfg.validate().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_zero_retry_after() {
+        let cfg = Config {
+            retry_after_secs: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn expand_vars_substitutes_defined_and_rejects_undefined_references() {
+        let env = HashMap::from([("AWS_REGION".to_string(), "eu-west-1".to_string())]);
+        let lookup = |name: &str| env.get(name).cloned();
+
+        assert_eq!(
+            expand_vars("https://kms.${AWS_REGION}.staging.internal", lookup).unwrap(),
+            "https://kms.eu-west-1.staging.internal"
+        );
+        assert_eq!(expand_vars("no refs", lookup).unwrap(), "no refs");
+        assert_eq!(
+            expand_vars("cost: $5, $$HOME", lookup).unwrap(),
+            "cost: $5, $HOME"
+        );
+
+        let err = expand_vars("https://kms.${AWS_REGION}.${STAGE}.internal", lookup).unwrap_err();
+        assert!(
+            err.to_string().contains("undefined variable ${STAGE}"),
+            "{err}"
+        );
+        assert!(expand_vars("${AWS_REGION", lookup).is_err());
+        assert!(expand_vars("${1BAD}", lookup).is_err());
+    }
+
+    #[test]
+    fn expansion_applies_only_to_config_variables() {
+        assert!(config_fields().contains(&"kms_endpoint_url"));
+        assert!(config_fields().contains(&"secret_arn"));
+
+        let vars = HashMap::from([
+            ("AWS_REGION".to_string(), "eu-west-1".to_string()),
+            (
+                "KMS_ENDPOINT_URL".to_string(),
+                "https://kms.${AWS_REGION}.internal".to_string(),
+            ),
+            // Not a config variable: left alone even with an unset reference.
+            ("PS1".to_string(), "${UNSET_PROMPT}".to_string()),
+        ]);
+        let expanded = expand_config_vars(&vars).unwrap();
+        assert_eq!(
+            expanded["KMS_ENDPOINT_URL"],
+            "https://kms.eu-west-1.internal"
+        );
+        assert_eq!(expanded["PS1"], "${UNSET_PROMPT}");
+
+        let mut vars = vars;
+        vars.insert("S3_PREFIX".into(), "schemas/${STAGE}/".into());
+        let err = expand_config_vars(&vars).unwrap_err();
+        assert!(format!("{err:#}").contains("S3_PREFIX"), "{err:#}");
+        assert!(format!("{err:#}").contains("${STAGE}"), "{err:#}");
+    }
+
+    #[test]
+    fn validate_rejects_empty_redaction_marker() {
+        let cfg = Config {
+            redaction_marker: String::new(),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unsupported_unknown_schema_status() {
+        for status in [400, 404] {
+            let cfg = Config {
+                unknown_schema_status: status,
+                ..valid_config()
+            };
+            assert!(cfg.validate().is_ok(), "{status}");
+        }
+        let cfg = Config {
+            unknown_schema_status: 503,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_max_schema_staleness_exceeds_refresh_interval() {
+        let cfg = Config {
+            max_schema_staleness_secs: default_schema_refresh_interval(),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        let cfg = Config {
+            max_schema_staleness_secs: default_schema_refresh_interval() * 3,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_zero_max_field_bytes() {
+        let cfg = Config {
+            max_field_bytes: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_schema_allowlist() {
+        let cfg = Config {
+            client_schema_allowlist: Some("payments=payments-".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err(), "allowlist without mTLS");
+        let cfg = Config {
+            tls_client_ca_path: Some("/run/acm/client-ca.pem".into()),
+            client_schema_allowlist: Some("payments".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err(), "malformed allowlist");
+        let cfg = Config {
+            tls_client_ca_path: Some("/run/acm/client-ca.pem".into()),
+            client_schema_allowlist: Some("payments=payments-".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_admin_and_mask_settings() {
+        let cfg = Config {
+            admin_client_cns: Some("support".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err(), "admin CNs without mTLS");
+        let cfg = Config {
+            mask_rules: Some("FINANCIAL=middle:2".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err(), "malformed mask rules");
+        let cfg = Config {
+            tls_client_ca_path: Some("/run/acm/client-ca.pem".into()),
+            admin_client_cns: Some("support".into()),
+            mask_rules: Some("FINANCIAL=last:4;default=full".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn schema_sources_include_primary_and_extras() {
+        let cfg = Config {
+            s3_extra_sources: Some("team-a/schemas/, team-b ,team-c/pii/".into()),
+            ..valid_config()
+        };
+        let sources = cfg.schema_sources().unwrap();
+        let pairs: Vec<(&str, &str)> = sources
+            .iter()
+            .map(|s| (s.bucket.as_str(), s.prefix.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("bucket", "schemas/"),
+                ("team-a", "schemas/"),
+                ("team-b", ""),
+                ("team-c", "pii/"),
+            ]
+        );
+
+        let cfg = Config {
+            s3_extra_sources: Some("/schemas/".into()),
+            ..valid_config()
         };
         assert!(cfg.validate().is_err());
     }
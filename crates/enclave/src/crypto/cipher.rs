@@ -12,30 +12,106 @@
 //!
 //! **Do NOT substitute plain AES-256-GCM with a fixed nonce.** GCM nonce reuse
 //! is catastrophic — it breaks both confidentiality and authentication.
+//!
+//! **ChaCha20-Poly1305:** schemas may opt into [`Algorithm::ChaCha20Poly1305`]
+//! with `x-encryption-alg`. Its ciphertext carries the `c1` prefix instead of
+//! `v1`, so decryption always uses the algorithm that produced a value. The
+//! nonce is derived the same way, which keeps encryption deterministic; unlike
+//! AES-GCM-SIV, ChaCha20-Poly1305 is not nonce-misuse-resistant, so two
+//! different plaintexts whose derived nonces collide would leak their XOR.
+//! With a 96-bit HMAC-derived nonce that needs around 2^48 distinct values
+//! under one DEK.
+
+use std::borrow::Cow;
 
 use aes_gcm_siv::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256GcmSiv, Nonce,
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chacha20poly1305::ChaCha20Poly1305;
 use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
 use sha2::Sha256;
 use thiserror::Error;
 
+/// Name of the default field encryption algorithm, for logs and manifests.
+pub const ALGORITHM: &str = "AES-256-GCM-SIV";
+
 /// Byte length of an AES-256 key (32 bytes = 256 bits).
 pub const KEY_LEN: usize = 32;
 
 /// Byte length of an AES-GCM-SIV nonce (12 bytes = 96 bits).
 pub const NONCE_LEN: usize = 12;
 
-/// Prefix that appears at the start of every encrypted field value.
+/// Prefix that appears at the start of every AES-256-GCM-SIV field value.
 pub const VERSION_PREFIX: &str = "v1";
 
+/// Prefix that appears at the start of every ChaCha20-Poly1305 field value.
+pub const CHACHA_PREFIX: &str = "c1";
+
+/// Maximum length of the schema tag segment.
+pub const MAX_SCHEMA_TAG_LEN: usize = 64;
+
+/// AEAD used to encrypt a field. Both take a [`KEY_LEN`]-byte key and a
+/// [`NONCE_LEN`]-byte nonce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// AES-256-GCM-SIV (RFC 8452), the service default.
+    #[default]
+    Aes256GcmSiv,
+    /// ChaCha20-Poly1305 (RFC 8439).
+    ChaCha20Poly1305,
+}
+
+impl Algorithm {
+    /// Every supported algorithm.
+    pub const ALL: [Algorithm; 2] = [Algorithm::Aes256GcmSiv, Algorithm::ChaCha20Poly1305];
+
+    /// Name used in the `alg` member of the object representation.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Aes256GcmSiv => ALGORITHM,
+            Algorithm::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+        }
+    }
+
+    /// Prefix of this algorithm's string representation.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Algorithm::Aes256GcmSiv => VERSION_PREFIX,
+            Algorithm::ChaCha20Poly1305 => CHACHA_PREFIX,
+        }
+    }
+
+    /// The algorithm whose string representation starts with `prefix`.
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|alg| alg.prefix() == prefix)
+    }
+}
+
+/// How encrypted fields are written into payloads: the string from
+/// [`EncryptedField::to_string_repr`] or the object from
+/// [`EncryptedField::to_json_object`].
+pub use common::protocol::CiphertextEncoding;
+
 /// A parsed, encrypted field value.
 ///
-/// The string representation is `v1.<base64url(nonce)>.<base64url(ciphertext+tag)>`.
+/// The string representation is `v1.<base64url(nonce)>.<base64url(ciphertext+tag)>`,
+/// or `v1.<schema_tag>.<base64url(nonce)>.<base64url(ciphertext+tag)>` when the
+/// value records the schema that produced it; ChaCha20-Poly1305 values use
+/// `c1` in place of `v1`. The object representation
+/// carries the same parts as `{"alg", "nonce", "ct"}` members, plus
+/// `"schema_tag"` when recorded.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EncryptedField {
+    /// Algorithm that produced the ciphertext.
+    pub algorithm: Algorithm,
+    /// Short fingerprint of the schema that produced this value, if recorded.
+    ///
+    /// The tag is authenticated with the ciphertext (see [`bound_aad`]), so a
+    /// value whose tag was changed, added or stripped fails to decrypt.
+    pub schema_tag: Option<String>,
     /// Raw nonce bytes.
     pub nonce: [u8; NONCE_LEN],
     /// Raw ciphertext + authentication tag bytes.
@@ -45,27 +121,107 @@ pub struct EncryptedField {
 impl EncryptedField {
     /// Encode this value to its canonical string representation.
     pub fn to_string_repr(&self) -> String {
-        format!(
-            "{}.{}.{}",
-            VERSION_PREFIX,
-            URL_SAFE_NO_PAD.encode(self.nonce),
-            URL_SAFE_NO_PAD.encode(&self.ciphertext),
-        )
+        let prefix = self.algorithm.prefix();
+        let nonce = URL_SAFE_NO_PAD.encode(self.nonce);
+        let ciphertext = URL_SAFE_NO_PAD.encode(&self.ciphertext);
+        match &self.schema_tag {
+            Some(tag) => format!("{prefix}.{tag}.{nonce}.{ciphertext}"),
+            None => format!("{prefix}.{nonce}.{ciphertext}"),
+        }
     }
 
     /// Parse an encrypted field string back into an [`EncryptedField`].
     ///
+    /// Both the untagged and the schema-tagged forms are accepted; base64url
+    /// never contains `.`, so the segment count tells them apart.
+    ///
     /// # Errors
     ///
     /// Returns [`CipherError::InvalidFormat`] if the string does not match the
-    /// expected `v1.<nonce>.<ciphertext>` structure.
+    /// expected `v1.[<schema_tag>.]<nonce>.<ciphertext>` structure (or its
+    /// `c1` counterpart).
     pub fn from_str(s: &str) -> Result<Self, CipherError> {
-        let parts: Vec<&str> = s.splitn(3, '.').collect();
-        if parts.len() != 3 || parts[0] != VERSION_PREFIX {
+        // At most one segment more than the longest valid form, so a hostile
+        // string of dots cannot allocate a segment list larger than itself.
+        let mut parts: Vec<&str> = s.splitn(5, '.').collect();
+        let algorithm = parts
+            .first()
+            .and_then(|prefix| Algorithm::from_prefix(prefix))
+            .ok_or(CipherError::InvalidFormat)?;
+        let schema_tag = match parts.len() {
+            3 => None,
+            4 if valid_schema_tag(parts[1]) => Some(parts.remove(1).to_owned()),
+            _ => return Err(CipherError::InvalidFormat),
+        };
+        Self::decode(algorithm, schema_tag, parts[1], parts[2])
+    }
+
+    /// Whether `s` starts with the prefix of an encrypted field string.
+    pub fn has_prefix(s: &str) -> bool {
+        s.split_once('.')
+            .is_some_and(|(prefix, _)| Algorithm::from_prefix(prefix).is_some())
+    }
+
+    /// Encode this value as a JSON object:
+    /// `{"alg": "AES-256-GCM-SIV", "nonce": <base64url>, "ct": <base64url>}`,
+    /// with a `"schema_tag"` member when one is recorded.
+    pub fn to_json_object(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("alg".into(), self.algorithm.name().into());
+        if let Some(tag) = &self.schema_tag {
+            object.insert("schema_tag".into(), tag.as_str().into());
+        }
+        object.insert("nonce".into(), URL_SAFE_NO_PAD.encode(self.nonce).into());
+        object.insert("ct".into(), URL_SAFE_NO_PAD.encode(&self.ciphertext).into());
+        Value::Object(object)
+    }
+
+    /// Parse an encrypted field in either representation: a `v1.` string or
+    /// the object from [`to_json_object`](Self::to_json_object).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CipherError::InvalidFormat`] for any other value, including
+    /// objects with members other than the expected ones or an unknown `alg`.
+    pub fn from_json(value: &Value) -> Result<Self, CipherError> {
+        let object = match value {
+            Value::String(s) => return Self::from_str(s),
+            Value::Object(object) => object,
+            _ => return Err(CipherError::InvalidFormat),
+        };
+        let member = |name: &str| object.get(name).and_then(Value::as_str);
+        let schema_tag = match object.get("schema_tag") {
+            None => None,
+            Some(Value::String(tag)) if valid_schema_tag(tag) => Some(tag.clone()),
+            Some(_) => return Err(CipherError::InvalidFormat),
+        };
+        let expected_len = 3 + usize::from(schema_tag.is_some());
+        let algorithm = Algorithm::ALL
+            .into_iter()
+            .find(|alg| member("alg") == Some(alg.name()));
+        let Some(algorithm) = algorithm.filter(|_| object.len() == expected_len) else {
             return Err(CipherError::InvalidFormat);
+        };
+        match (member("nonce"), member("ct")) {
+            (Some(nonce), Some(ct)) => Self::decode(algorithm, schema_tag, nonce, ct),
+            _ => Err(CipherError::InvalidFormat),
         }
+    }
+
+    /// Whether `value` is an encrypted field in the object representation.
+    pub fn is_json_object(value: &Value) -> bool {
+        value.is_object() && Self::from_json(value).is_ok()
+    }
+
+    /// Build a field from its base64url-encoded nonce and ciphertext.
+    fn decode(
+        algorithm: Algorithm,
+        schema_tag: Option<String>,
+        nonce: &str,
+        ct: &str,
+    ) -> Result<Self, CipherError> {
         let nonce_bytes = URL_SAFE_NO_PAD
-            .decode(parts[1])
+            .decode(nonce)
             .map_err(|_| CipherError::InvalidFormat)?;
         if nonce_bytes.len() != NONCE_LEN {
             return Err(CipherError::InvalidFormat);
@@ -74,13 +230,25 @@ impl EncryptedField {
         nonce.copy_from_slice(&nonce_bytes);
 
         let ciphertext = URL_SAFE_NO_PAD
-            .decode(parts[2])
+            .decode(ct)
             .map_err(|_| CipherError::InvalidFormat)?;
 
-        Ok(Self { nonce, ciphertext })
+        Ok(Self {
+            algorithm,
+            schema_tag,
+            nonce,
+            ciphertext,
+        })
     }
 }
 
+/// Whether `tag` can be embedded as a ciphertext segment.
+fn valid_schema_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= MAX_SCHEMA_TAG_LEN
+        && tag.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
 /// Errors produced by the cipher layer.
 #[derive(Debug, Error)]
 pub enum CipherError {
@@ -88,13 +256,28 @@ pub enum CipherError {
     #[error("invalid DEK length: expected {KEY_LEN} bytes")]
     InvalidKeyLength,
 
-    /// AES-GCM-SIV encryption or decryption failed.
+    /// AEAD encryption or decryption failed.
     #[error("aead operation failed")]
     AeadFailure,
 
     /// The encrypted field string does not match the expected format.
     #[error("invalid encrypted field format")]
     InvalidFormat,
+
+    /// A value has too few characters of one class for format-preserving
+    /// encryption (see [`tokenize`](super::tokenize)).
+    #[error("value too short for format-preserving encryption")]
+    TooShortToTokenize,
+
+    /// A format-preserving token's tag does not match it, so the value cannot
+    /// be proven to be a token.
+    #[error("format-preserving token failed authentication")]
+    UnauthenticatedToken,
+
+    /// Hash, lookup and format-preserving tokens need the token key, and
+    /// none is configured.
+    #[error("no token key is configured for hash, lookup or format-preserving tokens")]
+    TokenKeyUnavailable,
 }
 
 /// Derive a deterministic 12-byte nonce from a DEK and plaintext.
@@ -106,9 +289,17 @@ pub enum CipherError {
 ///
 /// AES-GCM-SIV is nonce-misuse-resistant (RFC 8452 §3): reusing the same nonce
 /// for the same plaintext is safe and is the intended use case here.
-fn derive_nonce(dek: &[u8], plaintext: &[u8]) -> [u8; NONCE_LEN] {
+///
+/// A non-empty `aad` is mixed in (length-prefixed) so that the same plaintext
+/// bound to different contexts does not produce linkable ciphertexts. With an
+/// empty `aad` the derivation is unchanged from the original format.
+fn derive_nonce(dek: &[u8], aad: &[u8], plaintext: &[u8]) -> [u8; NONCE_LEN] {
     let mut mac =
         <Hmac<Sha256> as Mac>::new_from_slice(dek).expect("HMAC accepts keys of any length");
+    if !aad.is_empty() {
+        mac.update(&(aad.len() as u64).to_be_bytes());
+        mac.update(aad);
+    }
     mac.update(plaintext);
     let result = mac.finalize().into_bytes();
     let mut nonce = [0u8; NONCE_LEN];
@@ -116,44 +307,146 @@ fn derive_nonce(dek: &[u8], plaintext: &[u8]) -> [u8; NONCE_LEN] {
     nonce
 }
 
-/// Encrypt a plaintext string field using AES-256-GCM-SIV.
+/// [`encrypt_field_with_aad`] with no associated data.
+#[cfg(test)]
+pub(crate) fn encrypt_field(plaintext: &[u8], dek: &[u8]) -> Result<EncryptedField, CipherError> {
+    encrypt_field_with_aad(plaintext, dek, &[])
+}
+
+/// Encrypt a plaintext string field using AES-256-GCM-SIV, authenticating
+/// `aad` alongside it.
 ///
 /// The nonce is derived deterministically via `HMAC-SHA256(key=DEK, data=plaintext)[0..12]`,
 /// guaranteeing that identical plaintext + DEK always produces identical ciphertext.
-/// This is required for tokenisation and lookup use cases.
+/// This is required for tokenisation and lookup use cases. The ciphertext only
+/// decrypts when the same `aad` is supplied to [`decrypt_field_with_aad`].
 ///
 /// # Errors
 ///
 /// Returns [`CipherError::InvalidKeyLength`] if `dek` is not [`KEY_LEN`] bytes.
 /// Returns [`CipherError::AeadFailure`] on an internal AEAD error (unreachable
 /// with a valid key and well-formed nonce).
-pub fn encrypt_field(plaintext: &[u8], dek: &[u8]) -> Result<EncryptedField, CipherError> {
-    let cipher = build_cipher(dek)?;
-    let nonce_bytes = derive_nonce(dek, plaintext);
-    let nonce = Nonce::from_slice(&nonce_bytes);
+pub fn encrypt_field_with_aad(
+    plaintext: &[u8],
+    dek: &[u8],
+    aad: &[u8],
+) -> Result<EncryptedField, CipherError> {
+    encrypt_field_as(Algorithm::default(), plaintext, dek, aad, None)
+}
 
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext)
-        .map_err(|_| CipherError::AeadFailure)?;
+/// [`encrypt_field_with_aad`] with `algorithm` instead of the default,
+/// recording and authenticating `schema_tag` when given.
+///
+/// # Errors
+///
+/// As for [`encrypt_field_with_aad`]; returns [`CipherError::InvalidFormat`] if
+/// `schema_tag` is empty, longer than [`MAX_SCHEMA_TAG_LEN`], or not ASCII
+/// alphanumeric.
+pub fn encrypt_field_as(
+    algorithm: Algorithm,
+    plaintext: &[u8],
+    dek: &[u8],
+    aad: &[u8],
+    schema_tag: Option<&str>,
+) -> Result<EncryptedField, CipherError> {
+    if schema_tag.is_some_and(|tag| !valid_schema_tag(tag)) {
+        return Err(CipherError::InvalidFormat);
+    }
+    let aad = bound_aad(aad, schema_tag);
+    let nonce_bytes = derive_nonce(dek, &aad, plaintext);
+    let payload = Payload {
+        msg: plaintext,
+        aad: &aad,
+    };
+    let ciphertext = match algorithm {
+        Algorithm::Aes256GcmSiv => {
+            build_cipher(dek)?.encrypt(Nonce::from_slice(&nonce_bytes), payload)
+        }
+        Algorithm::ChaCha20Poly1305 => {
+            build_chacha(dek)?.encrypt(Nonce::from_slice(&nonce_bytes), payload)
+        }
+    }
+    .map_err(|_| CipherError::AeadFailure)?;
 
     Ok(EncryptedField {
+        algorithm,
+        schema_tag: schema_tag.map(str::to_owned),
         nonce: nonce_bytes,
         ciphertext,
     })
 }
 
-/// Decrypt an [`EncryptedField`] back to plaintext bytes.
+/// [`decrypt_field_with_aad`] with no associated data.
+#[cfg(test)]
+pub(crate) fn decrypt_field(field: &EncryptedField, dek: &[u8]) -> Result<Vec<u8>, CipherError> {
+    decrypt_field_with_aad(field, dek, &[])
+}
+
+/// Decrypt an [`EncryptedField`] that was encrypted with `aad`, using the
+/// algorithm recorded in the field.
 ///
 /// # Errors
 ///
 /// Returns [`CipherError::InvalidKeyLength`] if `dek` is not [`KEY_LEN`] bytes.
-/// Returns [`CipherError::AeadFailure`] if authentication fails (wrong key or tampered data).
-pub fn decrypt_field(field: &EncryptedField, dek: &[u8]) -> Result<Vec<u8>, CipherError> {
-    let cipher = build_cipher(dek)?;
+/// Returns [`CipherError::AeadFailure`] if authentication fails (wrong key,
+/// tampered data, or a mismatched `aad` or schema tag).
+pub fn decrypt_field_with_aad(
+    field: &EncryptedField,
+    dek: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CipherError> {
     let nonce = Nonce::from_slice(&field.nonce);
-    cipher
-        .decrypt(nonce, field.ciphertext.as_ref())
-        .map_err(|_| CipherError::AeadFailure)
+    let aad = bound_aad(aad, field.schema_tag.as_deref());
+    let payload = Payload {
+        msg: field.ciphertext.as_ref(),
+        aad: &aad,
+    };
+    match field.algorithm {
+        Algorithm::Aes256GcmSiv => build_cipher(dek)?.decrypt(nonce, payload),
+        Algorithm::ChaCha20Poly1305 => build_chacha(dek)?.decrypt(nonce, payload),
+    }
+    .map_err(|_| CipherError::AeadFailure)
+}
+
+/// Build the associated data binding a field's ciphertext to a tenant.
+///
+/// Without a tenant the AAD is empty, so untenanted ciphertext is unchanged
+/// from the original format. With a tenant it is
+/// `tenant=<id>\0path=<pii path>`, binding the value to both the tenant and
+/// the schema path it was encrypted at.
+pub fn field_aad(tenant: Option<&str>, path: &str) -> Vec<u8> {
+    match tenant {
+        Some(tenant) => format!("tenant={tenant}\0path={path}").into_bytes(),
+        None => Vec::new(),
+    }
+}
+
+/// The associated data authenticated for a value carrying `schema_tag`:
+/// `aad` itself when untagged, otherwise `schema=<tag>\0` followed by `aad`.
+/// No [`field_aad`] or [`value_aad`] starts with `schema=`, so a tag cannot
+/// be stripped or swapped without failing authentication.
+fn bound_aad<'a>(aad: &'a [u8], schema_tag: Option<&str>) -> Cow<'a, [u8]> {
+    match schema_tag {
+        Some(tag) => {
+            let mut bound = format!("schema={tag}\0").into_bytes();
+            bound.extend_from_slice(aad);
+            Cow::Owned(bound)
+        }
+        None => Cow::Borrowed(aad),
+    }
+}
+
+/// Build the associated data for a value encrypted by `/encrypt/value`.
+///
+/// Always starts with `value\0`, followed by `context=<context>` when there
+/// is one. No [`field_aad`] starts that way, so `/decrypt/value` cannot open
+/// a field encrypted by `/encrypt`, and a context cannot forge a tenant
+/// binding.
+pub fn value_aad(context: Option<&str>) -> Vec<u8> {
+    match context {
+        Some(context) => format!("value\0context={context}").into_bytes(),
+        None => b"value\0".to_vec(),
+    }
 }
 
 fn build_cipher(dek: &[u8]) -> Result<Aes256GcmSiv, CipherError> {
@@ -163,6 +456,22 @@ fn build_cipher(dek: &[u8]) -> Result<Aes256GcmSiv, CipherError> {
     Aes256GcmSiv::new_from_slice(dek).map_err(|_| CipherError::InvalidKeyLength)
 }
 
+/// Domain-separation label for deriving the ChaCha20-Poly1305 key from the
+/// DEK, so the two ciphers never run under the same key.
+const CHACHA_SUBKEY_LABEL: &[u8] = b"nitro-enc-svc/chacha20poly1305/v1";
+
+fn build_chacha(dek: &[u8]) -> Result<ChaCha20Poly1305, CipherError> {
+    if dek.len() != KEY_LEN {
+        return Err(CipherError::InvalidKeyLength);
+    }
+    let subkey = <Hmac<Sha256> as Mac>::new_from_slice(dek)
+        .map_err(|_| CipherError::InvalidKeyLength)?
+        .chain_update(CHACHA_SUBKEY_LABEL)
+        .finalize()
+        .into_bytes();
+    ChaCha20Poly1305::new_from_slice(&subkey).map_err(|_| CipherError::InvalidKeyLength)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -241,11 +550,146 @@ mod tests {
         assert_eq!(parsed.ciphertext, field.ciphertext);
     }
 
+    #[test]
+    fn chacha_fields_record_their_algorithm() {
+        let dek = test_dek_a();
+        let aad = field_aad(Some("tenant-a"), "card");
+        let aes = encrypt_field_with_aad(b"4111", &dek, &aad).unwrap();
+        let chacha =
+            encrypt_field_as(Algorithm::ChaCha20Poly1305, b"4111", &dek, &aad, None).unwrap();
+        assert_ne!(chacha.ciphertext, aes.ciphertext);
+
+        // ChaCha20-Poly1305 runs under a subkey, never the DEK itself.
+        let under_dek = ChaCha20Poly1305::new_from_slice(&dek).unwrap().decrypt(
+            Nonce::from_slice(&chacha.nonce),
+            Payload {
+                msg: &chacha.ciphertext,
+                aad: &aad,
+            },
+        );
+        assert!(under_dek.is_err());
+
+        let s = chacha.to_string_repr();
+        assert!(s.starts_with("c1."), "{s}");
+        assert!(EncryptedField::has_prefix(&s));
+        let parsed = EncryptedField::from_str(&s).unwrap();
+        assert_eq!(parsed, chacha);
+        assert_eq!(
+            decrypt_field_with_aad(&parsed, &dek, &aad).unwrap(),
+            b"4111"
+        );
+
+        let object = chacha.to_json_object();
+        assert_eq!(object["alg"], "ChaCha20-Poly1305");
+        assert_eq!(EncryptedField::from_json(&object).unwrap(), chacha);
+
+        // The prefix selects the cipher; relabelling a value breaks it.
+        let relabelled = EncryptedField {
+            algorithm: Algorithm::Aes256GcmSiv,
+            ..chacha
+        };
+        assert!(decrypt_field_with_aad(&relabelled, &dek, &aad).is_err());
+    }
+
     #[test]
     fn from_str_rejects_bad_prefix() {
         assert!(EncryptedField::from_str("v2.abc.def").is_err());
     }
 
+    #[test]
+    fn json_object_repr_round_trips() {
+        let dek = test_dek_a();
+        for field in [
+            encrypt_field(b"hello", &dek).unwrap(),
+            tagged(b"hello", &dek, "3f2a9c1be07d").unwrap(),
+        ] {
+            let object = field.to_json_object();
+            assert_eq!(object["alg"], ALGORITHM);
+            assert!(EncryptedField::is_json_object(&object));
+            let parsed = EncryptedField::from_json(&object).unwrap();
+            assert_eq!(parsed, field);
+            assert_eq!(decrypt_field(&parsed, &dek).unwrap(), b"hello");
+            // Both representations of one value parse to the same field.
+            let compact = Value::String(field.to_string_repr());
+            assert_eq!(EncryptedField::from_json(&compact).unwrap(), field);
+        }
+    }
+
+    #[test]
+    fn json_object_repr_rejects_other_objects() {
+        let dek = test_dek_a();
+        let valid = encrypt_field(b"hello", &dek).unwrap().to_json_object();
+        let with = |name: &str, value: Value| {
+            let mut object = valid.clone();
+            object[name] = value;
+            object
+        };
+        for object in [
+            with("alg", "AES-256-GCM".into()),
+            with("nonce", "AAAA".into()),
+            with("ct", 7.into()),
+            with("extra", true.into()),
+            with("schema_tag", "not a tag".into()),
+            serde_json::json!({"name": "Jane"}),
+        ] {
+            assert!(EncryptedField::from_json(&object).is_err(), "{object}");
+            assert!(!EncryptedField::is_json_object(&object));
+        }
+        assert!(!EncryptedField::is_json_object(&Value::String(
+            EncryptedField::from_json(&valid).unwrap().to_string_repr()
+        )));
+    }
+
+    fn tagged(plaintext: &[u8], dek: &[u8], tag: &str) -> Result<EncryptedField, CipherError> {
+        encrypt_field_as(Algorithm::default(), plaintext, dek, &[], Some(tag))
+    }
+
+    #[test]
+    fn schema_tagged_repr_round_trips() {
+        let dek = test_dek_a();
+        let field = tagged(b"hello", &dek, "3f2a9c1be07d").unwrap();
+        let s = field.to_string_repr();
+        assert!(s.starts_with("v1.3f2a9c1be07d."));
+        let parsed = EncryptedField::from_str(&s).unwrap();
+        assert_eq!(parsed, field);
+        assert_eq!(decrypt_field(&parsed, &dek).unwrap(), b"hello");
+
+        // Untagged values still parse, with no tag.
+        let plain = encrypt_field(b"hello", &dek).unwrap().to_string_repr();
+        assert_eq!(EncryptedField::from_str(&plain).unwrap().schema_tag, None);
+    }
+
+    #[test]
+    fn schema_tag_is_authenticated() {
+        let dek = test_dek_a();
+        let field = tagged(b"hello", &dek, "3f2a9c1be07d").unwrap();
+        for schema_tag in [None, Some("000000000000".to_owned())] {
+            let forged = EncryptedField {
+                schema_tag,
+                ..field.clone()
+            };
+            assert!(matches!(
+                decrypt_field(&forged, &dek),
+                Err(CipherError::AeadFailure)
+            ));
+        }
+    }
+
+    #[test]
+    fn schema_tag_must_be_alphanumeric() {
+        let dek = test_dek_a();
+        assert!(matches!(
+            tagged(b"hello", &dek, ""),
+            Err(CipherError::InvalidFormat)
+        ));
+        assert!(matches!(
+            tagged(b"hello", &dek, "a.b"),
+            Err(CipherError::InvalidFormat)
+        ));
+        assert!(EncryptedField::from_str("v1.a-b.AAAAAAAAAAAAAAAA.AAAA").is_err());
+        assert!(EncryptedField::from_str("v1.a.b.c.d").is_err());
+    }
+
     #[test]
     fn from_str_rejects_too_few_parts() {
         assert!(EncryptedField::from_str("v1.abc").is_err());
@@ -256,6 +700,32 @@ mod tests {
         assert!(EncryptedField::from_str("v1.!!!.abc").is_err());
     }
 
+    #[test]
+    fn empty_aad_matches_plain_encryption() {
+        let dek = test_dek_a();
+        let plain = encrypt_field(b"hello", &dek).unwrap();
+        let with_aad = encrypt_field_with_aad(b"hello", &dek, &[]).unwrap();
+        assert_eq!(plain, with_aad);
+        assert!(field_aad(None, "ssn").is_empty());
+    }
+
+    #[test]
+    fn aad_must_match_to_decrypt() {
+        let dek = test_dek_a();
+        let aad_a = field_aad(Some("tenant-a"), "ssn");
+        let aad_b = field_aad(Some("tenant-b"), "ssn");
+        let field = encrypt_field_with_aad(b"123-45-6789", &dek, &aad_a).unwrap();
+        assert_eq!(
+            decrypt_field_with_aad(&field, &dek, &aad_a).unwrap(),
+            b"123-45-6789"
+        );
+        assert!(decrypt_field_with_aad(&field, &dek, &aad_b).is_err());
+        assert!(decrypt_field(&field, &dek).is_err());
+        assert!(
+            decrypt_field_with_aad(&field, &dek, &field_aad(Some("tenant-a"), "name")).is_err()
+        );
+    }
+
     #[test]
     fn tampered_ciphertext_fails_auth() {
         let dek = test_dek_a();
@@ -264,4 +734,70 @@ mod tests {
         field.ciphertext[0] ^= 0xFF;
         assert!(decrypt_field(&field, &dek).is_err());
     }
+
+    /// Edge-case ciphertext strings and whether each parses, checked on every
+    /// `cargo test` alongside the randomised cases below.
+    const FROM_STR_CORPUS: &[(&str, bool)] = &[
+        ("", false),
+        (".", false),
+        ("v1", false),
+        ("v1.", false),
+        ("v1..", false),
+        ("v1...", false),
+        ("v1....", false),
+        ("v1.AAAAAAAAAAAAAAAA.", true),
+        ("v1.AAAAAAAAAAAAAAAA.=", false),
+        ("v1.abc.AAAAAAAAAAAAAAAA.AA", true),
+        ("v1.é.AAAAAAAAAAAAAAAA.AA", false),
+        ("v1.AAAAAAAAAAAAAAAA.AA.", false),
+        ("v1.AAAAAAAAAAAAAAAA.AA.AA.AA", false),
+        ("v1.\u{0}.\u{0}", false),
+        ("v1.🦀🦀🦀🦀.🦀", false),
+        ("V1.AAAAAAAAAAAAAAAA.AA", false),
+    ];
+
+    #[test]
+    fn from_str_corpus() {
+        for &(input, ok) in FROM_STR_CORPUS {
+            assert_eq!(EncryptedField::from_str(input).is_ok(), ok, "{input:?}");
+        }
+        // Extreme lengths: megabytes of separators or of one segment.
+        let dots = format!("v1{}", ".".repeat(1 << 20));
+        assert!(EncryptedField::from_str(&dots).is_err());
+        let long = format!("v1.{}.AA", "A".repeat(1 << 20));
+        assert!(EncryptedField::from_str(&long).is_err());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn from_str_never_panics(s in "\\PC*") {
+            let _ = EncryptedField::from_str(&s);
+        }
+
+        #[test]
+        fn from_str_never_panics_on_near_valid_input(
+            tag in proptest::option::of("[A-Za-z0-9é.]{0,16}"),
+            nonce in "[A-Za-z0-9_\\-=.]{0,24}",
+            ciphertext in "[A-Za-z0-9_\\-=.]{0,64}",
+        ) {
+            let s = match tag {
+                Some(tag) => format!("v1.{tag}.{nonce}.{ciphertext}"),
+                None => format!("v1.{nonce}.{ciphertext}"),
+            };
+            let _ = EncryptedField::from_str(&s);
+        }
+
+        #[test]
+        fn to_string_round_trips(
+            nonce in proptest::array::uniform12(proptest::num::u8::ANY),
+            ciphertext in proptest::collection::vec(proptest::num::u8::ANY, 0..256),
+            schema_tag in proptest::option::of("[a-f0-9]{1,12}"),
+        ) {
+            let field = EncryptedField { algorithm: Algorithm::default(), schema_tag, nonce, ciphertext };
+            let parsed = EncryptedField::from_str(&field.to_string_repr()).unwrap();
+            proptest::prop_assert_eq!(parsed.schema_tag, field.schema_tag);
+            proptest::prop_assert_eq!(parsed.nonce, field.nonce);
+            proptest::prop_assert_eq!(parsed.ciphertext, field.ciphertext);
+        }
+    }
 }
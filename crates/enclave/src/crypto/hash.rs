@@ -0,0 +1,98 @@
+//! Irreversible keyed hashing of PII fields annotated `x-pii-mode: hash`.
+//!
+//! Some fields only need a deterministic token for lookups or deduplication
+//! and must never be recoverable. They are replaced with
+//! `h1.<base64url-no-pad(HMAC-SHA256(key=subkey, data=plaintext))>`, where the
+//! subkey is derived from the non-rotating token key with a fixed
+//! domain-separation label. Deriving it from the DEK instead would change
+//! every token at each rotation, and the tokens would stop matching.
+//!
+//! As with encryption, a non-empty AAD (tenant binding, see
+//! [`field_aad`](super::cipher::field_aad)) is mixed in, so the same value
+//! hashes differently for different tenants and fields.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::cipher::{CipherError, KEY_LEN};
+
+/// Prefix that appears at the start of every hashed field value.
+pub const HASH_PREFIX: &str = "h1";
+
+/// Domain-separation label for deriving the hashing subkey from the token key.
+const HASH_SUBKEY_LABEL: &[u8] = b"nitro-enc-svc/pii-hash/v1";
+
+/// Whether `value` is a token produced by [`hash_field`].
+pub fn is_hashed(value: &str) -> bool {
+    value
+        .strip_prefix(HASH_PREFIX)
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Replace `plaintext` with its keyed hash token, bound to `aad`.
+///
+/// Identical plaintext, token key and `aad` always produce the identical
+/// token, so the token can be indexed; the plaintext cannot be recovered
+/// from it.
+///
+/// # Errors
+///
+/// Returns [`CipherError::InvalidKeyLength`] if `token_key` is not
+/// [`KEY_LEN`] bytes.
+pub fn hash_field(plaintext: &[u8], token_key: &[u8], aad: &[u8]) -> Result<String, CipherError> {
+    if token_key.len() != KEY_LEN {
+        return Err(CipherError::InvalidKeyLength);
+    }
+    let subkey = hmac(token_key)?.chain_update(HASH_SUBKEY_LABEL).finalize();
+
+    let mut mac = hmac(&subkey.into_bytes())?;
+    if !aad.is_empty() {
+        mac.update(&(aad.len() as u64).to_be_bytes());
+        mac.update(aad);
+    }
+    mac.update(plaintext);
+    Ok(format!(
+        "{HASH_PREFIX}.{}",
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    ))
+}
+
+fn hmac(key: &[u8]) -> Result<Hmac<Sha256>, CipherError> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|_| CipherError::InvalidKeyLength)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_deterministic_and_keyed() {
+        let token_key = [7u8; KEY_LEN];
+        let a = hash_field(b"jane@example.com", &token_key, &[]).unwrap();
+        assert_eq!(a, hash_field(b"jane@example.com", &token_key, &[]).unwrap());
+        assert!(is_hashed(&a));
+        assert!(!a.contains("jane"));
+
+        assert_ne!(a, hash_field(b"john@example.com", &token_key, &[]).unwrap());
+        assert_ne!(
+            a,
+            hash_field(b"jane@example.com", &[8u8; KEY_LEN], &[]).unwrap()
+        );
+        assert_ne!(
+            a,
+            hash_field(b"jane@example.com", &token_key, b"tenant=a").unwrap()
+        );
+    }
+
+    #[test]
+    fn hash_rejects_bad_key_and_recognises_prefix() {
+        assert!(matches!(
+            hash_field(b"x", &[0u8; 16], &[]),
+            Err(CipherError::InvalidKeyLength)
+        ));
+        assert!(!is_hashed("v1.abc.def"));
+        assert!(!is_hashed("h1"));
+        assert!(!is_hashed("h10.abc"));
+    }
+}
@@ -0,0 +1,142 @@
+//! Deterministic lookup tags for PII fields annotated `x-pii-mode: lookup`.
+//!
+//! Such fields stay reversibly encrypted. Ciphertext is a poor join key: it
+//! grows with the value and whoever holds it can ask for it to be decrypted.
+//! Alongside it the service therefore stores a fixed-size, 128-bit keyed tag,
+//! `t1.<base64url-no-pad(HMAC-SHA256(key=subkey, data=path || plaintext)[..16])>`,
+//! that is identical for identical plaintext and can serve as a dedup or join
+//! key without revealing the value.
+//!
+//! The [`LookupKey`] is derived from the non-rotating token key, so tags
+//! survive DEK rotation. The derivation mixes in its own domain-separation
+//! label, so tags are unrelated to `h1.` hash tokens, and, when present, the
+//! tenant, so tags never match across tenants.
+
+use std::fmt;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::cipher::{CipherError, KEY_LEN};
+
+/// Prefix that appears at the start of every lookup tag.
+pub const LOOKUP_PREFIX: &str = "t1";
+
+/// Length in bytes of the truncated tag (128 bits).
+const TAG_LEN: usize = 16;
+
+/// Domain-separation label for deriving the lookup subkey from the token key.
+const LOOKUP_SUBKEY_LABEL: &[u8] = b"nitro-enc-svc/pii-lookup/v1";
+
+/// Subkey used to compute lookup tags, derived from the token key.
+#[derive(Clone)]
+pub struct LookupKey([u8; KEY_LEN]);
+
+impl fmt::Debug for LookupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LookupKey(<redacted>)")
+    }
+}
+
+impl LookupKey {
+    /// Derive the lookup subkey for `tenant` (or for untenanted data) from
+    /// `token_key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CipherError::InvalidKeyLength`] if `token_key` is not
+    /// [`KEY_LEN`] bytes.
+    pub fn derive(token_key: &[u8], tenant: Option<&str>) -> Result<Self, CipherError> {
+        if token_key.len() != KEY_LEN {
+            return Err(CipherError::InvalidKeyLength);
+        }
+        let mut mac = hmac(token_key)?.chain_update(LOOKUP_SUBKEY_LABEL);
+        if let Some(tenant) = tenant {
+            mac.update(&(tenant.len() as u64).to_be_bytes());
+            mac.update(tenant.as_bytes());
+        }
+        Ok(Self(mac.finalize().into_bytes().into()))
+    }
+}
+
+/// The lookup tag of `plaintext` at field `path`.
+///
+/// Identical plaintext at the same path under the same [`LookupKey`] always
+/// yields the identical tag; the plaintext cannot be recovered from it.
+///
+/// # Errors
+///
+/// Returns [`CipherError::InvalidKeyLength`] if the HMAC cannot be keyed.
+pub fn derive_lookup_tag(
+    plaintext: &[u8],
+    path: &str,
+    subkey: &LookupKey,
+) -> Result<String, CipherError> {
+    let mut mac = hmac(&subkey.0)?;
+    mac.update(&(path.len() as u64).to_be_bytes());
+    mac.update(path.as_bytes());
+    mac.update(plaintext);
+    let digest = mac.finalize().into_bytes();
+    Ok(format!(
+        "{LOOKUP_PREFIX}.{}",
+        URL_SAFE_NO_PAD.encode(&digest[..TAG_LEN])
+    ))
+}
+
+fn hmac(key: &[u8]) -> Result<Hmac<Sha256>, CipherError> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|_| CipherError::InvalidKeyLength)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::hash_field;
+
+    fn tag(plaintext: &[u8], path: &str, key: &LookupKey) -> String {
+        derive_lookup_tag(plaintext, path, key).unwrap()
+    }
+
+    #[test]
+    fn identical_plaintext_yields_identical_tag() {
+        let key = LookupKey::derive(&[7u8; KEY_LEN], None).unwrap();
+        let a = tag(b"jane@example.com", "email", &key);
+        assert_eq!(a, tag(b"jane@example.com", "email", &key));
+        // A fresh derivation from the same token key is the same key.
+        let again = LookupKey::derive(&[7u8; KEY_LEN], None).unwrap();
+        assert_eq!(a, tag(b"jane@example.com", "email", &again));
+
+        assert!(a.starts_with("t1."));
+        assert!(!a.contains("jane"));
+        // 128 bits in unpadded base64url is 22 characters.
+        assert_eq!(a.len(), LOOKUP_PREFIX.len() + 1 + 22);
+    }
+
+    #[test]
+    fn tag_is_keyed_by_plaintext_path_token_key_and_tenant() {
+        let token_key = [7u8; KEY_LEN];
+        let key = LookupKey::derive(&token_key, None).unwrap();
+        let jane = tag(b"jane@example.com", "email", &key);
+
+        assert_ne!(jane, tag(b"john@example.com", "email", &key));
+        assert_ne!(jane, tag(b"jane@example.com", "contact", &key));
+        let other_key = LookupKey::derive(&[8u8; KEY_LEN], None).unwrap();
+        assert_ne!(jane, tag(b"jane@example.com", "email", &other_key));
+        let tenant = LookupKey::derive(&token_key, Some("tenant-a")).unwrap();
+        assert_ne!(jane, tag(b"jane@example.com", "email", &tenant));
+        // Unrelated to the `h1.` hash of the same value.
+        let hashed = hash_field(b"jane@example.com", &token_key, &[]).unwrap();
+        assert_ne!(jane[3..], hashed[3..3 + 22]);
+    }
+
+    #[test]
+    fn derive_rejects_bad_key_and_redacts_debug() {
+        assert!(matches!(
+            LookupKey::derive(&[0u8; 16], None),
+            Err(CipherError::InvalidKeyLength)
+        ));
+        assert!(
+            format!("{:?}", LookupKey::derive(&[7u8; KEY_LEN], None).unwrap()).contains("redacted")
+        );
+    }
+}
@@ -11,7 +11,22 @@
 //!
 //! The `v1` prefix enables future algorithm or key-version migration without
 //! breaking existing ciphertext.
+//!
+//! Ciphertext may additionally be bound to a tenant and field path through the
+//! AEAD associated data (see [`cipher::field_aad`]); the string format is the
+//! same, but decryption requires the same tenant.
+//!
+//! Fields annotated `x-pii-mode: hash` are instead replaced with an
+//! irreversible `h1.<base64url-no-pad(hmac)>` token; see [`hash`]. Fields
+//! annotated `x-pii-mode: lookup` stay encrypted and gain a deterministic
+//! `t1.` tag in a sibling field; see [`lookup`]. Fields annotated
+//! `x-pii-mode: fpe` are replaced with a format-preserving token of the same
+//! length and character classes, authenticated by an `f1.` tag in a sibling
+//! field; see [`tokenize`].
 
 pub mod cipher;
+pub mod hash;
+pub mod lookup;
+pub mod tokenize;
 
 pub use cipher::KEY_LEN;
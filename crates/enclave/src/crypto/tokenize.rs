@@ -0,0 +1,286 @@
+//! Format-preserving tokenization of PII fields annotated `x-pii-mode: fpe`.
+//!
+//! Some consumers validate field formats and cannot store `v1.` ciphertext:
+//! a 16-digit card number has to stay 16 digits. Such fields are encrypted
+//! with FF1 (NIST SP 800-38G) over AES-256, keyed by a subkey derived from the
+//! token key with its own domain-separation label. The token key, unlike the
+//! DEK, never rotates, so a token detokenizes for as long as it is stored.
+//!
+//! Each character class is encrypted separately and in place: the ASCII
+//! digits as one radix-10 numeral string, the lowercase and the uppercase
+//! ASCII letters as radix-26 strings. Every other character (separators such
+//! as `-` or spaces, and non-ASCII letters) stays where it is. A token
+//! therefore has the length and shape of its plaintext and carries no prefix.
+//!
+//! Any string decodes to *some* plaintext, so a token alone cannot be told
+//! from plaintext. Each token is therefore paired with an
+//! `f1.<base64url-no-pad(hmac)>` tag (see [`token_tag`]), stored in a sibling
+//! field, and [`detokenize`] refuses a token whose tag does not match.
+//!
+//! FF1 is deterministic: equal plaintext under the same key and AAD (tenant
+//! binding, see [`field_aad`](super::cipher::field_aad), used as the tweak)
+//! always yields the equal token. FF1 also needs a domain of at least a
+//! million values, so every class present in a value must have at least six
+//! digits or five letters; shorter values are refused.
+
+use aes::Aes256;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use fpe::ff1::{FlexibleNumeralString, FF1};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::cipher::{CipherError, KEY_LEN};
+
+/// Prefix that appears at the start of every token tag.
+pub const TAG_PREFIX: &str = "f1";
+
+/// Domain-separation label for deriving the FF1 subkey from the token key.
+const FPE_SUBKEY_LABEL: &[u8] = b"nitro-enc-svc/pii-fpe/v1";
+
+/// Domain-separation label for deriving the token tag subkey.
+const TAG_SUBKEY_LABEL: &[u8] = b"nitro-enc-svc/pii-fpe-tag/v1";
+
+/// Bytes of HMAC-SHA256 output kept in a token tag.
+const TAG_LEN: usize = 16;
+
+/// A run of ASCII characters encrypted as one numeral string.
+#[derive(Clone, Copy)]
+struct Class {
+    /// Tweak prefix keeping the classes' numeral strings independent.
+    tag: u8,
+    /// The character for numeral zero.
+    first: u8,
+    radix: u8,
+}
+
+const CLASSES: [Class; 3] = [
+    Class {
+        tag: b'd',
+        first: b'0',
+        radix: 10,
+    },
+    Class {
+        tag: b'l',
+        first: b'a',
+        radix: 26,
+    },
+    Class {
+        tag: b'u',
+        first: b'A',
+        radix: 26,
+    },
+];
+
+impl Class {
+    /// The numeral `c` stands for in this class, if it belongs to it.
+    fn numeral(self, c: char) -> Option<u16> {
+        let offset = u8::try_from(c).ok()?.checked_sub(self.first)?;
+        (offset < self.radix).then_some(u16::from(offset))
+    }
+
+    /// The character standing for `numeral`, which FF1 keeps below the radix.
+    fn char(self, numeral: u16) -> char {
+        char::from(self.first + (numeral % u16::from(self.radix)) as u8)
+    }
+}
+
+/// Replace `plaintext` with its format-preserving token, bound to `aad`.
+///
+/// # Errors
+///
+/// Returns [`CipherError::InvalidKeyLength`] if `key` is not [`KEY_LEN`]
+/// bytes, or [`CipherError::TooShortToTokenize`] if a character class present
+/// in `plaintext` is too short for FF1.
+pub fn tokenize(plaintext: &str, key: &[u8], aad: &[u8]) -> Result<String, CipherError> {
+    transform(plaintext, key, aad, true)
+}
+
+/// The tag authenticating `token` as a [`tokenize`] output under `key` and
+/// `aad`.
+///
+/// # Errors
+///
+/// Returns [`CipherError::InvalidKeyLength`] if `key` is not [`KEY_LEN`] bytes.
+pub fn token_tag(token: &str, key: &[u8], aad: &[u8]) -> Result<String, CipherError> {
+    let mac = tag_mac(token, key, aad)?.finalize().into_bytes();
+    Ok(format!(
+        "{TAG_PREFIX}.{}",
+        URL_SAFE_NO_PAD.encode(&mac[..TAG_LEN])
+    ))
+}
+
+/// Recover the plaintext of a [`tokenize`] token, once `tag` proves it is
+/// one.
+///
+/// # Errors
+///
+/// Returns [`CipherError::UnauthenticatedToken`] if `tag` is not the
+/// [`token_tag`] of `token` under `key` and `aad`, and otherwise fails as
+/// [`tokenize`] does.
+pub fn detokenize(token: &str, tag: &str, key: &[u8], aad: &[u8]) -> Result<String, CipherError> {
+    let expected = tag_bytes(tag).ok_or(CipherError::UnauthenticatedToken)?;
+    tag_mac(token, key, aad)?
+        .verify_truncated_left(&expected)
+        .map_err(|_| CipherError::UnauthenticatedToken)?;
+    transform(token, key, aad, false)
+}
+
+/// Whether `value` has the form of a [`token_tag`]. Only [`detokenize`],
+/// with the key and AAD, can tell whether it matches a token.
+pub fn is_token_tag(value: &str) -> bool {
+    tag_bytes(value).is_some()
+}
+
+/// The truncated MAC a well-formed tag carries.
+fn tag_bytes(tag: &str) -> Option<Vec<u8>> {
+    tag.strip_prefix(TAG_PREFIX)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|b64| URL_SAFE_NO_PAD.decode(b64).ok())
+        .filter(|bytes| bytes.len() == TAG_LEN)
+}
+
+/// The HMAC over `token` and `aad` that [`token_tag`] truncates.
+fn tag_mac(token: &str, key: &[u8], aad: &[u8]) -> Result<Hmac<Sha256>, CipherError> {
+    let mut mac = hmac(
+        &hmac(key)?
+            .chain_update(TAG_SUBKEY_LABEL)
+            .finalize()
+            .into_bytes(),
+    )?;
+    mac.update(&(aad.len() as u64).to_be_bytes());
+    mac.update(aad);
+    mac.update(token.as_bytes());
+    Ok(mac)
+}
+
+fn hmac(key: &[u8]) -> Result<Hmac<Sha256>, CipherError> {
+    if key.len() != KEY_LEN {
+        return Err(CipherError::InvalidKeyLength);
+    }
+    <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|_| CipherError::InvalidKeyLength)
+}
+
+fn transform(value: &str, key: &[u8], aad: &[u8], encrypt: bool) -> Result<String, CipherError> {
+    let subkey = hmac(key)?
+        .chain_update(FPE_SUBKEY_LABEL)
+        .finalize()
+        .into_bytes();
+
+    let mut chars: Vec<char> = value.chars().collect();
+    for class in CLASSES {
+        let (positions, numerals): (Vec<usize>, Vec<u16>) = chars
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &c)| Some((i, class.numeral(c)?)))
+            .unzip();
+        if positions.is_empty() {
+            continue;
+        }
+        let ff1 = FF1::<Aes256>::new(&subkey, u32::from(class.radix))
+            .map_err(|_| CipherError::InvalidFormat)?;
+        let tweak = [&[class.tag][..], aad].concat();
+        let numerals = FlexibleNumeralString::from(numerals);
+        let output = if encrypt {
+            ff1.encrypt(&tweak, &numerals)
+        } else {
+            ff1.decrypt(&tweak, &numerals)
+        }
+        .map_err(|_| CipherError::TooShortToTokenize)?;
+        for (i, numeral) in positions.into_iter().zip(Vec::<u16>::from(output)) {
+            chars[i] = class.char(numeral);
+        }
+    }
+    Ok(chars.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; KEY_LEN] = [0x42; KEY_LEN];
+
+    fn round_trip(plaintext: &str, aad: &[u8]) -> String {
+        let token = tokenize(plaintext, &KEY, aad).unwrap();
+        let tag = token_tag(&token, &KEY, aad).unwrap();
+        assert_eq!(detokenize(&token, &tag, &KEY, aad).unwrap(), plaintext);
+        token
+    }
+
+    #[test]
+    fn card_number_stays_sixteen_digits_and_round_trips() {
+        let token = round_trip("4111111111111111", &[]);
+        assert_eq!(token.len(), 16);
+        assert!(token.bytes().all(|b| b.is_ascii_digit()), "{token}");
+        assert_ne!(token, "4111111111111111");
+        assert_eq!(tokenize("4111111111111111", &KEY, &[]).unwrap(), token);
+
+        // The tweak binds the token to its AAD, and the token to the key.
+        let bound = tokenize("4111111111111111", &KEY, b"tenant=a\0path=card").unwrap();
+        assert_ne!(bound, token);
+        assert_ne!(
+            tokenize("4111111111111111", &[7; KEY_LEN], &[]).unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn only_tagged_tokens_detokenize() {
+        let aad = b"tenant=a\0path=card";
+        let token = tokenize("4111111111111111", &KEY, aad).unwrap();
+        let tag = token_tag(&token, &KEY, aad).unwrap();
+        assert!(tag.starts_with("f1."), "{tag}");
+        assert!(is_token_tag(&tag));
+        assert!(!is_token_tag("f1.AAAA") && !is_token_tag(&token));
+
+        // Plaintext, a token under another AAD or key, and malformed tags
+        // are all refused rather than decoded to garbage.
+        for (value, tag, key, aad) in [
+            ("4111111111111111", tag.as_str(), &KEY, &aad[..]),
+            (
+                token.as_str(),
+                tag.as_str(),
+                &KEY,
+                &b"tenant=b\0path=card"[..],
+            ),
+            (token.as_str(), tag.as_str(), &[7; KEY_LEN], &aad[..]),
+            (token.as_str(), "f1.AAAA", &KEY, &aad[..]),
+            (token.as_str(), "", &KEY, &aad[..]),
+        ] {
+            assert!(
+                matches!(
+                    detokenize(value, tag, key, aad),
+                    Err(CipherError::UnauthenticatedToken)
+                ),
+                "{value} {tag}"
+            );
+        }
+    }
+
+    #[test]
+    fn character_classes_and_separators_keep_their_places() {
+        let plaintext = "GB29-NWBK-6016-1331-9268-19 é";
+        let token = round_trip(plaintext, &[]);
+        assert_eq!(token.chars().count(), plaintext.chars().count());
+        for (p, t) in plaintext.chars().zip(token.chars()) {
+            match p {
+                '0'..='9' => assert!(t.is_ascii_digit()),
+                'A'..='Z' => assert!(t.is_ascii_uppercase()),
+                _ => assert_eq!(p, t),
+            }
+        }
+    }
+
+    #[test]
+    fn short_values_are_refused() {
+        assert!(matches!(
+            tokenize("123", &KEY, &[]),
+            Err(CipherError::TooShortToTokenize)
+        ));
+        assert!(matches!(
+            tokenize("123456", &[0; 16], &[]),
+            Err(CipherError::InvalidKeyLength)
+        ));
+        // Nothing to encrypt is not an error.
+        assert_eq!(tokenize("--", &KEY, &[]).unwrap(), "--");
+    }
+}
@@ -0,0 +1,163 @@
+//! Circuit breaker guarding the KMS calls made by the DEK rotation task.
+//!
+//! When KMS throttles or is unavailable, retrying at the normal rotation cadence
+//! only adds load. The breaker counts consecutive failures and, once the
+//! threshold is reached, *opens*: the next attempt is deferred by a much longer
+//! delay. That deferred attempt is a *half-open* probe — success closes the
+//! breaker and restores the normal cadence, failure re-opens it.
+//!
+//! The breaker is a plain state machine with no I/O so that the transitions can
+//! be unit-tested without a runtime or AWS clients.
+
+use std::time::Duration;
+
+/// Current state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Normal operation: attempts run at the regular interval.
+    Closed,
+    /// A single probe attempt is in flight after the open delay elapsed.
+    HalfOpen,
+    /// Too many consecutive failures: attempts are deferred by the open delay.
+    Open,
+}
+
+impl BreakerState {
+    /// Numeric encoding used for the `enclave_kms_breaker_state` gauge.
+    pub fn as_metric(self) -> u64 {
+        match self {
+            BreakerState::Closed => 0,
+            BreakerState::HalfOpen => 1,
+            BreakerState::Open => 2,
+        }
+    }
+}
+
+/// Consecutive-failure circuit breaker.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_delay: Duration,
+    consecutive_failures: u32,
+    state: BreakerState,
+}
+
+impl CircuitBreaker {
+    /// Create a closed breaker that opens after `failure_threshold` consecutive
+    /// failures and then waits `open_delay` before probing.
+    pub fn new(failure_threshold: u32, open_delay: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_delay,
+            consecutive_failures: 0,
+            state: BreakerState::Closed,
+        }
+    }
+
+    /// Current breaker state.
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Delay to wait before the next attempt, given the normal `interval`.
+    pub fn next_delay(&self, interval: Duration) -> Duration {
+        match self.state {
+            BreakerState::Open => self.open_delay,
+            BreakerState::Closed | BreakerState::HalfOpen => interval,
+        }
+    }
+
+    /// Called immediately before an attempt. An open breaker whose delay has
+    /// elapsed moves to half-open so the attempt acts as a probe.
+    pub fn before_attempt(&mut self) {
+        if self.state == BreakerState::Open {
+            self.state = BreakerState::HalfOpen;
+        }
+    }
+
+    /// Record a successful attempt, closing the breaker.
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.state = BreakerState::Closed;
+    }
+
+    /// Record a failed attempt. A failed probe re-opens the breaker immediately;
+    /// otherwise the breaker opens once the failure threshold is reached.
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.state == BreakerState::HalfOpen
+            || self.consecutive_failures >= self.failure_threshold
+        {
+            self.state = BreakerState::Open;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(60);
+    const OPEN: Duration = Duration::from_secs(600);
+
+    #[test]
+    fn stays_closed_below_threshold() {
+        let mut b = CircuitBreaker::new(3, OPEN);
+        b.record_failure();
+        b.record_failure();
+        assert_eq!(b.state(), BreakerState::Closed);
+        assert_eq!(b.next_delay(INTERVAL), INTERVAL);
+    }
+
+    #[test]
+    fn opens_after_threshold_and_extends_delay() {
+        let mut b = CircuitBreaker::new(3, OPEN);
+        for _ in 0..3 {
+            b.before_attempt();
+            b.record_failure();
+        }
+        assert_eq!(b.state(), BreakerState::Open);
+        assert_eq!(b.next_delay(INTERVAL), OPEN);
+    }
+
+    #[test]
+    fn half_open_probe_success_closes() {
+        let mut b = CircuitBreaker::new(1, OPEN);
+        b.record_failure();
+        assert_eq!(b.state(), BreakerState::Open);
+        b.before_attempt();
+        assert_eq!(b.state(), BreakerState::HalfOpen);
+        b.record_success();
+        assert_eq!(b.state(), BreakerState::Closed);
+        assert_eq!(b.next_delay(INTERVAL), INTERVAL);
+    }
+
+    #[test]
+    fn half_open_probe_failure_reopens() {
+        let mut b = CircuitBreaker::new(3, OPEN);
+        for _ in 0..3 {
+            b.record_failure();
+        }
+        b.before_attempt();
+        assert_eq!(b.state(), BreakerState::HalfOpen);
+        b.record_failure();
+        assert_eq!(b.state(), BreakerState::Open);
+        assert_eq!(b.next_delay(INTERVAL), OPEN);
+    }
+
+    #[test]
+    fn success_resets_failure_count() {
+        let mut b = CircuitBreaker::new(2, OPEN);
+        b.record_failure();
+        b.record_success();
+        b.record_failure();
+        assert_eq!(b.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn metric_encoding() {
+        assert_eq!(BreakerState::Closed.as_metric(), 0);
+        assert_eq!(BreakerState::HalfOpen.as_metric(), 1);
+        assert_eq!(BreakerState::Open.as_metric(), 2);
+    }
+}
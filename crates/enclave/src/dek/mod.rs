@@ -2,106 +2,722 @@
 //!
 //! # Lifecycle
 //!
-//! 1. At startup, [`fetch_and_store`] fetches the envelope-encrypted DEK from
-//!    AWS Secrets Manager and decrypts it via AWS KMS.
+//! 1. At startup, [`fetch_and_store`] fetches the DEK from a [`DekProvider`]:
+//!    in production [`SecretsManagerKms`], which fetches the envelope-encrypted
+//!    DEK from AWS Secrets Manager and decrypts it via AWS KMS.
 //! 2. The decrypted DEK lives only in enclave memory, wrapped in an `Arc<RwLock<_>>`.
 //! 3. A background Tokio task calls [`rotation_task`] on a configurable interval
-//!    to refresh the cached DEK.
+//!    to refresh the cached DEK from the same provider. Repeated failures trip a [`CircuitBreaker`]
+//!    that backs off hard so a throttled KMS is not hammered.
 //! 4. Encryption handlers borrow the DEK via [`DekStore::current`], which acquires a
 //!    short read lock and clones the key bytes into a zeroizable buffer.
 //!
 //! # Security invariants
 //!
 //! - The plaintext DEK is **never** written to disk, logged, or included in traces.
-//! - KMS key policy enforces Nitro attestation (PCR values); decryption fails if the
-//!   enclave image does not match the expected measurements.
+//! - When the KMS key policy carries a `kms:RecipientAttestation:PCR0` condition
+//!   (Terraform sets one once `kms_enclave_pcr0` is supplied), KMS only decrypts
+//!   for a request whose attestation document matches the expected measurements.
+//!   The policy is enforced by KMS, not checked here.
+//! - With `KMS_RECIPIENT_ATTESTATION` each decrypt sends the enclave's NSM
+//!   attestation document as the `Recipient`, so KMS returns the DEK encrypted
+//!   to a key that never leaves the enclave. A response that still carries
+//!   plaintext means KMS did not apply the attestation, and the DEK is rejected.
+//!   Without the flag the decrypt carries no attestation, and the PCR condition
+//!   above is then not met by any request.
 
+pub mod breaker;
+pub mod provider;
 pub mod store;
 
+pub use breaker::{BreakerState, CircuitBreaker};
+pub use provider::DekProvider;
 pub use store::DekStore;
 
+use store::DekBytes;
+
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::{Context, Result};
-use opentelemetry::metrics::Counter;
+use aws_sdk_kms::error::ProvideErrorMetadata;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use opentelemetry::KeyValue;
+use thiserror::Error;
 use tokio::time;
 use tracing::{info, warn};
 
-use crate::aws::AwsClients;
+use crate::aws::{recipient, AwsClients};
 use crate::config::Config;
+use crate::crypto::KEY_LEN;
+use crate::telemetry::heartbeat::Heartbeat;
+use crate::telemetry::Metrics;
+
+/// Fetch the DEK from `provider` and store it in `store`.
+///
+/// # Errors
+///
+/// Returns an error if the provider fails to produce a key.
+pub async fn fetch_and_store<P: DekProvider>(provider: &P, store: &DekStore) -> Result<()> {
+    let key = provider.fetch().await?;
+    store
+        .store(&key.0[..])
+        .await
+        .context("failed to store fetched DEK")?;
+    info!("DEK fetched and stored successfully");
+    Ok(())
+}
+
+/// The default [`DekProvider`]: fetches the envelope-encrypted DEK from
+/// Secrets Manager and decrypts it via KMS.
+///
+/// With `secret_share_arns` the DEK is split: every share is fetched and
+/// decrypted the same way and the shares are XORed together (see
+/// [`combine_shares`]).
+#[derive(Clone)]
+pub struct SecretsManagerKms {
+    aws: AwsClients,
+    cfg: Config,
+}
+
+impl SecretsManagerKms {
+    /// Create a provider for the secrets and KMS key configured in `cfg`.
+    pub fn new(aws: AwsClients, cfg: Config) -> Self {
+        Self { aws, cfg }
+    }
+}
+
+impl DekProvider for SecretsManagerKms {
+    /// # Errors
+    ///
+    /// Returns an error if a Secrets Manager call fails, if KMS decryption
+    /// fails, or if the decrypted key material (or any share) is not exactly
+    /// 32 bytes.
+    async fn fetch(&self) -> Result<DekBytes> {
+        let (aws, cfg) = (&self.aws, &self.cfg);
+        let arns = cfg.secret_arns()?;
+        if let [arn] = arns[..] {
+            let mut plaintext = fetch_share(aws, cfg, arn).await?;
+            let key = DekBytes::try_from(&plaintext[..]);
+            plaintext.fill(0);
+            return key.context("decrypted DEK has an unexpected length");
+        }
+
+        let mut shares = Vec::with_capacity(arns.len());
+        for arn in &arns {
+            match fetch_share(aws, cfg, arn).await {
+                Ok(share) => shares.push(share),
+                Err(e) => {
+                    shares.iter_mut().for_each(|s: &mut Vec<u8>| s.fill(0));
+                    return Err(e.context(format!("failed to fetch DEK share {arn}")));
+                }
+            }
+        }
+        let combined = combine_shares(&arns, &shares);
+        shares.iter_mut().for_each(|s| s.fill(0));
+        let key = combined?;
+        info!(shares = arns.len(), "DEK assembled from shares");
+        Ok(key)
+    }
+}
+
+/// Fetch the token key from the secret at `arn`, envelope-encrypted like the
+/// DEK. It is fetched once at startup and never rotated.
+///
+/// # Errors
+///
+/// Returns an error if the Secrets Manager call or KMS decryption fails, or
+/// if the key is not exactly 32 bytes.
+pub async fn fetch_token_key(aws: &AwsClients, cfg: &Config, arn: &str) -> Result<DekBytes> {
+    let mut plaintext = fetch_share(aws, cfg, arn).await?;
+    let key = DekBytes::try_from(&plaintext[..]);
+    plaintext.fill(0);
+    let key = key.context("decrypted token key has an unexpected length")?;
+    info!("token key fetched");
+    Ok(key)
+}
 
-/// Fetch the envelope-encrypted DEK from Secrets Manager, decrypt it via KMS,
-/// and store the plaintext key bytes in `store`.
+/// XOR the decrypted DEK `shares` (fetched from the corresponding `arns`)
+/// into the DEK.
 ///
 /// # Errors
 ///
-/// Returns an error if the Secrets Manager call fails, if KMS decryption fails,
-/// or if the decrypted key material is not exactly 32 bytes.
-pub async fn fetch_and_store(aws: &AwsClients, cfg: &Config, store: &DekStore) -> Result<()> {
+/// Returns an error naming the share if any is not exactly [`KEY_LEN`] bytes,
+/// or if the shares combine to an all-zero key, which only happens when they
+/// are copies of each other.
+fn combine_shares(arns: &[&str], shares: &[Vec<u8>]) -> Result<DekBytes> {
+    let mut key = DekBytes(Box::new([0u8; KEY_LEN]));
+    for (arn, share) in arns.iter().zip(shares) {
+        if share.len() != KEY_LEN {
+            anyhow::bail!(
+                "DEK share {arn} is {} bytes, expected {KEY_LEN}",
+                share.len()
+            );
+        }
+        key.0.iter_mut().zip(share).for_each(|(k, s)| *k ^= s);
+    }
+    if key.0.iter().all(|&b| b == 0) {
+        anyhow::bail!("DEK shares combine to an all-zero key; are two shares identical?");
+    }
+    Ok(key)
+}
+
+/// Fetch one envelope-encrypted secret from Secrets Manager and decrypt it via
+/// KMS, returning the plaintext bytes.
+async fn fetch_share(aws: &AwsClients, cfg: &Config, arn: &str) -> Result<Vec<u8>> {
     // Fetch the envelope-encrypted DEK blob from Secrets Manager.
     let secret = aws
         .secretsmanager
         .get_secret_value()
-        .secret_id(&cfg.secret_arn)
+        .secret_id(arn)
         .send()
         .await
         .context("failed to fetch DEK from Secrets Manager")?;
 
-    // The DEK ciphertext is expected to be stored as binary.
-    let ciphertext_bytes = secret
-        .secret_binary()
-        .context("DEK secret must be stored as binary in Secrets Manager")?
-        .as_ref()
-        .to_vec();
+    // The DEK ciphertext is normally stored as binary, but operators sometimes
+    // store it as a base64 string instead; accept either.
+    let ciphertext_bytes = secret_ciphertext(
+        secret.secret_binary().map(|b| b.as_ref()),
+        secret.secret_string(),
+    )?;
 
-    // Decrypt the ciphertext blob via KMS.
-    let decrypt_resp = aws
+    // Decrypt the ciphertext blob via KMS, attaching the attestation document
+    // as the recipient when that is enabled.
+    let recipient = recipient::required(cfg.kms_recipient_attestation, aws.kms_recipient.as_ref())?;
+    let request = aws
         .kms
         .decrypt()
         .key_id(&cfg.kms_key_id)
-        .ciphertext_blob(aws_sdk_kms::primitives::Blob::new(ciphertext_bytes))
+        .ciphertext_blob(aws_sdk_kms::primitives::Blob::new(ciphertext_bytes));
+    let decrypt_resp = recipient::attach(request, recipient)?
         .send()
         .await
-        .context("failed to decrypt DEK via KMS")?;
+        .map_err(|e| {
+            let reason = KmsFailure::classify(&e);
+            anyhow::Error::new(e).context(reason)
+        })?;
 
-    let plaintext = decrypt_resp
-        .plaintext()
-        .context("KMS decrypt response contained no plaintext")?;
+    recipient::plaintext(
+        decrypt_resp.plaintext().map(|b| b.as_ref()),
+        decrypt_resp.ciphertext_for_recipient().map(|b| b.as_ref()),
+        recipient,
+    )
+}
 
-    store
-        .store(plaintext.as_ref())
-        .await
-        .context("failed to store decrypted DEK (unexpected key length)")?;
+/// Why KMS failed to decrypt the DEK, classified from the error code so the
+/// operator knows what to fix. Displays as an actionable message; the failing
+/// error keeps it as context (see [`anyhow::Error::downcast_ref`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum KmsFailure {
+    /// The key policy, or its attestation conditions, refused this enclave.
+    #[error(
+        "KMS denied decrypting the DEK; check the key policy and its \
+         attestation (PCR) conditions against this enclave image"
+    )]
+    AccessDenied,
+    /// The key is disabled, pending deletion or otherwise unusable.
+    #[error(
+        "KMS key cannot decrypt the DEK in its current state; check that \
+         the key is enabled and not pending deletion"
+    )]
+    InvalidState,
+    /// The secret does not hold ciphertext this key can decrypt.
+    #[error(
+        "KMS rejected the DEK ciphertext; check that the secret holds \
+         ciphertext produced under KMS_KEY_ID"
+    )]
+    InvalidCiphertext,
+    /// Anything else: throttling, timeouts, network or KMS-internal errors.
+    #[error("failed to decrypt DEK via KMS")]
+    Other,
+}
 
-    info!("DEK fetched and stored successfully");
-    Ok(())
+impl KmsFailure {
+    /// Classify a KMS `Decrypt` error by its error code.
+    pub fn classify(err: &impl ProvideErrorMetadata) -> Self {
+        match err.code() {
+            Some("AccessDeniedException") => Self::AccessDenied,
+            Some("KMSInvalidStateException" | "DisabledException") => Self::InvalidState,
+            Some("InvalidCiphertextException" | "IncorrectKeyException") => Self::InvalidCiphertext,
+            _ => Self::Other,
+        }
+    }
+
+    /// Label for the `reason` attribute of `enclave_kms_decrypt_failures`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::AccessDenied => "access_denied",
+            Self::InvalidState => "invalid_state",
+            Self::InvalidCiphertext => "invalid_ciphertext",
+            Self::Other => "other",
+        }
+    }
 }
 
-/// Spawn a background task that periodically re-fetches and rotates the DEK.
+/// Extract the envelope-encrypted DEK from a Secrets Manager secret value.
+///
+/// `SecretBinary` is used when present; otherwise `SecretString` is decoded as
+/// standard base64 (surrounding whitespace, e.g. a trailing newline from
+/// `base64 < file`, is ignored).
+///
+/// # Errors
+///
+/// Returns an error if the secret has neither form, if the string is not valid
+/// base64, or if the selected form is empty.
+fn secret_ciphertext(binary: Option<&[u8]>, string: Option<&str>) -> Result<Vec<u8>> {
+    let bytes = match (binary, string) {
+        (Some(binary), _) => binary.to_vec(),
+        (None, Some(string)) => STANDARD.decode(string.trim()).context(
+            "DEK secret is stored as a string but is not valid base64; \
+             store the KMS ciphertext as SecretBinary or base64-encode it",
+        )?,
+        (None, None) => anyhow::bail!("DEK secret has neither SecretBinary nor SecretString"),
+    };
+    if bytes.is_empty() {
+        anyhow::bail!("DEK secret is empty");
+    }
+    Ok(bytes)
+}
+
+/// Spawn a background task that periodically re-fetches the DEK from
+/// `provider` and rotates it into `store`.
 ///
 /// The first rotation fires after one full interval (startup fetch is assumed
 /// to have already populated the store). On rotation failure the previous key
 /// is retained and a warning is emitted.
 ///
-/// `dek_rotations` is incremented on each successful rotation.
-pub fn rotation_task(
-    aws: AwsClients,
-    cfg: Config,
+/// After `kms_breaker_failure_threshold` consecutive failures the circuit
+/// breaker opens and the next attempt is deferred by
+/// `kms_breaker_backoff_multiplier` intervals; that attempt is a half-open
+/// probe whose success restores the normal cadence.
+///
+/// `metrics.dek_rotations` is incremented on each successful rotation and
+/// `metrics.kms_breaker_state` tracks the breaker state. The task registers
+/// the `dek_rotation` heartbeat in `metrics.task_heartbeats`.
+pub fn rotation_task<P: DekProvider + 'static>(
+    provider: P,
+    cfg: &Config,
     store: DekStore,
-    dek_rotations: Counter<u64>,
+    metrics: Arc<Metrics>,
 ) -> tokio::task::JoinHandle<()> {
-    let interval = std::time::Duration::from_secs(cfg.dek_rotation_interval_secs);
-    tokio::spawn(async move {
-        let mut ticker = time::interval(interval);
-        // First tick fires immediately — skip it so we don't double-fetch.
-        ticker.tick().await;
-        loop {
-            ticker.tick().await;
-            match fetch_and_store(&aws, &cfg, &store).await {
-                Ok(()) => {
-                    dek_rotations.add(1, &[]);
-                    info!("DEK rotated successfully");
+    let interval = Duration::from_secs(cfg.dek_rotation_interval_secs);
+    let open_delay = interval.saturating_mul(cfg.kms_breaker_backoff_multiplier);
+    let breaker = CircuitBreaker::new(cfg.kms_breaker_failure_threshold, open_delay);
+    let heartbeat = metrics.task_heartbeats.register("dek_rotation");
+    let provider = Arc::new(provider);
+    tokio::spawn(rotation_loop(
+        interval,
+        breaker,
+        metrics,
+        heartbeat,
+        move || {
+            let (provider, store) = (Arc::clone(&provider), store.clone());
+            async move { fetch_and_store(&*provider, &store).await }
+        },
+    ))
+}
+
+/// The body of [`rotation_task`], with the rotation itself injected so tests
+/// can drive it under a paused `tokio::time` clock.
+async fn rotation_loop<F, Fut>(
+    interval: Duration,
+    mut breaker: CircuitBreaker,
+    metrics: Arc<Metrics>,
+    heartbeat: Heartbeat,
+    mut rotate: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    loop {
+        let delay = breaker.next_delay(interval);
+        heartbeat.beat(delay);
+        time::sleep(delay).await;
+        breaker.before_attempt();
+        let probing = breaker.state() == BreakerState::HalfOpen;
+        metrics
+            .kms_breaker_state
+            .store(breaker.state().as_metric(), Ordering::Relaxed);
+
+        match rotate().await {
+            Ok(()) => {
+                breaker.record_success();
+                metrics.dek_rotations.add(1, &[]);
+                if probing {
+                    info!("KMS circuit breaker closed after successful probe");
                 }
-                Err(e) => warn!(error = %e, "DEK rotation failed; retaining previous key"),
+                info!("DEK rotated successfully");
             }
+            Err(e) => {
+                breaker.record_failure();
+                if let Some(reason) = e.downcast_ref::<KmsFailure>() {
+                    metrics
+                        .kms_decrypt_failures
+                        .add(1, &[KeyValue::new("reason", reason.as_str())]);
+                }
+                warn!(error = %e, "DEK rotation failed; retaining previous key");
+                if breaker.state() == BreakerState::Open {
+                    warn!(
+                        retry_in_secs = breaker.next_delay(interval).as_secs(),
+                        "KMS circuit breaker open; backing off"
+                    );
+                }
+            }
+        }
+        metrics
+            .kms_breaker_state
+            .store(breaker.state().as_metric(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_secret_is_used_as_is() {
+        let blob = [0x01u8, 0x02, 0xff];
+        assert_eq!(secret_ciphertext(Some(&blob), None).unwrap(), blob);
+        // Binary wins if, unusually, both forms are present.
+        assert_eq!(secret_ciphertext(Some(&blob), Some("AAAA")).unwrap(), blob);
+    }
+
+    #[test]
+    fn string_secret_is_base64_decoded() {
+        let blob = [0x42u8; 40];
+        let encoded = format!("{}\n", STANDARD.encode(blob));
+        assert_eq!(secret_ciphertext(None, Some(&encoded)).unwrap(), blob);
+    }
+
+    #[test]
+    fn unusable_secrets_are_rejected() {
+        assert!(secret_ciphertext(None, None).is_err());
+        assert!(secret_ciphertext(None, Some("not base64!")).is_err());
+        assert!(secret_ciphertext(None, Some("")).is_err());
+        assert!(secret_ciphertext(Some(&[]), None).is_err());
+    }
+
+    #[test]
+    fn kms_decrypt_errors_are_classified_by_code() {
+        use aws_sdk_kms::error::{ErrorMetadata, SdkError};
+        use aws_sdk_kms::operation::decrypt::DecryptError;
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_types::body::SdkBody;
+
+        let service_error = |code: &str| {
+            let meta = ErrorMetadata::builder()
+                .code(code)
+                .message("refused")
+                .build();
+            let raw = HttpResponse::new(400.try_into().unwrap(), SdkBody::empty());
+            SdkError::service_error(DecryptError::generic(meta), raw)
+        };
+        for (code, reason) in [
+            ("AccessDeniedException", KmsFailure::AccessDenied),
+            ("KMSInvalidStateException", KmsFailure::InvalidState),
+            ("DisabledException", KmsFailure::InvalidState),
+            ("InvalidCiphertextException", KmsFailure::InvalidCiphertext),
+            ("IncorrectKeyException", KmsFailure::InvalidCiphertext),
+            ("ThrottlingException", KmsFailure::Other),
+        ] {
+            assert_eq!(KmsFailure::classify(&service_error(code)), reason, "{code}");
         }
-    })
+        let timeout: SdkError<DecryptError, HttpResponse> = SdkError::timeout_error("timed out");
+        assert_eq!(KmsFailure::classify(&timeout), KmsFailure::Other);
+
+        // The reason survives further context and leads the message.
+        let err = anyhow::Error::new(service_error("AccessDeniedException"))
+            .context(KmsFailure::AccessDenied)
+            .context("failed to fetch DEK share arn:a");
+        assert_eq!(
+            err.downcast_ref::<KmsFailure>(),
+            Some(&KmsFailure::AccessDenied)
+        );
+        assert!(
+            format!("{err:#}").contains("check the key policy"),
+            "{err:#}"
+        );
+        assert_eq!(KmsFailure::InvalidCiphertext.as_str(), "invalid_ciphertext");
+    }
+
+    /// Answers Secrets Manager with a fixed binary secret and KMS with
+    /// `decrypt_response`, recording each KMS request body.
+    #[derive(Clone, Debug)]
+    struct FakeAws {
+        decrypt_response: &'static str,
+        kms_bodies: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl aws_smithy_runtime_api::client::http::HttpConnector for FakeAws {
+        fn call(
+            &self,
+            request: aws_smithy_runtime_api::client::orchestrator::HttpRequest,
+        ) -> aws_smithy_runtime_api::client::http::HttpConnectorFuture {
+            use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+
+            let target = request.headers().get("x-amz-target").unwrap_or_default();
+            let body = if target.starts_with("TrentService.") {
+                let sent = request.body().bytes().unwrap_or_default();
+                self.kms_bodies
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(sent).into_owned());
+                self.decrypt_response.to_string()
+            } else {
+                format!(r#"{{"SecretBinary":"{}"}}"#, STANDARD.encode(b"wrapped"))
+            };
+            let response = HttpResponse::new(200.try_into().unwrap(), body.into());
+            aws_smithy_runtime_api::client::http::HttpConnectorFuture::ready(Ok(response))
+        }
+    }
+
+    impl aws_smithy_runtime_api::client::http::HttpClient for FakeAws {
+        fn http_connector(
+            &self,
+            _settings: &aws_smithy_runtime_api::client::http::HttpConnectorSettings,
+            _components: &aws_smithy_runtime_api::client::runtime_components::RuntimeComponents,
+        ) -> aws_smithy_runtime_api::client::http::SharedHttpConnector {
+            aws_smithy_runtime_api::client::http::SharedHttpConnector::new(self.clone())
+        }
+    }
+
+    /// "Opens" recipient ciphertext by reversing it.
+    struct Reversing;
+
+    impl recipient::KmsRecipient for Reversing {
+        fn attestation_document(&self) -> Result<Vec<u8>> {
+            Ok(b"attestation-document".to_vec())
+        }
+
+        fn open(&self, ciphertext_for_recipient: &[u8]) -> Result<Vec<u8>> {
+            Ok(ciphertext_for_recipient.iter().rev().copied().collect())
+        }
+    }
+
+    async fn fake_clients(fake: &FakeAws, attest: bool) -> AwsClients {
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_config::Region::new("us-east-2"))
+            .credentials_provider(aws_sdk_kms::config::Credentials::new(
+                "AKID", "secret", None, None, "test",
+            ))
+            .retry_config(aws_config::retry::RetryConfig::disabled())
+            .http_client(aws_smithy_runtime_api::client::http::SharedHttpClient::new(
+                fake.clone(),
+            ))
+            .load()
+            .await;
+        AwsClients {
+            kms: aws_sdk_kms::Client::new(&config),
+            secretsmanager: aws_sdk_secretsmanager::Client::new(&config),
+            s3: aws_sdk_s3::Client::new(&config),
+            kms_recipient: attest.then(|| Arc::new(Reversing) as Arc<dyn recipient::KmsRecipient>),
+        }
+    }
+
+    #[tokio::test]
+    async fn recipient_attestation_is_sent_with_the_kms_decrypt() {
+        let cfg = Config {
+            kms_recipient_attestation: true,
+            ..crate::config::tests::valid_config()
+        };
+        let fake = FakeAws {
+            decrypt_response: r#"{"KeyId":"key","CiphertextForRecipient":"a2Vk"}"#,
+            kms_bodies: Arc::default(),
+        };
+        let aws = fake_clients(&fake, true).await;
+
+        let plaintext = fetch_share(&aws, &cfg, "arn").await.unwrap();
+        assert_eq!(plaintext, b"dek");
+
+        let bodies = fake.kms_bodies.lock().unwrap().clone();
+        assert_eq!(bodies.len(), 1, "{bodies:?}");
+        let sent: serde_json::Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert_eq!(
+            sent["Recipient"]["AttestationDocument"],
+            STANDARD.encode(b"attestation-document")
+        );
+        assert_eq!(
+            sent["Recipient"]["KeyEncryptionAlgorithm"],
+            "RSAES_OAEP_SHA_256"
+        );
+    }
+
+    #[tokio::test]
+    async fn plaintext_kms_response_is_refused_under_recipient_attestation() {
+        let fake = FakeAws {
+            decrypt_response: r#"{"KeyId":"key","Plaintext":"ZGVr"}"#,
+            kms_bodies: Arc::default(),
+        };
+
+        // Without the flag the decrypt carries no recipient and the plaintext
+        // is used.
+        let cfg = crate::config::tests::valid_config();
+        let aws = fake_clients(&fake, true).await;
+        assert_eq!(fetch_share(&aws, &cfg, "arn").await.unwrap(), b"dek");
+        let sent = fake.kms_bodies.lock().unwrap().pop().unwrap();
+        assert!(!sent.contains("Recipient"), "{sent}");
+
+        let cfg = Config {
+            kms_recipient_attestation: true,
+            ..cfg
+        };
+        let err = fetch_share(&aws, &cfg, "arn").await.unwrap_err();
+        assert!(err.to_string().contains("despite recipient"), "{err}");
+
+        // The flag without a recipient fails before KMS is called.
+        let aws = fake_clients(&fake, false).await;
+        assert!(fetch_share(&aws, &cfg, "arn").await.is_err());
+        assert_eq!(fake.kms_bodies.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn shares_combine_by_xor() {
+        let a: Vec<u8> = (0..32).collect();
+        let b: Vec<u8> = (0..32).map(|i| 0xA5 ^ i).collect();
+        let key = combine_shares(&["a", "b"], &[a.clone(), b]).unwrap();
+        assert_eq!(*key.0, [0xA5u8; KEY_LEN]);
+
+        // Three shares: any two leave the third's contribution in.
+        let c = vec![0x0Fu8; 32];
+        let key = combine_shares(&["a", "b", "c"], &[a.clone(), a.clone(), c]).unwrap();
+        assert_eq!(*key.0, [0x0Fu8; KEY_LEN]);
+    }
+
+    #[test]
+    fn combine_rejects_short_and_cancelling_shares() {
+        let a = vec![7u8; 32];
+        let err = combine_shares(&["a", "short"], &[a.clone(), vec![1u8; 16]]).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("short") && msg.contains("16 bytes"), "{msg}");
+
+        let err = combine_shares(&["a", "b"], &[a.clone(), a]).unwrap_err();
+        assert!(err.to_string().contains("all-zero"), "{err}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rotation_keeps_the_key_on_failure_and_backs_off_when_the_breaker_opens() {
+        use std::collections::VecDeque;
+        use std::sync::Mutex;
+
+        let store = DekStore::new();
+        store.store(&[1u8; 32]).await.unwrap();
+        let metrics = Arc::new(Metrics::new(&opentelemetry::global::meter("test")));
+        let start = time::Instant::now();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        // `None` is a failed KMS round trip, `Some(b)` a fresh key of bytes `b`.
+        let outcomes = Arc::new(Mutex::new(VecDeque::from([
+            None,
+            None,
+            Some(2u8),
+            Some(3u8),
+        ])));
+
+        let interval = Duration::from_secs(60);
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(300));
+        let task = {
+            let (store, attempts) = (store.clone(), attempts.clone());
+            tokio::spawn(rotation_loop(
+                interval,
+                breaker,
+                metrics.clone(),
+                Heartbeat::new("dek_rotation"),
+                move || {
+                    attempts.lock().unwrap().push(start.elapsed().as_secs());
+                    let outcome = outcomes.lock().unwrap().pop_front().flatten();
+                    let store = store.clone();
+                    async move {
+                        let byte = outcome.context("KMS unavailable")?;
+                        store.store(&[byte; 32]).await?;
+                        Ok(())
+                    }
+                },
+            ))
+        };
+
+        // Two failures: the seeded key stays and the breaker opens.
+        time::sleep(Duration::from_secs(121)).await;
+        assert_eq!(*attempts.lock().unwrap(), [60, 120]);
+        assert_eq!(store.generation(), 1);
+        assert_eq!(
+            metrics.kms_breaker_state.load(Ordering::Relaxed),
+            BreakerState::Open.as_metric()
+        );
+
+        // Nothing is attempted during the open delay; the probe then succeeds
+        // and the normal interval resumes.
+        time::sleep(Duration::from_secs(298)).await;
+        assert_eq!(attempts.lock().unwrap().len(), 2);
+        time::sleep(Duration::from_secs(62)).await;
+        assert_eq!(*attempts.lock().unwrap(), [60, 120, 420, 480]);
+        assert_eq!(store.generation(), 3);
+        assert_eq!(
+            metrics.kms_breaker_state.load(Ordering::Relaxed),
+            BreakerState::Closed.as_metric()
+        );
+        task.abort();
+    }
+
+    /// Hands out keys of the queued bytes in turn; `None` fails a fetch.
+    struct MemoryProvider(std::sync::Mutex<std::collections::VecDeque<Option<u8>>>);
+
+    impl DekProvider for MemoryProvider {
+        async fn fetch(&self) -> Result<DekBytes> {
+            let byte = self.0.lock().unwrap().pop_front().flatten();
+            let byte = byte.context("key backend unavailable")?;
+            Ok(DekBytes(Box::new([byte; KEY_LEN])))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rotation_task_rotates_keys_from_any_provider() {
+        let provider = MemoryProvider(std::sync::Mutex::new([Some(1), None, Some(2)].into()));
+        let store = DekStore::new();
+        fetch_and_store(&provider, &store).await.unwrap();
+        assert_eq!(*store.current().await.unwrap().0, [1u8; KEY_LEN]);
+
+        let cfg = Config {
+            dek_rotation_interval_secs: 60,
+            ..crate::config::tests::valid_config()
+        };
+        let metrics = Arc::new(Metrics::new(&opentelemetry::global::meter("test")));
+        let task = rotation_task(provider, &cfg, store.clone(), metrics);
+
+        // A failed fetch keeps the current key; the next one replaces it.
+        time::sleep(Duration::from_secs(61)).await;
+        assert_eq!(store.generation(), 1);
+        assert_eq!(*store.current().await.unwrap().0, [1u8; KEY_LEN]);
+        time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(store.generation(), 2);
+        assert_eq!(*store.current().await.unwrap().0, [2u8; KEY_LEN]);
+        task.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panicked_rotation_stops_the_heartbeat() {
+        let metrics = Arc::new(Metrics::new(&opentelemetry::global::meter("test")));
+        let heartbeat = metrics.task_heartbeats.register("dek_rotation");
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(300));
+        let task = tokio::spawn(rotation_loop(
+            Duration::from_secs(60),
+            breaker,
+            metrics.clone(),
+            heartbeat,
+            || async { panic!("rotation bug") },
+        ));
+
+        time::sleep(Duration::from_secs(61)).await;
+        assert!(task.await.unwrap_err().is_panic());
+        // Alive until a whole interval has passed without the next beat.
+        assert!(metrics
+            .task_heartbeats
+            .stalled(time::Instant::now())
+            .is_empty());
+        time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(
+            metrics.task_heartbeats.stalled(time::Instant::now()),
+            ["dek_rotation"]
+        );
+    }
 }
@@ -0,0 +1,26 @@
+//! [`DekProvider`]: the source [`fetch_and_store`](super::fetch_and_store) and
+//! [`rotation_task`](super::rotation_task) obtain the DEK from.
+//!
+//! Production uses [`SecretsManagerKms`](super::SecretsManagerKms). Other key
+//! backends, and tests that run without AWS, implement the trait themselves.
+
+use std::future::Future;
+
+use anyhow::Result;
+
+use super::store::DekBytes;
+
+/// A source of plaintext DEKs.
+pub trait DekProvider: Send + Sync {
+    /// Fetch the current DEK.
+    ///
+    /// Called once at startup and again on every rotation. The key is
+    /// returned in a [`DekBytes`], which zeroes it once the store has copied
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key cannot be obtained; the store keeps its
+    /// previous key.
+    fn fetch(&self) -> impl Future<Output = Result<DekBytes>> + Send;
+}
@@ -1,8 +1,10 @@
 //! [`DekStore`]: thread-safe cache for the decrypted Data Encryption Key.
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::futures::Notified;
+use tokio::sync::{Notify, RwLock};
 
 use crate::crypto::KEY_LEN;
 
@@ -16,6 +18,15 @@ pub enum DekError {
     /// The decrypted key material has an unexpected length.
     #[error("DEK has invalid length: expected {KEY_LEN} bytes, got {0}")]
     InvalidLength(usize),
+
+    /// The DEK was rotated after a caller pinned a generation.
+    #[error("DEK rotated during operation: pinned generation {pinned}, current {current}")]
+    GenerationChanged {
+        /// Generation the caller pinned.
+        pinned: u64,
+        /// Generation now in the store.
+        current: u64,
+    },
 }
 
 /// Fixed-size key buffer that holds exactly [`KEY_LEN`] bytes.
@@ -33,6 +44,20 @@ impl Drop for DekBytes {
     }
 }
 
+impl TryFrom<&[u8]> for DekBytes {
+    type Error = DekError;
+
+    /// Copy `key_bytes`, which must be exactly [`KEY_LEN`] bytes.
+    fn try_from(key_bytes: &[u8]) -> Result<Self, DekError> {
+        if key_bytes.len() != KEY_LEN {
+            return Err(DekError::InvalidLength(key_bytes.len()));
+        }
+        let mut buf = Box::new([0u8; KEY_LEN]);
+        buf.copy_from_slice(key_bytes);
+        Ok(Self(buf))
+    }
+}
+
 impl std::fmt::Debug for DekBytes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Never print key material — not even in debug builds.
@@ -40,6 +65,19 @@ impl std::fmt::Debug for DekBytes {
     }
 }
 
+/// A DEK together with the store generation it was read at.
+///
+/// Obtained from [`DekStore::pinned`]; pass `generation` to
+/// [`DekStore::ensure_generation`] at the end of a multi-field operation to
+/// confirm that no rotation happened while it ran.
+#[derive(Clone, Debug)]
+pub struct PinnedDek {
+    /// Store generation the key was read at. Starts at 1 for the first key.
+    pub generation: u64,
+    /// The key bytes.
+    pub key: DekBytes,
+}
+
 /// Thread-safe store for the current Data Encryption Key.
 ///
 /// Wraps an `Arc<RwLock<Option<DekBytes>>>` so that:
@@ -47,9 +85,17 @@ impl std::fmt::Debug for DekBytes {
 ///   simultaneously without contention.
 /// - A single write-lock holder (the background rotation task) can atomically
 ///   swap in a new key without blocking readers for more than a microsecond.
+///
+/// Every [`store`](Self::store) of a new key bumps a generation counter so that callers can
+/// pin one key for the duration of a logical batch and detect a mid-batch swap.
 #[derive(Clone, Debug)]
 pub struct DekStore {
     inner: Arc<RwLock<Option<DekBytes>>>,
+    /// Incremented under the write lock whenever the key changes; `0` means
+    /// no key yet.
+    generation: Arc<AtomicU64>,
+    /// Woken after every store, for callers waiting on the first key.
+    stored: Arc<Notify>,
 }
 
 impl DekStore {
@@ -57,6 +103,8 @@ impl DekStore {
     pub fn new() -> Self {
         Self {
             inner: Arc::new(RwLock::new(None)),
+            generation: Arc::new(AtomicU64::new(0)),
+            stored: Arc::new(Notify::new()),
         }
     }
 
@@ -68,6 +116,9 @@ impl DekStore {
     /// Store (or replace) the current DEK.
     ///
     /// The provided `key_bytes` slice must be exactly [`KEY_LEN`] bytes.
+    /// Storing the key already held (a rotation that fetched an unchanged
+    /// DEK) leaves the generation alone, so pinned callers are not failed
+    /// for a swap that changed nothing.
     ///
     /// # Errors
     ///
@@ -79,10 +130,27 @@ impl DekStore {
         let mut buf = Box::new([0u8; KEY_LEN]);
         buf.copy_from_slice(key_bytes);
         let mut lock = self.inner.write().await;
-        *lock = Some(DekBytes(buf));
+        if lock.as_ref().is_none_or(|current| current.0 != buf) {
+            *lock = Some(DekBytes(buf));
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+        drop(lock);
+        self.stored.notify_waiters();
         Ok(())
     }
 
+    /// Completes at the next [`store`](Self::store). Enable the future before
+    /// checking [`is_ready`](Self::is_ready) so a store in between is not
+    /// missed.
+    pub fn stored(&self) -> Notified<'_> {
+        self.stored.notified()
+    }
+
+    /// Current key generation (`0` until the first key is stored).
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
     /// Borrow a clone of the current DEK bytes.
     ///
     /// The clone is a short-lived copy; callers should use and drop it promptly.
@@ -94,6 +162,38 @@ impl DekStore {
         let lock = self.inner.read().await;
         lock.as_ref().cloned().ok_or(DekError::NotInitialised)
     }
+
+    /// Borrow a clone of the current DEK together with its generation.
+    ///
+    /// The generation is read under the same read lock as the key, so the pair
+    /// is always consistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DekError::NotInitialised`] if no DEK has been stored yet.
+    pub async fn pinned(&self) -> Result<PinnedDek, DekError> {
+        let lock = self.inner.read().await;
+        let key = lock.as_ref().cloned().ok_or(DekError::NotInitialised)?;
+        Ok(PinnedDek {
+            generation: self.generation(),
+            key,
+        })
+    }
+
+    /// Confirm that the store still holds the key generation `pinned`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DekError::GenerationChanged`] if a rotation happened since the
+    /// generation was pinned.
+    pub fn ensure_generation(&self, pinned: u64) -> Result<(), DekError> {
+        let current = self.generation();
+        if current == pinned {
+            Ok(())
+        } else {
+            Err(DekError::GenerationChanged { pinned, current })
+        }
+    }
 }
 
 impl Default for DekStore {
@@ -141,6 +241,55 @@ mod tests {
         assert_eq!(&current.0[..], key2.as_slice());
     }
 
+    #[tokio::test]
+    async fn generation_increments_on_store() {
+        let store = DekStore::new();
+        assert_eq!(store.generation(), 0);
+        store.store(&[0x01u8; KEY_LEN]).await.unwrap();
+        assert_eq!(store.generation(), 1);
+        store.store(&[0x02u8; KEY_LEN]).await.unwrap();
+        assert_eq!(store.generation(), 2);
+        // A rejected key does not bump the generation.
+        assert!(store.store(&[0u8; 16]).await.is_err());
+        assert_eq!(store.generation(), 2);
+    }
+
+    #[tokio::test]
+    async fn storing_the_same_key_keeps_the_generation() {
+        let store = DekStore::new();
+        store.store(&[0x01u8; KEY_LEN]).await.unwrap();
+        let pinned = store.pinned().await.unwrap();
+        store.store(&[0x01u8; KEY_LEN]).await.unwrap();
+        assert_eq!(store.generation(), 1);
+        assert!(store.ensure_generation(pinned.generation).is_ok());
+    }
+
+    #[tokio::test]
+    async fn pinned_generation_survives_without_rotation() {
+        let store = DekStore::new();
+        assert!(store.pinned().await.is_err());
+        store.store(&[0x01u8; KEY_LEN]).await.unwrap();
+        let pinned = store.pinned().await.unwrap();
+        assert_eq!(pinned.generation, 1);
+        assert_eq!(&pinned.key.0[..], &[0x01u8; KEY_LEN]);
+        assert!(store.ensure_generation(pinned.generation).is_ok());
+    }
+
+    #[tokio::test]
+    async fn pinned_generation_detects_rotation() {
+        let store = DekStore::new();
+        store.store(&[0x01u8; KEY_LEN]).await.unwrap();
+        let pinned = store.pinned().await.unwrap();
+        store.store(&[0x02u8; KEY_LEN]).await.unwrap();
+        assert!(matches!(
+            store.ensure_generation(pinned.generation),
+            Err(DekError::GenerationChanged {
+                pinned: 1,
+                current: 2
+            })
+        ));
+    }
+
     #[test]
     fn dek_bytes_redacted_in_debug() {
         let mut buf = Box::new([0u8; KEY_LEN]);
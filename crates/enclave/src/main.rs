@@ -5,10 +5,24 @@
 //! 2. Start the IMDS vsock bridge (TCP 127.0.0.1:8004 → vsock(parent,8004)).
 //! 3. Initialise the telemetry pipeline (OTEL + tracing).
 //! 4. Initialise AWS SDK clients pointing at the vsock proxy.
-//! 5. Fetch + decrypt the DEK from Secrets Manager / KMS and seed [`DekStore`].
-//! 6. Load OpenAPI schemas from S3 into [`SchemaCache`].
+//! 5. Fetch + decrypt the DEK from Secrets Manager / KMS and seed [`DekStore`],
+//!    then the token key when `TOKEN_KEY_SECRET_ARN` is set (each bounded by
+//!    `STARTUP_DEK_TIMEOUT_SECS`).
+//! 6. Load OpenAPI schemas from S3 into [`SchemaCache`]
+//!    (bounded by `STARTUP_SCHEMA_TIMEOUT_SECS`).
 //! 7. Spawn background tasks: DEK rotation, schema refresh.
-//! 8. Build the Axum router and start the TLS server.
+//! 8. Emit the boot manifest (see [`telemetry::manifest`]), build the Axum
+//!    router and start the TLS server (two routers on two ports
+//!    when `READ_ONLY_PORT` is set).
+//!
+//! Steps 4–6 and the TLS build in step 8 are timed; see
+//! [`telemetry::startup`].
+//!
+//! `enclave validate-schemas <dir>` instead validates a local directory of
+//! schema files offline and exits, `enclave diff-schemas <old> <new>`
+//! prints the PII paths added or removed between two schema versions, and
+//! `enclave explain-schemas <file>` prints why each PII path was selected;
+//! see [`schema::validate`].
 
 mod aws;
 mod config;
@@ -19,19 +33,24 @@ mod server;
 mod telemetry;
 
 use anyhow::{Context, Result};
+use axum::Router;
+use common::protocol::EncryptionSettings;
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use tokio::net::TcpListener;
 use tokio_rustls::TlsAcceptor;
 use tokio_vsock::{VsockAddr, VsockListener, VsockStream, VMADDR_CID_ANY};
 use tower::ServiceExt as _;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
+use std::future::Future;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
 use config::Config;
 use dek::DekStore;
 use schema::SchemaCache;
-use server::state::AppState;
+use server::state::{AppState, ServerSettings};
 use telemetry::Metrics;
 
 /// Spawn a background task that bridges TCP 127.0.0.1:4317 → vsock(parent_cid, 4317).
@@ -202,8 +221,111 @@ async fn start_imds_bridge(parent_cid: u32) -> Result<()> {
     Ok(())
 }
 
+/// Await a startup phase, failing with a descriptive error if it does not
+/// complete within `timeout`.
+///
+/// Used to bound the AWS calls made during boot so that a wedged vsock proxy
+/// fails startup quickly instead of hanging indefinitely.
+async fn with_startup_timeout<T>(
+    phase: &str,
+    timeout: Duration,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(res) => res,
+        Err(_) => anyhow::bail!("{phase} timed out after {}s", timeout.as_secs_f64()),
+    }
+}
+
+/// Read the TLS certificate, key and optional client CA named by `files` and
+/// build the server configuration of one listener.
+fn load_tls_config(
+    files: &server::tls::TlsFiles,
+    options: &server::tls::TlsOptions,
+) -> Result<Arc<rustls::ServerConfig>> {
+    let cert_pem = std::fs::read(&files.cert_path)
+        .with_context(|| format!("failed to read TLS cert: {}", files.cert_path))?;
+    let key_pem = std::fs::read(&files.key_path)
+        .with_context(|| format!("failed to read TLS key: {}", files.key_path))?;
+    let client_ca_pem = files
+        .client_ca_path
+        .as_ref()
+        .map(|path| {
+            std::fs::read(path).with_context(|| format!("failed to read TLS client CA: {path}"))
+        })
+        .transpose()?;
+    server::tls::build_server_config(&cert_pem, &key_pem, client_ca_pem.as_deref(), options)
+}
+
+/// Startup failure categories, each reported with its own process exit code so
+/// orchestration can tell them apart from each other and from crashes
+/// (`101`, a panic) or other errors (`1`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+enum StartupFailure {
+    /// Configuration is missing or invalid (exit code 10).
+    #[error("configuration invalid")]
+    Config,
+    /// The DEK could not be fetched, decrypted or stored (exit code 11).
+    #[error("DEK fetch failed")]
+    Dek,
+    /// Strict schema loading found no schemas (exit code 12).
+    #[error("no schemas found")]
+    NoSchemas,
+    /// The TLS certificate, key or client CA could not be loaded (exit code 13).
+    #[error("TLS load failed")]
+    Tls,
+}
+
+impl StartupFailure {
+    /// The process exit code for this failure.
+    fn exit_code(self) -> u8 {
+        match self {
+            StartupFailure::Config => 10,
+            StartupFailure::Dek => 11,
+            StartupFailure::NoSchemas => 12,
+            StartupFailure::Tls => 13,
+        }
+    }
+}
+
+/// Exit code for an error returned by [`run`]: the code of the
+/// [`StartupFailure`] attached to it, or `1` for anything else.
+fn exit_code(err: &anyhow::Error) -> u8 {
+    err.downcast_ref::<StartupFailure>()
+        .map_or(1, |failure| failure.exit_code())
+}
+
+/// The server settings derived from `cfg`. A value that does not parse (an
+/// allowlist, a mask rule, a status code) is a configuration failure like any
+/// rejected by [`Config::from_env`].
+fn server_settings(cfg: &Config) -> Result<ServerSettings> {
+    ServerSettings::from_config(cfg).context(StartupFailure::Config)
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ERROR: {e:?}");
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+async fn run() -> Result<()> {
+    // Offline subcommand: no AWS, DEK, configuration, or listeners required.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("validate-schemas") {
+        return schema::validate::run_cli(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some("diff-schemas") {
+        return schema::validate::run_diff_cli(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some("explain-schemas") {
+        return schema::validate::run_explain_cli(&args[2..]);
+    }
+
     // Install the aws-lc-rs Rustls CryptoProvider as the process default.
     // Both hyper-rustls and opentelemetry-otlp (via tonic) pull in rustls
     // 0.23.x which requires an explicit default when multiple provider features
@@ -215,11 +337,8 @@ async fn main() -> Result<()> {
     // -----------------------------------------------------------------------
     // 1. Configuration
     // -----------------------------------------------------------------------
-    let cfg = Config::from_env().map_err(|e| {
-        // Telemetry is not yet up; write to stderr directly.
-        eprintln!("ERROR: configuration invalid: {e}");
-        e
-    })?;
+    let cfg = Config::from_env().context(StartupFailure::Config)?;
+    let settings = server_settings(&cfg)?;
 
     // -----------------------------------------------------------------------
     // 2. IMDS vsock bridge
@@ -233,7 +352,7 @@ async fn main() -> Result<()> {
     // -----------------------------------------------------------------------
     // Must start before init_telemetry() so the OTEL SDK and log writer can
     // reach the ADOT Collector on the parent EC2 via vsock bridges.
-    //   Port 4317: OTLP/gRPC traces → vsock(cid, 4317)
+    //   Port 4317: OTLP/gRPC traces, metrics and logs → vsock(cid, 4317)
     //   Port 4318: JSON log lines  → vsock(cid, 4318) → tcplog receiver
     start_otlp_bridge(cfg.vsock_proxy_cid).await?;
 
@@ -244,29 +363,98 @@ async fn main() -> Result<()> {
     // -----------------------------------------------------------------------
     // 3. Telemetry
     // -----------------------------------------------------------------------
-    telemetry::init_telemetry(&cfg.otel_exporter_otlp_endpoint, &cfg.log_level, log_writer)?;
+    telemetry::init_telemetry(
+        &cfg.otel_exporter_otlp_endpoint,
+        &cfg.log_level,
+        cfg.otel_logs_enabled,
+        log_writer,
+    )?;
     info!(
         version = env!("CARGO_PKG_VERSION"),
         tls_port = cfg.tls_port,
+        expand_env_vars = cfg.expand_env_vars,
         "nitro-enc-svc starting"
     );
 
     // -----------------------------------------------------------------------
     // 4. AWS clients
     // -----------------------------------------------------------------------
-    let aws = aws::AwsClients::init(cfg.vsock_proxy_cid, cfg.vsock_proxy_port).await?;
+    let mut timings = telemetry::startup::StartupTimings::default();
+    let mut aws = timings
+        .time(
+            "aws_clients",
+            aws::AwsClients::init(
+                cfg.vsock_proxy_cid,
+                cfg.vsock_proxy_port,
+                &cfg.endpoint_overrides(),
+                cfg.vsock_buffers(),
+            ),
+        )
+        .await?;
+    if cfg.kms_recipient_attestation {
+        let recipient = aws::recipient::NsmRecipient::generate().context(StartupFailure::Dek)?;
+        aws.kms_recipient = Some(Arc::new(recipient));
+    }
 
     // -----------------------------------------------------------------------
     // 5. DEK initialisation
     // -----------------------------------------------------------------------
     let dek_store = DekStore::new();
-    dek::fetch_and_store(&aws, &cfg, &dek_store).await?;
+    let dek_provider = dek::SecretsManagerKms::new(aws.clone(), cfg.clone());
+    timings
+        .time(
+            "dek_fetch",
+            with_startup_timeout(
+                "startup DEK fetch",
+                Duration::from_secs(cfg.startup_dek_timeout_secs),
+                dek::fetch_and_store(&dek_provider, &dek_store),
+            ),
+        )
+        .await
+        .context(StartupFailure::Dek)?;
+    let token_key = match &cfg.token_key_secret_arn {
+        Some(arn) => Some(
+            timings
+                .time(
+                    "token_key_fetch",
+                    with_startup_timeout(
+                        "startup token key fetch",
+                        Duration::from_secs(cfg.startup_dek_timeout_secs),
+                        dek::fetch_token_key(&aws, &cfg, arn.trim()),
+                    ),
+                )
+                .await
+                .context(StartupFailure::Dek)?,
+        ),
+        None => None,
+    };
 
     // -----------------------------------------------------------------------
     // 6. Schema cache initialisation
     // -----------------------------------------------------------------------
-    let schema_cache = SchemaCache::new();
-    schema::load_all(&aws, &cfg, &schema_cache).await?;
+    let schema_cache = SchemaCache::new()
+        .with_tombstone_grace(Duration::from_secs(cfg.schema_tombstone_grace_secs))
+        .with_max_schemas(cfg.max_cached_schemas, cfg.schema_load_lenient)
+        .retain_documents(cfg.retain_schema_documents)
+        .with_eager_schemas(cfg.eager_schema_names())
+        .with_quarantine_threshold(cfg.schema_quarantine_threshold);
+    let schema_loader = schema::SchemaLoader::new(aws.clone(), cfg.clone());
+    timings
+        .time(
+            "schema_load",
+            with_startup_timeout(
+                "startup schema load",
+                Duration::from_secs(cfg.startup_schema_timeout_secs),
+                schema_loader.load_all(&schema_cache),
+            ),
+        )
+        .await?;
+    if schema_cache.is_empty() && !cfg.schema_load_lenient {
+        return Err(anyhow::anyhow!(
+            "no schema objects found in any configured S3 source"
+        ))
+        .context(StartupFailure::NoSchemas);
+    }
 
     // -----------------------------------------------------------------------
     // 7. Metrics instruments
@@ -277,23 +465,32 @@ async fn main() -> Result<()> {
     // -----------------------------------------------------------------------
     // 8. Background tasks
     // -----------------------------------------------------------------------
-    let _dek_rotation = dek::rotation_task(
-        aws.clone(),
-        cfg.clone(),
-        dek_store.clone(),
-        metrics.dek_rotations.clone(),
+    let _dek_rotation = dek::rotation_task(dek_provider, &cfg, dek_store.clone(), metrics.clone());
+    let _schema_refresh = schema::refresh_task(
+        schema_loader.clone(),
+        schema_cache.clone(),
+        metrics.task_heartbeats.register("schema_refresh"),
     );
-    let _schema_refresh = schema::refresh_task(aws.clone(), cfg.clone(), schema_cache.clone());
 
     // -----------------------------------------------------------------------
     // 9. TLS configuration (cert + key written by ACM for Nitro Enclaves)
     // -----------------------------------------------------------------------
-    let cert_pem = std::fs::read(&cfg.tls_cert_path)
-        .with_context(|| format!("failed to read TLS cert: {}", cfg.tls_cert_path))?;
-    let key_pem = std::fs::read(&cfg.tls_key_path)
-        .with_context(|| format!("failed to read TLS key: {}", cfg.tls_key_path))?;
-    let tls_cfg = server::tls::build_server_config(&cert_pem, &key_pem)?;
+    // With READ_ONLY_PORT set, each port gets its own config so they can
+    // differ in certificate and client auth.
+    let (tls_cfg, read_only_tls_cfg) = timings
+        .time_sync("tls_config", || -> Result<_> {
+            let options = server::tls::TlsOptions::from_config(&cfg);
+            let primary = load_tls_config(&server::tls::TlsFiles::primary(&cfg), &options)?;
+            let read_only = cfg
+                .read_only_port
+                .map(|_| load_tls_config(&server::tls::TlsFiles::read_only(&cfg), &options))
+                .transpose()?;
+            Ok((primary, read_only))
+        })
+        .context(StartupFailure::Tls)?;
     let tls_acceptor = TlsAcceptor::from(tls_cfg);
+    let read_only_acceptor = read_only_tls_cfg.map(TlsAcceptor::from);
+    timings.report(&metrics.startup_phase_ms);
 
     // -----------------------------------------------------------------------
     // 10. HTTPS server (TLS accept loop)
@@ -303,20 +500,105 @@ async fn main() -> Result<()> {
         schema_cache,
         cfg.schema_header_name.clone(),
         metrics,
-    );
-    let router = server::router::build(state);
+    )
+    .with_settings(settings)
+    .with_encryption(EncryptionSettings {
+        encoding: cfg.ciphertext_encoding,
+    })
+    .with_schema_loader(schema_loader);
+    let state = match token_key {
+        Some(key) => state.with_token_key(key),
+        None => state,
+    };
+    let state = if cfg.audit_queue_capacity > 0 {
+        let (audit, queue) = telemetry::audit::AuditLog::channel(
+            cfg.audit_queue_capacity,
+            state.metrics.audit_events_dropped.clone(),
+        );
+        let _audit_writer = telemetry::audit::writer_task(queue);
+        state.with_audit(audit)
+    } else {
+        state
+    };
+    if cfg.proxy_probe_interval_secs > 0 {
+        // The KMS proxy sits at VSOCK_PROXY_PORT + 1 (see aws::vsock_connector).
+        let _proxy_probe = aws::probe::probe_task(
+            cfg.vsock_proxy_cid,
+            cfg.vsock_proxy_port + 1,
+            Duration::from_secs(cfg.proxy_probe_interval_secs),
+            state.proxy_reachable.clone(),
+        );
+    }
+    // The audit trail's record of what this instance serves with. The NSM is
+    // not queried yet, so PCR values are left out.
+    telemetry::manifest::BootManifest::build(&state.schema_cache, &state.dek_store, None).emit();
 
     // Nitro Enclaves have no external network interface — the only way the
     // vsock-proxy sidecar (on the parent EC2) can reach us is via AF_VSOCK.
     // TCP sockets inside the enclave are not reachable from outside. Binding
     // on VMADDR_CID_ANY (0xFFFFFFFF) accepts connections from any peer CID.
-    info!(port = cfg.tls_port, "listening (TLS, vsock)");
-    let mut listener = VsockListener::bind(VsockAddr::new(VMADDR_CID_ANY, cfg.tls_port as u32))
-        .context("failed to bind vsock TLS listener")?;
+    let mut servers = tokio::task::JoinSet::new();
+    match cfg.read_only_port.zip(read_only_acceptor) {
+        Some((read_only_port, read_only_acceptor)) => {
+            info!(
+                port = cfg.tls_port,
+                read_only_port,
+                "listening (TLS, vsock; read-only and admin routes on a second port)"
+            );
+            servers.spawn(serve(
+                bind_vsock(cfg.tls_port)?,
+                tls_acceptor,
+                state.metrics.clone(),
+                server::router::build_primary(state.clone()),
+            ));
+            servers.spawn(serve(
+                bind_vsock(read_only_port)?,
+                read_only_acceptor,
+                state.metrics.clone(),
+                server::router::build_read_only(state),
+            ));
+        }
+        None => {
+            info!(port = cfg.tls_port, "listening (TLS, vsock)");
+            servers.spawn(serve(
+                bind_vsock(cfg.tls_port)?,
+                tls_acceptor,
+                state.metrics.clone(),
+                server::router::build(state),
+            ));
+        }
+    }
+
+    // Listeners run until accept fails; the first to stop ends the process.
+    match servers.join_next().await {
+        Some(res) => res.context("listener task panicked")?,
+        None => Ok(()),
+    }
+}
+
+/// Bind a vsock listener on `port` for any peer CID.
+fn bind_vsock(port: u16) -> Result<VsockListener> {
+    VsockListener::bind(VsockAddr::new(VMADDR_CID_ANY, u32::from(port)))
+        .with_context(|| format!("failed to bind vsock TLS listener on port {port}"))
+}
 
+/// TLS accept loop: serve `router` on every connection accepted by `listener`,
+/// each on its own task. The negotiated TLS parameters of each connection are
+/// logged at debug level and counted by version in `metrics`.
+///
+/// # Errors
+///
+/// Returns an error when accepting a connection fails.
+async fn serve(
+    mut listener: VsockListener,
+    tls_acceptor: TlsAcceptor,
+    metrics: Arc<Metrics>,
+    router: Router,
+) -> Result<()> {
     loop {
         let (vsock_stream, peer_addr) = listener.accept().await?;
         let acceptor = tls_acceptor.clone();
+        let metrics = Arc::clone(&metrics);
         let router = router.clone();
 
         tokio::spawn(async move {
@@ -328,10 +610,36 @@ async fn main() -> Result<()> {
                 }
             };
 
+            let params = server::tls::ConnectionParams::of(tls_stream.get_ref().1);
+            debug!(
+                peer = %peer_addr,
+                tls_version = params.version,
+                tls_cipher = params.cipher_suite,
+                tls_sni = params.sni.as_deref().unwrap_or("-"),
+                "TLS connection accepted"
+            );
+            metrics.tls_handshakes.add(
+                1,
+                &[opentelemetry::KeyValue::new("version", params.version)],
+            );
+
+            // With mTLS enabled, expose the verified client CN to handlers.
+            let identity = tls_stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|leaf| server::identity::common_name(leaf))
+                .map(server::identity::ClientIdentity);
+
             let io = TokioIo::new(tls_stream);
             let svc =
                 hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
-                    router.clone().oneshot(req.map(axum::body::Body::new))
+                    let mut req = req.map(axum::body::Body::new);
+                    if let Some(id) = &identity {
+                        req.extensions_mut().insert(id.clone());
+                    }
+                    router.clone().oneshot(req)
                 });
 
             if let Err(e) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
@@ -343,3 +651,73 @@ async fn main() -> Result<()> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn startup_timeout_passes_through_fast_result() {
+        let res = with_startup_timeout("fast", Duration::from_secs(5), async { Ok(7) }).await;
+        assert_eq!(res.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn startup_timeout_fails_slow_phase() {
+        let res: Result<()> =
+            with_startup_timeout("slow phase", Duration::from_millis(10), async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await;
+        let err = res.unwrap_err().to_string();
+        assert!(err.contains("slow phase timed out"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn startup_timeout_propagates_inner_error() {
+        let res: Result<()> = with_startup_timeout("failing", Duration::from_secs(5), async {
+            anyhow::bail!("boom")
+        })
+        .await;
+        assert_eq!(res.unwrap_err().to_string(), "boom");
+    }
+
+    #[test]
+    fn startup_failures_map_to_distinct_exit_codes() {
+        let codes = [
+            StartupFailure::Config,
+            StartupFailure::Dek,
+            StartupFailure::NoSchemas,
+            StartupFailure::Tls,
+        ]
+        .map(|failure| exit_code(&anyhow::anyhow!("cause").context(failure)));
+        assert_eq!(codes, [10, 11, 12, 13]);
+
+        // The category survives further context added on top of it.
+        let nested = anyhow::anyhow!("bad cert")
+            .context(StartupFailure::Tls)
+            .context("while starting");
+        assert_eq!(exit_code(&nested), 13);
+        assert_eq!(exit_code(&anyhow::anyhow!("unclassified")), 1);
+    }
+
+    #[test]
+    fn invalid_server_settings_exit_as_config_failures() {
+        let valid = config::tests::valid_config();
+        assert!(server_settings(&valid).is_ok());
+
+        let bad_status = Config {
+            unknown_schema_status: 42,
+            ..valid.clone()
+        };
+        let bad_allowlist = Config {
+            client_schema_allowlist: Some("no-equals-sign".into()),
+            ..valid
+        };
+        for cfg in [bad_status, bad_allowlist] {
+            let err = server_settings(&cfg).unwrap_err();
+            assert_eq!(exit_code(&err), 10, "{err:#}");
+        }
+    }
+}
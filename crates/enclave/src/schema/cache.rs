@@ -2,14 +2,50 @@
 //!
 //! Schemas are loaded at startup and refreshed on a configurable interval.
 //! The cache uses `arc-swap` for lock-free reads on the hot path.
+//!
+//! Optionally, schema names dropped by a refresh are remembered as
+//! *tombstones* for a grace period so that lookups can distinguish "recently
+//! removed" from "never existed".
+//!
+//! Each entry remembers the S3 source it was loaded from, so a single source
+//! can be reloaded with [`SchemaCache::replace_source`] without touching the
+//! others.
+//!
+//! The number of cached schemas can be capped, and the parsed documents need
+//! not be kept once their PII paths are derived; see
+//! [`SchemaCache::with_max_schemas`] and [`SchemaCache::retain_documents`].
+//!
+//! Objects that a lenient load skips because they fail to parse are counted
+//! across loads; one that fails on enough consecutive loads is *quarantined*
+//! (see [`SchemaCache::record_parse_failures`]) so operators get a clear
+//! "fix this file" signal.
+//!
+//! Resolving a schema into PII paths can be deferred: with
+//! [`SchemaCache::with_eager_schemas`], only the listed schemas are resolved
+//! when installed and the rest on their first [`get`](SchemaCache::get).
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, PoisonError,
+    },
+    time::{Duration, Instant},
+};
 
 use arc_swap::ArcSwap;
+use common::protocol::QuarantinedSchema;
 use openapiv3::OpenAPI;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+use tracing::warn;
 
-use super::resolver::{resolve_pii_paths, PiiFieldPaths};
+use super::resolver::{
+    resolve_schema, EmbeddedJsonPaths, EncryptionAlg, PiiCategories, PiiConditions, PiiFieldPaths,
+    PiiMaxLengths, RootKind,
+};
 
 /// Errors from the schema cache.
 #[derive(Debug, Error)]
@@ -17,16 +53,298 @@ pub enum CacheError {
     /// The requested schema name has no entry in the cache.
     #[error("unknown schema: {0}")]
     UnknownSchema(String),
+
+    /// The schema was removed by a recent refresh and is within the tombstone grace period.
+    #[error("schema removed: {0}")]
+    RemovedSchema(String),
+}
+
+/// A source reload would define a schema name another source already owns.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("schema name {name:?} is already defined by source {existing}")]
+pub struct MergeConflict {
+    /// The contested schema name.
+    pub name: String,
+    /// The source currently holding the name (`unattributed` if none).
+    pub existing: String,
+}
+
+/// A load would cache more schemas than [`SchemaCache::with_max_schemas`] allows.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{count} schemas exceed the limit of {limit} cached schemas")]
+pub struct TooManySchemas {
+    /// Schemas the cache would hold after the load.
+    pub count: usize,
+    /// The configured limit.
+    pub limit: usize,
+}
+
+/// A schema document could not be serialised to compute its fingerprint.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("failed to fingerprint schema {name:?}: {error}")]
+pub struct FingerprintError {
+    /// The schema name.
+    pub name: String,
+    /// Why serialisation failed.
+    pub error: String,
+}
+
+/// Why [`SchemaCache::replace_all_sourced`] or [`SchemaCache::replace_source`]
+/// left the cache unchanged.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ReplaceError {
+    /// The source defines a name another source owns.
+    #[error(transparent)]
+    Conflict(#[from] MergeConflict),
+    /// The merged cache would exceed the schema limit.
+    #[error(transparent)]
+    TooManySchemas(#[from] TooManySchemas),
+    /// A schema could not be fingerprinted.
+    #[error(transparent)]
+    Fingerprint(#[from] FingerprintError),
+}
+
+/// A parsed schema document and the name of the source it was loaded from.
+#[derive(Debug, Clone)]
+pub struct SourcedSchema {
+    /// Source name, see [`SchemaSource::name`](crate::config::SchemaSource::name).
+    pub source: String,
+    /// The parsed document.
+    pub api: OpenAPI,
 }
 
 /// A single cached entry: the parsed API document and its derived PII paths.
 #[derive(Debug, Clone)]
 pub struct CachedSchema {
-    /// The parsed OpenAPI document. Retained for future validation / debugging.
+    /// The parsed OpenAPI document, for future validation / debugging; `None`
+    /// when the cache does not [retain documents](SchemaCache::retain_documents)
+    /// or for inline schemas.
     #[allow(dead_code)]
-    pub api: Arc<OpenAPI>,
+    pub api: Option<Arc<OpenAPI>>,
     /// Pre-computed set of dot-notation paths that are marked `x-pii: true`.
     pub pii_paths: Arc<PiiFieldPaths>,
+    /// String fields holding double-encoded JSON (`x-pii-json: true`) and the
+    /// PII paths of their embedded documents.
+    pub embedded_json: Arc<EmbeddedJsonPaths>,
+    /// Sibling conditions (`x-pii-when`) for conditionally-PII paths.
+    pub conditions: Arc<PiiConditions>,
+    /// Schema-declared `maxLength` of PII string fields.
+    pub max_lengths: Arc<PiiMaxLengths>,
+    /// `x-pii-category` of PII fields that declare one.
+    pub categories: Arc<PiiCategories>,
+    /// PII paths hashed irreversibly (`x-pii-mode: hash`) instead of encrypted.
+    pub hashed: Arc<PiiFieldPaths>,
+    /// PII paths that also get a lookup tag (`x-pii-mode: lookup`).
+    pub lookup: Arc<PiiFieldPaths>,
+    /// PII paths replaced with format-preserving tokens (`x-pii-mode: fpe`).
+    pub tokenized: Arc<PiiFieldPaths>,
+    /// PII paths typed `integer`/`number`, whose number values are encrypted
+    /// from their exact JSON token and restored as numbers on decrypt.
+    pub numeric: Arc<PiiFieldPaths>,
+    /// PII and embedded-JSON paths left out by [`scoped`](Self::scoped),
+    /// which a payload must not fill in.
+    pub out_of_scope: Arc<PiiFieldPaths>,
+    /// Expected payload root kind, if the schema constrains it.
+    pub root: Option<RootKind>,
+    /// Property names the schema declares at the payload root, if any.
+    pub top_level_keys: Option<Arc<HashSet<String>>>,
+    /// Algorithm `/encrypt` uses for this schema (`x-encryption-alg`).
+    pub algorithm: EncryptionAlg,
+    /// Hex-encoded SHA-256 of the canonical JSON serialisation of `api`.
+    /// Non-sensitive; lets clients detect that a schema changed between calls.
+    pub fingerprint: Arc<str>,
+    /// The source the schema was loaded from; `None` when installed through
+    /// [`SchemaCache::replace_all`] without source information.
+    pub source: Option<Arc<str>>,
+}
+
+impl CachedSchema {
+    /// An entry for caller-supplied inline PII paths (`/encrypt` with
+    /// `pii_paths`). It is never stored in the cache and carries no OpenAPI
+    /// document, annotations, or root constraint.
+    pub fn inline(pii_paths: PiiFieldPaths) -> Self {
+        Self {
+            api: None,
+            pii_paths: Arc::new(pii_paths),
+            embedded_json: Arc::default(),
+            conditions: Arc::default(),
+            max_lengths: Arc::default(),
+            categories: Arc::default(),
+            hashed: Arc::default(),
+            lookup: Arc::default(),
+            tokenized: Arc::default(),
+            numeric: Arc::default(),
+            out_of_scope: Arc::default(),
+            root: None,
+            top_level_keys: None,
+            algorithm: EncryptionAlg::default(),
+            fingerprint: "inline".into(),
+            source: None,
+        }
+    }
+
+    /// The entry for the reserved no-op schema (`NOOP_SCHEMA_NAME`): no PII
+    /// paths and no constraints, so payloads pass through unchanged. It is
+    /// never stored in the cache.
+    pub fn noop() -> Self {
+        Self {
+            fingerprint: "noop".into(),
+            ..Self::inline(PiiFieldPaths::new())
+        }
+    }
+
+    /// This entry restricted to the PII, lookup and embedded-JSON paths that
+    /// lie under one of `prefixes` (see [`path_in_scope`]); the others are
+    /// recorded in [`out_of_scope`](Self::out_of_scope). Maps consulted per
+    /// path (conditions, lengths, modes) are shared unchanged.
+    pub fn scoped(&self, prefixes: &[&str]) -> Self {
+        let in_scope = |path: &String| prefixes.iter().any(|p| path_in_scope(path, p));
+        let filter = |paths: &PiiFieldPaths| -> Arc<PiiFieldPaths> {
+            Arc::new(paths.iter().filter(|p| in_scope(p)).cloned().collect())
+        };
+        let out_of_scope = self
+            .pii_paths
+            .iter()
+            .chain(self.embedded_json.keys())
+            .filter(|p| !in_scope(p))
+            .chain(self.out_of_scope.iter())
+            .cloned()
+            .collect();
+        Self {
+            out_of_scope: Arc::new(out_of_scope),
+            pii_paths: filter(&self.pii_paths),
+            lookup: filter(&self.lookup),
+            embedded_json: Arc::new(
+                self.embedded_json
+                    .iter()
+                    .filter(|(path, _)| in_scope(path))
+                    .map(|(path, inner)| (path.clone(), inner.clone()))
+                    .collect(),
+            ),
+            ..self.clone()
+        }
+    }
+
+    /// Resolve `api`, whose fingerprint is `fingerprint`, into a cache entry
+    /// attributed to `source`, keeping the document itself only when `retain`
+    /// is set.
+    fn resolve(
+        api: OpenAPI,
+        fingerprint: Arc<str>,
+        source: Option<Arc<str>>,
+        retain: bool,
+    ) -> Self {
+        let resolved = resolve_schema(&api);
+        Self {
+            api: retain.then(|| Arc::new(api)),
+            pii_paths: Arc::new(resolved.pii_paths),
+            embedded_json: Arc::new(resolved.embedded_json),
+            conditions: Arc::new(resolved.conditions),
+            max_lengths: Arc::new(resolved.max_lengths),
+            categories: Arc::new(resolved.categories),
+            hashed: Arc::new(resolved.hashed),
+            lookup: Arc::new(resolved.lookup),
+            tokenized: Arc::new(resolved.tokenized),
+            numeric: Arc::new(resolved.numeric),
+            out_of_scope: Arc::default(),
+            root: resolved.root,
+            top_level_keys: resolved.top_level_keys.map(Arc::new),
+            algorithm: resolved.algorithm,
+            fingerprint,
+            source,
+        }
+    }
+}
+
+/// Whether the dot-notation `path` is `prefix` itself or lies below it, e.g.
+/// `debtor.name` and `debtor[].iban` under `debtor`, but not `debtors.name`.
+pub fn path_in_scope(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.') || rest.starts_with("[]"))
+}
+
+/// A slot in the cache map: a schema resolved when installed, or one
+/// resolved on its first lookup.
+#[derive(Debug, Clone)]
+enum Entry {
+    Ready(CachedSchema),
+    Lazy(Arc<LazySchema>),
+}
+
+/// A parsed schema whose resolution waits for its first lookup.
+#[derive(Debug)]
+struct LazySchema {
+    source: Option<Arc<str>>,
+    fingerprint: Arc<str>,
+    retain: bool,
+    /// The document, until resolution takes it.
+    api: Mutex<Option<OpenAPI>>,
+    resolved: OnceLock<CachedSchema>,
+    /// The cache's PII path byte count, which resolution adds to.
+    pii_path_bytes: Arc<AtomicUsize>,
+}
+
+impl Entry {
+    fn source(&self) -> Option<&str> {
+        match self {
+            Self::Ready(entry) => entry.source.as_deref(),
+            Self::Lazy(lazy) => lazy.source.as_deref(),
+        }
+    }
+
+    fn fingerprint(&self) -> &Arc<str> {
+        match self {
+            Self::Ready(entry) => &entry.fingerprint,
+            Self::Lazy(lazy) => &lazy.fingerprint,
+        }
+    }
+
+    /// The resolved schema, if resolution has happened.
+    fn resolved(&self) -> Option<&CachedSchema> {
+        match self {
+            Self::Ready(entry) => Some(entry),
+            Self::Lazy(lazy) => lazy.resolved.get(),
+        }
+    }
+
+    /// The resolved schema, resolving a lazy entry on first use.
+    fn resolve(&self) -> &CachedSchema {
+        match self {
+            Self::Ready(entry) => entry,
+            Self::Lazy(lazy) => lazy.resolved.get_or_init(|| {
+                // Only the first initialiser runs, so the document is still there.
+                let api = lazy
+                    .api
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .take()
+                    .unwrap_or_default();
+                let entry = CachedSchema::resolve(
+                    api,
+                    lazy.fingerprint.clone(),
+                    lazy.source.clone(),
+                    lazy.retain,
+                );
+                lazy.pii_path_bytes
+                    .fetch_add(path_bytes(&entry), Ordering::Relaxed);
+                entry
+            }),
+        }
+    }
+}
+
+/// Summed byte length of `entry`'s PII paths.
+fn path_bytes(entry: &CachedSchema) -> usize {
+    entry.pii_paths.iter().map(String::len).sum()
+}
+
+/// A schema object that a lenient load skipped because it failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFailure {
+    /// `s3://bucket/key` of the object.
+    pub location: String,
+    /// Why it failed to parse.
+    pub error: String,
 }
 
 /// Shared, lock-free cache of schemas keyed by schema name.
@@ -35,17 +353,146 @@ pub struct CachedSchema {
 /// refresh task can atomically swap in a completely new map.
 #[derive(Clone, Debug)]
 pub struct SchemaCache {
-    inner: Arc<ArcSwap<HashMap<String, CachedSchema>>>,
+    inner: Arc<ArcSwap<HashMap<String, Entry>>>,
+    /// Names removed by a refresh, with the time of removal.
+    tombstones: Arc<ArcSwap<HashMap<String, Instant>>>,
+    /// How long a removed name is reported as removed; zero disables tombstones.
+    tombstone_grace: Duration,
+    /// Set by the first [`replace_all`](Self::replace_all).
+    loaded: Arc<AtomicBool>,
+    /// Woken after every install, for callers waiting on the first load.
+    installed: Arc<Notify>,
+    /// Total length of every cached PII path string, updated on each load.
+    pii_path_bytes: Arc<AtomicUsize>,
+    /// When the last [`replace_all`](Self::replace_all) (successful load) ran.
+    last_refreshed: Arc<ArcSwap<Option<Instant>>>,
+    /// Serialises writers, so a source reload never races a full refresh.
+    write_lock: Arc<Mutex<()>>,
+    /// Most schemas the cache may hold; zero means no limit.
+    max_schemas: usize,
+    /// Whether a load over `max_schemas` is truncated rather than refused.
+    truncate_over_limit: bool,
+    /// Whether entries keep their parsed OpenAPI document.
+    retain_documents: bool,
+    /// Schemas resolved when installed; `None` resolves every schema then.
+    eager: Option<Arc<HashSet<String>>>,
+    /// Objects that failed to parse on the latest load, with the number of
+    /// consecutive loads they have failed on.
+    parse_failures: Arc<Mutex<HashMap<String, QuarantinedSchema>>>,
+    /// Consecutive failures after which an object is quarantined; zero
+    /// disables quarantine.
+    quarantine_threshold: u32,
 }
 
 impl SchemaCache {
-    /// Create a new, empty [`SchemaCache`].
+    /// Create a new, empty [`SchemaCache`] with tombstones disabled.
     pub fn new() -> Self {
         Self {
             inner: Arc::new(ArcSwap::new(Arc::new(HashMap::new()))),
+            tombstones: Arc::new(ArcSwap::new(Arc::new(HashMap::new()))),
+            tombstone_grace: Duration::ZERO,
+            loaded: Arc::new(AtomicBool::new(false)),
+            installed: Arc::new(Notify::new()),
+            pii_path_bytes: Arc::new(AtomicUsize::new(0)),
+            last_refreshed: Arc::new(ArcSwap::new(Arc::new(None))),
+            write_lock: Arc::new(Mutex::new(())),
+            max_schemas: 0,
+            truncate_over_limit: false,
+            retain_documents: true,
+            eager: None,
+            parse_failures: Arc::new(Mutex::new(HashMap::new())),
+            quarantine_threshold: 0,
         }
     }
 
+    /// Remember schema names removed by [`replace_all`](Self::replace_all) for
+    /// `grace`, during which [`get`](Self::get) reports
+    /// [`CacheError::RemovedSchema`] instead of [`CacheError::UnknownSchema`].
+    pub fn with_tombstone_grace(mut self, grace: Duration) -> Self {
+        self.tombstone_grace = grace;
+        self
+    }
+
+    /// Cap the cache at `max` schemas (zero: no limit). A load over the cap is
+    /// refused with [`TooManySchemas`], or, with `truncate`, cut down to the
+    /// first `max` names in sorted order with a warning.
+    pub fn with_max_schemas(mut self, max: usize, truncate: bool) -> Self {
+        self.max_schemas = max;
+        self.truncate_over_limit = truncate;
+        self
+    }
+
+    /// Whether entries keep their parsed OpenAPI document (the default).
+    /// Without it only the derived PII paths and annotations are stored,
+    /// which is much smaller for large schemas.
+    pub fn retain_documents(mut self, retain: bool) -> Self {
+        self.retain_documents = retain;
+        self
+    }
+
+    /// Resolve only the schemas named in `eager` when they are installed;
+    /// the rest are resolved on their first [`get`](Self::get) and cached from
+    /// then on. `None` (the default) resolves every schema when installed.
+    pub fn with_eager_schemas(mut self, eager: Option<HashSet<String>>) -> Self {
+        self.eager = eager.map(Arc::new);
+        self
+    }
+
+    /// Quarantine an object once it has failed to parse on `threshold`
+    /// consecutive loads (zero: never).
+    pub fn with_quarantine_threshold(mut self, threshold: u32) -> Self {
+        self.quarantine_threshold = threshold;
+        self
+    }
+
+    /// Record the objects that failed to parse during one full load.
+    ///
+    /// An object that failed on the previous load too has its count bumped;
+    /// one that no longer fails (fixed or deleted) is forgotten. Returns the
+    /// objects that reached the quarantine threshold with this load.
+    pub fn record_parse_failures(&self, failures: Vec<ParseFailure>) -> Vec<QuarantinedSchema> {
+        let Ok(mut tracked) = self.parse_failures.lock() else {
+            return Vec::new();
+        };
+        let previous = std::mem::take(&mut *tracked);
+        let mut newly_quarantined = Vec::new();
+        for ParseFailure { location, error } in failures {
+            let failures = previous
+                .get(&location)
+                .map_or(1, |entry| entry.failures.saturating_add(1));
+            let entry = QuarantinedSchema {
+                location: location.clone(),
+                failures,
+                error,
+            };
+            if self.quarantine_threshold > 0 && failures == self.quarantine_threshold {
+                newly_quarantined.push(entry.clone());
+            }
+            tracked.insert(location, entry);
+        }
+        newly_quarantined
+    }
+
+    /// Objects currently quarantined, sorted by location.
+    pub fn quarantined(&self) -> Vec<QuarantinedSchema> {
+        if self.quarantine_threshold == 0 {
+            return Vec::new();
+        }
+        let mut quarantined: Vec<QuarantinedSchema> = self
+            .parse_failures
+            .lock()
+            .map(|tracked| {
+                tracked
+                    .values()
+                    .filter(|entry| entry.failures >= self.quarantine_threshold)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        quarantined.sort_by(|a, b| a.location.cmp(&b.location));
+        quarantined
+    }
+
     /// Return the number of schemas currently cached.
     pub fn len(&self) -> usize {
         self.inner.load().len()
@@ -57,41 +504,307 @@ impl SchemaCache {
         self.inner.load().is_empty()
     }
 
+    /// Return `true` once at least one load has completed, even if it
+    /// produced no schemas.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Acquire)
+    }
+
+    /// Completes at the next install of a new schema map. Enable the future
+    /// before checking [`is_loaded`](Self::is_loaded) so a load in between is
+    /// not missed.
+    pub fn installed(&self) -> Notified<'_> {
+        self.installed.notified()
+    }
+
+    /// Time between the last successful load and `now`, or `None` if no load
+    /// has completed yet.
+    pub fn staleness(&self, now: Instant) -> Option<Duration> {
+        self.last_refreshed
+            .load()
+            .map(|refreshed| now.saturating_duration_since(refreshed))
+    }
+
+    /// Approximate memory held by cached PII paths: the summed byte length of
+    /// every path string across all cached schemas resolved so far.
+    pub fn pii_path_bytes(&self) -> usize {
+        self.pii_path_bytes.load(Ordering::Relaxed)
+    }
+
     /// Look up a schema by name.
     ///
-    /// This is a lock-free read; safe to call on the hot encryption path.
+    /// This is a lock-free read; safe to call on the hot encryption path. The
+    /// first lookup of a lazily resolved schema resolves it.
     ///
     /// # Errors
     ///
-    /// Returns [`CacheError::UnknownSchema`] if `name` is not present.
+    /// Returns [`CacheError::RemovedSchema`] if `name` was removed by a refresh
+    /// within the tombstone grace period, or [`CacheError::UnknownSchema`] if
+    /// it is otherwise not present.
     pub fn get(&self, name: &str) -> Result<CachedSchema, CacheError> {
+        if let Some(entry) = self.inner.load().get(name) {
+            return Ok(entry.resolve().clone());
+        }
+        match self.tombstones.load().get(name) {
+            Some(removed_at) if removed_at.elapsed() < self.tombstone_grace => {
+                Err(CacheError::RemovedSchema(name.to_owned()))
+            }
+            _ => Err(CacheError::UnknownSchema(name.to_owned())),
+        }
+    }
+
+    /// Name and fingerprint of every cached schema, sorted by name.
+    pub fn fingerprints(&self) -> BTreeMap<String, String> {
         self.inner
             .load()
-            .get(name)
-            .cloned()
-            .ok_or_else(|| CacheError::UnknownSchema(name.to_owned()))
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.fingerprint().to_string()))
+            .collect()
+    }
+
+    /// Number of PII paths of every cached schema, by name; `None` for a
+    /// lazy schema not yet resolved. Resolves nothing.
+    pub fn pii_path_counts(&self) -> BTreeMap<String, Option<usize>> {
+        self.inner
+            .load()
+            .iter()
+            .map(|(name, entry)| {
+                let count = entry.resolved().map(|schema| schema.pii_paths.len());
+                (name.clone(), count)
+            })
+            .collect()
     }
 
-    /// Atomically replace the entire schema map.
+    /// Return the names of all cached schemas whose PII paths include `path`,
+    /// sorted alphabetically.
     ///
-    /// Called by the background refresh task after fetching and parsing all
-    /// schema files from S3.
+    /// Used by schema authors to debug overlapping definitions. Resolves any
+    /// schema not yet resolved.
+    pub fn schemas_with_path(&self, path: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .inner
+            .load()
+            .iter()
+            .filter(|(_, entry)| entry.resolve().pii_paths.contains(path))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Atomically replace the entire schema map with unattributed entries.
+    #[cfg(test)]
     pub fn replace_all(&self, schemas: HashMap<String, OpenAPI>) {
-        let new_map: HashMap<String, CachedSchema> = schemas
+        let new_map = schemas
             .into_iter()
             .map(|(name, api)| {
-                let pii_paths = resolve_pii_paths(&api);
-                let entry = CachedSchema {
-                    api: Arc::new(api),
-                    pii_paths: Arc::new(pii_paths),
-                };
+                let entry = self.entry(&name, api, None, true).unwrap();
                 (name, entry)
             })
             .collect();
+        let _writer = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.install(new_map, true);
+    }
+
+    /// Atomically replace the entire schema map, recording each schema's source.
+    ///
+    /// Called by the background refresh task after fetching and parsing all
+    /// schema files from S3.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReplaceError::TooManySchemas`] if `schemas` exceeds the limit
+    /// and the cache does not truncate, or [`ReplaceError::Fingerprint`] if a
+    /// schema cannot be fingerprinted; the cache is left unchanged.
+    pub fn replace_all_sourced(
+        &self,
+        mut schemas: HashMap<String, SourcedSchema>,
+    ) -> Result<(), ReplaceError> {
+        self.enforce_limit(&mut schemas, 0)?;
+        let mut sources: HashMap<String, Arc<str>> = HashMap::new();
+        let new_map = schemas
+            .into_iter()
+            .map(|(name, SourcedSchema { source, api })| {
+                let source = sources
+                    .entry(source)
+                    .or_insert_with_key(|s| s.as_str().into())
+                    .clone();
+                let entry = self.entry(&name, api, Some(source), self.retain_documents)?;
+                Ok((name, entry))
+            })
+            .collect::<Result<_, FingerprintError>>()?;
+        let _writer = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.install(new_map, true);
+        Ok(())
+    }
+
+    /// Replace only the schemas loaded from `source`, keeping every other
+    /// source's entries as they are. Names `source` no longer defines are
+    /// removed (and tombstoned, when enabled).
+    ///
+    /// A partial reload does not count as a refresh for staleness purposes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReplaceError::Conflict`] if `schemas` defines a name held by
+    /// another source, [`ReplaceError::TooManySchemas`] if the merged cache
+    /// would exceed the limit and the cache does not truncate, or
+    /// [`ReplaceError::Fingerprint`] if a schema cannot be fingerprinted; the
+    /// cache is left unchanged.
+    pub fn replace_source(
+        &self,
+        source: &str,
+        mut schemas: HashMap<String, OpenAPI>,
+    ) -> Result<(), ReplaceError> {
+        let _writer = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.inner.load();
+        let mut new_map: HashMap<String, Entry> = current
+            .iter()
+            .filter(|(_, entry)| entry.source() != Some(source))
+            .map(|(name, entry)| (name.clone(), entry.clone()))
+            .collect();
+        if let Some((name, entry)) = schemas.keys().find_map(|name| new_map.get_key_value(name)) {
+            return Err(MergeConflict {
+                name: name.clone(),
+                existing: entry.source().unwrap_or("unattributed").into(),
+            }
+            .into());
+        }
+        self.enforce_limit(&mut schemas, new_map.len())?;
+        let source: Arc<str> = source.into();
+        for (name, api) in schemas {
+            let entry = self.entry(&name, api, Some(source.clone()), self.retain_documents)?;
+            new_map.insert(name, entry);
+        }
+        self.install(new_map, false);
+        Ok(())
+    }
+
+    /// The map entry for schema `name`: resolved now if it is eager, or
+    /// fingerprinted now and resolved on first lookup otherwise.
+    fn entry(
+        &self,
+        name: &str,
+        api: OpenAPI,
+        source: Option<Arc<str>>,
+        retain: bool,
+    ) -> Result<Entry, FingerprintError> {
+        let fingerprint: Arc<str> = fingerprint(&api)
+            .map_err(|e| FingerprintError {
+                name: name.to_owned(),
+                error: e.to_string(),
+            })?
+            .into();
+        if self.eager.as_ref().is_none_or(|eager| eager.contains(name)) {
+            let entry = CachedSchema::resolve(api, fingerprint, source, retain);
+            return Ok(Entry::Ready(entry));
+        }
+        Ok(Entry::Lazy(Arc::new(LazySchema {
+            source,
+            fingerprint,
+            retain,
+            api: Mutex::new(Some(api)),
+            resolved: OnceLock::new(),
+            pii_path_bytes: Arc::clone(&self.pii_path_bytes),
+        })))
+    }
+
+    /// Check that `kept` existing entries plus `schemas` fit the limit, or,
+    /// when truncating, drop the excess from `schemas` (last names first).
+    fn enforce_limit<T>(
+        &self,
+        schemas: &mut HashMap<String, T>,
+        kept: usize,
+    ) -> Result<(), TooManySchemas> {
+        let count = kept + schemas.len();
+        if self.max_schemas == 0 || count <= self.max_schemas {
+            return Ok(());
+        }
+        if !self.truncate_over_limit {
+            return Err(TooManySchemas {
+                count,
+                limit: self.max_schemas,
+            });
+        }
+        let mut names: Vec<String> = schemas.keys().cloned().collect();
+        names.sort();
+        let room = self.max_schemas.saturating_sub(kept);
+        for name in &names[room.min(names.len())..] {
+            schemas.remove(name);
+        }
+        warn!(
+            limit = self.max_schemas,
+            dropped = names.len() - schemas.len(),
+            "schema load exceeds MAX_CACHED_SCHEMAS; extra schemas not cached"
+        );
+        Ok(())
+    }
+
+    /// Publish `new_map`, updating tombstones and size accounting; `refreshed`
+    /// also records the load for staleness. Callers hold `write_lock`.
+    fn install(&self, new_map: HashMap<String, Entry>, refreshed: bool) {
+        if !self.tombstone_grace.is_zero() {
+            self.update_tombstones(&new_map);
+        }
+        let pii_path_bytes = new_map
+            .values()
+            .filter_map(Entry::resolved)
+            .map(path_bytes)
+            .sum();
+        self.pii_path_bytes.store(pii_path_bytes, Ordering::Relaxed);
         self.inner.store(Arc::new(new_map));
+        if refreshed {
+            self.last_refreshed.store(Arc::new(Some(Instant::now())));
+        }
+        self.loaded.store(true, Ordering::Release);
+        self.installed.notify_waiters();
+    }
+
+    /// Record names present in the current map but absent from `new_map`,
+    /// forget expired tombstones, and clear tombstones for re-added names.
+    ///
+    /// Runs before the new map is published so that a lookup never misses
+    /// both the schema and its tombstone.
+    fn update_tombstones(&self, new_map: &HashMap<String, Entry>) {
+        let now = Instant::now();
+        let mut tombstones: HashMap<String, Instant> = self
+            .tombstones
+            .load()
+            .iter()
+            .filter(|(name, removed_at)| {
+                !new_map.contains_key(*name)
+                    && now.duration_since(**removed_at) < self.tombstone_grace
+            })
+            .map(|(name, removed_at)| (name.clone(), *removed_at))
+            .collect();
+        for name in self.inner.load().keys() {
+            if !new_map.contains_key(name) {
+                tombstones.insert(name.clone(), now);
+            }
+        }
+        self.tombstones.store(Arc::new(tombstones));
     }
 }
 
+/// Compute the hex-encoded SHA-256 fingerprint of a parsed OpenAPI document.
+///
+/// The document is re-serialised as JSON so the fingerprint is independent of
+/// the source format (YAML vs JSON) and whitespace.
+///
+/// # Errors
+///
+/// Returns the serialisation error if the document cannot be written as JSON,
+/// rather than a fingerprint of part of it.
+pub fn fingerprint(api: &OpenAPI) -> Result<String, serde_json::Error> {
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, api)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
 impl Default for SchemaCache {
     fn default() -> Self {
         Self::new()
@@ -107,11 +820,284 @@ mod tests {
             .unwrap()
     }
 
+    fn sourced(source: &str) -> SourcedSchema {
+        SourcedSchema {
+            source: source.into(),
+            api: make_empty_api(),
+        }
+    }
+
+    #[test]
+    fn path_scope_matches_whole_segments() {
+        assert!(path_in_scope("debtor", "debtor"));
+        assert!(path_in_scope("debtor.name", "debtor"));
+        assert!(path_in_scope("debtor[].iban", "debtor"));
+        assert!(path_in_scope("debtor.**.iban", "debtor"));
+        assert!(!path_in_scope("debtors.name", "debtor"));
+        assert!(!path_in_scope("**.debtor", "debtor"));
+    }
+
+    #[test]
+    fn source_reload_leaves_other_sources_alone() {
+        let cache = SchemaCache::new().with_tombstone_grace(Duration::from_secs(60));
+        cache
+            .replace_all_sourced(HashMap::from([
+                ("payments-v1".to_string(), sourced("team-a/schemas/")),
+                ("payments-v2".to_string(), sourced("team-a/schemas/")),
+                ("identity-v1".to_string(), sourced("team-b/pii/")),
+            ]))
+            .unwrap();
+        let identity_before = cache.get("identity-v1").unwrap();
+
+        cache
+            .replace_source(
+                "team-a/schemas/",
+                HashMap::from([
+                    ("payments-v2".to_string(), make_empty_api()),
+                    ("payments-v3".to_string(), make_empty_api()),
+                ]),
+            )
+            .unwrap();
+
+        assert_eq!(cache.len(), 3);
+        assert!(matches!(
+            cache.get("payments-v1"),
+            Err(CacheError::RemovedSchema(_))
+        ));
+        assert_eq!(
+            cache.get("payments-v3").unwrap().source.as_deref(),
+            Some("team-a/schemas/")
+        );
+        let identity_after = cache.get("identity-v1").unwrap();
+        assert!(Arc::ptr_eq(
+            identity_before.api.as_ref().unwrap(),
+            identity_after.api.as_ref().unwrap()
+        ));
+        assert_eq!(identity_after.source.as_deref(), Some("team-b/pii/"));
+    }
+
+    #[test]
+    fn source_reload_rejects_names_owned_elsewhere() {
+        let cache = SchemaCache::new();
+        cache
+            .replace_all_sourced(HashMap::from([(
+                "identity-v1".to_string(),
+                sourced("team-b/pii/"),
+            )]))
+            .unwrap();
+        let ReplaceError::Conflict(err) = cache
+            .replace_source(
+                "team-a/schemas/",
+                HashMap::from([("identity-v1".to_string(), make_empty_api())]),
+            )
+            .unwrap_err()
+        else {
+            panic!("expected a merge conflict");
+        };
+        assert_eq!(err.existing, "team-b/pii/");
+        assert_eq!(
+            cache.get("identity-v1").unwrap().source.as_deref(),
+            Some("team-b/pii/")
+        );
+    }
+
+    #[test]
+    fn schema_limit_refuses_or_truncates_loads() {
+        let three = || {
+            HashMap::from([
+                ("a-v1".to_string(), sourced("team-a/")),
+                ("b-v1".to_string(), sourced("team-a/")),
+                ("c-v1".to_string(), sourced("team-a/")),
+            ])
+        };
+
+        let strict = SchemaCache::new().with_max_schemas(2, false);
+        assert_eq!(
+            strict.replace_all_sourced(three()),
+            Err(ReplaceError::TooManySchemas(TooManySchemas {
+                count: 3,
+                limit: 2
+            }))
+        );
+        assert!(!strict.is_loaded());
+        strict
+            .replace_all_sourced(HashMap::from([("a-v1".to_string(), sourced("team-a/"))]))
+            .unwrap();
+        let err = strict
+            .replace_source(
+                "team-b/",
+                HashMap::from([
+                    ("x-v1".to_string(), make_empty_api()),
+                    ("y-v1".to_string(), make_empty_api()),
+                ]),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ReplaceError::TooManySchemas(TooManySchemas { count: 3, limit: 2 })
+        );
+        assert_eq!(strict.len(), 1);
+
+        let lenient = SchemaCache::new().with_max_schemas(2, true);
+        lenient.replace_all_sourced(three()).unwrap();
+        assert_eq!(lenient.len(), 2);
+        assert!(lenient.get("a-v1").is_ok() && lenient.get("b-v1").is_ok());
+        assert!(lenient.get("c-v1").is_err());
+    }
+
+    #[test]
+    fn documents_can_be_dropped_after_resolution() {
+        let with_iban: OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{},
+                "components":{"schemas":{"Payment":{"type":"object","properties":{
+                    "iban":{"type":"string","x-pii":true}}}}}}"#,
+        )
+        .unwrap();
+        let load = |cache: &SchemaCache| {
+            cache
+                .replace_all_sourced(HashMap::from([(
+                    "payments-v1".to_string(),
+                    SourcedSchema {
+                        source: "team-a/".into(),
+                        api: with_iban.clone(),
+                    },
+                )]))
+                .unwrap();
+            cache.get("payments-v1").unwrap()
+        };
+
+        let retained = load(&SchemaCache::new());
+        let dropped = load(&SchemaCache::new().retain_documents(false));
+        assert!(retained.api.is_some());
+        assert!(dropped.api.is_none());
+        // Everything derived from the document is still there.
+        assert_eq!(dropped.pii_paths, retained.pii_paths);
+        assert_eq!(dropped.fingerprint, retained.fingerprint);
+    }
+
+    fn load_eager_and_lazy() -> SchemaCache {
+        let with_iban: OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{},
+                "components":{"schemas":{"Payment":{"type":"object","properties":{
+                    "iban":{"type":"string","x-pii":true}}}}}}"#,
+        )
+        .unwrap();
+        let cache =
+            SchemaCache::new().with_eager_schemas(Some(HashSet::from(["payments-v1".to_string()])));
+        cache.replace_all(HashMap::from([
+            ("payments-v1".to_string(), with_iban.clone()),
+            ("refunds-v1".to_string(), with_iban),
+        ]));
+        cache
+    }
+
+    fn is_resolved(cache: &SchemaCache, name: &str) -> bool {
+        cache.inner.load()[name].resolved().is_some()
+    }
+
+    #[test]
+    fn eager_schema_is_resolved_on_install() {
+        let cache = load_eager_and_lazy();
+        assert!(is_resolved(&cache, "payments-v1"));
+        assert_eq!(cache.pii_path_bytes(), "iban".len());
+
+        let entry = cache.get("payments-v1").unwrap();
+        assert!(entry.pii_paths.contains("iban"));
+        assert!(entry.api.is_some());
+    }
+
+    #[test]
+    fn lazy_schema_resolves_on_first_get() {
+        let cache = load_eager_and_lazy();
+        assert!(!is_resolved(&cache, "refunds-v1"));
+        // Fingerprints are known without resolving.
+        let fingerprints = cache.fingerprints();
+        assert_eq!(fingerprints["refunds-v1"], fingerprints["payments-v1"]);
+        assert!(!is_resolved(&cache, "refunds-v1"));
+
+        let entry = cache.get("refunds-v1").unwrap();
+        assert!(entry.pii_paths.contains("iban"));
+        assert_eq!(entry.fingerprint.as_ref(), fingerprints["refunds-v1"]);
+        assert!(is_resolved(&cache, "refunds-v1"));
+        assert_eq!(cache.pii_path_bytes(), 2 * "iban".len());
+
+        // Later lookups share the cached resolution.
+        let again = cache.get("refunds-v1").unwrap();
+        assert!(Arc::ptr_eq(&entry.pii_paths, &again.pii_paths));
+        assert_eq!(cache.pii_path_bytes(), 2 * "iban".len());
+    }
+
     #[test]
     fn initially_empty() {
         let cache = SchemaCache::new();
         assert!(cache.is_empty());
         assert_eq!(cache.len(), 0);
+        assert!(!cache.is_loaded());
+    }
+
+    #[test]
+    fn empty_load_marks_cache_loaded() {
+        let cache = SchemaCache::new();
+        cache.replace_all(HashMap::new());
+        assert!(cache.is_empty());
+        assert!(cache.is_loaded());
+    }
+
+    #[test]
+    fn repeated_parse_failures_escalate_to_quarantine() {
+        let cache = SchemaCache::new().with_quarantine_threshold(3);
+        let failure = |location: &str| ParseFailure {
+            location: location.into(),
+            error: "not valid YAML or JSON".into(),
+        };
+        let bad = "s3://bucket/schemas/bad.yaml";
+        let flaky = "s3://bucket/schemas/flaky.yaml";
+
+        assert!(cache
+            .record_parse_failures(vec![failure(bad), failure(flaky)])
+            .is_empty());
+        assert!(cache.record_parse_failures(vec![failure(bad)]).is_empty());
+        assert!(cache.quarantined().is_empty());
+
+        let escalated = cache.record_parse_failures(vec![failure(bad), failure(flaky)]);
+        assert_eq!(escalated.len(), 1);
+        assert_eq!(escalated[0].location, bad);
+        assert_eq!(escalated[0].failures, 3);
+        // Only the load that crosses the threshold reports it as new.
+        assert!(cache.record_parse_failures(vec![failure(bad)]).is_empty());
+        assert_eq!(cache.quarantined().len(), 1);
+        assert_eq!(cache.quarantined()[0].failures, 4);
+
+        // A fixed object is released.
+        cache.record_parse_failures(Vec::new());
+        assert!(cache.quarantined().is_empty());
+    }
+
+    #[test]
+    fn quarantine_disabled_by_default() {
+        let cache = SchemaCache::new();
+        for _ in 0..5 {
+            let failures = vec![ParseFailure {
+                location: "s3://bucket/schemas/bad.yaml".into(),
+                error: "bad".into(),
+            }];
+            assert!(cache.record_parse_failures(failures).is_empty());
+        }
+        assert!(cache.quarantined().is_empty());
+    }
+
+    #[test]
+    fn staleness_measured_from_last_load() {
+        let cache = SchemaCache::new();
+        assert_eq!(cache.staleness(Instant::now()), None);
+
+        cache.replace_all(HashMap::new());
+        let later = Instant::now() + Duration::from_secs(90);
+        assert!(cache.staleness(later).unwrap() >= Duration::from_secs(90));
+
+        // A new successful load resets the clock.
+        cache.replace_all(HashMap::new());
+        assert!(cache.staleness(Instant::now()).unwrap() < Duration::from_secs(90));
     }
 
     #[test]
@@ -131,6 +1117,91 @@ mod tests {
         assert!(cache.get("other").is_err());
     }
 
+    #[test]
+    fn schemas_with_path_filters_by_pii_path() {
+        let with_iban: OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{},
+                "components":{"schemas":{"Payment":{"type":"object","properties":{
+                    "account":{"type":"object","properties":{
+                        "iban":{"type":"string","x-pii":true}}}}}}}}"#,
+        )
+        .unwrap();
+        let cache = SchemaCache::new();
+        let mut map = HashMap::new();
+        map.insert("payments-v1".into(), with_iban);
+        map.insert("orders-v1".into(), make_empty_api());
+        cache.replace_all(map);
+
+        assert_eq!(cache.schemas_with_path("account.iban"), vec!["payments-v1"]);
+        assert!(cache.schemas_with_path("account.bic").is_empty());
+        assert_eq!(cache.pii_path_bytes(), "account.iban".len());
+    }
+
+    #[test]
+    fn fingerprint_is_stable_and_content_sensitive() {
+        let a = make_empty_api();
+        let mut b = make_empty_api();
+        b.info.version = "2".into();
+        let a = fingerprint(&a).unwrap();
+        assert_eq!(a, fingerprint(&make_empty_api()).unwrap());
+        assert_ne!(a, fingerprint(&b).unwrap());
+        assert_eq!(a.len(), 64);
+    }
+
+    #[test]
+    fn removed_schema_reported_within_tombstone_window() {
+        let cache = SchemaCache::new().with_tombstone_grace(Duration::from_secs(60));
+        cache.replace_all(HashMap::from([
+            ("schema-a".to_string(), make_empty_api()),
+            ("schema-b".to_string(), make_empty_api()),
+        ]));
+        cache.replace_all(HashMap::from([("schema-b".to_string(), make_empty_api())]));
+
+        assert!(matches!(
+            cache.get("schema-a"),
+            Err(CacheError::RemovedSchema(_))
+        ));
+        assert!(matches!(
+            cache.get("never-existed"),
+            Err(CacheError::UnknownSchema(_))
+        ));
+
+        // Re-adding the schema clears its tombstone.
+        cache.replace_all(HashMap::from([("schema-a".to_string(), make_empty_api())]));
+        assert!(cache.get("schema-a").is_ok());
+        assert!(matches!(
+            cache.get("schema-b"),
+            Err(CacheError::RemovedSchema(_))
+        ));
+    }
+
+    #[test]
+    fn tombstone_expires_after_grace() {
+        let cache = SchemaCache::new().with_tombstone_grace(Duration::from_millis(20));
+        cache.replace_all(HashMap::from([("schema-a".to_string(), make_empty_api())]));
+        cache.replace_all(HashMap::new());
+        assert!(matches!(
+            cache.get("schema-a"),
+            Err(CacheError::RemovedSchema(_))
+        ));
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(matches!(
+            cache.get("schema-a"),
+            Err(CacheError::UnknownSchema(_))
+        ));
+    }
+
+    #[test]
+    fn tombstones_disabled_by_default() {
+        let cache = SchemaCache::new();
+        cache.replace_all(HashMap::from([("schema-a".to_string(), make_empty_api())]));
+        cache.replace_all(HashMap::new());
+        assert!(matches!(
+            cache.get("schema-a"),
+            Err(CacheError::UnknownSchema(_))
+        ));
+    }
+
     #[test]
     fn replace_all_is_atomic() {
         let cache = SchemaCache::new();
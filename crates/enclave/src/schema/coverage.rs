@@ -0,0 +1,181 @@
+//! Required PII coverage: a startup check that fields on an external
+//! compliance list are marked PII wherever a schema declares them.
+//!
+//! Patterns come from `REQUIRED_PII_PATHS`. `*.ssn` names the field `ssn` at
+//! any depth (including the top level); `*.address.zip` names `zip` directly
+//! under any `address`; a pattern without `*.` is an exact path. Array
+//! segments are matched by name, so `*.card_number` also covers
+//! `orders[].card_number`. A declared field counts as covered when it
+//! resolves to a PII path itself, element-wise (`path[]`) or through an
+//! `x-pii-recursive` annotation on its name.
+
+use openapiv3::OpenAPI;
+
+use super::resolver::{declared_paths, resolve_pii_paths};
+
+/// Field patterns that must be PII wherever a schema declares them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequiredPiiPaths(Vec<String>);
+
+impl RequiredPiiPaths {
+    /// Parse a comma-separated pattern list; blank entries are ignored.
+    pub fn parse(raw: &str) -> Self {
+        Self(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        )
+    }
+
+    /// Whether no pattern is configured.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether any pattern names the declared path `path`.
+    fn matches(&self, path: &str) -> bool {
+        let normalized = path.replace("[]", "");
+        self.0
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(suffix) => {
+                    normalized == suffix
+                        || normalized
+                            .strip_suffix(suffix)
+                            .is_some_and(|head| head.ends_with('.'))
+                }
+                None => normalized == *pattern || path == pattern,
+            })
+    }
+}
+
+/// A schema that declares required fields without marking them PII.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageGap {
+    /// Schema name.
+    pub schema: String,
+    /// Declared paths matching a required pattern that are not PII, sorted.
+    pub paths: Vec<String>,
+}
+
+/// The uncovered required fields of each schema, sorted by schema name.
+/// Schemas without gaps are omitted.
+pub fn coverage_gaps<'a>(
+    schemas: impl IntoIterator<Item = (&'a str, &'a OpenAPI)>,
+    required: &RequiredPiiPaths,
+) -> Vec<CoverageGap> {
+    if required.is_empty() {
+        return Vec::new();
+    }
+    let mut gaps: Vec<CoverageGap> = schemas
+        .into_iter()
+        .filter_map(|(name, api)| {
+            let pii = resolve_pii_paths(api);
+            let mut paths: Vec<String> = declared_paths(api)
+                .into_iter()
+                .filter(|path| required.matches(path))
+                .filter(|path| {
+                    let field = path.rsplit('.').next().unwrap_or(path);
+                    !pii.contains(path)
+                        && !pii.contains(&format!("{path}[]"))
+                        && !pii.contains(&format!("**.{}", field.trim_end_matches("[]")))
+                })
+                .collect();
+            if paths.is_empty() {
+                return None;
+            }
+            paths.sort();
+            Some(CoverageGap {
+                schema: name.to_owned(),
+                paths,
+            })
+        })
+        .collect();
+    gaps.sort_by(|a, b| a.schema.cmp(&b.schema));
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_api(yaml: &str) -> OpenAPI {
+        serde_yaml::from_str(yaml).expect("valid YAML")
+    }
+
+    const COMPLIANT: &str = r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        orders:
+          type: array
+          items:
+            type: object
+            properties:
+              card_number: { type: string, x-pii: true }
+              amount: { type: number }
+"#;
+
+    const NON_COMPLIANT: &str = r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        spouse:
+          type: object
+          properties:
+            ssn: { type: string }
+        orders:
+          type: array
+          items:
+            type: object
+            properties:
+              card_number: { type: string }
+"#;
+
+    #[test]
+    fn compliant_schema_has_no_gaps() {
+        let required = RequiredPiiPaths::parse("*.ssn, *.card_number");
+        let api = parse_api(COMPLIANT);
+        assert!(coverage_gaps([("customer-v1", &api)], &required).is_empty());
+    }
+
+    #[test]
+    fn non_compliant_schema_lists_unmarked_fields() {
+        let required = RequiredPiiPaths::parse("*.ssn,*.card_number");
+        let good = parse_api(COMPLIANT);
+        let bad = parse_api(NON_COMPLIANT);
+        let gaps = coverage_gaps([("good-v1", &good), ("bad-v1", &bad)], &required);
+        assert_eq!(
+            gaps,
+            vec![CoverageGap {
+                schema: "bad-v1".into(),
+                paths: vec!["orders[].card_number".into(), "spouse.ssn".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn patterns_match_by_suffix_or_exactly() {
+        let required = RequiredPiiPaths::parse("*.address.zip,account.iban,");
+        assert!(required.matches("address.zip"));
+        assert!(required.matches("billing.address.zip"));
+        assert!(!required.matches("billing.myaddress.zip"));
+        assert!(required.matches("account.iban"));
+        assert!(!required.matches("old.account.iban"));
+        assert!(RequiredPiiPaths::parse(" , ").is_empty());
+    }
+}
@@ -14,51 +14,292 @@
 //! - **No AWS KMS dependency.** S3 reads are allowed; KMS is not.
 
 pub mod cache;
+pub mod coverage;
+pub mod path_map;
 pub mod resolver;
+pub mod single_flight;
+pub mod validate;
 
-pub use cache::SchemaCache;
-pub use resolver::PiiFieldPaths;
+pub use cache::{
+    FingerprintError, MergeConflict, ParseFailure, ReplaceError, SchemaCache, TooManySchemas,
+};
+pub use resolver::{EmbeddedJsonPaths, PiiCategories, PiiConditions, PiiFieldPaths, PiiMaxLengths};
 
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use openapiv3::OpenAPI;
+use thiserror::Error;
 use tokio::time;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 use crate::aws::AwsClients;
-use crate::config::Config;
+use crate::config::{Config, SchemaSource};
+use crate::telemetry::heartbeat::Heartbeat;
+use cache::SourcedSchema;
+use path_map::PathMap;
+use single_flight::SingleFlight;
 
-/// Fetch all OpenAPI schema files from S3 and atomically replace the cache.
-///
-/// Lists objects under `cfg.s3_prefix`, fetches each one, parses it as YAML
-/// (falling back to JSON), extracts PII field paths, and calls
-/// [`SchemaCache::replace_all`].
+/// A schema fetched and parsed from one S3 object.
+#[derive(Debug)]
+struct LoadedSchema {
+    /// Schema name derived from the object key.
+    name: String,
+    /// Name of the source the object was listed under.
+    source: String,
+    /// `s3://bucket/key` of the object, for diagnostics.
+    location: String,
+    /// The parsed document.
+    api: OpenAPI,
+}
+
+/// Fetch, parse and merge the schemas of every configured source, recording
+/// the objects a lenient load skipped in `cache`.
+async fn fetch_all(
+    aws: &AwsClients,
+    cfg: &Config,
+    cache: &SchemaCache,
+) -> Result<HashMap<String, SourcedSchema>> {
+    let mut loaded = Vec::new();
+    let mut skipped = Vec::new();
+    for source in cfg.schema_sources()? {
+        loaded.extend(load_source(aws, &source, cfg.schema_load_lenient, &mut skipped).await?);
+    }
+    record_skipped(cache, skipped);
+    merge_sources(loaded)
+}
+
+/// Check `schemas` against `cfg.required_pii_paths`, logging each schema that
+/// declares a required field without marking it PII.
 ///
 /// # Errors
 ///
-/// Returns an error if the S3 list call fails or if any individual object
-/// cannot be fetched or parsed.
-pub async fn load_all(aws: &AwsClients, cfg: &Config, cache: &SchemaCache) -> Result<()> {
+/// Returns an error listing the offending schemas unless
+/// `cfg.required_pii_lenient` is set.
+fn check_required_pii(cfg: &Config, schemas: &HashMap<String, SourcedSchema>) -> Result<()> {
+    let required =
+        coverage::RequiredPiiPaths::parse(cfg.required_pii_paths.as_deref().unwrap_or_default());
+    let gaps = coverage::coverage_gaps(
+        schemas
+            .iter()
+            .map(|(name, sourced)| (name.as_str(), &sourced.api)),
+        &required,
+    );
+    for gap in &gaps {
+        warn!(
+            schema = %gap.schema,
+            paths = %gap.paths.join(","),
+            "schema declares required PII fields without marking them PII"
+        );
+    }
+    if gaps.is_empty() || cfg.required_pii_lenient {
+        return Ok(());
+    }
+    let schemas: Vec<&str> = gaps.iter().map(|gap| gap.schema.as_str()).collect();
+    anyhow::bail!(
+        "required PII fields are not marked PII in schemas: {}",
+        schemas.join(", ")
+    );
+}
+
+/// Record the objects skipped by one full load, logging an error for each one
+/// that has just been quarantined.
+fn record_skipped(cache: &SchemaCache, skipped: Vec<ParseFailure>) {
+    for quarantined in cache.record_parse_failures(skipped) {
+        error!(
+            location = %quarantined.location,
+            failures = quarantined.failures,
+            error = %quarantined.error,
+            "quarantined schema: object has failed to parse on every recent load"
+        );
+    }
+}
+
+/// Errors from [`SchemaLoader::reload_source`].
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    /// No configured source has the requested name.
+    #[error("unknown schema source: {0}")]
+    UnknownSource(String),
+    /// The source now defines a schema name owned by another source.
+    #[error(transparent)]
+    Conflict(#[from] MergeConflict),
+    /// The reloaded source would push the cache over `MAX_CACHED_SCHEMAS`.
+    #[error(transparent)]
+    TooManySchemas(#[from] TooManySchemas),
+    /// A reloaded schema could not be fingerprinted.
+    #[error(transparent)]
+    Fingerprint(#[from] FingerprintError),
+    /// Listing, fetching or parsing the source's objects failed.
+    #[error("failed to load schema source: {0:#}")]
+    Load(anyhow::Error),
+}
+
+/// Outcome of one full load, shared by every caller that joined it.
+type SharedLoad = Result<(), Arc<anyhow::Error>>;
+
+/// The AWS clients and configuration needed to load the schema sources: in
+/// full at startup and on each refresh, or one at a time on demand
+/// (`POST /admin/reload-schemas`).
+///
+/// Clones share a [`SingleFlight`] guard, so only one load runs at a time and
+/// a full load requested while another is in flight joins it, sharing its
+/// outcome rather than installing the same schemas a second time.
+#[derive(Clone)]
+pub struct SchemaLoader {
+    aws: AwsClients,
+    cfg: Config,
+    flight: Arc<SingleFlight<SharedLoad>>,
+}
+
+impl SchemaLoader {
+    /// Create a loader for the sources configured in `cfg`.
+    pub fn new(aws: AwsClients, cfg: Config) -> Self {
+        Self {
+            aws,
+            cfg,
+            flight: Arc::default(),
+        }
+    }
+
+    /// Fetch all OpenAPI schema files from every configured S3 source and
+    /// atomically replace the cache.
+    ///
+    /// For each source from [`Config::schema_sources`], lists objects under the
+    /// source prefix, fetches each one, and parses it as YAML (falling back to
+    /// JSON). A leading UTF-8 byte-order mark is ignored; with
+    /// `schema_load_lenient`, objects that fail to parse are skipped and
+    /// counted towards quarantine (see [`SchemaCache::record_parse_failures`]).
+    /// Schemas from all sources are merged into one map and installed with
+    /// [`SchemaCache::replace_all_sourced`].
+    ///
+    /// Before installing, every schema is checked against `required_pii_paths`
+    /// (see [`coverage`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any S3 list call fails, if any individual object
+    /// cannot be fetched or parsed, if two sources define the same schema name,
+    /// if a strict load exceeds `max_cached_schemas`, or if a schema declares a
+    /// required PII field without marking it PII and `required_pii_lenient` is
+    /// unset.
+    pub async fn load_all(&self, cache: &SchemaCache) -> Result<()> {
+        self.install_all(cache, "load").await
+    }
+
+    /// Fetch, check and install every source, joining the full load already
+    /// in flight if there is one. `origin` labels the emitted path map.
+    async fn install_all(&self, cache: &SchemaCache, origin: &'static str) -> Result<()> {
+        let shared = self
+            .flight
+            .run(|| async { self.install_all_now(cache, origin).await.map_err(Arc::new) })
+            .await;
+        shared.map_err(|e| anyhow::anyhow!("{e:#}"))
+    }
+
+    /// [`install_all`](Self::install_all) without joining other loads.
+    async fn install_all_now(&self, cache: &SchemaCache, origin: &'static str) -> Result<()> {
+        let schemas = fetch_all(&self.aws, &self.cfg, cache).await?;
+        check_required_pii(&self.cfg, &schemas)?;
+        cache.replace_all_sourced(schemas)?;
+        info!(count = cache.len(), "schema cache refreshed");
+        PathMap::build(cache, origin).emit();
+        Ok(())
+    }
+
+    /// Re-fetch the source named `name` (see [`SchemaSource::name`]) and merge
+    /// it into `cache`, leaving every other source's schemas untouched.
+    /// Returns the number of schemas the source now defines.
+    ///
+    /// # Errors
+    ///
+    /// See [`ReloadError`]; on any error the cache is left unchanged.
+    pub async fn reload_source(
+        &self,
+        cache: &SchemaCache,
+        name: &str,
+    ) -> Result<usize, ReloadError> {
+        // Not identical to a full load, so it waits its turn instead of joining.
+        self.flight
+            .exclusive(|| self.reload_source_now(cache, name))
+            .await
+    }
+
+    /// [`reload_source`](Self::reload_source) without waiting for other loads.
+    async fn reload_source_now(
+        &self,
+        cache: &SchemaCache,
+        name: &str,
+    ) -> Result<usize, ReloadError> {
+        let sources = self.cfg.schema_sources().map_err(ReloadError::Load)?;
+        let source = sources
+            .iter()
+            .find(|s| s.name() == name)
+            .ok_or_else(|| ReloadError::UnknownSource(name.to_owned()))?;
+        // A single source's skips do not count towards quarantine, which
+        // tracks consecutive full loads.
+        let loaded = load_source(
+            &self.aws,
+            source,
+            self.cfg.schema_load_lenient,
+            &mut Vec::new(),
+        )
+        .await
+        .map_err(ReloadError::Load)?;
+        let schemas: HashMap<String, OpenAPI> = merge_sources(loaded)
+            .map_err(ReloadError::Load)?
+            .into_iter()
+            .map(|(schema, sourced)| (schema, sourced.api))
+            .collect();
+        let count = schemas.len();
+        cache.replace_source(name, schemas).map_err(|e| match e {
+            ReplaceError::Conflict(e) => ReloadError::from(e),
+            ReplaceError::TooManySchemas(e) => ReloadError::from(e),
+            ReplaceError::Fingerprint(e) => ReloadError::from(e),
+        })?;
+        info!(source = %name, count, "schema source reloaded");
+        PathMap::build(cache, format!("reload:{name}")).emit();
+        Ok(count)
+    }
+}
+
+/// Fetch and parse every schema object under one S3 source.
+///
+/// With `lenient`, objects that fail to parse are appended to `skipped`
+/// instead of failing the load.
+async fn load_source(
+    aws: &AwsClients,
+    source: &SchemaSource,
+    lenient: bool,
+    skipped: &mut Vec<ParseFailure>,
+) -> Result<Vec<LoadedSchema>> {
     let list = aws
         .s3
         .list_objects_v2()
-        .bucket(&cfg.s3_bucket)
-        .prefix(&cfg.s3_prefix)
+        .bucket(&source.bucket)
+        .prefix(&source.prefix)
         .send()
         .await
-        .context("failed to list S3 objects for schemas")?;
+        .with_context(|| {
+            format!(
+                "failed to list S3 objects for schemas in s3://{}/{}",
+                source.bucket, source.prefix
+            )
+        })?;
 
     let objects = list.contents().to_vec();
     if objects.is_empty() {
         warn!(
-            bucket = %cfg.s3_bucket,
-            prefix = %cfg.s3_prefix,
+            bucket = %source.bucket,
+            prefix = %source.prefix,
             "no schema files found in S3"
         );
     }
 
-    let mut schemas: HashMap<String, OpenAPI> = HashMap::new();
+    let mut loaded = Vec::with_capacity(objects.len());
 
     for obj in &objects {
         let key = match obj.key() {
@@ -66,12 +307,12 @@ pub async fn load_all(aws: &AwsClients, cfg: &Config, cache: &SchemaCache) -> Re
             None => continue,
         };
 
-        let name = schema_name_from_key(key, &cfg.s3_prefix);
+        let name = schema_name_from_key(key, &source.prefix);
 
         let get = aws
             .s3
             .get_object()
-            .bucket(&cfg.s3_bucket)
+            .bucket(&source.bucket)
             .key(key)
             .send()
             .await
@@ -84,50 +325,131 @@ pub async fn load_all(aws: &AwsClients, cfg: &Config, cache: &SchemaCache) -> Re
             .with_context(|| format!("failed to read body for S3 key: {key}"))?
             .into_bytes();
 
-        let text = std::str::from_utf8(&body_bytes)
-            .with_context(|| format!("S3 object {key} is not valid UTF-8"))?;
-
-        let api: OpenAPI = if let Ok(parsed) = serde_yaml::from_str(text) {
-            parsed
-        } else if let Ok(parsed) = serde_json::from_str(text) {
-            parsed
-        } else {
-            anyhow::bail!(
-                "failed to parse OpenAPI schema from S3 key {key}: not valid YAML or JSON"
-            );
+        let location = format!("s3://{}/{key}", source.bucket);
+        let api = match parse_or_skip(key, &location, &body_bytes, lenient, skipped)? {
+            Some(api) => api,
+            None => continue,
         };
 
-        info!(schema = %name, key = %key, "loaded schema from S3");
-        schemas.insert(name, api);
+        info!(schema = %name, bucket = %source.bucket, key = %key, "loaded schema from S3");
+        loaded.push(LoadedSchema {
+            name,
+            source: source.name(),
+            location,
+            api,
+        });
     }
 
-    cache.replace_all(schemas);
-    info!(count = cache.len(), "schema cache refreshed");
-    Ok(())
+    Ok(loaded)
+}
+
+/// UTF-8 byte-order mark some editors prepend to text files.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Parse the body of a schema object as YAML, falling back to JSON.
+///
+/// A leading UTF-8 BOM is stripped before parsing. A document naming an
+/// unsupported `x-encryption-alg` is rejected like an unparseable one.
+fn parse_schema(key: &str, body: &[u8]) -> Result<OpenAPI> {
+    let body = body.strip_prefix(UTF8_BOM).unwrap_or(body);
+    let text =
+        std::str::from_utf8(body).with_context(|| format!("S3 object {key} is not valid UTF-8"))?;
+
+    let api: OpenAPI = if let Ok(parsed) = serde_yaml::from_str(text) {
+        parsed
+    } else if let Ok(parsed) = serde_json::from_str(text) {
+        parsed
+    } else {
+        anyhow::bail!("failed to parse OpenAPI schema from S3 key {key}: not valid YAML or JSON");
+    };
+    if let Err(value) = resolver::encryption_algorithm(&api) {
+        anyhow::bail!("schema {key} names unsupported x-encryption-alg {value}");
+    }
+    Ok(api)
+}
+
+/// [`parse_schema`], except that with `lenient` a failure is logged, appended
+/// to `skipped` and reported as `Ok(None)` so the caller can skip the object.
+fn parse_or_skip(
+    key: &str,
+    location: &str,
+    body: &[u8],
+    lenient: bool,
+    skipped: &mut Vec<ParseFailure>,
+) -> Result<Option<OpenAPI>> {
+    match parse_schema(key, body) {
+        Ok(api) => Ok(Some(api)),
+        Err(e) if lenient => {
+            warn!(key = %key, error = %format!("{e:#}"), "skipping schema object that failed to parse");
+            skipped.push(ParseFailure {
+                location: location.to_owned(),
+                error: format!("{e:#}"),
+            });
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Merge schemas loaded from all sources into one name-keyed map.
+///
+/// A name defined by more than one object is rejected rather than silently
+/// shadowed, since the shadowing document could mark fewer fields as PII.
+fn merge_sources(loaded: Vec<LoadedSchema>) -> Result<HashMap<String, SourcedSchema>> {
+    let mut schemas: HashMap<String, SourcedSchema> = HashMap::with_capacity(loaded.len());
+    let mut locations: HashMap<String, String> = HashMap::with_capacity(loaded.len());
+    for LoadedSchema {
+        name,
+        source,
+        location,
+        api,
+    } in loaded
+    {
+        if let Some(existing) = locations.get(&name) {
+            anyhow::bail!("schema name {name:?} is defined by both {existing} and {location}");
+        }
+        locations.insert(name.clone(), location);
+        schemas.insert(name, SourcedSchema { source, api });
+    }
+    Ok(schemas)
 }
 
 /// Spawn a background task that periodically refreshes the schema cache from S3.
 ///
 /// On refresh failure the previous cache contents are retained and a warning is
-/// emitted; the service continues to operate with stale schemas.
+/// emitted; the service continues to operate with stale schemas. `heartbeat`
+/// is beaten once per interval. Refreshes go through `loader`, so they are
+/// checked like [`SchemaLoader::load_all`] and one that fires during another
+/// full load joins it.
 pub fn refresh_task(
-    aws: AwsClients,
-    cfg: Config,
+    loader: SchemaLoader,
     cache: SchemaCache,
+    heartbeat: Heartbeat,
 ) -> tokio::task::JoinHandle<()> {
-    let interval = std::time::Duration::from_secs(cfg.schema_refresh_interval_secs);
-    tokio::spawn(async move {
-        let mut ticker = time::interval(interval);
-        // First tick fires immediately — skip it so we don't double-load at startup.
+    let interval = Duration::from_secs(loader.cfg.schema_refresh_interval_secs);
+    tokio::spawn(refresh_loop(interval, heartbeat, move || {
+        let (loader, cache) = (loader.clone(), cache.clone());
+        async move { loader.install_all(&cache, "refresh").await }
+    }))
+}
+
+/// The body of [`refresh_task`], with the load injected so tests can drive
+/// it under a paused `tokio::time` clock.
+async fn refresh_loop<F, Fut>(interval: Duration, heartbeat: Heartbeat, mut install: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut ticker = time::interval(interval);
+    // First tick fires immediately — skip it so we don't double-load at startup.
+    ticker.tick().await;
+    loop {
+        heartbeat.beat(interval);
         ticker.tick().await;
-        loop {
-            ticker.tick().await;
-            match load_all(&aws, &cfg, &cache).await {
-                Ok(()) => info!("schema cache refreshed"),
-                Err(e) => warn!(error = %e, "schema refresh failed; retaining previous cache"),
-            }
+        if let Err(e) = install().await {
+            warn!(error = %e, "schema refresh failed; retaining previous cache");
         }
-    })
+    }
 }
 
 /// Derive a schema name from an S3 object key.
@@ -175,4 +497,149 @@ mod tests {
     fn schema_name_no_extension() {
         assert_eq!(schema_name_from_key("schemas/bare", "schemas/"), "bare");
     }
+
+    const MINIMAL_SCHEMA: &str =
+        r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#;
+
+    #[test]
+    fn bom_prefixed_schema_parses() {
+        let yaml = "openapi: \"3.0.0\"\ninfo:\n  title: t\n  version: \"1\"\npaths: {}\n";
+        let body = [UTF8_BOM, yaml.as_bytes()].concat();
+        let api = parse_schema("schemas/bom.yaml", &body).unwrap();
+        assert_eq!(api.info.title, "t");
+    }
+
+    #[test]
+    fn unsupported_encryption_alg_fails_to_parse() {
+        let with = |alg: &str| {
+            format!(
+                r#"{{"openapi":"3.0.0","info":{{"title":"t","version":"1"}},"paths":{{}},"x-encryption-alg":{alg}}}"#
+            )
+        };
+        let api =
+            parse_schema("schemas/p.json", with(r#""chacha20-poly1305""#).as_bytes()).unwrap();
+        assert_eq!(
+            resolver::resolve_schema(&api).algorithm,
+            resolver::EncryptionAlg::ChaCha20Poly1305
+        );
+        for alg in [r#""AES-256-GCM""#, "true"] {
+            let err = parse_schema("schemas/p.json", with(alg).as_bytes()).unwrap_err();
+            assert!(err.to_string().contains("x-encryption-alg"), "{err}");
+        }
+    }
+
+    #[test]
+    fn binary_object_fails_strict_and_is_skipped_lenient() {
+        let blob = [0x89, b'P', b'N', b'G', 0xFF, 0xFE, 0x00];
+        let location = "s3://bucket/schemas/logo.png";
+        let mut skipped = Vec::new();
+        assert!(parse_or_skip("schemas/logo.png", location, &blob, false, &mut skipped).is_err());
+        assert!(skipped.is_empty());
+        assert!(
+            parse_or_skip("schemas/logo.png", location, &blob, true, &mut skipped)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].location, location);
+        assert!(skipped[0].error.contains("not valid UTF-8"), "{skipped:?}");
+    }
+
+    #[test]
+    fn malformed_yaml_is_skipped_lenient() {
+        let mut skipped = Vec::new();
+        let body = b"openapi: [unterminated";
+        assert!(parse_or_skip(
+            "schemas/bad.yaml",
+            "s3://b/schemas/bad.yaml",
+            body,
+            true,
+            &mut skipped
+        )
+        .unwrap()
+        .is_none());
+        assert_eq!(skipped.len(), 1);
+    }
+
+    fn loaded(name: &str, location: &str) -> LoadedSchema {
+        LoadedSchema {
+            name: name.into(),
+            source: location.trim_start_matches("s3://").into(),
+            location: location.into(),
+            api: parse_schema(location, MINIMAL_SCHEMA.as_bytes()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn two_sources_merge_into_one_cache() {
+        let merged = merge_sources(vec![
+            loaded("payments-v1", "s3://team-a/schemas/payments-v1.yaml"),
+            loaded("identity-v1", "s3://team-b/pii/identity-v1.yaml"),
+        ])
+        .unwrap();
+        let cache = SchemaCache::new();
+        cache.replace_all_sourced(merged).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("payments-v1").is_ok());
+        assert!(cache.get("identity-v1").is_ok());
+    }
+
+    #[test]
+    fn duplicate_names_across_sources_rejected() {
+        let err = merge_sources(vec![
+            loaded("payments-v1", "s3://team-a/schemas/payments-v1.yaml"),
+            loaded("payments-v1", "s3://team-b/pii/payments-v1.json"),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("team-a") && err.contains("team-b"), "{err}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refresh_retains_the_cache_on_failure_and_replaces_it_on_success() {
+        use std::collections::VecDeque;
+        use std::sync::{Arc, Mutex};
+
+        let schema = |name: &str| {
+            let api: OpenAPI = serde_json::from_str(MINIMAL_SCHEMA).unwrap();
+            let sourced = SourcedSchema {
+                source: "s3://bucket/schemas/".into(),
+                api,
+            };
+            HashMap::from([(name.to_owned(), sourced)])
+        };
+        let cache = SchemaCache::new();
+        cache.replace_all_sourced(schema("initial")).unwrap();
+
+        let fetches = Arc::new(Mutex::new(VecDeque::from([None, Some("refreshed")])));
+        let heartbeat = Heartbeat::new("schema_refresh");
+        let task = tokio::spawn(refresh_loop(Duration::from_secs(60), heartbeat, {
+            let (fetches, cache) = (fetches.clone(), cache.clone());
+            move || {
+                let next = fetches.lock().unwrap().pop_front().flatten();
+                let cache = cache.clone();
+                async move {
+                    let schemas = next.map(schema).context("S3 unavailable")?;
+                    cache.replace_all_sourced(schemas)?;
+                    Ok(())
+                }
+            }
+        }));
+
+        // No refresh before the first full interval.
+        time::sleep(Duration::from_secs(59)).await;
+        assert_eq!(fetches.lock().unwrap().len(), 2);
+
+        // The first refresh fails: the previous schemas stay.
+        time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(fetches.lock().unwrap().len(), 1);
+        assert!(cache.get("initial").is_ok());
+
+        // The second succeeds and replaces them.
+        time::sleep(Duration::from_secs(60)).await;
+        assert!(fetches.lock().unwrap().is_empty());
+        assert!(cache.get("refreshed").is_ok());
+        assert!(cache.get("initial").is_err());
+        task.abort();
+    }
 }
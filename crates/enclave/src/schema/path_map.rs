@@ -0,0 +1,126 @@
+//! The PII path map: one structured event emitted after every schema load.
+//!
+//! It records each cached schema with the number of PII paths it resolves
+//! to, so a governance pipeline ingesting the logs can track coverage over
+//! time; `/admin/schemas` and `/admin/schemas/by-path` answer live queries.
+//! The event carries names and counts only, never paths or payload data.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use tracing::info;
+
+use super::SchemaCache;
+
+/// Contents of the `pii_path_map` event.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct PathMap {
+    /// What caused the load: `load` (a full load, as at startup),
+    /// `refresh` or `reload:<source>`.
+    pub trigger: String,
+    /// PII path count of every cached schema, sorted by name; `null` for a
+    /// schema outside `EAGER_SCHEMAS` that no request has resolved yet.
+    pub schemas: BTreeMap<String, Option<usize>>,
+    /// Sum of the known counts.
+    pub total_paths: usize,
+}
+
+impl PathMap {
+    /// Build the map from the schemas `cache` now holds.
+    pub fn build(cache: &SchemaCache, trigger: impl Into<String>) -> Self {
+        let schemas = cache.pii_path_counts();
+        let total_paths = schemas.values().flatten().sum();
+        Self {
+            trigger: trigger.into(),
+            schemas,
+            total_paths,
+        }
+    }
+
+    /// Emit the map as a single `pii_path_map` log event.
+    pub fn emit(&self) {
+        let schemas = serde_json::to_string(&self.schemas).unwrap_or_default();
+        info!(
+            event = "pii_path_map",
+            trigger = %self.trigger,
+            schemas = %schemas,
+            schema_count = self.schemas.len(),
+            total_paths = self.total_paths,
+            "PII path map"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    const SCHEMA: &str = r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        email: { type: string, x-pii: true }
+        tier: { type: string }
+"#;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn event_carries_names_and_counts_but_no_paths() {
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(SCHEMA).unwrap();
+        let cache = SchemaCache::new()
+            .with_eager_schemas(Some(HashSet::from(["customers-v1".to_string()])));
+        cache.replace_all(HashMap::from([
+            ("customers-v1".to_string(), api.clone()),
+            ("customers-v2".to_string(), api),
+        ]));
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            PathMap::build(&cache, "load").emit();
+        });
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(logs.trim()).unwrap();
+        let fields = &line["fields"];
+        assert_eq!(fields["event"], "pii_path_map");
+        assert_eq!(fields["trigger"], "load");
+        assert_eq!(fields["schema_count"], 2);
+        assert_eq!(fields["total_paths"], 2);
+        // The lazy schema is reported without being resolved.
+        let schemas: serde_json::Value =
+            serde_json::from_str(fields["schemas"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            schemas,
+            serde_json::json!({"customers-v1": 2, "customers-v2": null})
+        );
+        assert!(!logs.contains("ssn") && !logs.contains("email"), "{logs}");
+
+        cache.get("customers-v2").unwrap();
+        assert_eq!(PathMap::build(&cache, "refresh").total_paths, 4);
+    }
+}
@@ -2,16 +2,275 @@
 //!
 //! Given a parsed [`openapiv3::OpenAPI`] document, this module produces the set of
 //! JSON pointer paths (dot-notation) to properties annotated with `x-pii: true`.
+//!
+//! String properties annotated with `x-pii-json: true` carry a JSON document
+//! serialised as a string. The sub-schema named by `x-pii-json-schema` is
+//! resolved separately and recorded in [`ResolvedSchema::embedded_json`].
+//!
+//! A PII property may additionally carry `x-pii-when: {"field": ..., "equals": ...}`
+//! to make encryption conditional on a sibling discriminator; these are recorded
+//! in [`ResolvedSchema::conditions`].
+//!
+//! A PII property annotated `x-pii-mode: hash` is hashed irreversibly rather
+//! than encrypted; such paths are recorded in [`ResolvedSchema::hashed`].
+//! One annotated `x-pii-mode: lookup` is encrypted and also given a
+//! deterministic lookup tag; see [`ResolvedSchema::lookup`]. One annotated
+//! `x-pii-mode: fpe` is replaced with a format-preserving token instead,
+//! unless it is an array element or recursive, which are encrypted; see
+//! [`ResolvedSchema::tokenized`].
+//!
+//! A property annotated `x-pii-recursive: true` is PII wherever a property of
+//! that name appears in a payload, at any depth and whatever the shape around
+//! it. It is recorded as the recursive path `**.<name>`.
+//!
+//! A `maxLength` on a PII string field is recorded in
+//! [`ResolvedSchema::max_lengths`] so oversized values can be rejected before
+//! encryption.
+//!
+//! An `allOf` composes its members in order. A later member's declaration of
+//! a path replaces an earlier one's, so a specialisation can un-mark a field
+//! its base marks with `x-pii: false` (or re-mark it after a base's `false`).
+//! The override is confined to the composition: a path also reached through
+//! another component stays PII if that component marks it.
+//!
+//! Why each path was selected — the component the walk started from, the
+//! `$ref`s it followed and where the annotation sat — is recorded in
+//! [`ResolvedSchema::provenance`]; see [`explain_pii_paths`].
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 use openapiv3::{OpenAPI, ReferenceOr, Schema, SchemaKind, Type};
+use serde::Deserialize;
 
 /// A set of dot-notation field paths that are marked as PII in the schema.
 ///
 /// Example paths: `"ssn"`, `"user.address.zip"`, `"orders[].card_number"`.
 pub type PiiFieldPaths = HashSet<String>;
 
+/// String fields holding an embedded JSON document, keyed by dot-notation path.
+///
+/// Each value is the resolution of the sub-schema describing the embedded
+/// document; its paths are relative to the embedded document's root.
+pub type EmbeddedJsonPaths = HashMap<String, ResolvedSchema>;
+
+/// Sibling conditions for conditionally-PII fields, keyed by dot-notation path.
+///
+/// Paths absent from this map are encrypted unconditionally.
+pub type PiiConditions = HashMap<String, PiiCondition>;
+
+/// Schema-declared `maxLength` (in characters) of PII string fields, keyed by
+/// dot-notation path. Paths absent from this map fall back to the global
+/// per-field byte limit.
+pub type PiiMaxLengths = HashMap<String, usize>;
+
+/// `x-pii-category` classification (e.g. `FINANCIAL`, `CONTACT`) of PII
+/// fields, keyed by dot-notation path.
+pub type PiiCategories = HashMap<String, String>;
+
+/// A simple sibling-equality condition from an `x-pii-when` annotation.
+///
+/// The annotated field is encrypted only when the sibling property `field` in
+/// the same object equals `equals`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PiiCondition {
+    /// Name of the sibling property to inspect.
+    pub field: String,
+    /// Value the sibling must hold for the annotated field to be encrypted.
+    pub equals: serde_json::Value,
+}
+
+impl PiiCondition {
+    /// Evaluate the condition against the object holding the annotated field.
+    pub fn matches(&self, object: &serde_json::Map<String, serde_json::Value>) -> bool {
+        object.get(&self.field) == Some(&self.equals)
+    }
+}
+
+/// Where the `x-pii` annotation that selected a path sits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationSite {
+    /// `x-pii` on the property itself.
+    Property,
+    /// `x-pii-recursive` on a property, matching its name at any depth.
+    Recursive,
+    /// `x-pii` on the `items` schema of an array.
+    ArrayItems,
+    /// `x-pii` on an array schema as a whole.
+    Array,
+}
+
+impl AnnotationSite {
+    /// Short description for the explain listing.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnnotationSite::Property => "x-pii on property",
+            AnnotationSite::Recursive => "x-pii-recursive on property",
+            AnnotationSite::ArrayItems => "x-pii on array items",
+            AnnotationSite::Array => "x-pii on array",
+        }
+    }
+}
+
+/// Why a PII path was selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathProvenance {
+    /// Top-level component the walk started from.
+    pub component: String,
+    /// Components reached through `$ref` on the way to the annotation, in
+    /// the order they were followed.
+    pub refs: Vec<String>,
+    /// Where the annotation sits.
+    pub annotation: AnnotationSite,
+}
+
+impl fmt::Display for PathProvenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.component)?;
+        for name in &self.refs {
+            write!(f, " -> {name}")?;
+        }
+        write!(f, "; {}", self.annotation.as_str())
+    }
+}
+
+/// Maximum nesting depth of `x-pii-json` documents inside one another.
+///
+/// Bounds resolution of self-referencing embedded schemas.
+const MAX_EMBEDDED_JSON_DEPTH: usize = 4;
+
+/// JSON kind a payload root must have for the schema's paths to apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootKind {
+    /// Paths descend from object properties (`"ssn"`, `"user.zip"`).
+    Object,
+    /// Paths descend from array items (`"[]"`, `"[].ssn"`).
+    Array,
+}
+
+impl RootKind {
+    /// Whether `value` has this kind.
+    pub fn matches(self, value: &serde_json::Value) -> bool {
+        match self {
+            RootKind::Object => value.is_object(),
+            RootKind::Array => value.is_array(),
+        }
+    }
+
+    /// Lower-case name for error messages.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RootKind::Object => "object",
+            RootKind::Array => "array",
+        }
+    }
+}
+
+/// Field encryption algorithm a schema selects with `x-encryption-alg`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncryptionAlg {
+    /// `AES-256-GCM-SIV`, used when the schema names none.
+    #[default]
+    Aes256GcmSiv,
+    /// `ChaCha20-Poly1305`.
+    ChaCha20Poly1305,
+}
+
+impl EncryptionAlg {
+    /// The algorithm called `name`, ignoring ASCII case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("AES-256-GCM-SIV") {
+            Some(Self::Aes256GcmSiv)
+        } else if name.eq_ignore_ascii_case("ChaCha20-Poly1305") {
+            Some(Self::ChaCha20Poly1305)
+        } else {
+            None
+        }
+    }
+}
+
+/// Everything the resolver derives from a single OpenAPI document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedSchema {
+    /// Paths whose string values are encrypted directly.
+    pub pii_paths: PiiFieldPaths,
+    /// String fields that contain double-encoded JSON with PII of their own.
+    pub embedded_json: EmbeddedJsonPaths,
+    /// Sibling conditions for the subset of `pii_paths` annotated `x-pii-when`.
+    pub conditions: PiiConditions,
+    /// `maxLength` of the subset of `pii_paths` that declare one.
+    pub max_lengths: PiiMaxLengths,
+    /// `x-pii-category` of the subset of `pii_paths` that declare one.
+    pub categories: PiiCategories,
+    /// Subset of `pii_paths` annotated `x-pii-mode: hash`.
+    pub hashed: PiiFieldPaths,
+    /// Subset of `pii_paths` annotated `x-pii-mode: lookup`: object properties
+    /// (not array elements) that get a lookup tag in a `<name>_lookup` sibling.
+    pub lookup: PiiFieldPaths,
+    /// Subset of `pii_paths` annotated `x-pii-mode: fpe`: object properties
+    /// (not array elements) whose token is authenticated by a tag in a
+    /// `<name>_fpe` sibling.
+    pub tokenized: PiiFieldPaths,
+    /// Subset of `pii_paths` whose schema type is `integer` or `number`.
+    pub numeric: PiiFieldPaths,
+    /// Expected payload root kind: [`RootKind::Object`] if any top-level
+    /// component is an object, else [`RootKind::Array`] if any is an array,
+    /// else `None` (no constraint).
+    pub root: Option<RootKind>,
+    /// Property names declared by the top-level object components, or `None`
+    /// when none declares any (a free-form schema).
+    pub top_level_keys: Option<HashSet<String>>,
+    /// Why each of `pii_paths` was selected. A path reachable from several
+    /// components keeps the first one walked.
+    pub provenance: HashMap<String, PathProvenance>,
+    /// Algorithm named by the document's `x-encryption-alg`, else the default.
+    pub algorithm: EncryptionAlg,
+    /// Paths explicitly annotated `x-pii: false`, which un-mark the same path
+    /// from an earlier `allOf` member.
+    pub unmarked: PiiFieldPaths,
+}
+
+impl ResolvedSchema {
+    /// Forget `path` and everything recorded about it.
+    fn unmark(&mut self, path: &str) {
+        self.pii_paths.remove(path);
+        self.conditions.remove(path);
+        self.max_lengths.remove(path);
+        self.categories.remove(path);
+        self.hashed.remove(path);
+        self.lookup.remove(path);
+        self.tokenized.remove(path);
+        self.numeric.remove(path);
+        self.provenance.remove(path);
+    }
+
+    /// Apply a later `allOf` member on top of the earlier ones: every path it
+    /// declares, PII or not, replaces what they recorded for that path.
+    fn overlay(&mut self, later: ResolvedSchema) {
+        for path in later.unmarked.iter().chain(&later.pii_paths) {
+            self.unmark(path);
+        }
+        self.absorb(later);
+    }
+
+    /// Union `other` into `self`, keeping existing provenance.
+    fn absorb(&mut self, other: ResolvedSchema) {
+        self.pii_paths.extend(other.pii_paths);
+        self.embedded_json.extend(other.embedded_json);
+        self.conditions.extend(other.conditions);
+        self.max_lengths.extend(other.max_lengths);
+        self.categories.extend(other.categories);
+        self.hashed.extend(other.hashed);
+        self.lookup.extend(other.lookup);
+        self.tokenized.extend(other.tokenized);
+        self.numeric.extend(other.numeric);
+        for (path, provenance) in other.provenance {
+            self.provenance.entry(path).or_insert(provenance);
+        }
+        self.unmarked.extend(other.unmarked);
+    }
+}
+
 /// Walk an [`OpenAPI`] document and collect all dot-notation paths to properties
 /// marked `x-pii: true`.
 ///
@@ -22,20 +281,197 @@ pub type PiiFieldPaths = HashSet<String>;
 /// Array items are represented with the `[]` suffix on the array field name
 /// (e.g. `"orders[].card_number"`, `"AddressLine[]"` for an array of PII strings).
 pub fn resolve_pii_paths(api: &OpenAPI) -> PiiFieldPaths {
-    let mut paths = HashSet::new();
+    resolve_schema(api).pii_paths
+}
+
+/// [`resolve_pii_paths`], with the reason each path was selected, sorted by
+/// path.
+pub fn explain_pii_paths(api: &OpenAPI) -> BTreeMap<String, PathProvenance> {
+    resolve_schema(api).provenance.into_iter().collect()
+}
+
+/// Maximum property nesting depth walked by [`declared_paths`].
+///
+/// Bounds the walk of self-referencing (`$ref` cycle) schemas.
+const MAX_DECLARED_DEPTH: usize = 32;
+
+/// Every property path the document declares, PII or not, in the notation of
+/// [`resolve_pii_paths`]. Array properties appear without the `[]` suffix;
+/// properties of their items follow it (`"orders[].card_number"`).
+pub fn declared_paths(api: &OpenAPI) -> PiiFieldPaths {
+    let mut out = PiiFieldPaths::new();
+    if let Some(components) = &api.components {
+        for schema_ref in components.schemas.values() {
+            if let ReferenceOr::Item(schema) = schema_ref {
+                walk_declared(api, schema, "", 0, &mut out);
+            }
+        }
+    }
+    out
+}
+
+/// Recursively collect property paths for [`declared_paths`].
+fn walk_declared(
+    api: &OpenAPI,
+    schema: &Schema,
+    prefix: &str,
+    depth: usize,
+    out: &mut PiiFieldPaths,
+) {
+    if depth > MAX_DECLARED_DEPTH {
+        return;
+    }
+    match &schema.schema_kind {
+        SchemaKind::Type(Type::Object(obj)) => {
+            for (prop_name, prop_ref) in &obj.properties {
+                let path = if prefix.is_empty() {
+                    prop_name.clone()
+                } else {
+                    format!("{prefix}.{prop_name}")
+                };
+                let resolved = match prop_ref {
+                    ReferenceOr::Item(s) => Some(s.as_ref()),
+                    ReferenceOr::Reference { reference } => resolve_ref(api, reference),
+                };
+                if let Some(prop_schema) = resolved {
+                    walk_declared(api, prop_schema, &path, depth + 1, out);
+                }
+                out.insert(path);
+            }
+        }
+        SchemaKind::Type(Type::Array(arr)) => {
+            let resolved = match &arr.items {
+                Some(ReferenceOr::Item(s)) => Some(s.as_ref()),
+                Some(ReferenceOr::Reference { reference }) => resolve_ref(api, reference),
+                None => None,
+            };
+            if let Some(items_schema) = resolved {
+                walk_declared(api, items_schema, &format!("{prefix}[]"), depth + 1, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// PII paths that changed between two versions of a schema.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PiiPathDiff {
+    /// Paths that are PII in the new version only, sorted.
+    pub added: Vec<String>,
+    /// Paths that were PII in the old version only, sorted.
+    pub removed: Vec<String>,
+}
+
+impl PiiPathDiff {
+    /// Whether both versions resolve to the same PII paths.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Compare the PII paths of two versions of a schema.
+///
+/// Only the direct paths from [`resolve_pii_paths`] are compared, so a review
+/// of a schema bump shows exactly which fields start or stop being encrypted.
+pub fn diff_pii_paths(old: &OpenAPI, new: &OpenAPI) -> PiiPathDiff {
+    let old = resolve_pii_paths(old);
+    let new = resolve_pii_paths(new);
+    let mut added: Vec<String> = new.difference(&old).cloned().collect();
+    let mut removed: Vec<String> = old.difference(&new).cloned().collect();
+    added.sort();
+    removed.sort();
+    PiiPathDiff { added, removed }
+}
+
+/// Walk an [`OpenAPI`] document and collect both direct PII paths and
+/// embedded-JSON fields (`x-pii-json: true`).
+///
+/// See [`resolve_pii_paths`] for the path notation.
+pub fn resolve_schema(api: &OpenAPI) -> ResolvedSchema {
+    let mut out = ResolvedSchema {
+        // Unknown names are rejected when the document is parsed.
+        algorithm: encryption_algorithm(api).unwrap_or_default(),
+        ..ResolvedSchema::default()
+    };
 
     let components = match &api.components {
         Some(c) => c,
-        None => return paths,
+        None => return out,
     };
 
-    for (_name, schema_ref) in &components.schemas {
+    for (name, schema_ref) in &components.schemas {
         if let ReferenceOr::Item(schema) = schema_ref {
-            walk_schema(api, schema, "", &mut paths);
+            let trail = Trail {
+                component: name,
+                refs: Vec::new(),
+            };
+            walk_schema(api, schema, "", 0, &trail, &mut out);
+            if let SchemaKind::Type(Type::Object(obj)) = &schema.schema_kind {
+                if !obj.properties.is_empty() {
+                    out.top_level_keys
+                        .get_or_insert_with(HashSet::new)
+                        .extend(obj.properties.keys().cloned());
+                }
+            }
+            out.root = match (&schema.schema_kind, out.root) {
+                (SchemaKind::Type(Type::Object(_)), _) => Some(RootKind::Object),
+                (SchemaKind::Type(Type::Array(_)), None) => Some(RootKind::Array),
+                (_, root) => root,
+            };
         }
     }
 
-    paths
+    out
+}
+
+/// The algorithm named by the document's top-level `x-encryption-alg`
+/// extension (e.g. `ChaCha20-Poly1305`), or the default when it has none.
+///
+/// # Errors
+///
+/// Returns the extension's value when it is not the name of a supported
+/// [`EncryptionAlg`].
+pub fn encryption_algorithm(api: &OpenAPI) -> Result<EncryptionAlg, String> {
+    let Some(value) = api.extensions.get("x-encryption-alg") else {
+        return Ok(EncryptionAlg::default());
+    };
+    value
+        .as_str()
+        .and_then(EncryptionAlg::from_name)
+        .ok_or_else(|| value.to_string())
+}
+
+/// The route a walk took from its top-level component, for
+/// [`ResolvedSchema::provenance`].
+struct Trail<'a> {
+    component: &'a str,
+    refs: Vec<&'a str>,
+}
+
+impl<'a> Trail<'a> {
+    /// The trail extended by following `reference`, if it is a `$ref`.
+    fn follow(&self, reference: Option<&'a str>) -> Trail<'a> {
+        let mut refs = self.refs.clone();
+        if let Some(reference) = reference {
+            refs.push(
+                reference
+                    .strip_prefix("#/components/schemas/")
+                    .unwrap_or(reference),
+            );
+        }
+        Trail {
+            component: self.component,
+            refs,
+        }
+    }
+
+    fn provenance(&self, annotation: AnnotationSite) -> PathProvenance {
+        PathProvenance {
+            component: self.component.to_owned(),
+            refs: self.refs.iter().map(|r| (*r).to_owned()).collect(),
+            annotation,
+        }
+    }
 }
 
 /// Resolve a `$ref` string (e.g. `"#/components/schemas/Foo"`) to the
@@ -52,15 +488,127 @@ fn resolve_ref<'a>(api: &'a OpenAPI, reference: &str) -> Option<&'a Schema> {
     }
 }
 
+/// Return `true` if `schema` carries the boolean extension `name` set to `true`.
+fn has_flag(schema: &Schema, name: &str) -> bool {
+    schema
+        .schema_data
+        .extensions
+        .get(name)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Return `true` if `schema` explicitly carries `x-pii: false`.
+fn is_unmarked(schema: &Schema) -> bool {
+    schema.schema_data.extensions.get("x-pii") == Some(&serde_json::Value::Bool(false))
+}
+
+/// Parse a property's `x-pii-when` extension, if present.
+///
+/// A malformed annotation yields `None`, so the field falls back to
+/// unconditional encryption rather than silently leaking plaintext.
+fn pii_condition(schema: &Schema) -> Option<PiiCondition> {
+    let raw = schema.schema_data.extensions.get("x-pii-when")?;
+    serde_json::from_value(raw.clone()).ok()
+}
+
+/// A property's `x-pii-category` classification, if declared.
+fn pii_category(schema: &Schema) -> Option<String> {
+    schema
+        .schema_data
+        .extensions
+        .get("x-pii-category")?
+        .as_str()
+        .map(str::to_owned)
+}
+
+/// Whether a property is annotated `x-pii-mode: hash`.
+///
+/// Any other mode (including the implicit default, `encrypt`) keeps the field
+/// reversibly encrypted.
+fn is_hash_mode(schema: &Schema) -> bool {
+    pii_mode(schema) == Some("hash")
+}
+
+/// Whether a property is annotated `x-pii-mode: lookup`.
+fn is_lookup_mode(schema: &Schema) -> bool {
+    pii_mode(schema) == Some("lookup")
+}
+
+/// Whether a property is annotated `x-pii-mode: fpe`.
+fn is_fpe_mode(schema: &Schema) -> bool {
+    pii_mode(schema) == Some("fpe")
+}
+
+/// A property's `x-pii-mode` annotation, if it is a string.
+fn pii_mode(schema: &Schema) -> Option<&str> {
+    schema
+        .schema_data
+        .extensions
+        .get("x-pii-mode")
+        .and_then(|v| v.as_str())
+}
+
+/// Whether a schema is typed `integer` or `number`.
+fn is_numeric(schema: &Schema) -> bool {
+    matches!(
+        schema.schema_kind,
+        SchemaKind::Type(Type::Integer(_) | Type::Number(_))
+    )
+}
+
+/// The `maxLength` of a string schema, if declared.
+fn max_length(schema: &Schema) -> Option<usize> {
+    match &schema.schema_kind {
+        SchemaKind::Type(Type::String(s)) => s.max_length,
+        _ => None,
+    }
+}
+
+/// Resolve the sub-schema named by a property's `x-pii-json-schema` extension.
+///
+/// Accepts either a bare component name (`"Inner"`) or a full reference
+/// (`"#/components/schemas/Inner"`).
+fn embedded_schema<'a>(api: &'a OpenAPI, schema: &Schema) -> Option<&'a Schema> {
+    let name = schema
+        .schema_data
+        .extensions
+        .get("x-pii-json-schema")?
+        .as_str()?;
+    if name.starts_with("#/") {
+        resolve_ref(api, name)
+    } else {
+        resolve_ref(api, &format!("#/components/schemas/{name}"))
+    }
+}
+
 /// Recursively walk a [`Schema`], appending discovered PII paths to `out`.
 ///
 /// - **Object properties**: each property is walked; `$ref` properties are
 ///   resolved via [`resolve_ref`] and walked transitively.
 /// - **Array items**: if the items schema carries `x-pii: true` (e.g. an array
 ///   of PII strings), the array path itself (with `[]` suffix) is emitted.
-///   Items are also walked recursively for arrays of objects with nested PII.
-///   `$ref` items are resolved before walking.
-fn walk_schema(api: &OpenAPI, schema: &Schema, prefix: &str, out: &mut PiiFieldPaths) {
+///   The same applies when the array schema itself is marked `x-pii: true`
+///   ("encrypt every element"); the item annotations take precedence over the
+///   array's. Items are also walked recursively for arrays of objects with
+///   nested PII. `$ref` items are resolved before walking.
+/// - **Composition**: the members of an `allOf` are walked in order, each
+///   overlaying the ones before it (see [`ResolvedSchema::unmarked`]).
+/// - **Embedded JSON**: a property with `x-pii-json: true` has the sub-schema
+///   named by `x-pii-json-schema` resolved from its own root and recorded in
+///   [`ResolvedSchema::embedded_json`]. `depth` counts how many embedded
+///   documents enclose the current walk.
+///
+/// `trail` records the `$ref`s followed so far for
+/// [`ResolvedSchema::provenance`].
+fn walk_schema<'a>(
+    api: &'a OpenAPI,
+    schema: &'a Schema,
+    prefix: &str,
+    depth: usize,
+    trail: &Trail<'a>,
+    out: &mut ResolvedSchema,
+) {
     match &schema.schema_kind {
         SchemaKind::Type(Type::Object(obj)) => {
             for (prop_name, prop_ref) in &obj.properties {
@@ -70,24 +618,71 @@ fn walk_schema(api: &OpenAPI, schema: &Schema, prefix: &str, out: &mut PiiFieldP
                     format!("{prefix}.{prop_name}")
                 };
 
-                let resolved: Option<&Schema> = match prop_ref {
-                    ReferenceOr::Item(s) => Some(s.as_ref()),
-                    ReferenceOr::Reference { reference } => resolve_ref(api, reference),
+                let (resolved, reference): (Option<&Schema>, _) = match prop_ref {
+                    ReferenceOr::Item(s) => (Some(s.as_ref()), None),
+                    ReferenceOr::Reference { reference } => {
+                        (resolve_ref(api, reference), Some(reference.as_str()))
+                    }
                 };
+                let trail = trail.follow(reference);
 
                 if let Some(prop_schema) = resolved {
-                    let is_pii = prop_schema
-                        .schema_data
-                        .extensions
-                        .get("x-pii")
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false);
-
-                    if is_pii {
-                        out.insert(path.clone());
+                    // An `x-pii` array is emitted element-wise (`path[]`) by the
+                    // array branch below; the bare path would never match a leaf.
+                    let is_array =
+                        matches!(prop_schema.schema_kind, SchemaKind::Type(Type::Array(_)));
+                    let recursive = has_flag(prop_schema, "x-pii-recursive");
+                    if (has_flag(prop_schema, "x-pii") || recursive) && !is_array {
+                        let pii_path = if recursive {
+                            format!("**.{prop_name}")
+                        } else {
+                            path.clone()
+                        };
+                        if let Some(condition) = pii_condition(prop_schema) {
+                            out.conditions.insert(pii_path.clone(), condition);
+                        }
+                        if let Some(max) = max_length(prop_schema) {
+                            out.max_lengths.insert(pii_path.clone(), max);
+                        }
+                        if let Some(category) = pii_category(prop_schema) {
+                            out.categories.insert(pii_path.clone(), category);
+                        }
+                        if is_hash_mode(prop_schema) {
+                            out.hashed.insert(pii_path.clone());
+                        }
+                        // Lookup and token tag siblings are placed by exact
+                        // path only.
+                        if is_fpe_mode(prop_schema) && !recursive {
+                            out.tokenized.insert(pii_path.clone());
+                        }
+                        if is_lookup_mode(prop_schema) && !recursive {
+                            out.lookup.insert(pii_path.clone());
+                        }
+                        if is_numeric(prop_schema) {
+                            out.numeric.insert(pii_path.clone());
+                        }
+                        let site = if recursive {
+                            AnnotationSite::Recursive
+                        } else {
+                            AnnotationSite::Property
+                        };
+                        out.provenance
+                            .entry(pii_path.clone())
+                            .or_insert_with(|| trail.provenance(site));
+                        out.pii_paths.insert(pii_path);
+                    } else if is_unmarked(prop_schema) && !is_array {
+                        out.unmarked.insert(path.clone());
+                    }
+
+                    if has_flag(prop_schema, "x-pii-json") && depth < MAX_EMBEDDED_JSON_DEPTH {
+                        if let Some(inner_schema) = embedded_schema(api, prop_schema) {
+                            let mut inner = ResolvedSchema::default();
+                            walk_schema(api, inner_schema, "", depth + 1, &trail, &mut inner);
+                            out.embedded_json.insert(path.clone(), inner);
+                        }
                     }
 
-                    walk_schema(api, prop_schema, &path, out);
+                    walk_schema(api, prop_schema, &path, depth, &trail, out);
                 }
             }
         }
@@ -99,28 +694,70 @@ fn walk_schema(api: &OpenAPI, schema: &Schema, prefix: &str, out: &mut PiiFieldP
                     format!("{prefix}[]")
                 };
 
-                let resolved: Option<&Schema> = match items_ref {
-                    ReferenceOr::Item(s) => Some(s.as_ref()),
-                    ReferenceOr::Reference { reference } => resolve_ref(api, reference),
+                let (resolved, reference): (Option<&Schema>, _) = match items_ref {
+                    ReferenceOr::Item(s) => (Some(s.as_ref()), None),
+                    ReferenceOr::Reference { reference } => {
+                        (resolve_ref(api, reference), Some(reference.as_str()))
+                    }
                 };
+                let trail = trail.follow(reference);
 
                 if let Some(items_schema) = resolved {
                     // If the items themselves carry `x-pii: true` (e.g. an array
-                    // of PII strings like AddressLine[]), emit the array path.
-                    let items_is_pii = items_schema
-                        .schema_data
-                        .extensions
-                        .get("x-pii")
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false);
-
-                    if items_is_pii {
-                        out.insert(array_path.clone());
+                    // of PII strings like AddressLine[]), or the array is marked
+                    // as a whole, emit the array path.
+                    let annotated = if has_flag(items_schema, "x-pii") {
+                        Some((items_schema, AnnotationSite::ArrayItems))
+                    } else if has_flag(schema, "x-pii") {
+                        Some((schema, AnnotationSite::Array))
+                    } else {
+                        None
+                    };
+                    if let Some((annotated, site)) = annotated {
+                        out.pii_paths.insert(array_path.clone());
+                        out.provenance
+                            .entry(array_path.clone())
+                            .or_insert_with(|| trail.provenance(site));
+                        if let Some(condition) = pii_condition(annotated) {
+                            out.conditions.insert(array_path.clone(), condition);
+                        }
+                        if let Some(max) = max_length(items_schema) {
+                            out.max_lengths.insert(array_path.clone(), max);
+                        }
+                        if let Some(category) = pii_category(annotated) {
+                            out.categories.insert(array_path.clone(), category);
+                        }
+                        if is_hash_mode(annotated) {
+                            out.hashed.insert(array_path.clone());
+                        }
+                        if is_numeric(items_schema) {
+                            out.numeric.insert(array_path.clone());
+                        }
+                    } else if is_unmarked(items_schema) || is_unmarked(schema) {
+                        out.unmarked.insert(array_path.clone());
                     }
 
-                    walk_schema(api, items_schema, &array_path, out);
+                    walk_schema(api, items_schema, &array_path, depth, &trail, out);
+                }
+            }
+        }
+        SchemaKind::AllOf { all_of } => {
+            let mut composed = ResolvedSchema::default();
+            for member_ref in all_of {
+                let (resolved, reference) = match member_ref {
+                    ReferenceOr::Item(s) => (Some(s), None),
+                    ReferenceOr::Reference { reference } => {
+                        (resolve_ref(api, reference), Some(reference.as_str()))
+                    }
+                };
+                let trail = trail.follow(reference);
+                if let Some(member) = resolved {
+                    let mut part = ResolvedSchema::default();
+                    walk_schema(api, member, prefix, depth, &trail, &mut part);
+                    composed.overlay(part);
                 }
             }
+            out.absorb(composed);
         }
         _ => {}
     }
@@ -161,6 +798,34 @@ components:
         assert!(!paths.contains("name"), "unexpected 'name' in {paths:?}");
     }
 
+    #[test]
+    fn recursive_pii_field_emits_wildcard_path() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: test
+  version: "1"
+paths: {}
+components:
+  schemas:
+    Transfer:
+      type: object
+      properties:
+        details:
+          type: object
+          properties:
+            accountId:
+              type: string
+              x-pii-recursive: true
+              x-pii-mode: lookup
+              maxLength: 34
+"#;
+        let resolved = resolve_schema(&parse_api(yaml));
+        assert_eq!(resolved.pii_paths, ["**.accountId".to_string()].into());
+        assert_eq!(resolved.max_lengths.get("**.accountId"), Some(&34));
+        assert!(resolved.lookup.is_empty());
+    }
+
     #[test]
     fn nested_pii_field_detected() {
         let yaml = r#"
@@ -240,6 +905,85 @@ components:
         assert!(!paths.contains("amount"), "{paths:?}");
     }
 
+    #[test]
+    fn explain_reports_ref_chain_and_annotation_site() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Address:
+      type: object
+      properties:
+        street: { type: string, x-pii: true }
+    Party:
+      type: object
+      properties:
+        address: { $ref: '#/components/schemas/Address' }
+    Payment:
+      type: object
+      properties:
+        creditor: { $ref: '#/components/schemas/Party' }
+        lines:
+          type: array
+          items: { type: string, x-pii: true }
+"#,
+        );
+        let explained = explain_pii_paths(&api);
+        assert_eq!(
+            explained["creditor.address.street"],
+            PathProvenance {
+                component: "Payment".into(),
+                refs: vec!["Party".into(), "Address".into()],
+                annotation: AnnotationSite::Property,
+            }
+        );
+        assert_eq!(
+            explained["creditor.address.street"].to_string(),
+            "Payment -> Party -> Address; x-pii on property"
+        );
+        assert_eq!(
+            explained["lines[]"],
+            PathProvenance {
+                component: "Payment".into(),
+                refs: vec![],
+                annotation: AnnotationSite::ArrayItems,
+            }
+        );
+        // Walking `Address` on its own selects `street` directly.
+        assert!(explained["street"].refs.is_empty());
+        let paths: Vec<_> = resolve_pii_paths(&api).into_iter().collect();
+        assert_eq!(explained.len(), paths.len());
+    }
+
+    #[test]
+    fn declared_paths_include_non_pii_properties() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Node:
+      type: object
+      properties:
+        name: { type: string }
+        children:
+          type: array
+          items: { $ref: '#/components/schemas/Node' }
+"#,
+        );
+        let declared = declared_paths(&api);
+        assert!(declared.contains("name"));
+        assert!(declared.contains("children"));
+        assert!(declared.contains("children[].name"));
+        // The `$ref` cycle is cut off rather than walked forever.
+        assert!(declared.len() < 100);
+    }
+
     // ── array item PII ────────────────────────────────────────────────────────
 
     /// An array whose items have `x-pii: true` (e.g. an array of PII strings)
@@ -348,6 +1092,200 @@ components:
         );
     }
 
+    // ── embedded JSON ─────────────────────────────────────────────────────────
+
+    /// A string property with `x-pii-json: true` records the named sub-schema's
+    /// PII paths relative to the embedded document, not the outer payload.
+    #[test]
+    fn embedded_json_sub_schema_resolved() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: test
+  version: "1"
+paths: {}
+components:
+  schemas:
+    Inner:
+      type: object
+      properties:
+        ssn:
+          type: string
+          x-pii: true
+        note:
+          type: string
+    Envelope:
+      type: object
+      properties:
+        metadata:
+          type: string
+          x-pii-json: true
+          x-pii-json-schema: Inner
+        legacy:
+          type: string
+          x-pii-json: true
+          x-pii-json-schema: '#/components/schemas/Inner'
+"#;
+        let api = parse_api(yaml);
+        let resolved = resolve_schema(&api);
+        let inner = resolved.embedded_json.get("metadata").expect("metadata");
+        assert!(inner.pii_paths.contains("ssn"), "{inner:?}");
+        assert!(!inner.pii_paths.contains("note"), "{inner:?}");
+        assert!(resolved.embedded_json.contains_key("legacy"));
+        // The container string itself is not encrypted as a whole.
+        assert!(!resolved.pii_paths.contains("metadata"));
+    }
+
+    /// A self-embedding schema must terminate rather than recurse forever.
+    #[test]
+    fn embedded_json_self_reference_terminates() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: test
+  version: "1"
+paths: {}
+components:
+  schemas:
+    Node:
+      type: object
+      properties:
+        child:
+          type: string
+          x-pii-json: true
+          x-pii-json-schema: Node
+"#;
+        let api = parse_api(yaml);
+        let mut depth = 0;
+        let mut cur = resolve_schema(&api);
+        while let Some(next) = cur.embedded_json.remove("child") {
+            depth += 1;
+            cur = next;
+        }
+        assert_eq!(depth, MAX_EMBEDDED_JSON_DEPTH);
+    }
+
+    /// A later `allOf` member's `x-pii: false` un-marks a path its base marks,
+    /// but only within the composition.
+    #[test]
+    fn all_of_false_overrides_inherited_pii() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: test
+  version: "1"
+paths: {}
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        email: { type: string, x-pii: true, x-pii-mode: hash }
+        name: { type: string, x-pii: true }
+    Directory:
+      type: object
+      properties:
+        listed:
+          allOf:
+            - $ref: '#/components/schemas/Person'
+            - type: object
+              properties:
+                email: { type: string, x-pii: false }
+"#;
+        let resolved = resolve_schema(&parse_api(yaml));
+        assert!(!resolved.pii_paths.contains("listed.email"), "{resolved:?}");
+        assert!(!resolved.hashed.contains("listed.email"));
+        assert!(resolved.pii_paths.contains("listed.name"));
+        assert_eq!(
+            resolved.provenance["listed.name"].to_string(),
+            "Directory -> Person; x-pii on property"
+        );
+        // `Person` walked on its own still marks `email`.
+        assert!(resolved.pii_paths.contains("email"));
+    }
+
+    /// An `x-pii: false` earlier in an `allOf` than the member marking the path
+    /// does not suppress it.
+    #[test]
+    fn all_of_false_before_base_does_not_override() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: test
+  version: "1"
+paths: {}
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        email: { type: string, x-pii: true }
+    Directory:
+      type: object
+      properties:
+        listed:
+          allOf:
+            - type: object
+              properties:
+                email: { type: string, x-pii: false }
+            - $ref: '#/components/schemas/Person'
+        relisted:
+          allOf:
+            - $ref: '#/components/schemas/Person'
+            - type: object
+              properties:
+                email: { type: string, x-pii: false }
+            - type: object
+              properties:
+                email: { type: string, x-pii: true, x-pii-mode: fpe }
+"#;
+        let resolved = resolve_schema(&parse_api(yaml));
+        assert!(resolved.pii_paths.contains("listed.email"), "{resolved:?}");
+        assert!(resolved.pii_paths.contains("relisted.email"));
+        assert!(resolved.tokenized.contains("relisted.email"));
+    }
+
+    #[test]
+    fn pii_when_condition_resolved() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: test
+  version: "1"
+paths: {}
+components:
+  schemas:
+    Identity:
+      type: object
+      properties:
+        id_type:
+          type: string
+        id_number:
+          type: string
+          x-pii: true
+          x-pii-when:
+            field: id_type
+            equals: SSN
+        broken:
+          type: string
+          x-pii: true
+          x-pii-when: SSN
+"#;
+        let api = parse_api(yaml);
+        let resolved = resolve_schema(&api);
+        assert!(resolved.pii_paths.contains("id_number"));
+        assert_eq!(
+            resolved.conditions.get("id_number"),
+            Some(&PiiCondition {
+                field: "id_type".into(),
+                equals: serde_json::json!("SSN"),
+            })
+        );
+        // A malformed condition falls back to unconditional encryption.
+        assert!(resolved.pii_paths.contains("broken"));
+        assert!(!resolved.conditions.contains_key("broken"));
+    }
+
     // ── iso-20022.yaml integration tests ──────────────────────────────────────
 
     const ISO_20022_YAML: &str = include_str!("../../../../config/iso-20022.yaml");
@@ -415,4 +1353,306 @@ components:
             );
         }
     }
+
+    #[test]
+    fn root_kind_follows_top_level_components() {
+        let object = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Names:
+      type: array
+      items: { type: string }
+    Person:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        );
+        assert_eq!(resolve_schema(&object).root, Some(RootKind::Object));
+
+        let array = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Names:
+      type: array
+      items: { type: string, x-pii: true }
+"#,
+        );
+        assert_eq!(resolve_schema(&array).root, Some(RootKind::Array));
+
+        let empty = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+"#,
+        );
+        assert_eq!(resolve_schema(&empty).root, None);
+    }
+
+    #[test]
+    fn top_level_keys_union_object_components() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        address:
+          type: object
+          properties:
+            city: { type: string }
+    Account:
+      type: object
+      properties:
+        iban: { type: string }
+    FreeForm:
+      type: object
+"#,
+        );
+        let keys = resolve_schema(&api).top_level_keys.unwrap();
+        let mut keys: Vec<_> = keys.into_iter().collect();
+        keys.sort();
+        assert_eq!(keys, ["address", "iban", "ssn"]);
+
+        let free_form = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Anything: { type: object }
+"#,
+        );
+        assert_eq!(resolve_schema(&free_form).top_level_keys, None);
+    }
+
+    #[test]
+    fn max_length_captured_for_pii_strings() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true, maxLength: 11 }
+        name: { type: string, x-pii: true }
+        nickname: { type: string, maxLength: 20 }
+        aliases:
+          type: array
+          items: { type: string, x-pii: true, maxLength: 64 }
+"#,
+        );
+        let resolved = resolve_schema(&api);
+        assert_eq!(
+            resolved.max_lengths,
+            PiiMaxLengths::from([("ssn".into(), 11), ("aliases[]".into(), 64)])
+        );
+    }
+
+    #[test]
+    fn pii_category_captured() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Account:
+      type: object
+      properties:
+        iban: { type: string, x-pii: true, x-pii-category: FINANCIAL }
+        email: { type: string, x-pii: true, x-pii-category: CONTACT }
+        name: { type: string, x-pii: true }
+"#,
+        );
+        let resolved = resolve_schema(&api);
+        assert_eq!(
+            resolved.categories,
+            PiiCategories::from([
+                ("iban".into(), "FINANCIAL".into()),
+                ("email".into(), "CONTACT".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn diff_reports_added_and_removed_paths() {
+        let v1 = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        fax: { type: string, x-pii: true }
+        name: { type: string }
+"#,
+        );
+        let v2 = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "2" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        email: { type: string, x-pii: true }
+        name: { type: string }
+"#,
+        );
+        let diff = diff_pii_paths(&v1, &v2);
+        assert_eq!(
+            diff,
+            PiiPathDiff {
+                added: vec!["email".into()],
+                removed: vec!["fax".into()],
+            }
+        );
+        assert!(!diff.is_empty());
+        assert!(diff_pii_paths(&v2, &v2).is_empty());
+    }
+
+    #[test]
+    fn hash_mode_captured() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Contact:
+      type: object
+      properties:
+        email: { type: string, x-pii: true, x-pii-mode: hash }
+        phone: { type: string, x-pii: true, x-pii-mode: encrypt }
+        aliases:
+          type: array
+          items: { type: string, x-pii: true, x-pii-mode: hash }
+"#,
+        );
+        let resolved = resolve_schema(&api);
+        assert_eq!(resolved.pii_paths.len(), 3);
+        assert_eq!(
+            resolved.hashed,
+            PiiFieldPaths::from(["email".into(), "aliases[]".into()])
+        );
+    }
+
+    #[test]
+    fn fpe_mode_captured_for_properties_only() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Card:
+      type: object
+      properties:
+        number: { type: string, x-pii: true, x-pii-mode: fpe }
+        holder: { type: string, x-pii: true }
+        previous:
+          type: array
+          items: { type: string, x-pii: true, x-pii-mode: fpe }
+"#,
+        );
+        let resolved = resolve_schema(&api);
+        assert_eq!(resolved.pii_paths.len(), 3);
+        // Array elements have no sibling to hold a token tag, so they are
+        // encrypted.
+        assert_eq!(resolved.tokenized, PiiFieldPaths::from(["number".into()]));
+        assert!(resolved.hashed.is_empty());
+    }
+
+    #[test]
+    fn lookup_mode_captured_for_properties_only() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Contact:
+      type: object
+      properties:
+        email: { type: string, x-pii: true, x-pii-mode: lookup }
+        phone: { type: string, x-pii: true }
+        aliases:
+          type: array
+          items: { type: string, x-pii: true, x-pii-mode: lookup }
+"#,
+        );
+        let resolved = resolve_schema(&api);
+        assert_eq!(resolved.pii_paths.len(), 3);
+        assert_eq!(resolved.lookup, PiiFieldPaths::from(["email".into()]));
+        assert!(resolved.hashed.is_empty());
+    }
+
+    #[test]
+    fn array_level_pii_flag_encrypts_every_element() {
+        let api = parse_api(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        aliases:
+          type: array
+          x-pii: true
+          x-pii-category: IDENTITY
+          items: { type: string, maxLength: 64 }
+        tags:
+          type: array
+          items: { type: string }
+"#,
+        );
+        let resolved = resolve_schema(&api);
+        assert_eq!(
+            resolved.pii_paths,
+            PiiFieldPaths::from(["aliases[]".into()])
+        );
+        assert_eq!(resolved.max_lengths.get("aliases[]"), Some(&64));
+        assert_eq!(
+            resolved.categories.get("aliases[]").map(String::as_str),
+            Some("IDENTITY")
+        );
+        assert_eq!(
+            resolved.provenance["aliases[]"].annotation,
+            AnnotationSite::Array
+        );
+    }
 }
@@ -0,0 +1,181 @@
+//! Coalescing of concurrent identical schema loads.
+//!
+//! A [`SingleFlight`] runs at most one operation at a time. A caller that
+//! arrives while an identical run ([`run`](SingleFlight::run)) is in flight
+//! waits for it and receives a clone of its result instead of starting a
+//! second one; [`exclusive`](SingleFlight::exclusive) operations wait their
+//! turn without sharing results.
+
+use std::future::Future;
+use std::sync::{Mutex, PoisonError};
+
+use tokio::sync::broadcast;
+
+/// Single-flight guard for one kind of operation producing `T`.
+#[derive(Debug)]
+pub struct SingleFlight<T> {
+    /// Result channel of the run in flight, if any; followers subscribe.
+    inflight: Mutex<Option<broadcast::Sender<T>>>,
+    /// Held by whichever operation is running.
+    running: tokio::sync::Mutex<()>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self {
+            inflight: Mutex::new(None),
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+}
+
+/// Clears the in-flight slot if the leading run's future is dropped before
+/// it finishes, so followers do not wait forever.
+struct Leader<'a, T> {
+    flight: &'a SingleFlight<T>,
+    finished: bool,
+}
+
+impl<T> Leader<'_, T> {
+    /// Take the slot's sender; the next caller leads a new run.
+    fn finish(mut self) -> Option<broadcast::Sender<T>> {
+        self.finished = true;
+        self.flight.slot().take()
+    }
+}
+
+impl<T> Drop for Leader<'_, T> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.flight.slot().take();
+        }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    /// Run `f`, or join the run already in flight and return its result.
+    ///
+    /// If the leading run is cancelled before finishing, its followers start
+    /// a new one, led by whichever of them gets there first.
+    pub async fn run<F, Fut>(&self, f: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        loop {
+            let follower = {
+                let mut slot = self.slot();
+                match &*slot {
+                    Some(tx) => Some(tx.subscribe()),
+                    None => {
+                        *slot = Some(broadcast::channel(1).0);
+                        None
+                    }
+                }
+            };
+            match follower {
+                Some(mut rx) => {
+                    if let Ok(out) = rx.recv().await {
+                        return out;
+                    }
+                }
+                None => {
+                    let leader = Leader {
+                        flight: self,
+                        finished: false,
+                    };
+                    let _running = self.running.lock().await;
+                    let out = f().await;
+                    if let Some(tx) = leader.finish() {
+                        // No followers is not an error.
+                        let _ = tx.send(out.clone());
+                    }
+                    return out;
+                }
+            }
+        }
+    }
+
+    /// Run `f` once no other operation is running, without sharing its
+    /// result.
+    pub async fn exclusive<F, Fut, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let _running = self.running.lock().await;
+        f().await
+    }
+}
+
+impl<T> SingleFlight<T> {
+    fn slot(&self) -> std::sync::MutexGuard<'_, Option<broadcast::Sender<T>>> {
+        self.inflight.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn concurrent_runs_share_one_load() {
+        let flight = Arc::new(SingleFlight::<usize>::default());
+        let loads = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Notify::new());
+        let load = || {
+            let (loads, release) = (Arc::clone(&loads), Arc::clone(&release));
+            move || async move {
+                let n = loads.fetch_add(1, Ordering::SeqCst) + 1;
+                release.notified().await;
+                n
+            }
+        };
+
+        let first = tokio::spawn({
+            let (flight, load) = (Arc::clone(&flight), load());
+            async move { flight.run(load).await }
+        });
+        let second = tokio::spawn({
+            let (flight, load) = (Arc::clone(&flight), load());
+            async move { flight.run(load).await }
+        });
+        // Let both triggers reach the flight before the load completes.
+        while flight.slot().as_ref().map_or(0, |tx| tx.receiver_count()) == 0 {
+            tokio::task::yield_now().await;
+        }
+        release.notify_one();
+        assert_eq!(first.await.unwrap(), 1);
+        assert_eq!(second.await.unwrap(), 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // A trigger after the flight has landed loads afresh.
+        release.notify_one();
+        assert_eq!(flight.run(load()).await, 2);
+    }
+
+    #[tokio::test]
+    async fn cancelled_leader_hands_over_to_a_follower() {
+        let flight = Arc::new(SingleFlight::<&str>::default());
+        let leader = tokio::spawn({
+            let flight = Arc::clone(&flight);
+            async move { flight.run(std::future::pending).await }
+        });
+        while flight.slot().is_none() {
+            tokio::task::yield_now().await;
+        }
+        let follower = tokio::spawn({
+            let flight = Arc::clone(&flight);
+            async move { flight.run(|| async { "follower" }).await }
+        });
+        while flight.slot().as_ref().map_or(0, |tx| tx.receiver_count()) == 0 {
+            tokio::task::yield_now().await;
+        }
+        leader.abort();
+        assert_eq!(follower.await.unwrap(), "follower");
+        assert_eq!(flight.exclusive(|| async { 7 }).await, 7);
+    }
+}
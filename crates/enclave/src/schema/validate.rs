@@ -0,0 +1,302 @@
+//! Offline schema validation: the `enclave validate-schemas <dir>`,
+//! `enclave diff-schemas <old> <new>` and `enclave explain-schemas <file>`
+//! subcommands.
+//!
+//! Lets schema authors check, before uploading to S3, that every schema file in
+//! a local directory parses and that the resolver finds the expected PII paths,
+//! lets reviewers of a schema bump see which PII paths were added or removed,
+//! and shows why each path was selected when a field is unexpectedly (not)
+//! encrypted.
+//! Uses the same parsing and resolution code as the running service, but needs
+//! no AWS access, DEK, or configuration.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::resolver::{
+    diff_pii_paths, explain_pii_paths, resolve_pii_paths, PathProvenance, PiiPathDiff,
+};
+use super::{parse_schema, schema_name_from_key};
+
+/// File extensions treated as schema files, matching the S3 loader.
+const SCHEMA_EXTENSIONS: [&str; 3] = ["yaml", "yml", "json"];
+
+/// Resolution result for one schema file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaReport {
+    /// Schema name, derived from the file name as the S3 loader would.
+    pub name: String,
+    /// Resolved PII paths, sorted.
+    pub pii_paths: Vec<String>,
+}
+
+/// Parse every schema file in `dir` and resolve its PII paths.
+///
+/// Files are processed in name order; non-schema files are ignored. Every
+/// parse failure is written to `err` before returning.
+///
+/// # Errors
+///
+/// Returns an error if the directory cannot be read or any schema file fails
+/// to parse.
+pub fn validate_dir(dir: &Path, err: &mut impl Write) -> Result<Vec<SchemaReport>> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read schema directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| SCHEMA_EXTENSIONS.contains(&ext))
+        })
+        .collect();
+    files.sort();
+
+    let mut reports = Vec::with_capacity(files.len());
+    let mut failures = 0usize;
+    for path in files {
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let body =
+            std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        match parse_schema(file_name, &body) {
+            Ok(api) => {
+                let mut pii_paths: Vec<String> = resolve_pii_paths(&api).into_iter().collect();
+                pii_paths.sort();
+                reports.push(SchemaReport {
+                    name: schema_name_from_key(file_name, ""),
+                    pii_paths,
+                });
+            }
+            Err(e) => {
+                failures += 1;
+                writeln!(err, "FAIL {}: {e:#}", path.display())?;
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{failures} schema file(s) failed to parse");
+    }
+    Ok(reports)
+}
+
+/// Write `reports` as a human-readable listing: one schema name per line,
+/// followed by its PII paths indented.
+///
+/// # Errors
+///
+/// Returns an error if writing to `out` fails.
+pub fn print_reports(reports: &[SchemaReport], out: &mut impl Write) -> Result<()> {
+    for report in reports {
+        writeln!(
+            out,
+            "{} ({} PII paths)",
+            report.name,
+            report.pii_paths.len()
+        )?;
+        for path in &report.pii_paths {
+            writeln!(out, "  {path}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse two schema files and compare their PII paths.
+///
+/// # Errors
+///
+/// Returns an error if either file cannot be read or parsed.
+pub fn diff_files(old: &Path, new: &Path) -> Result<PiiPathDiff> {
+    Ok(diff_pii_paths(&load_file(old)?, &load_file(new)?))
+}
+
+/// Read and parse a single schema file.
+fn load_file(path: &Path) -> Result<openapiv3::OpenAPI> {
+    let body = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    parse_schema(file_name, &body).with_context(|| format!("{} is not a schema", path.display()))
+}
+
+/// Write `diff` with one `+ path` line per added path and one `- path` line per
+/// removed path, or a note that nothing changed.
+///
+/// # Errors
+///
+/// Returns an error if writing to `out` fails.
+pub fn print_diff(diff: &PiiPathDiff, out: &mut impl Write) -> Result<()> {
+    if diff.is_empty() {
+        writeln!(out, "no PII path changes")?;
+    }
+    for path in &diff.added {
+        writeln!(out, "+ {path}")?;
+    }
+    for path in &diff.removed {
+        writeln!(out, "- {path}")?;
+    }
+    Ok(())
+}
+
+/// Parse a schema file and explain each of its PII paths.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+pub fn explain_file(path: &Path) -> Result<BTreeMap<String, PathProvenance>> {
+    Ok(explain_pii_paths(&load_file(path)?))
+}
+
+/// Write one `path  <- provenance` line per explained path.
+///
+/// # Errors
+///
+/// Returns an error if writing to `out` fails.
+pub fn print_explanation(
+    explained: &BTreeMap<String, PathProvenance>,
+    out: &mut impl Write,
+) -> Result<()> {
+    for (path, provenance) in explained {
+        writeln!(out, "{path}  <- {provenance}")?;
+    }
+    Ok(())
+}
+
+/// Entry point for `enclave explain-schemas <file>`.
+///
+/// # Errors
+///
+/// Returns an error on a usage error or if the schema fails to parse.
+pub fn run_explain_cli(args: &[String]) -> Result<()> {
+    let [file] = args else {
+        anyhow::bail!("usage: enclave explain-schemas <schema-file>");
+    };
+    let explained = explain_file(Path::new(file))?;
+    print_explanation(&explained, &mut std::io::stdout().lock())
+}
+
+/// Entry point for `enclave diff-schemas <old-file> <new-file>`.
+///
+/// # Errors
+///
+/// Returns an error on a usage error or if either schema fails to parse.
+pub fn run_diff_cli(args: &[String]) -> Result<()> {
+    let [old, new] = args else {
+        anyhow::bail!("usage: enclave diff-schemas <old-schema-file> <new-schema-file>");
+    };
+    let diff = diff_files(Path::new(old), Path::new(new))?;
+    print_diff(&diff, &mut std::io::stdout().lock())
+}
+
+/// Entry point for `enclave validate-schemas <dir>`.
+///
+/// # Errors
+///
+/// Returns an error (and hence a non-zero exit status) on a usage error or if
+/// any schema fails to parse.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let [dir] = args else {
+        anyhow::bail!("usage: enclave validate-schemas <schema-dir>");
+    };
+    let reports = validate_dir(Path::new(dir), &mut std::io::stderr())?;
+    print_reports(&reports, &mut std::io::stdout().lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/schemas")
+            .join(name)
+    }
+
+    #[test]
+    fn valid_fixtures_resolve_expected_paths() {
+        let mut err = Vec::new();
+        let reports = validate_dir(&fixture("valid"), &mut err).unwrap();
+        assert!(err.is_empty());
+        assert_eq!(
+            reports,
+            vec![
+                SchemaReport {
+                    name: "identity-v1".into(),
+                    pii_paths: vec!["aliases[]".into(), "ssn".into()],
+                },
+                SchemaReport {
+                    name: "payments-v1".into(),
+                    pii_paths: vec!["account.iban".into(), "card_number".into()],
+                },
+            ]
+        );
+
+        let mut out = Vec::new();
+        print_reports(&reports, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("payments-v1 (2 PII paths)\n  account.iban\n"));
+    }
+
+    #[test]
+    fn invalid_fixture_fails_and_is_reported() {
+        let mut err = Vec::new();
+        let result = validate_dir(&fixture("invalid"), &mut err);
+        assert!(result.is_err());
+        let err = String::from_utf8(err).unwrap();
+        assert!(err.contains("broken.yaml"), "{err}");
+        assert!(!err.contains("payments-v1"), "{err}");
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        assert!(validate_dir(&fixture("does-not-exist"), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn cli_requires_exactly_one_argument() {
+        assert!(run_cli(&[]).is_err());
+        assert!(run_cli(&["a".into(), "b".into()]).is_err());
+    }
+
+    #[test]
+    fn diff_files_reports_changes_between_versions() {
+        let diff = diff_files(
+            &fixture("diff/customer-v1.yaml"),
+            &fixture("diff/customer-v2.yaml"),
+        )
+        .unwrap();
+        assert_eq!(diff.added, vec!["contact.email".to_owned()]);
+        assert_eq!(diff.removed, vec!["fax".to_owned()]);
+
+        let mut out = Vec::new();
+        print_diff(&diff, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "+ contact.email\n- fax\n");
+    }
+
+    #[test]
+    fn explain_lists_provenance_per_path() {
+        let explained = explain_file(&fixture("valid/identity-v1.json")).unwrap();
+        let mut out = Vec::new();
+        print_explanation(&explained, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 2, "{out}");
+        assert!(out.contains("aliases[]  <- "), "{out}");
+        assert!(run_explain_cli(&[]).is_err());
+    }
+
+    #[test]
+    fn diff_cli_requires_two_readable_schemas() {
+        assert!(run_diff_cli(&["only-one".into()]).is_err());
+        let missing = fixture("diff/missing.yaml").display().to_string();
+        let v1 = fixture("diff/customer-v1.yaml").display().to_string();
+        assert!(run_diff_cli(&[v1, missing]).is_err());
+    }
+}
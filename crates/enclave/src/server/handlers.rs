@@ -1,111 +1,223 @@
 //! Axum request handlers for all service endpoints.
 
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::Ordering;
+
 use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    body::Bytes,
+    extract::{rejection::JsonRejection, Extension, Query, State},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, HeaderName, StatusCode,
+    },
     response::{IntoResponse, Response},
     Json,
 };
 use common::protocol::{
-    DecryptRequest, DecryptResponse, EncryptRequest, EncryptResponse, ErrorResponse, HealthResponse,
+    BatchEncryptRequest, BatchEncryptResponse, BatchItemResult, DecryptRequest, DecryptResponse,
+    DecryptValueRequest, DecryptValueResponse, DrainResponse, EncryptRequest, EncryptResponse,
+    EncryptValueRequest, EncryptValueResponse, EncryptionSettings, ErrorCode, ErrorResponse,
+    FieldError, HealthResponse, RedactRequest, RedactResponse, ReloadSchemasQuery,
+    ReloadSchemasResponse, SchemaListResponse, SchemaPathQuery, SchemaPathResponse, StatsResponse,
+    VerifyRequest, VerifyResponse,
 };
-use tracing::warn;
+use thiserror::Error;
+use tracing::{info, info_span, warn};
 
+use super::identity::ClientIdentity;
+use super::mask::MaskPolicy;
+use super::middleware::{deadline_exceeded, Deadline};
+use super::patch;
 use super::state::AppState;
-use crate::crypto::cipher::{decrypt_field, encrypt_field, CipherError, EncryptedField};
-use crate::schema::PiiFieldPaths;
+use super::stream::{self, Leaf, PathTrie, StreamError};
+use crate::crypto::cipher::{
+    decrypt_field_with_aad, encrypt_field_as, encrypt_field_with_aad, field_aad, value_aad,
+    Algorithm, CipherError, CiphertextEncoding, EncryptedField, NONCE_LEN,
+};
+use crate::crypto::hash::{hash_field, is_hashed};
+use crate::crypto::lookup::{derive_lookup_tag, LookupKey};
+use crate::crypto::tokenize::{detokenize, is_token_tag, token_tag, tokenize};
+use crate::schema::cache::{path_in_scope, CacheError, CachedSchema};
+use crate::schema::resolver::{EncryptionAlg, PiiCondition, RootKind};
+use crate::schema::ReloadError;
+use crate::schema::{
+    EmbeddedJsonPaths, PiiCategories, PiiConditions, PiiFieldPaths, PiiMaxLengths,
+};
+use crate::telemetry::audit::AuditEvent;
+use crate::telemetry::metrics::FieldLengths;
+
+/// Response header carrying the fingerprint of the schema applied by `/encrypt`.
+pub const SCHEMA_FINGERPRINT_HEADER: &str = "x-schema-fingerprint";
+
+/// Request header naming the tenant whose ciphertext is being produced or read.
+///
+/// When present, every PII field is bound to the tenant through the AEAD
+/// associated data, so ciphertext copied between tenants fails to decrypt.
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// Request header limiting `/encrypt` to the schema's PII paths under the
+/// listed comma-separated path prefixes (e.g. `debtor.*`).
+pub const PII_SCOPE_HEADER: &str = "x-pii-scope";
+
+/// Upper bound on the number of inline `pii_paths` in one `/encrypt` request.
+const MAX_INLINE_PII_PATHS: usize = 256;
+
+/// Upper bound on the number of items in one `/encrypt/batch` request.
+const MAX_BATCH_ITEMS: usize = 1000;
 
 /// `POST /encrypt` — encrypt PII fields in the request payload.
 ///
 /// The schema is identified by the value of the `X-Schema-Name` request header
 /// (or the configured header name). PII fields are replaced with
-/// `v1.<nonce>.<ciphertext>` strings.
+/// `v1.<nonce>.<ciphertext>` strings, or with irreversible `h1.<hmac>` tokens
+/// for fields annotated `x-pii-mode: hash`. The fingerprint of the applied schema is
+/// returned in the `X-Schema-Fingerprint` response header. An `X-Tenant-Id`
+/// header binds every field to that tenant (see [`TENANT_HEADER`]).
+///
+/// When `ALLOW_INLINE_SCHEMA` is set, a request may instead list its PII paths
+/// in a `pii_paths` body field; no schema header or cache lookup is involved
+/// and no fingerprint header is returned.
+///
+/// Fields without an explicit `x-pii-mode` follow the runtime
+/// [`EncryptionSettings`]; one snapshot is taken per request.
+///
+/// With `Accept: application/merge-patch+json` the body is instead a JSON
+/// Merge Patch holding only the changed members (see [`patch`]).
+///
+/// A request with `"collect_errors": true` does not fail on a field that is
+/// too long or holds invalid embedded JSON: that field is set to `null`, the
+/// others are encrypted, and the response is `207 Multi-Status` with the
+/// failures in `error.details`.
 pub async fn encrypt(
     State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    deadline: Option<Extension<Deadline>>,
     headers: HeaderMap,
-    Json(req): Json<EncryptRequest>,
+    req: Result<Json<EncryptRequest>, JsonRejection>,
 ) -> Response {
     use crate::telemetry::Metrics;
+    let deadline = deadline.map(|Extension(d)| d);
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
     let start = std::time::Instant::now();
+    let _active = state.track_request();
+    let inline = req.pii_paths.is_some();
+    await_readiness(&state, !inline).await;
+    let encryption = state.encryption.load_full();
 
-    // Extract schema name from the configured header.
-    let schema_name = match headers.get(state.schema_header_name.as_str()) {
-        Some(v) => match v.to_str() {
-            Ok(s) => s.to_owned(),
-            Err(_) => {
-                let err = ErrorResponse::new(
-                    "bad_request",
-                    format!(
-                        "{} header contains non-ASCII characters",
-                        state.schema_header_name
-                    ),
-                );
-                let attrs = Metrics::error_attrs();
-                state.metrics.encrypt_requests.add(1, &attrs);
-                state
-                    .metrics
-                    .encrypt_latency_ms
-                    .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-                return (StatusCode::BAD_REQUEST, Json(err)).into_response();
-            }
-        },
-        None => {
+    // Resolve the schema: caller-supplied inline PII paths, or the cached
+    // schema named by the configured header.
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    let cached = match req.pii_paths {
+        Some(_) if !schemaless_permitted(&state, identity) => {
             let err = ErrorResponse::new(
-                "bad_request",
-                format!("missing {} header", state.schema_header_name),
+                ErrorCode::Forbidden,
+                "client is not permitted to use inline pii_paths",
             );
+            Err(Box::new(error_response(&state, StatusCode::FORBIDDEN, err)))
+        }
+        Some(paths) => inline_schema(&state, paths)
+            .map_err(|err| Box::new(error_response(&state, StatusCode::BAD_REQUEST, err))),
+        None => encrypt_schema(&state, identity, &headers),
+    }
+    .and_then(|cached| {
+        pii_scope(cached, &headers)
+            .map_err(|err| Box::new(error_response(&state, StatusCode::BAD_REQUEST, err)))
+    });
+    let cached = match cached {
+        Ok(cached) => cached,
+        Err(resp) => {
             let attrs = Metrics::error_attrs();
             state.metrics.encrypt_requests.add(1, &attrs);
             state
                 .metrics
                 .encrypt_latency_ms
                 .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-            return (StatusCode::BAD_REQUEST, Json(err)).into_response();
+            return *resp;
         }
     };
 
-    // Resolve the schema from the cache.
-    let cached = match state.schema_cache.get(&schema_name) {
-        Ok(s) => s,
-        Err(_) => {
-            let err = ErrorResponse::new("bad_request", format!("unknown schema: {schema_name}"));
+    // Tenant binding for the AAD; required when `REQUIRE_TENANT` is set.
+    let tenant = match tenant_id(&state, &headers) {
+        Ok(t) => t,
+        Err(err) => {
             let attrs = Metrics::error_attrs();
             state.metrics.encrypt_requests.add(1, &attrs);
             state
                 .metrics
                 .encrypt_latency_ms
                 .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-            return (StatusCode::BAD_REQUEST, Json(err)).into_response();
+            return error_response(&state, StatusCode::BAD_REQUEST, err);
         }
     };
 
-    // Borrow the current DEK — 503 if not yet initialised.
-    let dek = match state.dek_store.current().await {
+    // Pin the current DEK generation — 503 if not yet initialised.
+    let pinned = match state.dek_store.pinned().await {
         Ok(d) => d,
         Err(_) => {
-            let err = ErrorResponse::new("service_unavailable", "DEK not yet initialised");
             let attrs = Metrics::error_attrs();
             state.metrics.encrypt_requests.add(1, &attrs);
             state
                 .metrics
                 .encrypt_latency_ms
                 .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-            return (StatusCode::SERVICE_UNAVAILABLE, Json(err)).into_response();
+            return not_ready(&state, "DEK not yet initialised");
         }
     };
 
-    // Traverse and encrypt all PII fields in-place.
+    let ctx = CipherContext {
+        dek: &pinned.key.0[..],
+        token_key: state.token_key.as_deref().map(|k| &k.0[..]),
+        tenant: tenant.as_deref(),
+        schema_tag: schema_tag(&state, &cached),
+        algorithm: algorithm(&cached),
+        field_lengths: Some(&state.metrics.field_lengths),
+        deadline,
+        encoding: encryption.encoding,
+        min_encrypt_len: state.settings.min_encrypt_len,
+        max_array_items: state.settings.max_array_items,
+    };
+    // Keep the input when the caller wants only the changes back.
+    let original = headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(patch::accepts_merge_patch)
+        .then(|| req.payload.clone());
     let mut payload = req.payload;
-    if let Err(e) = encrypt_pii_fields(&mut payload, &cached.pii_paths, &dek.0[..]) {
-        warn!(error = %e, "encryption failed");
-        let err = ErrorResponse::new("internal_error", "encryption failed");
+    let field_errors = if req.collect_errors {
+        isolate_failing_fields(&state, &cached, &mut payload)
+    } else {
+        Vec::new()
+    };
+    let payload = match encrypt_payload(&state, &cached, &ctx, payload) {
+        Ok(payload) => payload,
+        Err((status, err)) => {
+            let attrs = Metrics::error_attrs();
+            state.metrics.encrypt_requests.add(1, &attrs);
+            state
+                .metrics
+                .encrypt_latency_ms
+                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+            return error_response(&state, status, err);
+        }
+    };
+
+    // All fields must have been encrypted under a single key generation.
+    if let Err(e) = state.dek_store.ensure_generation(pinned.generation) {
+        warn!(error = %e, "DEK rotated mid-request");
+        let err = ErrorResponse::new(
+            ErrorCode::ServiceUnavailable,
+            "DEK rotated during request; retry",
+        );
         let attrs = Metrics::error_attrs();
         state.metrics.encrypt_requests.add(1, &attrs);
         state
             .metrics
             .encrypt_latency_ms
             .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(err)).into_response();
+        return error_response(&state, StatusCode::SERVICE_UNAVAILABLE, err);
     }
 
     let attrs = Metrics::success_attrs();
@@ -114,292 +226,6213 @@ pub async fn encrypt(
         .metrics
         .encrypt_latency_ms
         .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-    (StatusCode::OK, Json(EncryptResponse { payload })).into_response()
+    audit(&state, "encrypt", identity, &headers, inline, tenant);
+    let (status, error) = if field_errors.is_empty() {
+        (StatusCode::OK, None)
+    } else {
+        state.metrics.error_responses.record(ErrorCode::BadRequest);
+        let err = ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("{} PII fields could not be encrypted", field_errors.len()),
+        );
+        (
+            StatusCode::MULTI_STATUS,
+            Some(err.with_details(field_errors)),
+        )
+    };
+    let body = match original {
+        Some(original) => (
+            status,
+            [(CONTENT_TYPE, patch::MERGE_PATCH_CONTENT_TYPE)],
+            Json(patch::diff(&original, &payload)),
+        )
+            .into_response(),
+        None => (status, Json(EncryptResponse { payload, error })).into_response(),
+    };
+    if inline {
+        return body;
+    }
+    (
+        [(SCHEMA_FINGERPRINT_HEADER, cached.fingerprint.to_string())],
+        body,
+    )
+        .into_response()
 }
 
-/// `GET /health` — liveness and readiness check.
+/// `POST /encrypt/batch` — encrypt PII fields in several payloads that share
+/// one schema.
 ///
-/// Returns `200 OK` when the DEK is loaded and at least one schema is cached.
-/// Returns `503 Service Unavailable` otherwise.
-pub async fn health(State(state): State<AppState>) -> Response {
-    let dek_ready = state.dek_store.is_ready().await;
-    let schemas_loaded = state.schema_cache.len();
-
-    let (status_code, status_str) = if dek_ready && schemas_loaded > 0 {
-        (StatusCode::OK, "ok")
-    } else {
-        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+/// The schema header, tenant, and DEK generation apply to the whole batch, as
+/// for `/encrypt`. Items are encrypted concurrently, but `results` always
+/// follow request order and each carries its original `index`. A failing item
+/// gets an `error` in its slot without affecting the others; failures that
+/// concern the whole request (schema, tenant, DEK) fail the batch.
+///
+/// The batch runs in an `encrypt_batch` span with one `encrypt_batch_item`
+/// child span per item, carrying only the item index and schema name, so
+/// slow items stand out in traces.
+pub async fn encrypt_batch(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    deadline: Option<Extension<Deadline>>,
+    headers: HeaderMap,
+    Json(req): Json<BatchEncryptRequest>,
+) -> Response {
+    use crate::telemetry::Metrics;
+    let deadline = deadline.map(|Extension(d)| d);
+    let start = std::time::Instant::now();
+    let _active = state.track_request();
+    let encryption = state.encryption.load_full();
+    let record = |attrs: &[opentelemetry::KeyValue]| {
+        state.metrics.encrypt_requests.add(1, attrs);
+        state
+            .metrics
+            .encrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, attrs);
     };
 
-    let body = HealthResponse {
-        status: status_str.into(),
-        dek_ready,
-        schemas_loaded,
+    if req.items.len() > MAX_BATCH_ITEMS {
+        record(&Metrics::error_attrs());
+        let err = ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("batch exceeds {MAX_BATCH_ITEMS} items"),
+        );
+        return error_response(&state, StatusCode::BAD_REQUEST, err);
+    }
+    let cached = encrypt_schema(&state, identity.as_ref().map(|Extension(id)| id), &headers)
+        .and_then(|cached| {
+            pii_scope(cached, &headers)
+                .map_err(|err| Box::new(error_response(&state, StatusCode::BAD_REQUEST, err)))
+        });
+    let cached = match cached {
+        Ok(cached) => cached,
+        Err(resp) => {
+            record(&Metrics::error_attrs());
+            return *resp;
+        }
     };
-    (status_code, Json(body)).into_response()
+    let tenant = match tenant_id(&state, &headers) {
+        Ok(t) => t,
+        Err(err) => {
+            record(&Metrics::error_attrs());
+            return error_response(&state, StatusCode::BAD_REQUEST, err);
+        }
+    };
+    let pinned = match state.dek_store.pinned().await {
+        Ok(d) => d,
+        Err(_) => {
+            record(&Metrics::error_attrs());
+            return not_ready(&state, "DEK not yet initialised");
+        }
+    };
+
+    // The header was validated by `encrypt_schema`.
+    let schema_name = headers
+        .get(state.schema_header_name.as_str())
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let batch_span = info_span!(
+        "encrypt_batch",
+        schema = schema_name,
+        items = req.items.len()
+    );
+
+    // Encrypt items on the blocking pool; they complete in any order and are
+    // slotted back by index. Item spans are created here, with an explicit
+    // parent, since the blocking threads do not inherit the current span.
+    // At most one item per core runs at a time, so a large batch cannot
+    // occupy the blocking pool that every other request shares.
+    let permits = std::sync::Arc::new(tokio::sync::Semaphore::new(
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get),
+    ));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, payload) in req.items.into_iter().enumerate() {
+        let permit = match std::sync::Arc::clone(&permits).acquire_owned().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                warn!(error = %e, "batch concurrency limit unavailable");
+                record(&Metrics::error_attrs());
+                let err = ErrorResponse::new(ErrorCode::InternalError, "encryption failed");
+                return error_response(&state, StatusCode::INTERNAL_SERVER_ERROR, err);
+            }
+        };
+        let state = state.clone();
+        let cached = cached.clone();
+        let encryption = std::sync::Arc::clone(&encryption);
+        let dek = pinned.key.clone();
+        let tenant = tenant.clone();
+        let item_span =
+            info_span!(parent: &batch_span, "encrypt_batch_item", index, schema = schema_name);
+        tasks.spawn_blocking(move || {
+            let _permit = permit;
+            let _entered = item_span.enter();
+            let ctx = CipherContext {
+                dek: &dek.0[..],
+                token_key: state.token_key.as_deref().map(|k| &k.0[..]),
+                tenant: tenant.as_deref(),
+                schema_tag: schema_tag(&state, &cached),
+                algorithm: algorithm(&cached),
+                field_lengths: Some(&state.metrics.field_lengths),
+                deadline,
+                encoding: encryption.encoding,
+                min_encrypt_len: state.settings.min_encrypt_len,
+                max_array_items: state.settings.max_array_items,
+            };
+            let result = encrypt_payload(&state, &cached, &ctx, payload);
+            (index, result)
+        });
+    }
+    let mut slots: Vec<Option<BatchItemResult>> = Vec::new();
+    slots.resize_with(tasks.len(), || None);
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = match joined {
+            Ok(done) => done,
+            Err(e) => {
+                warn!(error = %e, "batch item task failed");
+                record(&Metrics::error_attrs());
+                let err = ErrorResponse::new(ErrorCode::InternalError, "encryption failed");
+                return error_response(&state, StatusCode::INTERNAL_SERVER_ERROR, err);
+            }
+        };
+        if let Err((StatusCode::GATEWAY_TIMEOUT, _)) = result {
+            // Dropping `tasks` cancels the items that have not started.
+            record(&Metrics::error_attrs());
+            return deadline_exceeded(&state);
+        }
+        slots[index] = Some(match result {
+            Ok(payload) => BatchItemResult {
+                index,
+                payload: Some(payload),
+                error: None,
+            },
+            Err((_, err)) => {
+                state.metrics.error_responses.record(err.code);
+                BatchItemResult {
+                    index,
+                    payload: None,
+                    error: Some(err),
+                }
+            }
+        });
+    }
+
+    // All items must have been encrypted under a single key generation.
+    if let Err(e) = state.dek_store.ensure_generation(pinned.generation) {
+        warn!(error = %e, "DEK rotated mid-request");
+        record(&Metrics::error_attrs());
+        let err = ErrorResponse::new(
+            ErrorCode::ServiceUnavailable,
+            "DEK rotated during request; retry",
+        );
+        return error_response(&state, StatusCode::SERVICE_UNAVAILABLE, err);
+    }
+
+    record(&Metrics::success_attrs());
+    audit(
+        &state,
+        "encrypt_batch",
+        identity.as_ref().map(|Extension(id)| id),
+        &headers,
+        false,
+        tenant,
+    );
+    let results = slots.into_iter().flatten().collect();
+    (
+        StatusCode::OK,
+        [(SCHEMA_FINGERPRINT_HEADER, cached.fingerprint.to_string())],
+        Json(BatchEncryptResponse { results }),
+    )
+        .into_response()
 }
 
-/// `POST /decrypt` — decrypt PII fields in the request payload.
+/// `POST /encrypt/stream` — encrypt PII fields in a large payload without
+/// building a JSON tree.
 ///
-/// The schema is identified by the value of the `X-Schema-Name` request header
-/// (or the configured header name). Fields at PII paths that carry an encrypted
-/// `v1.<nonce>.<ciphertext>` value are decrypted back to plaintext. Fields that
-/// are not in the `v1.` format are left unchanged.
-pub async fn decrypt(
+/// The request body is the payload itself (no `{"payload": ...}` envelope);
+/// the response is the same `{"payload": ...}` document `/encrypt` returns.
+/// The body is copied through in one pass and only leaves at the schema's PII
+/// paths are rewritten, so memory stays close to the size of the body.
+/// Schemas with sibling conditions, embedded JSON fields or recursive paths
+/// need the whole document at once and fall back to the buffered transform.
+pub async fn encrypt_stream(
     State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    deadline: Option<Extension<Deadline>>,
     headers: HeaderMap,
-    Json(req): Json<DecryptRequest>,
+    body: Bytes,
 ) -> Response {
     use crate::telemetry::Metrics;
+    let deadline = deadline.map(|Extension(d)| d);
     let start = std::time::Instant::now();
+    let _active = state.track_request();
+    let encryption = state.encryption.load_full();
+    let record = |attrs: &[opentelemetry::KeyValue]| {
+        state.metrics.encrypt_requests.add(1, attrs);
+        state
+            .metrics
+            .encrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, attrs);
+    };
+
+    let cached = encrypt_schema(&state, identity.as_ref().map(|Extension(id)| id), &headers)
+        .and_then(|cached| {
+            pii_scope(cached, &headers)
+                .map_err(|err| Box::new(error_response(&state, StatusCode::BAD_REQUEST, err)))
+        });
+    let cached = match cached {
+        Ok(cached) => cached,
+        Err(resp) => {
+            record(&Metrics::error_attrs());
+            return *resp;
+        }
+    };
+    let tenant = match tenant_id(&state, &headers) {
+        Ok(t) => t,
+        Err(err) => {
+            record(&Metrics::error_attrs());
+            return error_response(&state, StatusCode::BAD_REQUEST, err);
+        }
+    };
+    let pinned = match state.dek_store.pinned().await {
+        Ok(d) => d,
+        Err(_) => {
+            record(&Metrics::error_attrs());
+            return not_ready(&state, "DEK not yet initialised");
+        }
+    };
+
+    let ctx = CipherContext {
+        dek: &pinned.key.0[..],
+        token_key: state.token_key.as_deref().map(|k| &k.0[..]),
+        tenant: tenant.as_deref(),
+        schema_tag: schema_tag(&state, &cached),
+        algorithm: algorithm(&cached),
+        field_lengths: Some(&state.metrics.field_lengths),
+        deadline,
+        encoding: encryption.encoding,
+        min_encrypt_len: state.settings.min_encrypt_len,
+        max_array_items: state.settings.max_array_items,
+    };
+    let payload = match stream_payload(&state, &cached, &ctx, &body) {
+        Ok(payload) => payload,
+        Err((status, err)) => {
+            record(&Metrics::error_attrs());
+            return error_response(&state, status, err);
+        }
+    };
+
+    // All fields must have been encrypted under a single key generation.
+    if let Err(e) = state.dek_store.ensure_generation(pinned.generation) {
+        warn!(error = %e, "DEK rotated mid-request");
+        record(&Metrics::error_attrs());
+        let err = ErrorResponse::new(
+            ErrorCode::ServiceUnavailable,
+            "DEK rotated during request; retry",
+        );
+        return error_response(&state, StatusCode::SERVICE_UNAVAILABLE, err);
+    }
+
+    record(&Metrics::success_attrs());
+    audit(
+        &state,
+        "encrypt_stream",
+        identity.as_ref().map(|Extension(id)| id),
+        &headers,
+        false,
+        tenant,
+    );
+    let mut response = Vec::with_capacity(payload.len() + 12);
+    response.extend_from_slice(br#"{"payload":"#);
+    response.extend_from_slice(&payload);
+    response.push(b'}');
+    (
+        StatusCode::OK,
+        [
+            (CONTENT_TYPE, "application/json".to_owned()),
+            (
+                HeaderName::from_static(SCHEMA_FINGERPRINT_HEADER),
+                cached.fingerprint.to_string(),
+            ),
+        ],
+        response,
+    )
+        .into_response()
+}
 
+/// Resolve the cached schema named by the request's schema header, enforcing
+/// the client allowlist and schema freshness.
+///
+/// Returns the complete error response to send on failure (boxed, as it is
+/// much larger than the schema handle); the caller records request metrics.
+fn encrypt_schema(
+    state: &AppState,
+    identity: Option<&ClientIdentity>,
+    headers: &HeaderMap,
+) -> Result<CachedSchema, Box<Response>> {
     // Extract schema name from the configured header.
     let schema_name = match headers.get(state.schema_header_name.as_str()) {
         Some(v) => match v.to_str() {
             Ok(s) => s.to_owned(),
             Err(_) => {
                 let err = ErrorResponse::new(
-                    "bad_request",
+                    ErrorCode::BadRequest,
                     format!(
                         "{} header contains non-ASCII characters",
                         state.schema_header_name
                     ),
                 );
-                let attrs = Metrics::error_attrs();
-                state.metrics.decrypt_requests.add(1, &attrs);
-                state
-                    .metrics
-                    .decrypt_latency_ms
-                    .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-                return (StatusCode::BAD_REQUEST, Json(err)).into_response();
+                return Err(Box::new(error_response(
+                    state,
+                    StatusCode::BAD_REQUEST,
+                    err,
+                )));
             }
         },
         None => {
             let err = ErrorResponse::new(
-                "bad_request",
+                ErrorCode::BadRequest,
                 format!("missing {} header", state.schema_header_name),
             );
-            let attrs = Metrics::error_attrs();
-            state.metrics.decrypt_requests.add(1, &attrs);
-            state
-                .metrics
-                .decrypt_latency_ms
-                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-            return (StatusCode::BAD_REQUEST, Json(err)).into_response();
+            return Err(Box::new(error_response(
+                state,
+                StatusCode::BAD_REQUEST,
+                err,
+            )));
         }
     };
 
+    // Enforce the per-client schema allowlist, when configured.
+    if !schema_permitted(state, identity, &schema_name) {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            format!("client is not permitted to use schema: {schema_name}"),
+        );
+        return Err(Box::new(error_response(state, StatusCode::FORBIDDEN, err)));
+    }
+
+    // Shed load rather than encrypt with schemas that stopped refreshing.
+    if schemas_stale(state) {
+        let err = ErrorResponse::new(
+            ErrorCode::SchemasStale,
+            "schemas have not refreshed recently; retry later",
+        );
+        return Err(Box::new(with_retry_after(
+            state,
+            error_response(state, StatusCode::SERVICE_UNAVAILABLE, err),
+        )));
+    }
+
     // Resolve the schema from the cache.
-    let cached = match state.schema_cache.get(&schema_name) {
-        Ok(s) => s,
-        Err(_) => {
-            let err = ErrorResponse::new("bad_request", format!("unknown schema: {schema_name}"));
-            let attrs = Metrics::error_attrs();
-            state.metrics.decrypt_requests.add(1, &attrs);
-            state
-                .metrics
-                .decrypt_latency_ms
-                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-            return (StatusCode::BAD_REQUEST, Json(err)).into_response();
-        }
+    cached_schema(state, &schema_name).map_err(|e| {
+        let (status, err) = match e {
+            CacheError::RemovedSchema(_) => (
+                StatusCode::GONE,
+                ErrorResponse::new(
+                    ErrorCode::SchemaRemoved,
+                    format!("schema recently removed: {schema_name}"),
+                ),
+            ),
+            CacheError::UnknownSchema(_) => {
+                return Box::new(unknown_schema(state, &schema_name));
+            }
+        };
+        Box::new(error_response(state, status, err))
+    })
+}
+
+/// The schema named `name`: the pass-through entry for the configured
+/// `NOOP_SCHEMA_NAME`, which is always available, or else the cached schema.
+fn cached_schema(state: &AppState, name: &str) -> Result<CachedSchema, CacheError> {
+    if state.settings.noop_schema.as_deref() == Some(name) {
+        return Ok(CachedSchema::noop());
+    }
+    state.schema_cache.get(name)
+}
+
+/// `cached` limited to the path prefixes listed in the [`PII_SCOPE_HEADER`],
+/// or unchanged when the header is absent. A trailing `.*` on a prefix is
+/// optional. Every prefix must cover at least one PII or embedded-JSON path
+/// of the schema, so a typo fails instead of silently encrypting nothing;
+/// [`encrypt_payload`] rejects payloads that fill in an excluded path.
+fn pii_scope(cached: CachedSchema, headers: &HeaderMap) -> Result<CachedSchema, ErrorResponse> {
+    let Some(value) = headers.get(PII_SCOPE_HEADER) else {
+        return Ok(cached);
     };
+    let value = value.to_str().map_err(|_| {
+        ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("{PII_SCOPE_HEADER} header contains non-ASCII characters"),
+        )
+    })?;
+    let prefixes: Vec<&str> = value
+        .split(',')
+        .map(|p| p.trim().strip_suffix(".*").unwrap_or(p.trim()))
+        .filter(|p| !p.is_empty())
+        .collect();
+    if prefixes.is_empty() {
+        return Err(ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("{PII_SCOPE_HEADER} header must list at least one path prefix"),
+        ));
+    }
+    let known = cached.pii_paths.iter().chain(cached.embedded_json.keys());
+    if let Some(unknown) = prefixes
+        .iter()
+        .find(|prefix| !known.clone().any(|path| path_in_scope(path, prefix)))
+    {
+        return Err(ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("{PII_SCOPE_HEADER} prefix matches no PII path of the schema: {unknown}"),
+        ));
+    }
+    Ok(cached.scoped(&prefixes))
+}
 
-    // Borrow the current DEK — 503 if not yet initialised.
-    let dek = match state.dek_store.current().await {
-        Ok(d) => d,
-        Err(_) => {
-            let err = ErrorResponse::new("service_unavailable", "DEK not yet initialised");
-            let attrs = Metrics::error_attrs();
-            state.metrics.decrypt_requests.add(1, &attrs);
-            state
-                .metrics
-                .decrypt_latency_ms
-                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-            return (StatusCode::SERVICE_UNAVAILABLE, Json(err)).into_response();
+/// Check and encrypt one payload against `cached`: root kind, field lengths,
+/// then every PII and embedded-JSON field.
+///
+/// Returns the status and body to report on failure; nothing is recorded.
+fn encrypt_payload(
+    state: &AppState,
+    cached: &CachedSchema,
+    ctx: &CipherContext<'_>,
+    mut payload: serde_json::Value,
+) -> Result<serde_json::Value, (StatusCode, ErrorResponse)> {
+    // The payload root must be the kind the schema's paths descend from.
+    if let Some(err) = payload_root_error(state, cached.root, &payload) {
+        return Err((StatusCode::BAD_REQUEST, err));
+    }
+    if let Some(err) = unknown_keys_error(state, cached, &payload) {
+        return Err((StatusCode::BAD_REQUEST, err));
+    }
+    if let Some(err) = out_of_scope_error(cached, &mut payload) {
+        return Err((StatusCode::BAD_REQUEST, err));
+    }
+
+    // Reject oversized PII values, or too many of them, before spending any
+    // work encrypting them.
+    check_field_count(
+        &mut payload,
+        &cached.pii_paths,
+        &cached.numeric,
+        state.settings.max_encrypted_fields,
+    )
+    .and_then(|()| {
+        check_field_lengths(
+            &mut payload,
+            &cached.pii_paths,
+            &cached.max_lengths,
+            &cached.embedded_json,
+            state.settings.max_field_bytes,
+        )
+    })
+    .and_then(|()| {
+        if !state.settings.strict_leaf_types {
+            return Ok(());
         }
+        visit_leaf_types(
+            &mut payload,
+            &cached.pii_paths,
+            &cached.numeric,
+            &mut |_, e| Err(e),
+        )
+    })
+    .map_err(|e| e.into_response_parts("encryption failed"))?;
+
+    // Traverse and encrypt all PII fields in-place.
+    add_lookup_tags(
+        &mut payload,
+        &cached.lookup,
+        &cached.conditions,
+        &cached.numeric,
+        ctx,
+    )
+    .and_then(|()| {
+        encrypt_pii_fields(
+            &mut payload,
+            &cached.pii_paths,
+            &cached.conditions,
+            &cached.hashed,
+            &cached.tokenized,
+            &cached.numeric,
+            ctx,
+        )
+    })
+    .and_then(|()| encrypt_embedded_json(&mut payload, &cached.embedded_json, ctx))
+    .map_err(|e| {
+        warn!(error = %e, "encryption failed");
+        e.into_response_parts("encryption failed")
+    })?;
+    Ok(payload)
+}
+
+/// Streaming counterpart of [`encrypt_payload`]: check and encrypt the raw
+/// JSON `body` in one pass, returning the encrypted document's bytes.
+///
+/// Falls back to the buffered transform when the schema has sibling
+/// conditions, embedded JSON fields, lookup or token tags or recursive (`**`)
+/// paths,
+/// when ciphertext is written as JSON objects, and under `strict_leaf_types`,
+/// since the single pass only ever sees scalar leaves.
+fn stream_payload(
+    state: &AppState,
+    cached: &CachedSchema,
+    ctx: &CipherContext<'_>,
+    body: &[u8],
+) -> Result<Vec<u8>, (StatusCode, ErrorResponse)> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        (
+            StatusCode::BAD_REQUEST,
+            ErrorResponse::new(ErrorCode::BadRequest, format!("invalid JSON payload: {e}")),
+        )
     };
+    let strict_keys =
+        state.settings.reject_unknown_top_level_keys && cached.top_level_keys.is_some();
+    if !cached.conditions.is_empty()
+        || !cached.embedded_json.is_empty()
+        || !cached.lookup.is_empty()
+        || !cached.tokenized.is_empty()
+        || !cached.out_of_scope.is_empty()
+        || cached.pii_paths.iter().any(|path| is_recursive_path(path))
+        || ctx.encoding == CiphertextEncoding::JsonObject
+        || strict_keys
+        || state.settings.strict_leaf_types
+    {
+        let payload = serde_json::from_slice(body).map_err(|e| invalid(&e))?;
+        let payload = encrypt_payload(state, cached, ctx, payload)?;
+        return serde_json::to_vec(&payload).map_err(|e| invalid(&e));
+    }
 
-    // Traverse and decrypt all PII fields in-place.
-    let mut payload = req.payload;
-    if let Err(e) = decrypt_pii_fields(&mut payload, &cached.pii_paths, &dek.0[..]) {
-        warn!(error = %e, "decryption failed");
-        let err = ErrorResponse::new("internal_error", "decryption failed");
-        let attrs = Metrics::error_attrs();
-        state.metrics.decrypt_requests.add(1, &attrs);
-        state
-            .metrics
-            .decrypt_latency_ms
-            .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-        return (StatusCode::INTERNAL_SERVER_ERROR, Json(err)).into_response();
+    if let Some(expected) = cached.root.filter(|_| state.settings.enforce_payload_root) {
+        match stream::root_kind(body) {
+            Some(actual) if actual != expected.as_str() => {
+                return Err((StatusCode::BAD_REQUEST, root_error(expected, actual)));
+            }
+            _ => {}
+        }
     }
 
-    let attrs = Metrics::success_attrs();
-    state.metrics.decrypt_requests.add(1, &attrs);
-    state
-        .metrics
-        .decrypt_latency_ms
-        .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
-    (StatusCode::OK, Json(DecryptResponse { payload })).into_response()
+    let max_field_bytes = state.settings.max_field_bytes;
+    let max_fields = state.settings.max_encrypted_fields;
+    let mut fields = 0usize;
+    let trie = PathTrie::new(cached.pii_paths.iter()).with_max_array_items(ctx.max_array_items);
+    stream::transform(body, &trie, |path, leaf| {
+        ctx.check_deadline()?;
+        if matches!(leaf, Leaf::String(_)) || cached.numeric.contains(path) {
+            fields += 1;
+            if max_fields != 0 && fields > max_fields {
+                return Err(TraversalError::TooManyFields(max_fields));
+            }
+        }
+        let plaintext = match leaf {
+            Leaf::String(s) => {
+                let limit = match cached.max_lengths.get(path) {
+                    Some(&max) if s.chars().count() > max => Some(format!("{max} characters")),
+                    Some(_) => None,
+                    None => (s.len() > max_field_bytes).then(|| format!("{max_field_bytes} bytes")),
+                };
+                if let Some(limit) = limit {
+                    return Err(TraversalError::FieldTooLong {
+                        path: path.to_owned(),
+                        limit,
+                    });
+                }
+                if s.chars().count() < ctx.min_encrypt_len {
+                    return Ok(None);
+                }
+                s
+            }
+            Leaf::Number(n) if cached.numeric.contains(path) => n,
+            Leaf::Number(_) => return Ok(None),
+        };
+        let aad = field_aad(ctx.tenant, path);
+        Ok(Some(protect_leaf(
+            plaintext.as_bytes(),
+            Protection::of(path, &cached.hashed, &cached.tokenized),
+            ctx,
+            &aad,
+        )?))
+    })
+    .map_err(|e| match e {
+        StreamError::Leaf(e) => {
+            warn!(error = %e, "encryption failed");
+            e.into_response_parts("encryption failed")
+        }
+        StreamError::ArrayTooLarge(max) => {
+            TraversalError::ArrayTooLarge(max).into_response_parts("encryption failed")
+        }
+        e => invalid(&e),
+    })
 }
 
-/// Catch-all 404 handler.
-pub async fn not_found() -> impl IntoResponse {
-    let err = ErrorResponse::new("not_found", "the requested resource does not exist");
-    (StatusCode::NOT_FOUND, Json(err))
-}
+/// `GET /health` (also `GET /readyz`) — liveness and readiness check.
+///
+/// Returns `200 OK` when the readiness thresholds in
+/// [`ServerSettings`](super::state::ServerSettings) are met (by default: the DEK
+/// is loaded and at least one schema is cached), the instance is not draining,
+/// and schemas are not stale (see `MAX_SCHEMA_STALENESS_SECS`).
+/// Returns `503 Service Unavailable` otherwise.
+///
+/// A stalled background task does not stop the instance from serving with
+/// its current DEK and schemas, so it is reported as `"degraded"` with
+/// `200 OK` rather than taking the target out of the load balancer.
+pub async fn health(State(state): State<AppState>) -> Response {
+    let dek_ready = state.dek_store.is_ready().await;
+    let schemas_loaded = state.schema_cache.len();
+    let draining = state.draining.load(Ordering::Relaxed);
 
-// ---------------------------------------------------------------------------
-// PII field traversal helpers
-// ---------------------------------------------------------------------------
+    let schemas_stale = schemas_stale(&state);
+    let proxy_reachable = state.proxy_reachable.load(Ordering::Relaxed);
+    let stalled_tasks: Vec<String> = state
+        .metrics
+        .task_heartbeats
+        .stalled(tokio::time::Instant::now())
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    let ready = !draining
+        && !schemas_stale
+        && proxy_reachable
+        && state.settings.is_ready(dek_ready, schemas_loaded);
+    let (status_code, status_str) = match (ready, stalled_tasks.is_empty()) {
+        (true, true) => (StatusCode::OK, "ok"),
+        (true, false) => (StatusCode::OK, "degraded"),
+        (false, _) => (StatusCode::SERVICE_UNAVAILABLE, "degraded"),
+    };
 
-/// Segments of a dot-notation PII field path.
-enum PathSegment {
-    /// Navigate into an object property by name.
-    Key(String),
-    /// Expand into every element of a JSON array.
-    ArrayItem,
+    let body = HealthResponse {
+        status: status_str.into(),
+        dek_ready,
+        schemas_loaded,
+        draining,
+        schemas_stale,
+        proxy_reachable,
+        stalled_tasks,
+    };
+    (status_code, Json(body)).into_response()
 }
 
-/// Parse a dot-notation PII path into a list of [`PathSegment`]s.
+/// `POST /admin/drain` — put the instance into drain mode.
 ///
-/// Array fields use the `[]` suffix before the dot separator, e.g.
-/// `"orders[].card_number"` → `[Key("orders"), ArrayItem, Key("card_number")]`.
-fn parse_path(path: &str) -> Vec<PathSegment> {
-    let mut segments = Vec::new();
-    for part in path.split('.') {
-        if let Some(key) = part.strip_suffix("[]") {
-            segments.push(PathSegment::Key(key.to_owned()));
-            segments.push(PathSegment::ArrayItem);
-        } else {
-            segments.push(PathSegment::Key(part.to_owned()));
-        }
+/// Readiness reports degraded so the load balancer deregisters the target, but
+/// requests keep being served and the DEK and schemas are left untouched.
+/// Requires the admin role.
+pub async fn start_drain(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(ErrorCode::Forbidden, "draining requires the admin role");
+        return error_response(&state, StatusCode::FORBIDDEN, err);
     }
-    segments
+    state.draining.store(true, Ordering::Relaxed);
+    info!("drain requested; readiness now reports degraded");
+    (StatusCode::OK, Json(DrainResponse { draining: true })).into_response()
 }
 
-/// Recursively navigate `value` following `segments` and encrypt any string
-/// leaf found at the end of the path.
-fn encrypt_at_path(
-    value: &mut serde_json::Value,
-    segments: &[PathSegment],
-    dek: &[u8],
-) -> Result<(), CipherError> {
-    if segments.is_empty() {
-        if let serde_json::Value::String(s) = value {
-            let encrypted = encrypt_field(s.as_bytes(), dek)?;
-            *value = serde_json::Value::String(encrypted.to_string_repr());
-        }
-        return Ok(());
+/// `DELETE /admin/drain` — leave drain mode and report ready again. Requires
+/// the admin role.
+pub async fn stop_drain(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "clearing drain mode requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    state.draining.store(false, Ordering::Relaxed);
+    info!("drain cleared");
+    (StatusCode::OK, Json(DrainResponse { draining: false })).into_response()
+}
+
+/// `GET /admin/schemas` — list the cached schemas with their fingerprints,
+/// and the schema objects quarantined for repeatedly failing to parse.
+/// Requires the admin role.
+pub async fn list_schemas(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "listing schemas requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    let body = SchemaListResponse {
+        schemas: state.schema_cache.fingerprints(),
+        quarantined: state.schema_cache.quarantined(),
+    };
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// `GET /admin/schemas/by-path?path=<dot.path>` — list the cached schemas that
+/// mark `path` as PII. Requires the admin role.
+pub async fn schemas_with_path(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    Query(query): Query<SchemaPathQuery>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "listing schemas requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    let schemas = state.schema_cache.schemas_with_path(&query.path);
+    let body = SchemaPathResponse {
+        path: query.path,
+        schemas,
+    };
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// `POST /admin/reload-schemas?source=<bucket/prefix>` — re-fetch one schema
+/// source and merge it into the cache, leaving other sources untouched.
+///
+/// Requires the admin role. Responds `404` for an unconfigured source, `409`
+/// when the source now defines a schema name owned by another source or
+/// would exceed `MAX_CACHED_SCHEMAS`, and `502` when S3 fails; the cache is
+/// unchanged in every error case.
+pub async fn reload_schemas(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    Query(query): Query<ReloadSchemasQuery>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "reloading schemas requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    let Some(loader) = &state.schema_loader else {
+        let err = ErrorResponse::new(
+            ErrorCode::ServiceUnavailable,
+            "schema reloading is not available",
+        );
+        return error_response(&state, StatusCode::SERVICE_UNAVAILABLE, err);
+    };
+    match loader
+        .reload_source(&state.schema_cache, &query.source)
+        .await
+    {
+        Ok(schemas) => {
+            let body = ReloadSchemasResponse {
+                source: query.source,
+                schemas,
+            };
+            (StatusCode::OK, Json(body)).into_response()
+        }
+        Err(e) => {
+            warn!(source = %query.source, error = %e, "schema source reload failed");
+            let (status, code) = reload_failure(&e);
+            error_response(&state, status, ErrorResponse::new(code, e.to_string()))
+        }
+    }
+}
+
+/// The status and error code answering a failed `/admin/reload-schemas`.
+fn reload_failure(e: &ReloadError) -> (StatusCode, ErrorCode) {
+    match e {
+        ReloadError::UnknownSource(_) => (StatusCode::NOT_FOUND, ErrorCode::NotFound),
+        ReloadError::Conflict(_) => (StatusCode::CONFLICT, ErrorCode::BadRequest),
+        ReloadError::TooManySchemas(_) => (StatusCode::CONFLICT, ErrorCode::TooManySchemas),
+        ReloadError::Fingerprint(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+        }
+        ReloadError::Load(_) => (StatusCode::BAD_GATEWAY, ErrorCode::InternalError),
+    }
+}
+
+/// `GET /admin/stats` — runtime counters for diagnosing memory growth.
+///
+/// Reports only counts and sizes: never payloads, schema contents, or keys.
+/// Requires the admin role.
+pub async fn stats(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(ErrorCode::Forbidden, "stats require the admin role");
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    let body = StatsResponse {
+        schemas_cached: state.schema_cache.len(),
+        pii_path_bytes: state.schema_cache.pii_path_bytes(),
+        active_requests: state.active_requests.load(Ordering::Relaxed),
+        dek_generation: state.dek_store.generation(),
+    };
+    (StatusCode::OK, Json(body)).into_response()
+}
+
+/// `GET /admin/encryption` — the settings applied to newly encrypted data.
+/// Requires the admin role.
+pub async fn encryption_settings(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "reading encryption settings requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    let current = state.encryption.load_full();
+    (StatusCode::OK, Json(current.as_ref().clone())).into_response()
+}
+
+/// `PUT /admin/encryption` — replace the settings applied to newly encrypted
+/// data, without a restart.
+///
+/// Requests already in flight finish with the settings they started with;
+/// existing ciphertext keeps decrypting. Only clients holding the admin role
+/// may call it.
+pub async fn update_encryption_settings(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    Json(settings): Json<EncryptionSettings>,
+) -> Response {
+    if !state
+        .settings
+        .is_admin(identity.as_ref().map(|Extension(id)| id))
+    {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "updating encryption settings requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    info!(encoding = ?settings.encoding, "encryption settings updated");
+    state
+        .encryption
+        .store(std::sync::Arc::new(settings.clone()));
+    (StatusCode::OK, Json(settings)).into_response()
+}
+
+/// `POST /decrypt` — decrypt PII fields in the request payload.
+///
+/// The schema is identified by the value of the `X-Schema-Name` request header
+/// (or the configured header name). Fields at PII paths that carry an encrypted
+/// `v1.<nonce>.<ciphertext>` (or ChaCha20-Poly1305 `c1.`) value are decrypted
+/// back to plaintext. Other fields are left unchanged. Tenant-bound ciphertext only
+/// decrypts with the same `X-Tenant-Id` it was encrypted with.
+pub async fn decrypt(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    deadline: Option<Extension<Deadline>>,
+    headers: HeaderMap,
+    req: Result<Json<DecryptRequest>, JsonRejection>,
+) -> Response {
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    let deadline = deadline.map(|Extension(d)| d);
+    match decrypt_payload(&state, identity, deadline, &headers, req.payload).await {
+        Ok((payload, _)) => {
+            let tenant = tenant_id(&state, &headers).ok().flatten();
+            audit(&state, "decrypt", identity, &headers, false, tenant);
+            (StatusCode::OK, Json(DecryptResponse { payload })).into_response()
+        }
+        Err(resp) => resp,
+    }
+}
+
+/// `POST /encrypt/value` — encrypt a single string with the current DEK.
+///
+/// For lightweight callers such as a database trigger that have one value and
+/// no schema. The value is held to `max_field_bytes` and returned as a compact
+/// `v1.` ciphertext. An optional `context` is bound into the associated data
+/// (see [`value_aad`]); [`decrypt_value`] reverses it. Served only when
+/// `ALLOW_VALUE_ENDPOINT` is set, otherwise `404`, and with a client schema
+/// allowlist only to the clients it lists.
+pub async fn encrypt_value(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    req: Result<Json<EncryptValueRequest>, JsonRejection>,
+) -> Response {
+    use crate::telemetry::Metrics;
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    if let Some(resp) = value_endpoint_refusal(&state, identity, "/encrypt/value") {
+        return resp;
+    }
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
+    let start = std::time::Instant::now();
+    let _active = state.track_request();
+    let record = |attrs: &[opentelemetry::KeyValue]| {
+        state.metrics.encrypt_requests.add(1, attrs);
+        state
+            .metrics
+            .encrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, attrs);
+    };
+
+    let max = state.settings.max_field_bytes;
+    if req.value.len() > max {
+        record(&Metrics::error_attrs());
+        let err = ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("value exceeds the maximum length of {max} bytes"),
+        );
+        return error_response(&state, StatusCode::BAD_REQUEST, err);
+    }
+    let dek = match state.dek_store.current().await {
+        Ok(d) => d,
+        Err(_) => {
+            record(&Metrics::error_attrs());
+            return not_ready(&state, "DEK not yet initialised");
+        }
+    };
+    let aad = value_aad(req.context.as_deref());
+    match encrypt_field_with_aad(req.value.as_bytes(), &dek.0[..], &aad) {
+        Ok(field) => {
+            record(&Metrics::success_attrs());
+            audit(&state, "encrypt_value", identity, &headers, true, None);
+            let body = EncryptValueResponse {
+                value: field.to_string_repr(),
+            };
+            (StatusCode::OK, Json(body)).into_response()
+        }
+        Err(e) => {
+            warn!(error = %e, "value encryption failed");
+            record(&Metrics::error_attrs());
+            let err = ErrorResponse::new(ErrorCode::InternalError, "encryption failed");
+            error_response(&state, StatusCode::INTERNAL_SERVER_ERROR, err)
+        }
+    }
+}
+
+/// `POST /decrypt/value` — decrypt a single value from `/encrypt/value`.
+///
+/// The request carries the `context` the value was encrypted with, if any;
+/// any other context fails authentication. Gated like [`encrypt_value`].
+pub async fn decrypt_value(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    req: Result<Json<DecryptValueRequest>, JsonRejection>,
+) -> Response {
+    use crate::telemetry::Metrics;
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    if let Some(resp) = value_endpoint_refusal(&state, identity, "/decrypt/value") {
+        return resp;
+    }
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
+    let start = std::time::Instant::now();
+    let _active = state.track_request();
+    let record = |attrs: &[opentelemetry::KeyValue]| {
+        state.metrics.decrypt_requests.add(1, attrs);
+        state
+            .metrics
+            .decrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, attrs);
+    };
+
+    let Ok(field) = EncryptedField::from_str(&req.value) else {
+        record(&Metrics::error_attrs());
+        let err = ErrorResponse::new(ErrorCode::BadRequest, "value is not a v1. ciphertext");
+        return error_response(&state, StatusCode::BAD_REQUEST, err);
+    };
+    let dek = match state.dek_store.current().await {
+        Ok(d) => d,
+        Err(_) => {
+            record(&Metrics::error_attrs());
+            return not_ready(&state, "DEK not yet initialised");
+        }
+    };
+    let aad = value_aad(req.context.as_deref());
+    let plaintext = decrypt_field_with_aad(&field, &dek.0[..], &aad)
+        .and_then(|plain| String::from_utf8(plain).map_err(|_| CipherError::AeadFailure));
+    match plaintext {
+        Ok(value) => {
+            record(&Metrics::success_attrs());
+            audit(&state, "decrypt_value", identity, &headers, true, None);
+            (StatusCode::OK, Json(DecryptValueResponse { value })).into_response()
+        }
+        Err(e) => {
+            warn!(error = %e, "value decryption failed");
+            record(&Metrics::error_attrs());
+            let (status, err) = TraversalError::from(e).into_response_parts("decryption failed");
+            error_response(&state, status, err)
+        }
+    }
+}
+
+/// The response refusing a `/encrypt/value` or `/decrypt/value` request:
+/// `404` while `ALLOW_VALUE_ENDPOINT` is unset, `403` for a client the
+/// schema allowlist does not list. `None` when the request may proceed.
+fn value_endpoint_refusal(
+    state: &AppState,
+    identity: Option<&ClientIdentity>,
+    endpoint: &str,
+) -> Option<Response> {
+    if !state.settings.allow_value_endpoint {
+        let err = ErrorResponse::new(ErrorCode::NotFound, format!("{endpoint} is not enabled"));
+        return Some(error_response(state, StatusCode::NOT_FOUND, err));
+    }
+    if !schemaless_permitted(state, identity) {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            format!("client is not permitted to use {endpoint}"),
+        );
+        return Some(error_response(state, StatusCode::FORBIDDEN, err));
+    }
+    None
+}
+
+/// `POST /redact` — replace PII fields with the redaction marker.
+///
+/// For log pipelines that must drop PII rather than read it: every value at
+/// one of the schema's PII paths, plaintext or `v1.` ciphertext, becomes the
+/// configured `REDACTION_MARKER`. Needs no DEK. Schema resolution matches
+/// `/encrypt`; request and response shapes match `/decrypt`.
+pub async fn redact(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    req: Result<Json<RedactRequest>, JsonRejection>,
+) -> Response {
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
+    let _active = state.track_request();
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    let cached = match encrypt_schema(&state, identity, &headers) {
+        Ok(cached) => cached,
+        Err(resp) => return *resp,
+    };
+    let mut payload = req.payload;
+    if let Some(err) = payload_root_error(&state, cached.root, &payload) {
+        return error_response(&state, StatusCode::BAD_REQUEST, err);
+    }
+    redact_pii_fields(
+        &mut payload,
+        &cached.pii_paths,
+        &cached.embedded_json,
+        &state.settings.redaction_marker,
+    );
+    (StatusCode::OK, Json(RedactResponse { payload })).into_response()
+}
+
+/// `POST /verify` — list the PII paths of an already-encrypted payload that
+/// still hold plaintext.
+///
+/// Lets a caller confirm a document is fully protected before storing it
+/// elsewhere. A value counts as protected when it is `v1.` ciphertext (in
+/// either encoding), an `h1.` hash token, or a format-preserving token beside
+/// a well-formed `f1.` tag; `null`, values under a non-matching `x-pii-when`
+/// condition and strings shorter than `MIN_ENCRYPT_LEN` are not reported.
+/// Objects, arrays, booleans and numbers at PII paths are. No DEK is needed
+/// and nothing is decrypted.
+pub async fn verify(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    req: Result<Json<VerifyRequest>, JsonRejection>,
+) -> Response {
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
+    let _active = state.track_request();
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    let cached = match encrypt_schema(&state, identity, &headers) {
+        Ok(cached) => cached,
+        Err(resp) => return *resp,
+    };
+    let mut payload = req.payload;
+    if let Some(err) = payload_root_error(&state, cached.root, &payload) {
+        return error_response(&state, StatusCode::BAD_REQUEST, err);
+    }
+    let mut plaintext = BTreeSet::new();
+    plaintext_pii_paths(
+        &mut payload,
+        &PiiLayout {
+            pii_paths: &cached.pii_paths,
+            conditions: &cached.conditions,
+            tokenized: &cached.tokenized,
+            embedded: &cached.embedded_json,
+        },
+        state.settings.min_encrypt_len,
+        "",
+        &mut plaintext,
+    );
+    let plaintext_paths = plaintext.into_iter().collect();
+    (StatusCode::OK, Json(VerifyResponse { plaintext_paths })).into_response()
+}
+
+/// `POST /admin/decrypt/preview` — decrypt, then mask, PII fields.
+///
+/// Lets support staff confirm a value decrypts correctly without seeing it in
+/// full: each decrypted PII string is masked according to the configured
+/// [`MaskPolicy`](super::mask::MaskPolicy) for its `x-pii-category`. Only
+/// clients holding the admin role (`ADMIN_CLIENT_CNS`) may call it; others get
+/// `403 Forbidden`. Request and response shapes match `/decrypt`.
+pub async fn decrypt_preview(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    deadline: Option<Extension<Deadline>>,
+    headers: HeaderMap,
+    req: Result<Json<DecryptRequest>, JsonRejection>,
+) -> Response {
+    let Json(req) = match req {
+        Ok(req) => req,
+        Err(rejection) => return envelope_error(&state, rejection),
+    };
+    let identity = identity.as_ref().map(|Extension(id)| id);
+    if !state.settings.is_admin(identity) {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            "decrypt preview requires the admin role",
+        );
+        return error_response(&state, StatusCode::FORBIDDEN, err);
+    }
+    let deadline = deadline.map(|Extension(d)| d);
+    match decrypt_payload(&state, identity, deadline, &headers, req.payload).await {
+        Ok((mut payload, cached)) => {
+            let masked = mask_pii_fields(
+                &mut payload,
+                &cached.pii_paths,
+                &cached.categories,
+                &cached.embedded_json,
+                &state.settings.mask_policy,
+            );
+            match masked {
+                Ok(()) => {
+                    let tenant = tenant_id(&state, &headers).ok().flatten();
+                    audit(&state, "decrypt_preview", identity, &headers, false, tenant);
+                    (StatusCode::OK, Json(DecryptResponse { payload })).into_response()
+                }
+                Err(e) => {
+                    let (status, err) = e.into_response_parts("masking failed");
+                    error_response(&state, status, err)
+                }
+            }
+        }
+        Err(resp) => resp,
+    }
+}
+
+/// Shared `/decrypt` pipeline: authorise, resolve the schema, and decrypt
+/// `payload` in place, recording decrypt metrics.
+///
+/// Returns the decrypted payload with the schema used, or the complete error
+/// response to send.
+async fn decrypt_payload(
+    state: &AppState,
+    identity: Option<&ClientIdentity>,
+    deadline: Option<Deadline>,
+    headers: &HeaderMap,
+    mut payload: serde_json::Value,
+) -> Result<(serde_json::Value, CachedSchema), Response> {
+    use crate::telemetry::Metrics;
+    let start = std::time::Instant::now();
+    let _active = state.track_request();
+
+    // Extract schema name from the configured header.
+    let schema_name = match headers.get(state.schema_header_name.as_str()) {
+        Some(v) => match v.to_str() {
+            Ok(s) => s.to_owned(),
+            Err(_) => {
+                let err = ErrorResponse::new(
+                    ErrorCode::BadRequest,
+                    format!(
+                        "{} header contains non-ASCII characters",
+                        state.schema_header_name
+                    ),
+                );
+                let attrs = Metrics::error_attrs();
+                state.metrics.decrypt_requests.add(1, &attrs);
+                state
+                    .metrics
+                    .decrypt_latency_ms
+                    .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+                return Err(error_response(state, StatusCode::BAD_REQUEST, err));
+            }
+        },
+        None => {
+            let err = ErrorResponse::new(
+                ErrorCode::BadRequest,
+                format!("missing {} header", state.schema_header_name),
+            );
+            let attrs = Metrics::error_attrs();
+            state.metrics.decrypt_requests.add(1, &attrs);
+            state
+                .metrics
+                .decrypt_latency_ms
+                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+            return Err(error_response(state, StatusCode::BAD_REQUEST, err));
+        }
+    };
+
+    // Enforce the per-client schema allowlist, when configured.
+    if !schema_permitted(state, identity, &schema_name) {
+        let err = ErrorResponse::new(
+            ErrorCode::Forbidden,
+            format!("client is not permitted to use schema: {schema_name}"),
+        );
+        let attrs = Metrics::error_attrs();
+        state.metrics.decrypt_requests.add(1, &attrs);
+        state
+            .metrics
+            .decrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+        return Err(error_response(state, StatusCode::FORBIDDEN, err));
+    }
+
+    // Tenant binding for the AAD; required when `REQUIRE_TENANT` is set.
+    let tenant = match tenant_id(state, headers) {
+        Ok(t) => t,
+        Err(err) => {
+            let attrs = Metrics::error_attrs();
+            state.metrics.decrypt_requests.add(1, &attrs);
+            state
+                .metrics
+                .decrypt_latency_ms
+                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+            return Err(error_response(state, StatusCode::BAD_REQUEST, err));
+        }
+    };
+
+    // Resolve the schema from the cache.
+    let cached = match cached_schema(state, &schema_name) {
+        Ok(s) => s,
+        Err(e) => {
+            let (status, err) = match e {
+                CacheError::RemovedSchema(_) => (
+                    StatusCode::GONE,
+                    ErrorResponse::new(
+                        ErrorCode::SchemaRemoved,
+                        format!("schema recently removed: {schema_name}"),
+                    ),
+                ),
+                CacheError::UnknownSchema(_) => {
+                    let attrs = Metrics::error_attrs();
+                    state.metrics.decrypt_requests.add(1, &attrs);
+                    state
+                        .metrics
+                        .decrypt_latency_ms
+                        .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+                    return Err(unknown_schema(state, &schema_name));
+                }
+            };
+            let attrs = Metrics::error_attrs();
+            state.metrics.decrypt_requests.add(1, &attrs);
+            state
+                .metrics
+                .decrypt_latency_ms
+                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+            return Err(error_response(state, status, err));
+        }
+    };
+
+    // The payload root must be the kind the schema's paths descend from.
+    if let Some(err) = payload_root_error(state, cached.root, &payload) {
+        let attrs = Metrics::error_attrs();
+        state.metrics.decrypt_requests.add(1, &attrs);
+        state
+            .metrics
+            .decrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+        return Err(error_response(state, StatusCode::BAD_REQUEST, err));
+    }
+
+    // Borrow the current DEK — 503 if not yet initialised.
+    let dek = match state.dek_store.current().await {
+        Ok(d) => d,
+        Err(_) => {
+            let attrs = Metrics::error_attrs();
+            state.metrics.decrypt_requests.add(1, &attrs);
+            state
+                .metrics
+                .decrypt_latency_ms
+                .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+            return Err(not_ready(state, "DEK not yet initialised"));
+        }
+    };
+
+    // Flag nonce reuse before the ciphertext is replaced by plaintext.
+    if state.settings.audit_nonce_reuse {
+        let paths = reused_nonce_paths(&mut payload, &cached.pii_paths, tenant.is_some());
+        if !paths.is_empty() {
+            state.metrics.nonce_reuse.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                schema = %schema_name,
+                paths = ?paths,
+                "ciphertext nonce reused across fields; possible tampering"
+            );
+        }
+    }
+
+    // Traverse and decrypt all PII fields in-place.
+    let ctx = CipherContext {
+        dek: &dek.0[..],
+        token_key: state.token_key.as_deref().map(|k| &k.0[..]),
+        tenant: tenant.as_deref(),
+        schema_tag: None,
+        algorithm: Algorithm::default(),
+        field_lengths: None,
+        deadline,
+        encoding: CiphertextEncoding::CompactString,
+        min_encrypt_len: 0,
+        max_array_items: 0,
+    };
+    let result = decrypt_pii_fields(
+        &mut payload,
+        &cached.pii_paths,
+        &cached.tokenized,
+        &cached.numeric,
+        &ctx,
+    )
+    .and_then(|()| decrypt_embedded_json(&mut payload, &cached.embedded_json, &ctx));
+    if let Err(e) = result {
+        warn!(error = %e, "decryption failed");
+        let (status, err) = match e {
+            // Fields encrypted under one tenant only authenticate under that
+            // tenant's AAD, so a wrong `X-Tenant-Id` surfaces here.
+            TraversalError::Cipher(CipherError::AeadFailure) if tenant.is_some() => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::new(
+                    ErrorCode::TenantMismatch,
+                    "ciphertext does not authenticate for this X-Tenant-Id",
+                ),
+            ),
+            e => e.into_response_parts("decryption failed"),
+        };
+        let attrs = Metrics::error_attrs();
+        state.metrics.decrypt_requests.add(1, &attrs);
+        state
+            .metrics
+            .decrypt_latency_ms
+            .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+        return Err(error_response(state, status, err));
+    }
+
+    let attrs = Metrics::success_attrs();
+    state.metrics.decrypt_requests.add(1, &attrs);
+    state
+        .metrics
+        .decrypt_latency_ms
+        .record(start.elapsed().as_secs_f64() * 1000.0, &attrs);
+    Ok((payload, cached))
+}
+
+/// Catch-all 404 handler.
+pub async fn not_found(State(state): State<AppState>) -> Response {
+    let err = ErrorResponse::new(ErrorCode::NotFound, "the requested resource does not exist");
+    error_response(&state, StatusCode::NOT_FOUND, err)
+}
+
+/// Build an error response, counting it under its [`ErrorCode`] in
+/// `Metrics::error_responses`. Every handler error goes through here.
+pub(crate) fn error_response(state: &AppState, status: StatusCode, err: ErrorResponse) -> Response {
+    state.metrics.error_responses.record(err.code);
+    (status, Json(err)).into_response()
+}
+
+/// `503 Service Unavailable` for a request that arrived before the DEK or
+/// schemas were loaded, with a `Retry-After` header so clients back off
+/// instead of retrying immediately.
+fn not_ready(state: &AppState, message: &str) -> Response {
+    let err = ErrorResponse::new(ErrorCode::ServiceUnavailable, message);
+    with_retry_after(
+        state,
+        error_response(state, StatusCode::SERVICE_UNAVAILABLE, err),
+    )
+}
+
+/// Wait up to `ENCRYPT_READY_WAIT_MS` for the DEK and, when `schemas` is
+/// set, the first schema load, so a request arriving while they load is
+/// served rather than refused. Returns as soon as both are present, or when
+/// the wait elapses with the caller left to answer `503`.
+async fn await_readiness(state: &AppState, schemas: bool) {
+    let Some(wait) = state.settings.encrypt_ready_wait else {
+        return;
+    };
+    let ready = async {
+        loop {
+            let stored = state.dek_store.stored();
+            let installed = state.schema_cache.installed();
+            tokio::pin!(stored, installed);
+            stored.as_mut().enable();
+            installed.as_mut().enable();
+            if state.dek_store.is_ready().await && (!schemas || state.schema_cache.is_loaded()) {
+                return;
+            }
+            tokio::select! {
+                () = stored => {}
+                () = installed => {}
+            }
+        }
+    };
+    // Elapsing is not an error: the handler reports what is still missing.
+    let _ = tokio::time::timeout(wait, ready).await;
+}
+
+/// Response for a request naming a schema the cache does not hold.
+///
+/// Until the first schema load completes (e.g. right after a deploy) the
+/// schema is most likely on its way, so this is a retryable `503`
+/// `schemas_loading` with `Retry-After`. Afterwards it is the configured
+/// `UNKNOWN_SCHEMA_STATUS`, `400` or `404`.
+fn unknown_schema(state: &AppState, schema_name: &str) -> Response {
+    if !state.schema_cache.is_loaded() {
+        let err = ErrorResponse::new(ErrorCode::SchemasLoading, "schemas not yet loaded");
+        return with_retry_after(
+            state,
+            error_response(state, StatusCode::SERVICE_UNAVAILABLE, err),
+        );
+    }
+    let status = state.settings.unknown_schema_status;
+    let code = if status == StatusCode::NOT_FOUND {
+        ErrorCode::NotFound
+    } else {
+        ErrorCode::BadRequest
+    };
+    let err = ErrorResponse::new(code, format!("unknown schema: {schema_name}"));
+    error_response(state, status, err)
+}
+
+/// Add the configured `Retry-After` header to a 503 response.
+fn with_retry_after(state: &AppState, response: Response) -> Response {
+    (
+        [(RETRY_AFTER, state.settings.retry_after_secs.to_string())],
+        response,
+    )
+        .into_response()
+}
+
+/// Whether the last successful schema refresh is older than the configured
+/// staleness limit.
+fn schemas_stale(state: &AppState) -> bool {
+    let staleness = state.schema_cache.staleness(std::time::Instant::now());
+    state.settings.schemas_stale(staleness)
+}
+
+/// Read the optional [`TENANT_HEADER`].
+///
+/// Returns the 400 body to send if the header is malformed, or missing while
+/// `require_tenant` is set.
+fn tenant_id(state: &AppState, headers: &HeaderMap) -> Result<Option<String>, ErrorResponse> {
+    match headers.get(TENANT_HEADER).map(|v| v.to_str()) {
+        Some(Ok(t)) if !t.trim().is_empty() => Ok(Some(t.to_owned())),
+        Some(Ok(_)) => Err(ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("{TENANT_HEADER} header must not be empty"),
+        )),
+        Some(Err(_)) => Err(ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("{TENANT_HEADER} header contains non-ASCII characters"),
+        )),
+        None if state.settings.require_tenant => Err(ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("missing {TENANT_HEADER} header"),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Queue an audit event for a successful `action` (see [`AuditLog`]). The
+/// schema is the one named by the header, or none for `inline` PII paths and
+/// other schemaless requests.
+///
+/// [`AuditLog`]: crate::telemetry::audit::AuditLog
+fn audit(
+    state: &AppState,
+    action: &'static str,
+    identity: Option<&ClientIdentity>,
+    headers: &HeaderMap,
+    inline: bool,
+    tenant: Option<String>,
+) {
+    let schema = headers
+        .get(state.schema_header_name.as_str())
+        .filter(|_| !inline)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    state.audit.record(AuditEvent {
+        action,
+        schema,
+        client: identity.map(|id| id.0.clone()),
+        tenant,
+    });
+}
+
+/// The response for a request body that could not be read as the
+/// `{"payload": ...}` envelope.
+///
+/// Well-formed JSON of the wrong shape (most often the bare payload, without
+/// the envelope) gets a 400 explaining the expected form instead of axum's
+/// terse 422. The serde detail is not echoed, since it may quote field
+/// values. Other rejections (malformed JSON, wrong content type) keep axum's
+/// response.
+fn envelope_error(state: &AppState, rejection: JsonRejection) -> Response {
+    match rejection {
+        JsonRejection::JsonDataError(_) => {
+            let err = ErrorResponse::new(
+                ErrorCode::BadRequest,
+                r#"request body must be a JSON object wrapping the document in a "payload" key, e.g. {"payload":{"ssn":"123-45-6789"}}"#,
+            );
+            error_response(state, StatusCode::BAD_REQUEST, err)
+        }
+        other => other.into_response(),
+    }
+}
+
+/// The 400 body for a payload whose root is not the schema's `expected` kind,
+/// or `None` when it matches (or the check is disabled or unconstrained).
+fn payload_root_error(
+    state: &AppState,
+    expected: Option<RootKind>,
+    payload: &serde_json::Value,
+) -> Option<ErrorResponse> {
+    let expected = expected.filter(|_| state.settings.enforce_payload_root)?;
+    if expected.matches(payload) {
+        return None;
+    }
+    let actual = match payload {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    };
+    Some(root_error(expected, actual))
+}
+
+/// A 400 body naming the first PII path excluded by the [`PII_SCOPE_HEADER`]
+/// that holds a non-null value in `payload`, which would otherwise pass
+/// through unencrypted.
+fn out_of_scope_error(
+    cached: &CachedSchema,
+    payload: &mut serde_json::Value,
+) -> Option<ErrorResponse> {
+    let path = cached.out_of_scope.iter().find(|path| {
+        let mut filled = false;
+        visit_pii_leaves(
+            payload,
+            &parse_path(path),
+            cached.conditions.get(*path),
+            &mut |leaf| filled |= !leaf.is_null(),
+        );
+        filled
+    })?;
+    Some(ErrorResponse::new(
+        ErrorCode::BadRequest,
+        format!("PII field {path} is outside the {PII_SCOPE_HEADER} scope but has a value"),
+    ))
+}
+
+/// The 400 body for an object payload with top-level keys `cached` does not
+/// declare, or `None` when strict keys are disabled or the schema is free-form.
+fn unknown_keys_error(
+    state: &AppState,
+    cached: &CachedSchema,
+    payload: &serde_json::Value,
+) -> Option<ErrorResponse> {
+    if !state.settings.reject_unknown_top_level_keys {
+        return None;
+    }
+    let declared = cached.top_level_keys.as_deref()?;
+    let mut unknown: Vec<&str> = payload
+        .as_object()?
+        .keys()
+        .map(String::as_str)
+        .filter(|key| !declared.contains(*key))
+        .collect();
+    if unknown.is_empty() {
+        return None;
+    }
+    unknown.sort_unstable();
+    Some(ErrorResponse::new(
+        ErrorCode::BadRequest,
+        format!(
+            "payload has top-level keys not declared by the schema: {}",
+            unknown.join(", ")
+        ),
+    ))
+}
+
+/// The 400 body for a payload whose root is `actual` instead of `expected`.
+fn root_error(expected: RootKind, actual: &str) -> ErrorResponse {
+    ErrorResponse::new(
+        ErrorCode::BadRequest,
+        format!(
+            "payload must be a JSON {} for this schema, got {actual}",
+            expected.as_str()
+        ),
+    )
+}
+
+/// Build an ad-hoc schema from caller-supplied PII paths.
+///
+/// Returns the 400 body to send if inline schemas are disabled or the paths
+/// are malformed: empty, too many, or containing an empty segment.
+fn inline_schema(state: &AppState, paths: Vec<String>) -> Result<CachedSchema, ErrorResponse> {
+    let bad_request = |message: String| ErrorResponse::new(ErrorCode::BadRequest, message);
+    if !state.settings.allow_inline_schema {
+        return Err(bad_request("inline pii_paths are not enabled".into()));
+    }
+    if paths.is_empty() {
+        return Err(bad_request("pii_paths must not be empty".into()));
+    }
+    if paths.len() > MAX_INLINE_PII_PATHS {
+        return Err(bad_request(format!(
+            "pii_paths must list at most {MAX_INLINE_PII_PATHS} paths"
+        )));
+    }
+    for path in &paths {
+        // `[]` alone is allowed as the first segment, for array-rooted payloads.
+        let malformed = path.split('.').enumerate().any(|(i, part)| {
+            let key = part.strip_suffix("[]").unwrap_or(part);
+            (key.is_empty() && !(i == 0 && part == "[]")) || key.contains("[]")
+        });
+        if malformed {
+            return Err(bad_request(format!(
+                "invalid PII path in pii_paths: {path:?}"
+            )));
+        }
+    }
+    Ok(CachedSchema::inline(paths.into_iter().collect()))
+}
+
+/// Whether the client may use `schema` under the configured allowlist, if any.
+fn schema_permitted(state: &AppState, identity: Option<&ClientIdentity>, schema: &str) -> bool {
+    match &state.settings.schema_allowlist {
+        Some(list) => list.permits(identity, schema),
+        None => true,
+    }
+}
+
+/// Whether the client may make requests that name no schema (inline
+/// `pii_paths` and the value endpoints) under the configured allowlist, if
+/// any. Such requests cannot be matched against a schema prefix, so only
+/// clients the allowlist names are let through.
+fn schemaless_permitted(state: &AppState, identity: Option<&ClientIdentity>) -> bool {
+    match &state.settings.schema_allowlist {
+        Some(list) => list.admits(identity),
+        None => true,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PII field traversal helpers
+// ---------------------------------------------------------------------------
+
+/// Errors produced while transforming PII fields in a payload.
+#[derive(Debug, Error)]
+enum TraversalError {
+    /// The cipher layer failed on a leaf value.
+    #[error(transparent)]
+    Cipher(#[from] CipherError),
+
+    /// A field declared as embedded JSON (`x-pii-json`) does not hold a JSON document.
+    #[error("field {0} does not contain a valid embedded JSON document")]
+    EmbeddedJson(String),
+
+    /// A PII string value exceeds the schema `maxLength` or the global byte limit.
+    #[error("field {path} exceeds the maximum length of {limit}")]
+    FieldTooLong {
+        /// Dot-notation path of the offending field.
+        path: String,
+        /// The limit that was exceeded, with its unit.
+        limit: String,
+    },
+
+    /// With `strict_leaf_types`, a PII path holds a value of a type encryption
+    /// would skip.
+    #[error("field {path} must be {expected}, found {found}")]
+    UnexpectedType {
+        /// Dot-notation path of the offending field.
+        path: String,
+        /// The types the path accepts.
+        expected: &'static str,
+        /// The JSON type the payload holds there.
+        found: &'static str,
+    },
+
+    /// The payload holds more PII leaves than `max_encrypted_fields`.
+    #[error("payload has more than {0} PII fields")]
+    TooManyFields(usize),
+
+    /// An array on a PII path holds more items than `max_array_items`.
+    #[error("payload array on a PII path has more than {0} items")]
+    ArrayTooLarge(usize),
+
+    /// The caller's [`Deadline`] passed before the traversal finished.
+    #[error("request deadline exceeded")]
+    DeadlineExceeded,
+}
+
+impl TraversalError {
+    /// Map this error to the HTTP status and body returned to the caller.
+    ///
+    /// Cipher failures are reported with the generic `failure` message so that
+    /// no crypto detail leaks; payload-shape errors name the offending path.
+    fn into_response_parts(self, failure: &str) -> (StatusCode, ErrorResponse) {
+        match self {
+            e @ TraversalError::Cipher(
+                CipherError::TooShortToTokenize | CipherError::UnauthenticatedToken,
+            ) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(ErrorCode::BadRequest, e.to_string()),
+            ),
+            e @ TraversalError::Cipher(CipherError::TokenKeyUnavailable) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse::new(ErrorCode::ServiceUnavailable, e.to_string()),
+            ),
+            TraversalError::Cipher(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse::new(ErrorCode::InternalError, failure),
+            ),
+            e @ (TraversalError::EmbeddedJson(_)
+            | TraversalError::FieldTooLong { .. }
+            | TraversalError::UnexpectedType { .. }) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(ErrorCode::BadRequest, e.to_string()),
+            ),
+            e @ TraversalError::TooManyFields(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(ErrorCode::TooManyPiiFields, e.to_string()),
+            ),
+            e @ TraversalError::ArrayTooLarge(_) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::new(ErrorCode::ArrayTooLarge, e.to_string()),
+            ),
+            e @ TraversalError::DeadlineExceeded => (
+                StatusCode::GATEWAY_TIMEOUT,
+                ErrorResponse::new(ErrorCode::DeadlineExceeded, e.to_string()),
+            ),
+        }
+    }
+}
+
+/// Per-request inputs to field encryption and decryption.
+#[derive(Clone, Copy)]
+struct CipherContext<'a> {
+    /// Raw DEK bytes.
+    dek: &'a [u8],
+    /// Raw token key bytes, when a token key is configured.
+    token_key: Option<&'a [u8]>,
+    /// Tenant bound into each field's AAD, if any.
+    tenant: Option<&'a str>,
+    /// Schema tag embedded in each ciphertext, if enabled.
+    schema_tag: Option<&'a str>,
+    /// Algorithm new ciphertext is produced with.
+    algorithm: Algorithm,
+    /// Where protected field sizes are counted, if anywhere.
+    field_lengths: Option<&'a FieldLengths>,
+    /// The caller's deadline, checked before each PII path.
+    deadline: Option<Deadline>,
+    /// How new ciphertext is written into the payload.
+    encoding: CiphertextEncoding,
+    /// String leaves with fewer characters than this are left as they are.
+    min_encrypt_len: usize,
+    /// Items an array on a PII path may hold (`0` for no limit).
+    max_array_items: usize,
+}
+
+impl CipherContext<'_> {
+    /// The token key hash, lookup and format-preserving tokens are derived
+    /// from.
+    fn token_key(&self) -> Result<&[u8], CipherError> {
+        self.token_key.ok_or(CipherError::TokenKeyUnavailable)
+    }
+
+    /// Fail once the caller's deadline has passed.
+    fn check_deadline(&self) -> Result<(), TraversalError> {
+        match self.deadline {
+            Some(deadline) if deadline.expired() => Err(TraversalError::DeadlineExceeded),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How one PII leaf is protected, per its `x-pii-mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protection {
+    /// Reversible `v1.`/`c1.` ciphertext (the default).
+    Encrypt,
+    /// Irreversible `h1.` hash token.
+    Hash,
+    /// Format-preserving token.
+    Tokenize,
+}
+
+impl Protection {
+    /// The protection of `path`; hashing wins over tokenization when a
+    /// merged schema marks a path both ways.
+    fn of(path: &str, hashed: &PiiFieldPaths, tokenized: &PiiFieldPaths) -> Self {
+        if hashed.contains(path) {
+            Self::Hash
+        } else if tokenized.contains(path) {
+            Self::Tokenize
+        } else {
+            Self::Encrypt
+        }
+    }
+}
+
+/// Suffix of the sibling field holding a `x-pii-mode: lookup` field's tag.
+const LOOKUP_SIBLING_SUFFIX: &str = "_lookup";
+
+/// Suffix of the sibling field holding the tag that authenticates a
+/// `x-pii-mode: fpe` field's token.
+const FPE_SIBLING_SUFFIX: &str = "_fpe";
+
+/// Length of the schema fingerprint prefix embedded in ciphertexts.
+const SCHEMA_TAG_LEN: usize = 12;
+
+/// The ciphertext schema tag for `cached`, when tagging is enabled.
+fn schema_tag<'a>(state: &AppState, cached: &'a CachedSchema) -> Option<&'a str> {
+    let fingerprint = &*cached.fingerprint;
+    state
+        .settings
+        .schema_tag_ciphertext
+        .then(|| fingerprint.get(..SCHEMA_TAG_LEN).unwrap_or(fingerprint))
+}
+
+/// The cipher algorithm `cached` selects with `x-encryption-alg`.
+fn algorithm(cached: &CachedSchema) -> Algorithm {
+    match cached.algorithm {
+        EncryptionAlg::Aes256GcmSiv => Algorithm::Aes256GcmSiv,
+        EncryptionAlg::ChaCha20Poly1305 => Algorithm::ChaCha20Poly1305,
+    }
+}
+
+/// Segments of a dot-notation PII field path.
+enum PathSegment {
+    /// Navigate into an object property by name.
+    Key(String),
+    /// Expand into every element of a JSON array.
+    ArrayItem,
+    /// Navigate into the property of this name in every object at any depth
+    /// below (and including) the current value.
+    RecursiveKey(String),
+}
+
+/// Parse a dot-notation PII path into a list of [`PathSegment`]s.
+///
+/// Array fields use the `[]` suffix before the dot separator, e.g.
+/// `"orders[].card_number"` → `[Key("orders"), ArrayItem, Key("card_number")]`.
+/// A `**` segment makes the following key recursive, e.g. `"**.accountId"` →
+/// `[RecursiveKey("accountId")]`; a trailing `**` is an ordinary key.
+fn parse_path(path: &str) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    let mut parts = path.split('.').peekable();
+    while let Some(mut part) = parts.next() {
+        let recursive = part == "**" && parts.peek().is_some();
+        if recursive {
+            part = parts.next().unwrap_or_default();
+        }
+        let (key, array) = match part.strip_suffix("[]") {
+            Some(key) => (key, true),
+            None => (part, false),
+        };
+        segments.push(if recursive {
+            PathSegment::RecursiveKey(key.to_owned())
+        } else {
+            PathSegment::Key(key.to_owned())
+        });
+        if array {
+            segments.push(PathSegment::ArrayItem);
+        }
+    }
+    segments
+}
+
+/// Whether `path` contains a [`PathSegment::RecursiveKey`].
+fn is_recursive_path(path: &str) -> bool {
+    parse_path(path)
+        .iter()
+        .any(|segment| matches!(segment, PathSegment::RecursiveKey(_)))
+}
+
+/// Apply `f` to every object in `value`, at any depth and including `value`
+/// itself, that has a property named `key`.
+fn visit_objects_with_key<E, F>(
+    value: &mut serde_json::Value,
+    key: &str,
+    f: &mut F,
+) -> Result<(), E>
+where
+    F: FnMut(&mut serde_json::Map<String, serde_json::Value>) -> Result<(), E>,
+{
+    match value {
+        serde_json::Value::Object(map) => {
+            if map.contains_key(key) {
+                f(map)?;
+            }
+            for child in map.values_mut() {
+                visit_objects_with_key(child, key, f)?;
+            }
+        }
+        serde_json::Value::Array(arr) => {
+            for item in arr.iter_mut() {
+                visit_objects_with_key(item, key, f)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The child a [`PathSegment::RecursiveKey`] match continues into with
+/// `rest`. When the path ends at the recursive key, only leaves match, so an
+/// object or array that merely shares the name is passed over. Ciphertext in
+/// the object encoding counts as a leaf.
+fn recursive_match<'a>(
+    map: &'a mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+    rest: &[PathSegment],
+) -> Option<&'a mut serde_json::Value> {
+    map.get_mut(key).filter(|child| {
+        let container =
+            child.is_array() || (child.is_object() && !EncryptedField::is_json_object(child));
+        !(rest.is_empty() && container)
+    })
+}
+
+/// The condition still to apply below `map` when the path continues with
+/// `rest`, or `None` when `condition` excludes `map`. A condition is evaluated
+/// on the object holding the final key segment.
+fn scoped_condition<'c>(
+    condition: Option<&'c PiiCondition>,
+    map: &serde_json::Map<String, serde_json::Value>,
+    rest: &[PathSegment],
+) -> Option<Option<&'c PiiCondition>> {
+    let is_leaf_parent = rest.iter().all(|s| matches!(s, PathSegment::ArrayItem));
+    match condition {
+        Some(c) if is_leaf_parent => c.matches(map).then_some(None),
+        other => Some(other),
+    }
+}
+
+/// Recursively navigate `value` following `segments` and encrypt any string
+/// leaf found at the end of the path, or replace it with its keyed hash when
+/// `hash` is set. With `numeric`, number leaves are protected too, using their
+/// exact JSON token as the plaintext.
+///
+/// When `condition` is set it is evaluated against the object holding the final
+/// key segment (for `[]`-terminated paths, the object holding the array); the
+/// leaf is only encrypted in objects where the condition matches.
+///
+/// An array on the path holding more than the context's `max_array_items`
+/// fails the traversal rather than being walked.
+fn encrypt_at_path(
+    value: &mut serde_json::Value,
+    segments: &[PathSegment],
+    condition: Option<&PiiCondition>,
+    protection: Protection,
+    numeric: bool,
+    ctx: &CipherContext<'_>,
+    aad: &[u8],
+) -> Result<(), TraversalError> {
+    if segments.is_empty() {
+        let Some(plaintext) = leaf_plaintext(value, numeric, ctx.min_encrypt_len) else {
+            return Ok(());
+        };
+        *value = protect_leaf_value(plaintext, protection, ctx, aad)?;
+        return Ok(());
+    }
+
+    match &segments[0] {
+        PathSegment::Key(key) => {
+            if let serde_json::Value::Object(map) = value {
+                let Some(condition) = scoped_condition(condition, map, &segments[1..]) else {
+                    return Ok(());
+                };
+                if let Some(child) = map.get_mut(key) {
+                    let tokenized = protection == Protection::Tokenize
+                        && segments.len() == 1
+                        && leaf_plaintext(child, numeric, ctx.min_encrypt_len).is_some();
+                    encrypt_at_path(
+                        child,
+                        &segments[1..],
+                        condition,
+                        protection,
+                        numeric,
+                        ctx,
+                        aad,
+                    )?;
+                    // The tag is what lets `/decrypt` tell the token from
+                    // plaintext.
+                    if let (true, Some(serde_json::Value::String(token))) =
+                        (tokenized, map.get(key))
+                    {
+                        let tag = token_tag(token, ctx.token_key()?, aad)?;
+                        map.insert(format!("{key}{FPE_SIBLING_SUFFIX}"), tag.into());
+                    }
+                }
+            }
+        }
+        PathSegment::ArrayItem => {
+            if let serde_json::Value::Array(arr) = value {
+                if ctx.max_array_items != 0 && arr.len() > ctx.max_array_items {
+                    return Err(TraversalError::ArrayTooLarge(ctx.max_array_items));
+                }
+                for item in arr.iter_mut() {
+                    encrypt_at_path(
+                        item,
+                        &segments[1..],
+                        condition,
+                        protection,
+                        numeric,
+                        ctx,
+                        aad,
+                    )?;
+                }
+            }
+        }
+        PathSegment::RecursiveKey(key) => {
+            let rest = &segments[1..];
+            visit_objects_with_key(value, key, &mut |map| {
+                let Some(condition) = scoped_condition(condition, map, rest) else {
+                    return Ok(());
+                };
+                match recursive_match(map, key, rest) {
+                    Some(child) => {
+                        encrypt_at_path(child, rest, condition, protection, numeric, ctx, aad)
+                    }
+                    None => Ok(()),
+                }
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// The plaintext [`encrypt_at_path`] protects in `value`, if it is a leaf it
+/// protects: a string of at least `min_encrypt_len` characters or, with
+/// `numeric`, a number's exact JSON token.
+fn leaf_plaintext(
+    value: &serde_json::Value,
+    numeric: bool,
+    min_encrypt_len: usize,
+) -> Option<&[u8]> {
+    match value {
+        serde_json::Value::String(s) if s.chars().count() < min_encrypt_len => None,
+        serde_json::Value::String(s) => Some(s.as_bytes()),
+        serde_json::Value::Number(n) if numeric => Some(n.as_str().as_bytes()),
+        _ => None,
+    }
+}
+
+/// The protected form of one PII leaf: its keyed hash, its format-preserving
+/// token, or its ciphertext string, per `protection`.
+fn protect_leaf(
+    plaintext: &[u8],
+    protection: Protection,
+    ctx: &CipherContext<'_>,
+    aad: &[u8],
+) -> Result<String, CipherError> {
+    let protected = match protection {
+        Protection::Hash => hash_field(plaintext, ctx.token_key()?, aad)?,
+        Protection::Tokenize => {
+            let plaintext =
+                std::str::from_utf8(plaintext).map_err(|_| CipherError::InvalidFormat)?;
+            tokenize(plaintext, ctx.token_key()?, aad)?
+        }
+        Protection::Encrypt => encrypt_leaf(plaintext, ctx, aad)?.to_string_repr(),
+    };
+    if let Some(lengths) = ctx.field_lengths {
+        lengths.record(plaintext.len(), protected.len());
+    }
+    Ok(protected)
+}
+
+/// [`protect_leaf`] as a JSON value, with ciphertext in the context's
+/// [`CiphertextEncoding`]. Hash and format-preserving tokens are always
+/// strings.
+fn protect_leaf_value(
+    plaintext: &[u8],
+    protection: Protection,
+    ctx: &CipherContext<'_>,
+    aad: &[u8],
+) -> Result<serde_json::Value, CipherError> {
+    if protection != Protection::Encrypt || ctx.encoding == CiphertextEncoding::CompactString {
+        return protect_leaf(plaintext, protection, ctx, aad).map(serde_json::Value::String);
+    }
+    let protected = encrypt_leaf(plaintext, ctx, aad)?.to_json_object();
+    if let Some(lengths) = ctx.field_lengths {
+        lengths.record(plaintext.len(), protected.to_string().len());
+    }
+    Ok(protected)
+}
+
+/// Encrypt one PII leaf, tagged with the context's schema tag if any.
+fn encrypt_leaf(
+    plaintext: &[u8],
+    ctx: &CipherContext<'_>,
+    aad: &[u8],
+) -> Result<EncryptedField, CipherError> {
+    encrypt_field_as(ctx.algorithm, plaintext, ctx.dek, aad, ctx.schema_tag)
+}
+
+/// Check every PII string value against its limit: the schema `maxLength`
+/// (in characters) when declared, otherwise `max_field_bytes`. Embedded-JSON
+/// fields are held to `max_field_bytes` as a whole.
+fn check_field_lengths(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    max_lengths: &PiiMaxLengths,
+    embedded: &EmbeddedJsonPaths,
+    max_field_bytes: usize,
+) -> Result<(), TraversalError> {
+    visit_field_lengths(
+        payload,
+        pii_paths,
+        max_lengths,
+        embedded,
+        max_field_bytes,
+        &mut |_, e| Err(e),
+    )
+}
+
+/// Apply `on_violation` to every value [`check_field_lengths`] would reject.
+fn visit_field_lengths<F>(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    max_lengths: &PiiMaxLengths,
+    embedded: &EmbeddedJsonPaths,
+    max_field_bytes: usize,
+    on_violation: &mut F,
+) -> Result<(), TraversalError>
+where
+    F: FnMut(&mut serde_json::Value, TraversalError) -> Result<(), TraversalError>,
+{
+    let paths = pii_paths
+        .iter()
+        .map(|path| (path, max_lengths.get(path).copied()))
+        .chain(embedded.keys().map(|path| (path, None)));
+    for (path, max_chars) in paths {
+        visit_path(payload, &parse_path(path), &mut |leaf| {
+            let serde_json::Value::String(s) = leaf else {
+                return Ok(());
+            };
+            let limit = match max_chars {
+                Some(max) => (s.chars().count() > max).then(|| format!("{max} characters")),
+                None => (s.len() > max_field_bytes).then(|| format!("{max_field_bytes} bytes")),
+            };
+            match limit {
+                Some(limit) => on_violation(
+                    leaf,
+                    TraversalError::FieldTooLong {
+                        path: path.clone(),
+                        limit,
+                    },
+                ),
+                None => Ok(()),
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// For `collect_errors`: set to `null` every field of `payload` that would
+/// fail the request (over its length limit, of a type strict mode rejects, or
+/// declared embedded JSON that does not parse) and report each, so the remaining fields can still be
+/// encrypted. Cipher failures are not specific to a field and still fail the
+/// request.
+fn isolate_failing_fields(
+    state: &AppState,
+    cached: &CachedSchema,
+    payload: &mut serde_json::Value,
+) -> Vec<FieldError> {
+    let mut failed = Vec::new();
+    let mut fail = |leaf: &mut serde_json::Value, path: &str, e: TraversalError| {
+        failed.push(FieldError {
+            path: path.to_owned(),
+            message: e.to_string(),
+        });
+        *leaf = serde_json::Value::Null;
+        Ok(())
+    };
+    let _ = visit_field_lengths(
+        payload,
+        &cached.pii_paths,
+        &cached.max_lengths,
+        &cached.embedded_json,
+        state.settings.max_field_bytes,
+        &mut |leaf, e| {
+            let path = match &e {
+                TraversalError::FieldTooLong { path, .. } => path.clone(),
+                _ => return Err(e),
+            };
+            fail(leaf, &path, e)
+        },
+    );
+    if state.settings.strict_leaf_types {
+        let _ = visit_leaf_types(
+            payload,
+            &cached.pii_paths,
+            &cached.numeric,
+            &mut |leaf, e| {
+                let path = match &e {
+                    TraversalError::UnexpectedType { path, .. } => path.clone(),
+                    _ => return Err(e),
+                };
+                fail(leaf, &path, e)
+            },
+        );
+    }
+    for path in cached.embedded_json.keys() {
+        let _ = visit_path(payload, &parse_path(path), &mut |leaf| match leaf
+            .as_str()
+            .map(serde_json::from_str::<serde_json::Value>)
+        {
+            Some(Err(_)) => fail(leaf, path, TraversalError::EmbeddedJson(path.clone())),
+            _ => Ok(()),
+        });
+    }
+    failed
+}
+
+/// Apply `on_violation` to every value at a PII path that encryption would
+/// pass over for its type: an object, array or boolean, or a number at a path
+/// not in `numeric`. `null` and ciphertext in the object encoding are
+/// accepted, and recursive paths only ever reach leaves.
+fn visit_leaf_types<F>(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    numeric: &PiiFieldPaths,
+    on_violation: &mut F,
+) -> Result<(), TraversalError>
+where
+    F: FnMut(&mut serde_json::Value, TraversalError) -> Result<(), TraversalError>,
+{
+    for path in pii_paths {
+        let numeric = numeric.contains(path);
+        visit_path(payload, &parse_path(path), &mut |leaf| {
+            let found = match leaf {
+                serde_json::Value::Null | serde_json::Value::String(_) => return Ok(()),
+                serde_json::Value::Number(_) if numeric => return Ok(()),
+                serde_json::Value::Object(_) if EncryptedField::is_json_object(leaf) => {
+                    return Ok(())
+                }
+                serde_json::Value::Number(_) => "number",
+                serde_json::Value::Bool(_) => "boolean",
+                serde_json::Value::Array(_) => "array",
+                serde_json::Value::Object(_) => "object",
+            };
+            let expected = if numeric {
+                "a string or number"
+            } else {
+                "a string"
+            };
+            on_violation(
+                leaf,
+                TraversalError::UnexpectedType {
+                    path: path.clone(),
+                    expected,
+                    found,
+                },
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Count the PII leaves in `payload` that encryption would protect, failing
+/// once there are more than `max_fields` (`0` for no limit).
+fn check_field_count(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    numeric: &PiiFieldPaths,
+    max_fields: usize,
+) -> Result<(), TraversalError> {
+    if max_fields == 0 {
+        return Ok(());
+    }
+    let mut fields = 0usize;
+    for path in pii_paths {
+        let segments = parse_path(path);
+        visit_path(payload, &segments, &mut |leaf| {
+            if is_protected_leaf(leaf, numeric.contains(path)) {
+                fields += 1;
+                if fields > max_fields {
+                    return Err(TraversalError::TooManyFields(max_fields));
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Whether encryption replaces `leaf`: strings always, numbers only at
+/// `numeric` paths. Mirrors [`encrypt_at_path`].
+fn is_protected_leaf(leaf: &serde_json::Value, numeric: bool) -> bool {
+    match leaf {
+        serde_json::Value::String(_) => true,
+        serde_json::Value::Number(_) => numeric,
+        _ => false,
+    }
+}
+
+/// Encrypt all PII string fields in `payload` according to `pii_paths`,
+/// honouring any sibling `conditions`. Paths in `hashed` are hashed instead,
+/// and paths in `tokenized` replaced with format-preserving tokens; paths in
+/// `numeric` also protect number values.
+fn encrypt_pii_fields(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    conditions: &PiiConditions,
+    hashed: &PiiFieldPaths,
+    tokenized: &PiiFieldPaths,
+    numeric: &PiiFieldPaths,
+    ctx: &CipherContext<'_>,
+) -> Result<(), TraversalError> {
+    for path in pii_paths {
+        ctx.check_deadline()?;
+        let segments = parse_path(path);
+        let aad = field_aad(ctx.tenant, path);
+        encrypt_at_path(
+            payload,
+            &segments,
+            conditions.get(path),
+            Protection::of(path, hashed, tokenized),
+            numeric.contains(path),
+            ctx,
+            &aad,
+        )?;
+    }
+    Ok(())
+}
+
+/// Store the lookup tag of every leaf at `lookup` paths in a sibling named
+/// `<field>_lookup`. Runs on the plaintext, before [`encrypt_pii_fields`];
+/// leaves whose sibling condition does not match are skipped, as they are
+/// left unencrypted.
+fn add_lookup_tags(
+    payload: &mut serde_json::Value,
+    lookup: &PiiFieldPaths,
+    conditions: &PiiConditions,
+    numeric: &PiiFieldPaths,
+    ctx: &CipherContext<'_>,
+) -> Result<(), TraversalError> {
+    if lookup.is_empty() {
+        return Ok(());
+    }
+    let subkey = LookupKey::derive(ctx.token_key()?, ctx.tenant)?;
+    for path in lookup {
+        let mut segments = parse_path(path);
+        // The resolver only records lookup paths ending in a property name.
+        let Some(PathSegment::Key(field)) = segments.pop() else {
+            continue;
+        };
+        let numeric = numeric.contains(path);
+        visit_path(payload, &segments, &mut |parent| {
+            let serde_json::Value::Object(map) = parent else {
+                return Ok(());
+            };
+            if conditions.get(path).is_some_and(|c| !c.matches(map)) {
+                return Ok(());
+            }
+            let plaintext = match map.get(&field) {
+                Some(serde_json::Value::String(s)) => s.as_bytes(),
+                Some(serde_json::Value::Number(n)) if numeric => n.as_str().as_bytes(),
+                _ => return Ok(()),
+            };
+            let tag = derive_lookup_tag(plaintext, path, &subkey)?;
+            map.insert(format!("{field}{LOOKUP_SIBLING_SUFFIX}"), tag.into());
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Recursively navigate `value` following `segments` and decrypt any string
+/// leaf at the end of the path that carries a `v1.` or `c1.` ciphertext prefix.
+/// Remaining leaves (including hash tokens, which are irreversible) are left
+/// unchanged. With `numeric`, a plaintext that is a JSON number token is
+/// restored as that exact number.
+fn decrypt_at_path(
+    value: &mut serde_json::Value,
+    segments: &[PathSegment],
+    numeric: bool,
+    dek: &[u8],
+    aad: &[u8],
+) -> Result<(), CipherError> {
+    if segments.is_empty() {
+        let plaintext = match value {
+            serde_json::Value::String(s) if EncryptedField::has_prefix(s) => {
+                decrypt_field_with_aad(&EncryptedField::from_str(s)?, dek, aad)?
+            }
+            serde_json::Value::Object(_) => match EncryptedField::from_json(value) {
+                Ok(field) => decrypt_field_with_aad(&field, dek, aad)?,
+                // Objects that are not ciphertext are left as-is.
+                Err(_) => return Ok(()),
+            },
+            // Non-encrypted strings are left as-is (idempotent path traversal).
+            _ => return Ok(()),
+        };
+        let plaintext = String::from_utf8(plaintext).map_err(|_| CipherError::AeadFailure)?;
+        *value = restored_leaf(plaintext, numeric);
+        return Ok(());
+    }
+
+    match &segments[0] {
+        PathSegment::Key(key) => {
+            if let serde_json::Value::Object(map) = value {
+                if let Some(child) = map.get_mut(key) {
+                    decrypt_at_path(child, &segments[1..], numeric, dek, aad)?;
+                }
+            }
+        }
+        PathSegment::ArrayItem => {
+            if let serde_json::Value::Array(arr) = value {
+                for item in arr.iter_mut() {
+                    decrypt_at_path(item, &segments[1..], numeric, dek, aad)?;
+                }
+            }
+        }
+        PathSegment::RecursiveKey(key) => {
+            let rest = &segments[1..];
+            visit_objects_with_key(
+                value,
+                key,
+                &mut |map| match recursive_match(map, key, rest) {
+                    Some(child) => decrypt_at_path(child, rest, numeric, dek, aad),
+                    None => Ok(()),
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// A decrypted leaf as a JSON value: with `numeric`, a plaintext that is a
+/// JSON number token is restored as that exact number.
+fn restored_leaf(plaintext: String, numeric: bool) -> serde_json::Value {
+    match plaintext.parse::<serde_json::Number>() {
+        Ok(n) if numeric => serde_json::Value::Number(n),
+        _ => serde_json::Value::String(plaintext),
+    }
+}
+
+/// Detokenize the format-preserving token at `path` in every object that
+/// pairs it with a `<field>_fpe` tag, removing the tag. Without a tag the
+/// value cannot be told from plaintext and is only decrypted if it is
+/// ciphertext, as at any other path; a tag that does not match fails.
+fn detokenize_at_path(
+    payload: &mut serde_json::Value,
+    path: &str,
+    numeric: bool,
+    ctx: &CipherContext<'_>,
+    aad: &[u8],
+) -> Result<(), TraversalError> {
+    let mut segments = parse_path(path);
+    // The resolver only records tokenized paths ending in a property name.
+    let Some(PathSegment::Key(field)) = segments.pop() else {
+        return Ok(());
+    };
+    let tag_field = format!("{field}{FPE_SIBLING_SUFFIX}");
+    visit_path(payload, &segments, &mut |parent| {
+        let serde_json::Value::Object(map) = parent else {
+            return Ok(());
+        };
+        let Some(tag) = map.shift_remove(&tag_field) else {
+            if let Some(child) = map.get_mut(&field) {
+                decrypt_at_path(child, &[], numeric, ctx.dek, aad)?;
+            }
+            return Ok(());
+        };
+        let (Some(serde_json::Value::String(token)), serde_json::Value::String(tag)) =
+            (map.get(&field), &tag)
+        else {
+            return Err(CipherError::UnauthenticatedToken.into());
+        };
+        let plaintext = detokenize(token, tag, ctx.token_key()?, aad)?;
+        map.insert(field.clone(), restored_leaf(plaintext, numeric));
+        Ok(())
+    })
+}
+
+/// Decrypt all PII string fields in `payload` according to `pii_paths`,
+/// detokenizing those at paths in `tokenized` and restoring number values at
+/// paths in `numeric`.
+fn decrypt_pii_fields(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    tokenized: &PiiFieldPaths,
+    numeric: &PiiFieldPaths,
+    ctx: &CipherContext<'_>,
+) -> Result<(), TraversalError> {
+    for path in pii_paths {
+        ctx.check_deadline()?;
+        let aad = field_aad(ctx.tenant, path);
+        let numeric = numeric.contains(path);
+        if tokenized.contains(path) {
+            detokenize_at_path(payload, path, numeric, ctx, &aad)?;
+        } else {
+            decrypt_at_path(payload, &parse_path(path), numeric, ctx.dek, &aad)?;
+        }
+    }
+    Ok(())
+}
+
+/// Schema paths of the ciphertext fields in `payload` whose nonce collides
+/// with another field's in a way encryption never produces.
+///
+/// Nonces are derived from the AAD and plaintext, so equal values at one path
+/// (or anywhere, without a tenant) legitimately share a nonce and ciphertext.
+/// The same nonce with different ciphertext bytes, or at different paths when
+/// the tenant binds the path into the AAD, means the stored values were
+/// spliced or forged. Only the envelopes are inspected; nothing is decrypted.
+fn reused_nonce_paths(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    tenant_bound: bool,
+) -> BTreeSet<String> {
+    let mut seen: HashMap<[u8; NONCE_LEN], (&str, Vec<u8>)> = HashMap::new();
+    let mut reused = BTreeSet::new();
+    for path in pii_paths {
+        let segments = parse_path(path);
+        let _ = visit_path(payload, &segments, &mut |leaf| {
+            let Ok(field) = EncryptedField::from_json(leaf) else {
+                return Ok(());
+            };
+            match seen.get(&field.nonce) {
+                Some((first, ciphertext))
+                    if *ciphertext != field.ciphertext || (tenant_bound && *first != path) =>
+                {
+                    reused.insert((*first).to_string());
+                    reused.insert(path.clone());
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(field.nonce, (path.as_str(), field.ciphertext));
+                }
+            }
+            Ok(())
+        });
+    }
+    reused
+}
+
+/// Recursively navigate `value` following `segments` and apply `f` to every
+/// leaf reached at the end of the path. Missing keys and shape mismatches are
+/// skipped, mirroring [`encrypt_at_path`].
+fn visit_path<F>(
+    value: &mut serde_json::Value,
+    segments: &[PathSegment],
+    f: &mut F,
+) -> Result<(), TraversalError>
+where
+    F: FnMut(&mut serde_json::Value) -> Result<(), TraversalError>,
+{
+    if segments.is_empty() {
+        return f(value);
+    }
+
+    match &segments[0] {
+        PathSegment::Key(key) => {
+            if let serde_json::Value::Object(map) = value {
+                if let Some(child) = map.get_mut(key) {
+                    visit_path(child, &segments[1..], f)?;
+                }
+            }
+        }
+        PathSegment::ArrayItem => {
+            if let serde_json::Value::Array(arr) = value {
+                for item in arr.iter_mut() {
+                    visit_path(item, &segments[1..], f)?;
+                }
+            }
+        }
+        PathSegment::RecursiveKey(key) => {
+            let rest = &segments[1..];
+            visit_objects_with_key(
+                value,
+                key,
+                &mut |map| match recursive_match(map, key, rest) {
+                    Some(child) => visit_path(child, rest, f),
+                    None => Ok(()),
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The PII paths of one (possibly embedded) document, as checked by
+/// [`plaintext_pii_paths`].
+struct PiiLayout<'a> {
+    pii_paths: &'a PiiFieldPaths,
+    conditions: &'a PiiConditions,
+    /// Format-preserving tokens look like plaintext, so these only count as
+    /// protected beside a `<field>_fpe` tag.
+    tokenized: &'a PiiFieldPaths,
+    embedded: &'a EmbeddedJsonPaths,
+}
+
+/// Add to `found` every PII path of `payload` holding a value that is neither
+/// ciphertext, a hash token nor a tagged format-preserving token. Objects,
+/// arrays, booleans and numbers that [`encrypt_pii_fields`] would leave as
+/// they are still count as plaintext; `null` and strings shorter than
+/// `min_encrypt_len` do not. Paths inside an embedded JSON document are
+/// reported as `<field>.<inner path>`, each prefixed with `prefix`; an
+/// embedded field that does not hold valid JSON is reported whole.
+fn plaintext_pii_paths(
+    payload: &mut serde_json::Value,
+    layout: &PiiLayout<'_>,
+    min_encrypt_len: usize,
+    prefix: &str,
+    found: &mut BTreeSet<String>,
+) {
+    let leaks = |leaf: &serde_json::Value| match leaf {
+        serde_json::Value::Null => false,
+        serde_json::Value::String(s) => {
+            s.chars().count() >= min_encrypt_len
+                && !is_hashed(s)
+                && EncryptedField::from_str(s).is_err()
+        }
+        other => EncryptedField::from_json(other).is_err(),
+    };
+    for path in layout.pii_paths {
+        let condition = layout.conditions.get(path);
+        let segments = parse_path(path);
+        let mut leaked = false;
+        match segments.split_last() {
+            // The resolver only records tokenized paths ending in a property
+            // name; the condition applies to the object holding it.
+            Some((PathSegment::Key(field), parents)) if layout.tokenized.contains(path) => {
+                let tag_field = format!("{field}{FPE_SIBLING_SUFFIX}");
+                visit_pii_leaves(payload, parents, None, &mut |parent| {
+                    let serde_json::Value::Object(map) = parent else {
+                        return;
+                    };
+                    if condition.is_some_and(|c| !c.matches(map)) {
+                        return;
+                    }
+                    let tagged = map
+                        .get(&tag_field)
+                        .and_then(serde_json::Value::as_str)
+                        .is_some_and(is_token_tag);
+                    leaked |= map.get(field).is_some_and(|leaf| !tagged && leaks(leaf));
+                });
+            }
+            _ => visit_pii_leaves(payload, &segments, condition, &mut |leaf| {
+                leaked |= leaks(leaf)
+            }),
+        }
+        if leaked {
+            found.insert(format!("{prefix}{path}"));
+        }
+    }
+    for (path, inner) in layout.embedded {
+        let _ = visit_path(payload, &parse_path(path), &mut |leaf| {
+            let serde_json::Value::String(s) = leaf else {
+                return Ok(());
+            };
+            match serde_json::from_str::<serde_json::Value>(s) {
+                Ok(mut doc) => plaintext_pii_paths(
+                    &mut doc,
+                    &PiiLayout {
+                        pii_paths: &inner.pii_paths,
+                        conditions: &inner.conditions,
+                        tokenized: &inner.tokenized,
+                        embedded: &inner.embedded_json,
+                    },
+                    min_encrypt_len,
+                    &format!("{prefix}{path}."),
+                    found,
+                ),
+                Err(_) => {
+                    found.insert(format!("{prefix}{path}"));
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Apply `f` to every leaf [`encrypt_at_path`] would consider: the values at
+/// the end of `segments` in objects where `condition`, if any, matches.
+/// Objects holding ciphertext in the object encoding count as leaves.
+fn visit_pii_leaves<F>(
+    value: &mut serde_json::Value,
+    segments: &[PathSegment],
+    condition: Option<&PiiCondition>,
+    f: &mut F,
+) where
+    F: FnMut(&serde_json::Value),
+{
+    if segments.is_empty() {
+        return f(value);
+    }
+
+    match &segments[0] {
+        PathSegment::Key(key) => {
+            if let serde_json::Value::Object(map) = value {
+                let Some(condition) = scoped_condition(condition, map, &segments[1..]) else {
+                    return;
+                };
+                if let Some(child) = map.get_mut(key) {
+                    visit_pii_leaves(child, &segments[1..], condition, f);
+                }
+            }
+        }
+        PathSegment::ArrayItem => {
+            if let serde_json::Value::Array(arr) = value {
+                for item in arr.iter_mut() {
+                    visit_pii_leaves(item, &segments[1..], condition, f);
+                }
+            }
+        }
+        PathSegment::RecursiveKey(key) => {
+            let rest = &segments[1..];
+            let _ = visit_objects_with_key(value, key, &mut |map| {
+                if let Some(condition) = scoped_condition(condition, map, rest) {
+                    if let Some(child) = recursive_match(map, key, rest) {
+                        visit_pii_leaves(child, rest, condition, f);
+                    }
+                }
+                Ok::<(), std::convert::Infallible>(())
+            });
+        }
+    }
+}
+
+/// Mask every PII string in an already-decrypted `payload` using the rule for
+/// its category, including PII inside embedded JSON documents. Hash tokens
+/// are irreversible and left intact so they can still be compared.
+fn mask_pii_fields(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    categories: &PiiCategories,
+    embedded: &EmbeddedJsonPaths,
+    policy: &MaskPolicy,
+) -> Result<(), TraversalError> {
+    for path in pii_paths {
+        let rule = policy.rule_for(categories.get(path).map(String::as_str));
+        let segments = parse_path(path);
+        visit_path(payload, &segments, &mut |leaf| {
+            if let serde_json::Value::String(s) = leaf {
+                if !is_hashed(s) {
+                    *s = rule.apply(s);
+                }
+            }
+            Ok(())
+        })?;
+    }
+    for (path, inner) in embedded {
+        let segments = parse_path(path);
+        visit_path(payload, &segments, &mut |leaf| {
+            transform_embedded(leaf, path, |doc| {
+                mask_pii_fields(
+                    doc,
+                    &inner.pii_paths,
+                    &inner.categories,
+                    &inner.embedded_json,
+                    policy,
+                )
+            })
+        })?;
+    }
+    Ok(())
+}
+
+/// Replace every non-null value at `pii_paths` with `marker`, descending into
+/// embedded JSON documents. An embedded field that does not hold valid JSON
+/// is redacted whole rather than passed through.
+fn redact_pii_fields(
+    payload: &mut serde_json::Value,
+    pii_paths: &PiiFieldPaths,
+    embedded: &EmbeddedJsonPaths,
+    marker: &str,
+) {
+    let mut redact = |leaf: &mut serde_json::Value| {
+        if !leaf.is_null() {
+            *leaf = serde_json::Value::String(marker.to_owned());
+        }
+        Ok(())
+    };
+    for path in pii_paths {
+        let _ = visit_path(payload, &parse_path(path), &mut redact);
+    }
+    for (path, inner) in embedded {
+        let _ = visit_path(payload, &parse_path(path), &mut |leaf| {
+            let redacted = transform_embedded(leaf, path, |doc| {
+                redact_pii_fields(doc, &inner.pii_paths, &inner.embedded_json, marker);
+                Ok(())
+            });
+            if redacted.is_err() {
+                redact(leaf)?;
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Parse the JSON document held in the string `leaf`, apply `transform` to it,
+/// and store the re-serialised document back as a string.
+///
+/// Non-string leaves (including `null`) are left untouched.
+fn transform_embedded<F>(
+    leaf: &mut serde_json::Value,
+    path: &str,
+    transform: F,
+) -> Result<(), TraversalError>
+where
+    F: FnOnce(&mut serde_json::Value) -> Result<(), TraversalError>,
+{
+    if let serde_json::Value::String(s) = leaf {
+        let mut doc: serde_json::Value =
+            serde_json::from_str(s).map_err(|_| TraversalError::EmbeddedJson(path.to_owned()))?;
+        transform(&mut doc)?;
+        *s = serde_json::to_string(&doc)
+            .map_err(|_| TraversalError::EmbeddedJson(path.to_owned()))?;
+    }
+    Ok(())
+}
+
+/// Encrypt PII inside every double-encoded JSON string listed in `embedded`.
+fn encrypt_embedded_json(
+    payload: &mut serde_json::Value,
+    embedded: &EmbeddedJsonPaths,
+    ctx: &CipherContext<'_>,
+) -> Result<(), TraversalError> {
+    for (path, inner) in embedded {
+        let segments = parse_path(path);
+        visit_path(payload, &segments, &mut |leaf| {
+            transform_embedded(leaf, path, |doc| {
+                add_lookup_tags(doc, &inner.lookup, &inner.conditions, &inner.numeric, ctx)?;
+                encrypt_pii_fields(
+                    doc,
+                    &inner.pii_paths,
+                    &inner.conditions,
+                    &inner.hashed,
+                    &inner.tokenized,
+                    &inner.numeric,
+                    ctx,
+                )?;
+                encrypt_embedded_json(doc, &inner.embedded_json, ctx)
+            })
+        })?;
+    }
+    Ok(())
+}
+
+/// Decrypt PII inside every double-encoded JSON string listed in `embedded`.
+fn decrypt_embedded_json(
+    payload: &mut serde_json::Value,
+    embedded: &EmbeddedJsonPaths,
+    ctx: &CipherContext<'_>,
+) -> Result<(), TraversalError> {
+    for (path, inner) in embedded {
+        let segments = parse_path(path);
+        visit_path(payload, &segments, &mut |leaf| {
+            transform_embedded(leaf, path, |doc| {
+                decrypt_pii_fields(doc, &inner.pii_paths, &inner.tokenized, &inner.numeric, ctx)?;
+                decrypt_embedded_json(doc, &inner.embedded_json, ctx)
+            })
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::{body::Body, http::Request, Router};
+    use tower::ServiceExt;
+
+    fn ctx(dek: &[u8]) -> CipherContext<'_> {
+        CipherContext {
+            dek,
+            token_key: Some(dek),
+            tenant: None,
+            schema_tag: None,
+            algorithm: Algorithm::default(),
+            field_lengths: None,
+            deadline: None,
+            encoding: CiphertextEncoding::CompactString,
+            min_encrypt_len: 0,
+            max_array_items: 0,
+        }
+    }
+
+    fn test_router() -> Router {
+        Router::new()
+            .route("/health", get(health))
+            .with_state(AppState::default())
+    }
+
+    /// Name the schema of [`test_app_with`] is cached under.
+    const TEST_SCHEMA: &str = "test-v1";
+
+    /// An OpenAPI document: the `openapi`, `info` and `paths` boilerplate
+    /// followed by `rest`, usually a `components:` block.
+    fn test_spec(rest: &str) -> openapiv3::OpenAPI {
+        serde_yaml::from_str(&format!(
+            "openapi: \"3.0.0\"\ninfo: {{ title: t, version: \"1\" }}\npaths: {{}}\n{rest}"
+        ))
+        .unwrap()
+    }
+
+    /// Every handler route, without the middleware of [`router::build`](super::super::router::build).
+    fn handler_router(state: AppState) -> Router {
+        use axum::routing::post;
+        Router::new()
+            .route("/health", get(health))
+            .route("/readyz", get(health))
+            .route("/encrypt", post(encrypt))
+            .route("/encrypt/batch", post(encrypt_batch))
+            .route("/encrypt/stream", post(encrypt_stream))
+            .route("/encrypt/value", post(encrypt_value))
+            .route("/decrypt", post(decrypt))
+            .route("/decrypt/value", post(decrypt_value))
+            .route("/redact", post(redact))
+            .route("/verify", post(verify))
+            .route("/admin/drain", post(start_drain).delete(stop_drain))
+            .route("/admin/reload-schemas", post(reload_schemas))
+            .route(
+                "/admin/encryption",
+                get(encryption_settings).put(update_encryption_settings),
+            )
+            .route("/admin/schemas", get(list_schemas))
+            .route("/admin/schemas/by-path", get(schemas_with_path))
+            .route("/admin/decrypt/preview", post(decrypt_preview))
+            .route("/admin/stats", get(stats))
+            .fallback(not_found)
+            .with_state(state)
+    }
+
+    /// A state with `settings`, a DEK of `0x42` bytes and the [`test_spec`] of
+    /// `schema_yaml` cached as [`TEST_SCHEMA`], and the [`handler_router`]
+    /// serving it.
+    async fn test_app_with(
+        settings: super::super::state::ServerSettings,
+        schema_yaml: &str,
+    ) -> (AppState, Router) {
+        use crate::crypto::KEY_LEN;
+        use crate::dek::store::DekBytes;
+        let state = AppState::default()
+            .with_settings(settings)
+            .with_token_key(DekBytes(Box::new([0x24u8; KEY_LEN])));
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state.schema_cache.replace_all(HashMap::from([(
+            TEST_SCHEMA.to_string(),
+            test_spec(schema_yaml),
+        )]));
+        (state.clone(), handler_router(state))
+    }
+
+    #[tokio::test]
+    async fn health_returns_503_when_not_ready() {
+        let app = test_router();
+        let req = Request::builder()
+            .uri("/health")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn health_returns_200_when_thresholds_relaxed() {
+        use super::super::state::ServerSettings;
+        let state = AppState::default().with_settings(ServerSettings {
+            min_schemas_for_ready: 0,
+            require_dek_for_ready: false,
+            ..ServerSettings::default()
+        });
+        let app = Router::new()
+            .route("/health", get(health))
+            .with_state(state);
+        let req = Request::builder()
+            .uri("/health")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_background_task_degrades_health() {
+        use super::super::state::ServerSettings;
+        let state = AppState::default().with_settings(ServerSettings {
+            min_schemas_for_ready: 0,
+            require_dek_for_ready: false,
+            ..ServerSettings::default()
+        });
+        let heartbeat = state.metrics.task_heartbeats.register("schema_refresh");
+        heartbeat.beat(std::time::Duration::from_secs(60));
+        let app = Router::new()
+            .route("/health", get(health))
+            .with_state(state);
+        let health_of = || {
+            let app = app.clone();
+            let req = Request::builder()
+                .uri("/health")
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: HealthResponse = serde_json::from_slice(&body).unwrap();
+                (status, body.status, body.stalled_tasks)
+            }
+        };
+
+        assert_eq!(
+            health_of().await,
+            (StatusCode::OK, "ok".to_string(), vec![])
+        );
+        // The task dies without beating again: the instance still serves,
+        // so health reports degraded without failing the check.
+        tokio::time::advance(std::time::Duration::from_secs(121)).await;
+        assert_eq!(
+            health_of().await,
+            (
+                StatusCode::OK,
+                "degraded".to_string(),
+                vec!["schema_refresh".to_string()]
+            )
+        );
+        heartbeat.beat(std::time::Duration::from_secs(60));
+        assert_eq!(
+            health_of().await,
+            (StatusCode::OK, "ok".to_string(), vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn drain_flips_readiness() {
+        use super::super::state::ServerSettings;
+        use axum::routing::post;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            min_schemas_for_ready: 0,
+            require_dek_for_ready: false,
+            admin_identities: ["ops".to_string()].into(),
+            ..ServerSettings::default()
+        });
+        let app = Router::new()
+            .route("/readyz", get(health))
+            .route("/admin/drain", post(start_drain).delete(stop_drain))
+            .with_state(state);
+        let status_of = |method: &str, uri: &str, cn: Option<&str>| {
+            let app = app.clone();
+            let mut req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+
+        assert_eq!(status_of("GET", "/readyz", None).await, StatusCode::OK);
+        for cn in [None, Some("app")] {
+            assert_eq!(
+                status_of("POST", "/admin/drain", cn).await,
+                StatusCode::FORBIDDEN
+            );
+        }
+        assert_eq!(status_of("GET", "/readyz", None).await, StatusCode::OK);
+        assert_eq!(
+            status_of("POST", "/admin/drain", Some("ops")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status_of("GET", "/readyz", None).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status_of("DELETE", "/admin/drain", Some("app")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status_of("DELETE", "/admin/drain", Some("ops")).await,
+            StatusCode::OK
+        );
+        assert_eq!(status_of("GET", "/readyz", None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn schema_allowlist_enforced_per_identity() {
+        use super::super::identity::SchemaAllowlist;
+        use super::super::state::ServerSettings;
+        use axum::routing::post;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            schema_allowlist: Some(
+                SchemaAllowlist::parse("payments=payments-;identity=identity-").unwrap(),
+            ),
+            ..ServerSettings::default()
+        });
+        state
+            .schema_cache
+            .replace_all(std::collections::HashMap::new());
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state);
+        let status_for = |cn: Option<&str>, schema: &str| {
+            let app = app.clone();
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", schema)
+                .body(Body::from(r#"{"payload":{}}"#))
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+
+        // Permitted: passes authorization and fails later on the (empty, loaded) cache.
+        assert_eq!(
+            status_for(Some("payments"), "payments-v1").await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status_for(Some("identity"), "identity-v1").await,
+            StatusCode::BAD_REQUEST
+        );
+        // Forbidden: outside the grant, unknown CN, or no client certificate.
+        assert_eq!(
+            status_for(Some("payments"), "identity-v1").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status_for(Some("identity"), "payments-v1").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status_for(Some("other"), "payments-v1").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(status_for(None, "payments-v1").await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn removed_schema_returns_410_within_tombstone_window() {
+        use crate::crypto::KEY_LEN;
+        use crate::schema::SchemaCache;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState {
+            schema_cache: SchemaCache::new()
+                .with_tombstone_grace(std::time::Duration::from_secs(60)),
+            ..AppState::default()
+        };
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+        state.schema_cache.replace_all(HashMap::new());
+
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state);
+        let send = |schema: &str| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", schema)
+                .body(Body::from(r#"{"payload":{}}"#))
+                .unwrap();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(send("payments-v1").await, StatusCode::GONE);
+        assert_eq!(send("never-existed").await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn not_ready_responses_carry_retry_after() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::http::header::RETRY_AFTER;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            retry_after_secs: 7,
+            ..ServerSettings::default()
+        });
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state.clone());
+        let send = || {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "payments-v1")
+                .body(Body::from(r#"{"payload":{}}"#))
+                .unwrap();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        // Schemas not yet loaded.
+        let resp = send().await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[RETRY_AFTER], "7");
+
+        // Schemas loaded, DEK not yet initialised.
+        let api: openapiv3::OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+        let resp = send().await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[RETRY_AFTER], "7");
+
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let resp = send().await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get(RETRY_AFTER).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn encrypt_waits_for_readiness_within_the_bound() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+        use std::time::Duration;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            encrypt_ready_wait: Some(Duration::from_secs(2)),
+            ..ServerSettings::default()
+        });
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state.clone());
+        let send = || {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "payments-v1")
+                .body(Body::from(r#"{"payload":{}}"#))
+                .unwrap();
+            async move { app.oneshot(req).await.unwrap() }
+        };
+
+        // Nothing arrives: 503 once the wait has elapsed, not before.
+        let start = tokio::time::Instant::now();
+        let resp = send().await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        // The schemas and the DEK arrive while the request waits.
+        let loader = tokio::spawn({
+            let state = state.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                let api: openapiv3::OpenAPI = serde_json::from_str(
+                    r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#,
+                )
+                .unwrap();
+                state
+                    .schema_cache
+                    .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+            }
+        });
+        let start = tokio::time::Instant::now();
+        let resp = send().await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(start.elapsed(), Duration::from_millis(600));
+        loader.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn encrypt_value_uses_current_dek_and_context() {
+        use super::super::identity::SchemaAllowlist;
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::http::header::RETRY_AFTER;
+        use axum::routing::post;
+
+        let call = |state: AppState, uri: &'static str, cn: Option<&'static str>, body: String| async move {
+            let app = Router::new()
+                .route("/encrypt/value", post(encrypt_value))
+                .route("/decrypt/value", post(decrypt_value))
+                .with_state(state);
+            let mut req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            app.oneshot(req).await.unwrap()
+        };
+        let send =
+            |state: AppState, body: &'static str| call(state, "/encrypt/value", None, body.into());
+        let enabled = AppState::default().with_settings(ServerSettings {
+            allow_value_endpoint: true,
+            ..ServerSettings::default()
+        });
+
+        // Disabled by default.
+        let resp = send(AppState::default(), r#"{"value":"x"}"#).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = call(
+            AppState::default(),
+            "/decrypt/value",
+            None,
+            r#"{"value":"x"}"#.into(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // DEK not yet initialised.
+        let resp = send(enabled.clone(), r#"{"value":"x"}"#).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.headers().contains_key(RETRY_AFTER));
+
+        let dek = [0x42u8; KEY_LEN];
+        enabled.dek_store.store(&dek).await.unwrap();
+        let resp = send(
+            enabled.clone(),
+            r#"{"value":"123-45-6789","context":"customers.ssn"}"#,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: EncryptValueResponse = serde_json::from_slice(&body).unwrap();
+        let field = EncryptedField::from_str(&body.value).unwrap();
+        let plaintext =
+            decrypt_field_with_aad(&field, &dek, &value_aad(Some("customers.ssn"))).unwrap();
+        assert_eq!(plaintext, b"123-45-6789");
+        assert!(decrypt_field_with_aad(&field, &dek, &value_aad(None)).is_err());
+
+        // `/decrypt/value` needs the same context back.
+        let decrypt = |state: AppState, cn, context: Option<&str>| {
+            let body = serde_json::json!({ "value": body.value, "context": context });
+            call(state, "/decrypt/value", cn, body.to_string())
+        };
+        let resp = decrypt(enabled.clone(), None, Some("customers.ssn")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let plain: DecryptValueResponse = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(plain.value, "123-45-6789");
+        for context in [None, Some("customers.email")] {
+            let resp = decrypt(enabled.clone(), None, context).await;
+            assert!(!resp.status().is_success(), "{context:?}");
+        }
+        let resp = call(
+            enabled.clone(),
+            "/decrypt/value",
+            None,
+            r#"{"value":"plain"}"#.into(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // With an allowlist, only the clients it names may use either endpoint.
+        let listed = enabled.with_settings(ServerSettings {
+            allow_value_endpoint: true,
+            schema_allowlist: Some(SchemaAllowlist::parse("billing=payments-").unwrap()),
+            ..ServerSettings::default()
+        });
+        for cn in [None, Some("stranger")] {
+            let resp = call(
+                listed.clone(),
+                "/encrypt/value",
+                cn,
+                r#"{"value":"x"}"#.into(),
+            )
+            .await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{cn:?}");
+            let resp = decrypt(listed.clone(), cn, Some("customers.ssn")).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{cn:?}");
+        }
+        let resp = decrypt(listed, Some("billing"), Some("customers.ssn")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn decrypt_value_refuses_schema_field_ciphertext() {
+        use super::super::state::ServerSettings;
+
+        for schema_tag_ciphertext in [false, true] {
+            let (_, app) = test_app_with(
+                ServerSettings {
+                    allow_value_endpoint: true,
+                    schema_tag_ciphertext,
+                    ..ServerSettings::default()
+                },
+                r#"
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+            )
+            .await;
+            let call = |uri: &'static str, body: serde_json::Value| {
+                let req = Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .header("X-Schema-Name", TEST_SCHEMA)
+                    .body(Body::from(body.to_string()))
+                    .unwrap();
+                app.clone().oneshot(req)
+            };
+
+            let resp = call(
+                "/encrypt",
+                serde_json::json!({ "payload": { "ssn": "123-45-6789" } }),
+            )
+            .await
+            .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let encrypted: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            let ssn = &encrypted["payload"]["ssn"];
+
+            let resp = call("/decrypt/value", serde_json::json!({ "value": ssn }))
+                .await
+                .unwrap();
+            assert!(
+                !resp.status().is_success(),
+                "schema_tag_ciphertext={schema_tag_ciphertext}"
+            );
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(!String::from_utf8_lossy(&bytes).contains("123-45-6789"));
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_schema_status_is_configurable_and_retryable_while_loading() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::http::header::RETRY_AFTER;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let send = |state: AppState, uri: &'static str| async move {
+            let app = Router::new()
+                .route("/encrypt", post(encrypt))
+                .route("/decrypt", post(decrypt))
+                .with_state(state);
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "missing-v1")
+                .body(Body::from(r#"{"payload":{}}"#))
+                .unwrap();
+            let resp = app.oneshot(req).await.unwrap();
+            let status = resp.status();
+            let retry_after = resp.headers().get(RETRY_AFTER).cloned();
+            let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            (
+                status,
+                body["code"].as_str().unwrap().to_owned(),
+                retry_after,
+            )
+        };
+        let api: openapiv3::OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#,
+        )
+        .unwrap();
+        let with_status = |status| {
+            AppState::default().with_settings(ServerSettings {
+                unknown_schema_status: status,
+                ..ServerSettings::default()
+            })
+        };
+
+        for (status, code) in [
+            (StatusCode::BAD_REQUEST, "bad_request"),
+            (StatusCode::NOT_FOUND, "not_found"),
+        ] {
+            let state = with_status(status);
+            state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+            state
+                .schema_cache
+                .replace_all(HashMap::from([("payments-v1".to_string(), api.clone())]));
+            for uri in ["/encrypt", "/decrypt"] {
+                let (got, got_code, retry_after) = send(state.clone(), uri).await;
+                assert_eq!((got, got_code.as_str()), (status, code), "{uri}");
+                assert!(retry_after.is_none());
+            }
+        }
+
+        // Before the first load completes the schema may still be on its way.
+        let state = with_status(StatusCode::NOT_FOUND);
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        for uri in ["/encrypt", "/decrypt"] {
+            let (status, code, retry_after) = send(state.clone(), uri).await;
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{uri}");
+            assert_eq!(code, "schemas_loading");
+            assert_eq!(retry_after.unwrap(), "5");
+        }
+    }
+
+    #[tokio::test]
+    async fn redact_replaces_plaintext_and_ciphertext_without_a_dek() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        email: { type: string, x-pii: true }
+        phone: { type: string, x-pii: true }
+        plan: { type: string }
+        cards:
+          type: array
+          items: { type: string, x-pii: true }
+        meta: { type: string, x-pii-json: true, x-pii-json-schema: Meta }
+    Meta:
+      type: object
+      properties:
+        dob: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        // No DEK is ever stored: redaction must not need one.
+        let state = AppState::default().with_settings(ServerSettings {
+            redaction_marker: "***".into(),
+            ..ServerSettings::default()
+        });
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/redact", post(redact))
+            .with_state(state);
+
+        let ciphertext = encrypt_field_with_aad(b"jane@example.com", &[0x42u8; KEY_LEN], &[])
+            .unwrap()
+            .to_string_repr();
+        let body = serde_json::json!({"payload": {
+            "ssn": "123-45-6789",
+            "email": ciphertext,
+            "phone": null,
+            "plan": "gold",
+            "cards": ["4111111111111111", "5500000000000004"],
+            "meta": r#"{"dob":"1990-01-01","tz":"UTC"}"#,
+        }});
+        let req = Request::builder()
+            .method("POST")
+            .uri("/redact")
+            .header("content-type", "application/json")
+            .header("X-Schema-Name", "customer-v1")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let payload = &body["payload"];
+
+        assert_eq!(payload["ssn"], "***");
+        assert_eq!(payload["email"], "***");
+        assert_eq!(payload["phone"], serde_json::Value::Null);
+        assert_eq!(payload["plan"], "gold");
+        assert_eq!(payload["cards"], serde_json::json!(["***", "***"]));
+        let meta: serde_json::Value =
+            serde_json::from_str(payload["meta"].as_str().unwrap()).unwrap();
+        assert_eq!(meta, serde_json::json!({"dob": "***", "tz": "UTC"}));
+    }
+
+    #[tokio::test]
+    async fn verify_lists_pii_paths_still_holding_plaintext() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        kind: { type: string }
+        ssn: { type: string, x-pii: true }
+        email: { type: string, x-pii: true, x-pii-mode: hash }
+        account: { type: integer, x-pii: true }
+        pan: { type: string, x-pii: true, x-pii-mode: fpe }
+        ref: { type: string, x-pii: true, x-pii-when: { field: kind, equals: person } }
+        cards:
+          type: array
+          items: { type: string, x-pii: true }
+        meta: { type: string, x-pii-json: true, x-pii-json-schema: Meta }
+    Meta:
+      type: object
+      properties:
+        dob: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        // Verification needs no key; this one is only used to build input.
+        let state = AppState::default();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/verify", post(verify))
+            .with_state(state);
+        let verify = |payload: serde_json::Value| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri("/verify")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(
+                    serde_json::json!({ "payload": payload }).to_string(),
+                ))
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: VerifyResponse = serde_json::from_slice(&bytes).unwrap();
+                body.plaintext_paths
+            }
+        };
+        let dek = [0x42u8; KEY_LEN];
+        let encrypted = |plaintext: &str| {
+            encrypt_field_with_aad(plaintext.as_bytes(), &dek, &[])
+                .unwrap()
+                .to_string_repr()
+        };
+        let object = encrypt_field_with_aad(b"5500", &dek, &[])
+            .unwrap()
+            .to_json_object();
+        let hashed = hash_field(b"jane@example.com", &dek, &[]).unwrap();
+        let token = tokenize("4111111111111111", &dek, &[]).unwrap();
+        let tag = token_tag(&token, &dek, &[]).unwrap();
+
+        let protected = serde_json::json!({
+            "kind": "company",
+            "ssn": encrypted("123-45-6789"),
+            "email": hashed,
+            "account": encrypted("42"),
+            "pan": token,
+            "pan_fpe": tag,
+            "ref": "not PII for companies",
+            "cards": [encrypted("4111"), object],
+            "meta": serde_json::json!({"dob": encrypted("1990-01-01")}).to_string(),
+            "extra": "plain",
+        });
+        assert!(verify(protected.clone()).await.is_empty());
+
+        let mut leaky = protected;
+        leaky["kind"] = "person".into();
+        leaky["account"] = 42.into();
+        leaky["cards"][1] = "5500".into();
+        leaky["meta"] = r#"{"dob":"1990-01-01"}"#.into();
+        // Values `/encrypt` cannot protect are still plaintext PII, and a
+        // format-preserving token is only told from plaintext by its tag.
+        leaky["ssn"] = serde_json::json!({"number": "123-45-6789"});
+        leaky["email"] = true.into();
+        leaky["pan_fpe"] = "f1.AAAA".into();
+        assert_eq!(
+            verify(leaky).await,
+            ["account", "cards[]", "email", "meta.dob", "pan", "ref", "ssn"]
+        );
+    }
+
+    #[test]
+    fn redact_drops_unparseable_embedded_json_whole() {
+        let mut val = serde_json::json!({"metadata": "{not json 123-45-6789", "name": "Alice"});
+        redact_pii_fields(
+            &mut val,
+            &PiiFieldPaths::new(),
+            &embedded_ssn(),
+            "[REDACTED]",
+        );
+        assert_eq!(
+            val,
+            serde_json::json!({"metadata": "[REDACTED]", "name": "Alice"})
+        );
+    }
+
+    #[tokio::test]
+    async fn pii_scope_header_limits_encryption_to_listed_prefixes() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Payment:
+      type: object
+      properties:
+        debtor:
+          type: object
+          properties:
+            name: { type: string, x-pii: true }
+            accounts:
+              type: array
+              items: { type: string, x-pii: true }
+        creditor:
+          type: object
+          properties:
+            name: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("payment-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/encrypt/batch", post(encrypt_batch))
+            .route("/encrypt/stream", post(encrypt_stream))
+            .with_state(state);
+        let send = |uri: &'static str, scope: Option<&'static str>, body: serde_json::Value| {
+            let app = app.clone();
+            let mut req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "payment-v1");
+            if let Some(scope) = scope {
+                req = req.header("X-Pii-Scope", scope);
+            }
+            let req = req.body(Body::from(body.to_string())).unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                (status, body)
+            }
+        };
+        let full = serde_json::json!({
+            "debtor": {"name": "Jane", "accounts": ["DE89"]},
+            "creditor": {"name": "Acme"}
+        });
+        let encrypt = |scope, payload: &serde_json::Value| {
+            send("/encrypt", scope, serde_json::json!({ "payload": payload }))
+        };
+        let encrypted = |v: &serde_json::Value| v.as_str().unwrap().starts_with("v1.");
+
+        let (status, unscoped) = encrypt(None, &full).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(encrypted(&unscoped["payload"]["debtor"]["name"]));
+        assert!(encrypted(&unscoped["payload"]["creditor"]["name"]));
+
+        let partial = serde_json::json!({
+            "debtor": {"name": "Jane", "accounts": ["DE89"]},
+            "creditor": {"name": null}
+        });
+        let (status, scoped) = encrypt(Some("debtor.*"), &partial).await;
+        assert_eq!(status, StatusCode::OK);
+        let payload = &scoped["payload"];
+        assert!(encrypted(&payload["debtor"]["name"]));
+        assert!(encrypted(&payload["debtor"]["accounts"][0]));
+        assert!(payload["creditor"]["name"].is_null());
+
+        // An excluded field holding a value would be returned in plaintext.
+        let (status, body) = encrypt(Some("debtor.*"), &full).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().contains("creditor.name"));
+        let (status, _) = send("/encrypt/stream", Some("debtor.*"), full.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let batch = serde_json::json!({ "items": [partial, full] });
+        let (status, body) = send("/encrypt/batch", Some("debtor.*"), batch).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["results"][0]["error"].is_null());
+        assert!(body["results"][1]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("creditor.name"));
+
+        let (status, _) = encrypt(Some("debtor.accounts, debtor.name, creditor"), &full).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // A prefix that names no PII path is a mistake, not an empty scope.
+        for scope in ["debt", "debtor, payee", " , "] {
+            let (status, body) = encrypt(Some(scope), &full).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{scope}");
+            assert!(body["message"].as_str().unwrap().contains("x-pii-scope"));
+            let (status, _) = send(
+                "/encrypt/batch",
+                Some(scope),
+                serde_json::json!({"items": []}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{scope}");
+        }
+    }
+
+    #[tokio::test]
+    async fn schemas_encrypt_with_their_own_algorithm() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let schema = |alg: &str| -> openapiv3::OpenAPI {
+            serde_yaml::from_str(&format!(
+                r#"
+openapi: "3.0.0"
+info: {{ title: t, version: "1" }}
+paths: {{}}
+{alg}
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        ssn: {{ type: string, x-pii: true }}
+"#
+            ))
+            .unwrap()
+        };
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state.schema_cache.replace_all(HashMap::from([
+            (
+                "payments-v1".to_string(),
+                schema("x-encryption-alg: ChaCha20-Poly1305"),
+            ),
+            ("identity-v1".to_string(), schema("")),
+        ]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state);
+        let call = |uri: &'static str, schema: &'static str, payload: serde_json::Value| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", schema)
+                .body(Body::from(
+                    serde_json::json!({"payload": payload}).to_string(),
+                ))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                body["payload"].clone()
+            }
+        };
+
+        let plain = serde_json::json!({"ssn": "123-45-6789"});
+        let payments = call("/encrypt", "payments-v1", plain.clone()).await;
+        let identity = call("/encrypt", "identity-v1", plain.clone()).await;
+        let ssn = |payload: &serde_json::Value| EncryptedField::from_json(&payload["ssn"]).unwrap();
+        assert!(payments["ssn"].as_str().unwrap().starts_with("c1."));
+        assert_eq!(ssn(&payments).algorithm, Algorithm::ChaCha20Poly1305);
+        assert!(identity["ssn"].as_str().unwrap().starts_with("v1."));
+        assert_eq!(ssn(&identity).algorithm, Algorithm::Aes256GcmSiv);
+
+        assert_eq!(call("/decrypt", "payments-v1", payments).await, plain);
+        assert_eq!(call("/decrypt", "identity-v1", identity).await, plain);
+    }
+
+    #[tokio::test]
+    async fn tenant_bound_round_trip_and_cross_tenant_failure() {
+        use super::super::state::ServerSettings;
+
+        let (_, app) = test_app_with(
+            ServerSettings {
+                require_tenant: true,
+                ..ServerSettings::default()
+            },
+            r#"
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .await;
+        let call = |uri: &'static str, tenant: Option<&'static str>, body: String| {
+            let app = app.clone();
+            let mut req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", TEST_SCHEMA);
+            if let Some(t) = tenant {
+                req = req.header(TENANT_HEADER, t);
+            }
+            let req = req.body(Body::from(body)).unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+                )
+            }
+        };
+        let plain = r#"{"payload":{"ssn":"123-45-6789"}}"#.to_string();
+
+        let (status, _) = call("/encrypt", None, plain.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, encrypted) = call("/encrypt", Some("tenant-a"), plain).await;
+        assert_eq!(status, StatusCode::OK);
+        let ciphertext = encrypted["payload"]["ssn"].as_str().unwrap().to_owned();
+        assert!(ciphertext.starts_with("v1."));
+        let body = serde_json::json!({ "payload": { "ssn": ciphertext } }).to_string();
+
+        let (status, decrypted) = call("/decrypt", Some("tenant-a"), body.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(decrypted["payload"]["ssn"], "123-45-6789");
+
+        let (status, err) = call("/decrypt", Some("tenant-b"), body).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(err["code"], "tenant_mismatch");
+    }
+
+    #[test]
+    fn tenant_changes_ciphertext() {
+        let dek = [0x42u8; crate::crypto::KEY_LEN];
+        let paths: PiiFieldPaths = ["ssn".to_string()].into();
+        let encrypt_as = |tenant| {
+            let mut val = serde_json::json!({ "ssn": "123-45-6789" });
+            let ctx = CipherContext {
+                dek: &dek,
+                token_key: None,
+                tenant,
+                schema_tag: None,
+                algorithm: Algorithm::default(),
+                field_lengths: None,
+                deadline: None,
+                encoding: CiphertextEncoding::CompactString,
+                min_encrypt_len: 0,
+                max_array_items: 0,
+            };
+            encrypt_pii_fields(
+                &mut val,
+                &paths,
+                &PiiConditions::new(),
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                &ctx,
+            )
+            .unwrap();
+            val
+        };
+        assert_ne!(encrypt_as(Some("tenant-a")), encrypt_as(Some("tenant-b")));
+        assert_ne!(encrypt_as(Some("tenant-a")), encrypt_as(None));
+    }
+
+    #[tokio::test]
+    async fn strict_mode_rejects_undeclared_top_level_keys() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+        plan: { type: string }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default().with_settings(ServerSettings {
+            reject_unknown_top_level_keys: true,
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/encrypt/stream", post(encrypt_stream))
+            .with_state(state);
+        let send = |uri: &'static str, body: &'static str| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, _) = send("/encrypt", r#"{"payload":{"ssn":"1","plan":"gold"}}"#).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send(
+            "/encrypt",
+            r#"{"payload":{"ssn":"1","tax_id":"2","dob":"1990-01-01"}}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("dob, tax_id"), "{body}");
+        assert!(!body.contains("1990"), "{body}");
+
+        let (status, _) = send("/encrypt/stream", r#"{"ssn":"1","tax_id":"2"}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn max_encrypted_fields_bounds_pii_leaves_per_request() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Roster:
+      type: object
+      properties:
+        people:
+          type: array
+          items:
+            type: object
+            properties:
+              ssn: { type: string, x-pii: true }
+              age: { type: integer }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default().with_settings(ServerSettings {
+            max_encrypted_fields: 3,
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("roster-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/encrypt/stream", post(encrypt_stream))
+            .with_state(state);
+        let send = |uri: &'static str, body: String| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "roster-v1")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        let people = |n: usize| {
+            serde_json::json!({
+                "people": (0..n).map(|i| serde_json::json!({"ssn": format!("{i}"), "age": i}))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // At the limit: every field is encrypted.
+        let (status, body) = send(
+            "/encrypt",
+            serde_json::json!({ "payload": people(3) }).to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body.matches("v1.").count(), 3);
+        let (status, _) = send("/encrypt/stream", people(3).to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // One over: rejected before anything is encrypted.
+        let (status, body) = send(
+            "/encrypt",
+            serde_json::json!({ "payload": people(4) }).to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains(r#""code":"too_many_pii_fields""#), "{body}");
+        assert!(body.contains("more than 3 PII fields"), "{body}");
+        let (status, body) = send("/encrypt/stream", people(4).to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains(r#""code":"too_many_pii_fields""#), "{body}");
+    }
+
+    #[tokio::test]
+    async fn max_array_items_bounds_arrays_on_pii_paths() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Statement:
+      type: object
+      properties:
+        transactions:
+          type: array
+          items:
+            type: object
+            properties:
+              card_number: { type: string, x-pii: true }
+        notes:
+          type: array
+          items: { type: string }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default().with_settings(ServerSettings {
+            max_array_items: 3,
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("statement-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/encrypt/stream", post(encrypt_stream))
+            .with_state(state);
+        let send = |uri: &'static str, body: String| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "statement-v1")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        // Arrays off every PII path are not counted.
+        let statement = |n: usize| {
+            serde_json::json!({
+                "transactions": (0..n)
+                    .map(|i| serde_json::json!({"card_number": format!("411111111111{i:04}")}))
+                    .collect::<Vec<_>>(),
+                "notes": ["a", "b", "c", "d", "e"],
+            })
+        };
+
+        // At the limit: every item is encrypted.
+        let (status, body) = send(
+            "/encrypt",
+            serde_json::json!({ "payload": statement(3) }).to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body.matches("v1.").count(), 3);
+        let (status, _) = send("/encrypt/stream", statement(3).to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // One over: rejected rather than traversed.
+        let (status, body) = send(
+            "/encrypt",
+            serde_json::json!({ "payload": statement(4) }).to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains(r#""code":"array_too_large""#), "{body}");
+        assert!(body.contains("more than 3 items"), "{body}");
+        let (status, body) = send("/encrypt/stream", statement(4).to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains(r#""code":"array_too_large""#), "{body}");
+
+        // The buffered traversal applies the same bound.
+        let dek = [0x42u8; KEY_LEN];
+        let bounded = CipherContext {
+            max_array_items: 3,
+            ..ctx(&dek)
+        };
+        let encrypt = |n: usize| {
+            encrypt_pii_fields(
+                &mut statement(n),
+                &["transactions[].card_number".to_string()].into(),
+                &PiiConditions::new(),
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                &bounded,
+            )
+        };
+        assert!(encrypt(3).is_ok());
+        assert!(matches!(encrypt(4), Err(TraversalError::ArrayTooLarge(3))));
+    }
+
+    #[tokio::test]
+    async fn collect_errors_returns_the_fields_that_could_be_encrypted() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        name: { type: string, x-pii: true }
+        ssn: { type: string, x-pii: true, maxLength: 11 }
+        email: { type: string, x-pii: true }
+        meta: { type: string, x-pii-json: true, x-pii-json-schema: Meta }
+    Meta:
+      type: object
+      properties:
+        phone: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state);
+        let send = |body: serde_json::Value| {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+        let payload = serde_json::json!({
+            "name": "Jane",
+            "ssn": "123-45-6789-000",
+            "email": "jane@example.com",
+            "meta": r#"{"phone":"555-0100"}"#
+        });
+
+        // Fail-fast stays the default.
+        let (status, err) = send(serde_json::json!({ "payload": payload })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(err["message"].as_str().unwrap().contains("ssn"));
+
+        let (status, body) =
+            send(serde_json::json!({ "payload": payload, "collect_errors": true })).await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        let out = &body["payload"];
+        assert!(
+            out["ssn"].is_null(),
+            "the failed field never leaks plaintext"
+        );
+        assert!(out["name"].as_str().unwrap().starts_with("v1."));
+        assert!(out["email"].as_str().unwrap().starts_with("v1."));
+        let meta: serde_json::Value = serde_json::from_str(out["meta"].as_str().unwrap()).unwrap();
+        assert!(meta["phone"].as_str().unwrap().starts_with("v1."));
+        assert_eq!(body["error"]["code"], "bad_request");
+        let details = body["error"]["details"].as_array().unwrap();
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0]["path"], "ssn");
+        assert!(!details[0]["message"].as_str().unwrap().contains("123-45"));
+
+        // An invalid embedded document is isolated too, and a clean payload
+        // gets a plain 200 without an error member.
+        let mut bad_meta = payload.clone();
+        bad_meta["ssn"] = "123-45-6789".into();
+        bad_meta["meta"] = "{not json".into();
+        let (status, body) =
+            send(serde_json::json!({ "payload": bad_meta, "collect_errors": true })).await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body["payload"]["meta"].is_null());
+        assert!(body["payload"]["ssn"].as_str().unwrap().starts_with("v1."));
+        assert_eq!(body["error"]["details"][0]["path"], "meta");
+
+        bad_meta["meta"] = r#"{"phone":"555-0100"}"#.into();
+        let (status, body) =
+            send(serde_json::json!({ "payload": bad_meta, "collect_errors": true })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn object_at_string_pii_path_is_skipped_unless_strict() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        name: { type: string, x-pii: true }
+        ssn: { type: string, x-pii: true }
+        age: { type: integer, x-pii: true }
+"#,
+        )
+        .unwrap();
+        let send = |strict: bool, uri: &'static str, body: serde_json::Value| {
+            let api = api.clone();
+            async move {
+                let state = AppState::default().with_settings(ServerSettings {
+                    strict_leaf_types: strict,
+                    ..ServerSettings::default()
+                });
+                state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+                state
+                    .schema_cache
+                    .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+                let app = Router::new()
+                    .route("/encrypt", post(encrypt))
+                    .route("/encrypt/stream", post(encrypt_stream))
+                    .with_state(state);
+                let req = Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .header("X-Schema-Name", "customer-v1")
+                    .body(Body::from(body.to_string()))
+                    .unwrap();
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+        let payload = serde_json::json!({
+            "name": "Jane",
+            "ssn": { "area": "123", "serial": "6789" },
+            "age": 42
+        });
+
+        // Lenient (the default): the object passes through untouched.
+        let (status, body) =
+            send(false, "/encrypt", serde_json::json!({ "payload": payload })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["payload"]["ssn"], payload["ssn"]);
+        assert!(body["payload"]["name"].as_str().unwrap().starts_with("v1."));
+
+        // Strict: rejected with the path, and nothing of the value echoed.
+        let (status, err) = send(true, "/encrypt", serde_json::json!({ "payload": payload })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(err["code"], "bad_request");
+        assert_eq!(err["message"], "field ssn must be a string, found object");
+        assert!(!err.to_string().contains("6789"));
+
+        // The streaming endpoint applies the same check rather than copying
+        // the object through.
+        let (status, body) = send(false, "/encrypt/stream", payload.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["payload"]["ssn"], payload["ssn"]);
+        let (status, err) = send(true, "/encrypt/stream", payload.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(err["message"], "field ssn must be a string, found object");
+
+        // Numbers are accepted at numeric paths, and null anywhere.
+        let clean = serde_json::json!({ "name": "Jane", "ssn": null, "age": 42 });
+        let (status, _) = send(true, "/encrypt", serde_json::json!({ "payload": clean })).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // With collect_errors the field is nulled and reported instead.
+        let (status, body) = send(
+            true,
+            "/encrypt",
+            serde_json::json!({ "payload": payload, "collect_errors": true }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body["payload"]["ssn"].is_null());
+        assert_eq!(body["error"]["details"][0]["path"], "ssn");
+    }
+
+    #[tokio::test]
+    async fn merge_patch_response_applies_to_the_fully_encrypted_payload() {
+        use crate::crypto::KEY_LEN;
+        use crate::server::patch::tests::apply;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        id: { type: integer }
+        user:
+          type: object
+          properties:
+            name: { type: string }
+            ssn: { type: string, x-pii: true }
+        cards:
+          type: array
+          items:
+            type: object
+            properties:
+              pan: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state);
+        let payload = serde_json::json!({
+            "id": 7,
+            "user": {"name": "Jane", "ssn": "123-45-6789"},
+            "cards": [{"pan": "4111111111111111"}]
+        });
+        let send = |accept: Option<&'static str>| {
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1");
+            if let Some(accept) = accept {
+                req = req.header("accept", accept);
+            }
+            let req = req
+                .body(Body::from(
+                    serde_json::json!({ "payload": payload }).to_string(),
+                ))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert!(resp.headers().contains_key(SCHEMA_FINGERPRINT_HEADER));
+                let content_type = resp.headers()[CONTENT_TYPE].to_str().unwrap().to_owned();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    content_type,
+                    serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+                )
+            }
+        };
+
+        let (_, full) = send(None).await;
+        let encrypted = &full["payload"];
+        let (content_type, patch) = send(Some("application/merge-patch+json")).await;
+        assert_eq!(content_type, patch::MERGE_PATCH_CONTENT_TYPE);
+
+        // Only the encrypted leaves (and the array holding one) are sent.
+        assert_eq!(patch.as_object().unwrap().len(), 2);
+        assert_eq!(patch["user"].as_object().unwrap().len(), 1);
+        assert_eq!(patch["user"]["ssn"], encrypted["user"]["ssn"]);
+        assert_eq!(patch["cards"], encrypted["cards"]);
+        assert_eq!(apply(&payload, &patch), *encrypted);
+    }
+
+    #[tokio::test]
+    async fn responses_keep_the_request_key_order() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state);
+        let send = |uri: &'static str, body: String| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+        let keys_in_order = |body: &str| {
+            let at: Vec<usize> = ["\"zip\"", "\"ssn\"", "\"address\"", "\"city\"", "\"age\""]
+                .iter()
+                .map(|k| body.find(k).unwrap())
+                .collect();
+            at.windows(2).all(|w| w[0] < w[1])
+        };
+
+        let request =
+            r#"{"payload":{"zip":"90210","ssn":"123-45-6789","address":{"city":"LA"},"age":40}}"#;
+        assert!(keys_in_order(request));
+        let encrypted = send("/encrypt", request.to_string()).await;
+        assert!(keys_in_order(&encrypted), "{encrypted}");
+        let decrypted = send("/decrypt", encrypted).await;
+        assert!(keys_in_order(&decrypted), "{decrypted}");
+    }
+
+    #[tokio::test]
+    async fn payload_root_must_match_schema() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        let app_with = |settings: ServerSettings| {
+            let state = AppState::default().with_settings(settings);
+            state
+                .schema_cache
+                .replace_all(HashMap::from([("identity-v1".to_string(), api.clone())]));
+            let dek_store = state.dek_store.clone();
+            let app = Router::new()
+                .route("/encrypt", post(encrypt))
+                .with_state(state);
+            (app, dek_store)
+        };
+        let send = |app: Router, body: &'static str| async move {
+            let req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "identity-v1")
+                .body(Body::from(body))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        };
+
+        let (app, dek_store) = app_with(ServerSettings::default());
+        dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        assert_eq!(
+            send(app.clone(), r#"{"payload":"just a string"}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            send(app.clone(), r#"{"payload":42}"#).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            send(app, r#"{"payload":{"ssn":"123-45-6789"}}"#).await,
+            StatusCode::OK
+        );
+
+        let (app, dek_store) = app_with(ServerSettings {
+            enforce_payload_root: false,
+            ..ServerSettings::default()
+        });
+        dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        assert_eq!(send(app, r#"{"payload":42}"#).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn payload_root_error_names_kinds() {
+        let state = AppState::default();
+        let err = payload_root_error(
+            &state,
+            Some(RootKind::Object),
+            &serde_json::json!("just a string"),
+        )
+        .unwrap();
+        assert_eq!(
+            err.message,
+            "payload must be a JSON object for this schema, got string"
+        );
+        assert!(
+            payload_root_error(&state, Some(RootKind::Array), &serde_json::json!([])).is_none()
+        );
+        assert!(payload_root_error(&state, None, &serde_json::json!(42)).is_none());
+    }
+
+    #[test]
+    fn field_length_limits() {
+        let paths: PiiFieldPaths = ["ssn".to_string(), "notes".to_string()].into();
+        let max_lengths = PiiMaxLengths::from([("ssn".to_string(), 11)]);
+        let check = |mut val: serde_json::Value| {
+            check_field_lengths(
+                &mut val,
+                &paths,
+                &max_lengths,
+                &EmbeddedJsonPaths::new(),
+                16,
+            )
+        };
+
+        // Within both the schema maxLength and the global byte limit.
+        assert!(
+            check(serde_json::json!({ "ssn": "123-45-6789", "notes": "0123456789abcdef" })).is_ok()
+        );
+        // maxLength counts characters, not bytes.
+        assert!(check(serde_json::json!({ "ssn": "ééééééééééé" })).is_ok());
+
+        let err = check(serde_json::json!({ "ssn": "123-45-67890" })).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "field ssn exceeds the maximum length of 11 characters"
+        );
+        let (status, _) = err.into_response_parts("encryption failed");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let err = check(serde_json::json!({ "notes": "0123456789abcdefg" })).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "field notes exceeds the maximum length of 16 bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn streaming_encrypt_matches_buffered_output() {
+        use crate::crypto::KEY_LEN;
+        use crate::dek::store::DekBytes;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_token_key(DekBytes(Box::new([0x24u8; KEY_LEN])));
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let schema = |extra: &str| -> openapiv3::OpenAPI {
+            serde_yaml::from_str(&format!(
+                r#"
+openapi: "3.0.0"
+info: {{ title: t, version: "1" }}
+paths: {{}}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        kind: {{ type: string }}
+        name: {{ type: string, x-pii: true }}
+        email: {{ type: string, x-pii: true, x-pii-mode: hash }}
+        account: {{ type: integer, x-pii: true }}
+        tags: {{ type: array, x-pii: true, items: {{ type: string }} }}
+        orders:
+          type: array
+          items:
+            type: object
+            properties:
+              card: {{ type: string, x-pii: true, maxLength: 19 }}
+              amount: {{ type: number }}
+        {extra}
+"#
+            ))
+            .unwrap()
+        };
+        state.schema_cache.replace_all(HashMap::from([
+            ("plain-v1".to_string(), schema("")),
+            (
+                "conditional-v1".to_string(),
+                schema(r#"ref: { type: string, x-pii: true, x-pii-when: { field: kind, equals: person } }"#),
+            ),
+        ]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/encrypt/stream", post(encrypt_stream))
+            .with_state(state);
+        let call = |uri: &'static str, schema: &'static str, body: String| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", schema)
+                .body(Body::from(body))
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+                )
+            }
+        };
+
+        let payload = r#"{
+            "kind": "person", "name": "Ann \"Q\" Lee\u00e9", "email": "ann@example.com",
+            "account": 98765432109876543210987, "tags": ["vip", "x"], "ref": "r-1",
+            "orders": [{"card": "4111 1111 1111 1111", "amount": 1.50}, {"amount": 2}],
+            "untouched": {"name": "not pii", "deep": [[1, {"a": null}], true]}
+        }"#;
+        for schema in ["plain-v1", "conditional-v1"] {
+            let (status, buffered) =
+                call("/encrypt", schema, format!(r#"{{"payload":{payload}}}"#)).await;
+            assert_eq!(status, StatusCode::OK);
+            let (status, streamed) = call("/encrypt/stream", schema, payload.to_owned()).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(streamed, buffered, "{schema}");
+        }
+
+        // Length limits and malformed bodies are reported as 400s.
+        let long_card = r#"{"orders":[{"card":"41111111111111111111"}]}"#;
+        let (status, err) = call("/encrypt/stream", "plain-v1", long_card.to_owned()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(err["message"].as_str().unwrap().contains("orders[].card"));
+        let (status, _) = call("/encrypt/stream", "plain-v1", r#"{"name": }"#.to_owned()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call("/encrypt/stream", "plain-v1", "[]".to_owned()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn schema_tagged_ciphertext_names_schema_and_decrypts() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            schema_tag_ciphertext: true,
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Person:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("people-v1".to_string(), api)]));
+        let fingerprint = state.schema_cache.get("people-v1").unwrap().fingerprint;
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state);
+        let call = |uri: &'static str, body: serde_json::Value| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "people-v1")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+            }
+        };
+
+        let encrypted = call(
+            "/encrypt",
+            serde_json::json!({ "payload": { "ssn": "123-45-6789" } }),
+        )
+        .await;
+        let ssn = encrypted["payload"]["ssn"].as_str().unwrap();
+        let field = EncryptedField::from_str(ssn).unwrap();
+        assert_eq!(
+            field.schema_tag.as_deref(),
+            Some(&fingerprint[..SCHEMA_TAG_LEN])
+        );
+
+        let decrypted = call("/decrypt", encrypted).await;
+        assert_eq!(decrypted["payload"]["ssn"], "123-45-6789");
+    }
+
+    #[tokio::test]
+    async fn large_numeric_pii_round_trips_exactly() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Account:
+      type: object
+      properties:
+        account_number: { type: integer, x-pii: true }
+        balance: { type: number, x-pii: true }
+        branch: { type: integer }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("accounts-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state);
+        let call = |uri: &'static str, body: String| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "accounts-v1")
+                .body(Body::from(body))
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(bytes.to_vec()).unwrap()
+            }
+        };
+
+        // 2^53 + 1 is the first integer f64 cannot represent; the account
+        // number is beyond u64 as well.
+        let payload = r#"{"account_number":98765432109876543210987,"balance":9007199254740993.25,"branch":9007199254740993}"#;
+        let encrypted = call("/encrypt", format!(r#"{{"payload":{payload}}}"#)).await;
+        assert!(
+            !encrypted.contains("98765432109876543210987"),
+            "{encrypted}"
+        );
+        assert!(
+            encrypted.contains(r#""branch":9007199254740993"#),
+            "{encrypted}"
+        );
+        let body: serde_json::Value = serde_json::from_str(&encrypted).unwrap();
+        assert!(body["payload"]["account_number"]
+            .as_str()
+            .unwrap()
+            .starts_with("v1."));
+
+        let decrypted = call("/decrypt", encrypted).await;
+        assert_eq!(decrypted, format!(r#"{{"payload":{payload}}}"#));
+    }
+
+    #[tokio::test]
+    async fn batch_item_spans_are_children_of_the_batch_span() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+        use std::fmt::Write as _;
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id};
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+        use tracing_subscriber::registry::LookupSpan;
+        use tracing_subscriber::Layer;
+
+        /// Name, parent name and recorded fields of a span.
+        type SpanRecord = (String, Option<String>, String);
+        /// Every span created.
+        #[derive(Clone, Default)]
+        struct Spans(Arc<Mutex<Vec<SpanRecord>>>);
+        impl<S> Layer<S> for Spans
+        where
+            S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+        {
+            fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+                let span = ctx.span(id).unwrap();
+                let parent = span.parent().map(|p| p.name().to_owned());
+                let mut fields = String::new();
+                attrs.record(
+                    &mut |field: &tracing::field::Field, value: &dyn std::fmt::Debug| {
+                        write!(fields, "{}={:?} ", field.name(), value).unwrap();
+                    },
+                );
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((span.name().to_owned(), parent, fields));
+            }
+        }
+
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Note:
+      type: object
+      properties:
+        body: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("notes-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt/batch", post(encrypt_batch))
+            .with_state(state);
+
+        let spans = Spans::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+        let req = Request::builder()
+            .method("POST")
+            .uri("/encrypt/batch")
+            .header("content-type", "application/json")
+            .header("X-Schema-Name", "notes-v1")
+            .body(Body::from(
+                r#"{"items":[{"body":"first secret"},{"body":"second secret"}]}"#,
+            ))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let spans = spans.0.lock().unwrap().clone();
+        let batch: Vec<_> = spans.iter().filter(|s| s.0 == "encrypt_batch").collect();
+        assert_eq!(batch.len(), 1);
+        assert!(batch[0].2.contains("items=2"), "{:?}", batch[0]);
+        let mut items: Vec<_> = spans
+            .iter()
+            .filter(|s| s.0 == "encrypt_batch_item")
+            .collect();
+        items.sort_by(|a, b| a.2.cmp(&b.2));
+        assert_eq!(items.len(), 2);
+        for (i, (_, parent, fields)) in items.into_iter().enumerate() {
+            assert_eq!(parent.as_deref(), Some("encrypt_batch"));
+            assert!(fields.contains(&format!("index={i} ")), "{fields}");
+            assert!(fields.contains(r#"schema="notes-v1""#), "{fields}");
+        }
+        assert!(spans.iter().all(|s| !s.2.contains("secret")), "{spans:?}");
+    }
+
+    #[tokio::test]
+    async fn caller_deadline_aborts_or_lets_work_complete() {
+        use crate::crypto::KEY_LEN;
+        use std::collections::HashMap;
+
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Note:
+      type: object
+      properties:
+        body: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("notes-v1".to_string(), api)]));
+        let app = super::super::router::build(state);
+        let items: Vec<_> = (0..50)
+            .map(|i| serde_json::json!({ "body": format!("note {i}") }))
+            .collect();
+        let batch = serde_json::json!({ "items": items }).to_string();
+        let send = |uri: &'static str, body: String, deadline: &'static str| {
+            let app = app.clone();
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "notes-v1")
+                .header("X-Deadline-Ms", deadline)
+                .body(Body::from(body))
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+                )
+            }
+        };
+
+        let (status, body) = send("/encrypt/batch", batch.clone(), "0").await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["code"], "deadline_exceeded");
+        let single = serde_json::json!({ "payload": { "body": "note" } }).to_string();
+        let (status, body) = send("/encrypt", single.clone(), "0").await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["code"], "deadline_exceeded");
+
+        let (status, body) = send("/encrypt/batch", batch, "60000").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"].as_array().unwrap().len(), 50);
+        let (status, _) = send("/encrypt", single.clone(), "60000").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = send("/encrypt", single, "soon").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "bad_request");
+    }
+
+    #[test]
+    fn traversal_stops_at_an_expired_deadline() {
+        use crate::crypto::KEY_LEN;
+        let dek = [0x42u8; KEY_LEN];
+        let ctx = CipherContext {
+            deadline: Some(Deadline::after(std::time::Duration::ZERO)),
+            ..ctx(&dek)
+        };
+        let mut val = serde_json::json!({"ssn": "123-45-6789"});
+        let err = encrypt_pii_fields(
+            &mut val,
+            &["ssn".to_string()].into(),
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx,
+        )
+        .unwrap_err();
+        assert!(matches!(err, TraversalError::DeadlineExceeded));
+        assert_eq!(val["ssn"], "123-45-6789");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn batch_results_follow_request_order() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            max_field_bytes: 64 * 1024,
+            ..ServerSettings::default()
+        });
+        let dek = [0x42u8; KEY_LEN];
+        state.dek_store.store(&dek).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Note:
+      type: object
+      properties:
+        id: { type: integer }
+        body: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("notes-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt/batch", post(encrypt_batch))
+            .with_state(state);
+
+        // Large items first so they tend to finish after the small ones; item 5
+        // exceeds the byte limit and fails on its own.
+        let sizes = [60_000, 30_000, 10_000, 1_000, 10, 70_000, 1];
+        let items: Vec<_> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| serde_json::json!({ "id": i, "body": "x".repeat(*n) }))
+            .collect();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/encrypt/batch")
+            .header("content-type", "application/json")
+            .header("X-Schema-Name", "notes-v1")
+            .body(Body::from(
+                serde_json::json!({ "items": items }).to_string(),
+            ))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: BatchEncryptResponse = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(body.results.len(), sizes.len());
+        for (i, result) in body.results.iter().enumerate() {
+            assert_eq!(result.index, i);
+            if i == 5 {
+                assert!(result.payload.is_none());
+                assert_eq!(result.error.as_ref().unwrap().code, ErrorCode::BadRequest);
+                continue;
+            }
+            let payload = result.payload.as_ref().unwrap();
+            assert_eq!(payload["id"], i);
+            let field = EncryptedField::from_str(payload["body"].as_str().unwrap()).unwrap();
+            let plaintext = decrypt_field_with_aad(&field, &dek, &[]).unwrap();
+            assert_eq!(plaintext.len(), sizes[i]);
+        }
+    }
+
+    #[tokio::test]
+    async fn reload_schemas_requires_admin_and_a_loader() {
+        use super::super::state::ServerSettings;
+        use axum::routing::post;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            admin_identities: ["ops".to_string()].into(),
+            ..ServerSettings::default()
+        });
+        let app = Router::new()
+            .route("/admin/reload-schemas", post(reload_schemas))
+            .with_state(state);
+        let call = |cn: &str| {
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/admin/reload-schemas?source=team-a/schemas/")
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut().insert(ClientIdentity(cn.into()));
+            app.clone().oneshot(req)
+        };
+
+        assert_eq!(call("app").await.unwrap().status(), StatusCode::FORBIDDEN);
+        // No AWS clients in tests, so the admin gets a clean 503.
+        assert_eq!(
+            call("ops").await.unwrap().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn reload_over_the_schema_cap_is_a_conflict() {
+        let e = ReloadError::from(crate::schema::TooManySchemas { count: 3, limit: 2 });
+        assert_eq!(
+            reload_failure(&e),
+            (StatusCode::CONFLICT, ErrorCode::TooManySchemas)
+        );
+    }
+
+    #[tokio::test]
+    async fn list_schemas_requires_admin() {
+        use super::super::state::ServerSettings;
+
+        let (_, app) = test_app_with(
+            ServerSettings {
+                admin_identities: ["ops".to_string()].into(),
+                ..ServerSettings::default()
+            },
+            r#"
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .await;
+        let call = |uri: &'static str, cn: Option<&str>| {
+            let mut req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            app.clone().oneshot(req)
+        };
+
+        for cn in [None, Some("app")] {
+            let resp = call("/admin/schemas", cn).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{cn:?}");
+        }
+        let resp = call("/admin/schemas", Some("ops")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains(TEST_SCHEMA), "{text}");
+    }
+
+    #[tokio::test]
+    async fn schemas_by_path_requires_admin() {
+        use super::super::state::ServerSettings;
+
+        let (_, app) = test_app_with(
+            ServerSettings {
+                admin_identities: ["ops".to_string()].into(),
+                ..ServerSettings::default()
+            },
+            r#"
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .await;
+        let call = |uri: &'static str, cn: Option<&str>| {
+            let mut req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            app.clone().oneshot(req)
+        };
+
+        for uri in [
+            "/admin/schemas/by-path?path=ssn",
+            "/admin/schemas/by-path?path=none",
+        ] {
+            for cn in [None, Some("app")] {
+                let resp = call(uri, cn).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{uri} {cn:?}");
+            }
+            let resp = call(uri, Some("ops")).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "{uri}");
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            let expected: &[&str] = if uri.ends_with("ssn") {
+                &[TEST_SCHEMA]
+            } else {
+                &[]
+            };
+            assert_eq!(body["schemas"], serde_json::json!(expected), "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn encryption_settings_swap_applies_to_next_request() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            admin_identities: ["ops".to_string()].into(),
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .route(
+                "/admin/encryption",
+                get(encryption_settings).put(update_encryption_settings),
+            )
+            .with_state(state.clone());
+        let call = |method: &'static str, uri: &'static str, body: String, cn: Option<&str>| {
+            let app = app.clone();
+            let mut req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body))
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+                )
+            }
+        };
+        let encrypt_body = r#"{"payload":{"ssn":"123-45-6789"}}"#.to_string();
+
+        let (status, before) = call("POST", "/encrypt", encrypt_body.clone(), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let ciphertext = before["payload"]["ssn"].as_str().unwrap().to_owned();
+        assert!(ciphertext.starts_with("v1."));
+
+        // A request already in flight holds the snapshot it started with.
+        let in_flight = state.encryption.load_full();
+
+        let update = r#"{"encoding":"json_object"}"#.to_string();
+        let (status, _) = call("PUT", "/admin/encryption", update.clone(), Some("app")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = call("PUT", "/admin/encryption", update, Some("ops")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["encoding"], "json_object");
+        let (_, body) = call("GET", "/admin/encryption", String::new(), Some("ops")).await;
+        assert_eq!(body["encoding"], "json_object");
+
+        assert_eq!(in_flight.encoding, CiphertextEncoding::CompactString);
+        let (status, after) = call("POST", "/encrypt", encrypt_body, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(after["payload"]["ssn"]["ct"].is_string(), "{after}");
+
+        // Data written under the previous settings still decrypts.
+        let decrypt_body = serde_json::json!({ "payload": { "ssn": ciphertext } }).to_string();
+        let (status, body) = call("POST", "/decrypt", decrypt_body, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["payload"]["ssn"], "123-45-6789");
+    }
+
+    #[tokio::test]
+    async fn every_pii_endpoint_is_audited() {
+        use super::super::state::ServerSettings;
+        use crate::telemetry::audit::AuditLog;
+
+        let settings = ServerSettings {
+            admin_identities: ["ops".to_string()].into(),
+            allow_value_endpoint: true,
+            ..ServerSettings::default()
+        };
+        let (state, _) = test_app_with(
+            settings,
+            r#"
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        name: { type: string, x-pii: true }
+"#,
+        )
+        .await;
+        let (log, mut rx) = AuditLog::channel(16, Default::default());
+        let app = handler_router(state.with_audit(log));
+
+        let payload = serde_json::json!({"name": "Jane"});
+        let calls = [
+            ("/encrypt/batch", serde_json::json!({ "items": [payload] })),
+            ("/encrypt/stream", payload.clone()),
+            ("/encrypt/value", serde_json::json!({ "value": "Jane" })),
+            (
+                "/admin/decrypt/preview",
+                serde_json::json!({ "payload": payload }),
+            ),
+        ];
+        for (uri, body) in calls {
+            let mut req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", TEST_SCHEMA)
+                .header("X-Tenant-Id", "acme")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            req.extensions_mut().insert(ClientIdentity("ops".into()));
+            let resp = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "{uri}");
+        }
+
+        let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        let actions: Vec<_> = events.iter().map(|e| e.action).collect();
+        assert_eq!(
+            actions,
+            [
+                "encrypt_batch",
+                "encrypt_stream",
+                "encrypt_value",
+                "decrypt_preview"
+            ]
+        );
+        assert!(events.iter().all(|e| e.client.as_deref() == Some("ops")));
+        assert_eq!(events[0].schema.as_deref(), Some(TEST_SCHEMA));
+        assert_eq!(events[0].tenant.as_deref(), Some("acme"));
+        assert_eq!(events[2].schema, None);
+    }
+
+    #[tokio::test]
+    async fn decrypt_preview_masks_and_requires_admin() {
+        use super::super::mask::MaskPolicy;
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            admin_identities: ["support".to_string()].into(),
+            mask_policy: MaskPolicy::parse("CONTACT=first:1").unwrap(),
+            ..ServerSettings::default()
+        });
+        let dek = [0x42u8; KEY_LEN];
+        state.dek_store.store(&dek).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Account:
+      type: object
+      properties:
+        card_number: { type: string, x-pii: true, x-pii-category: FINANCIAL }
+        email: { type: string, x-pii: true, x-pii-category: CONTACT }
+        status: { type: string }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+        let cached = state.schema_cache.get("payments-v1").unwrap();
+        let mut payload = serde_json::json!({
+            "card_number": "4111111111111111",
+            "email": "jane@example.com",
+            "status": "active",
+        });
+        encrypt_pii_fields(
+            &mut payload,
+            &cached.pii_paths,
+            &cached.conditions,
+            &cached.hashed,
+            &PiiFieldPaths::new(),
+            &cached.numeric,
+            &ctx(&dek),
+        )
+        .unwrap();
+        let body = serde_json::json!({ "payload": payload }).to_string();
+
+        let app = Router::new()
+            .route("/admin/decrypt/preview", post(decrypt_preview))
+            .with_state(state);
+        let call = |cn: Option<&'static str>| {
+            let app = app.clone();
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/admin/decrypt/preview")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "payments-v1")
+                .body(Body::from(body.clone()))
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&bytes).unwrap(),
+                )
+            }
+        };
+
+        assert_eq!(call(None).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(call(Some("payments")).await.0, StatusCode::FORBIDDEN);
+
+        let (status, resp) = call(Some("support")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            resp["payload"],
+            serde_json::json!({
+                "card_number": "************1111",
+                "email": "j***************",
+                "status": "active",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_payload_envelope_explains_expected_shape() {
+        use axum::routing::post;
+
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(AppState::default());
+        let send = |uri: &'static str, body: &'static str| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        for uri in ["/encrypt", "/decrypt"] {
+            let (status, body) = send(uri, r#"{ "ssn": "x" }"#).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+            let err: ErrorResponse = serde_json::from_str(&body).unwrap();
+            assert_eq!(err.code, ErrorCode::BadRequest);
+            assert!(err.message.contains(r#"{"payload":{"#), "{}", err.message);
+        }
+        // Malformed JSON keeps axum's own rejection.
+        let (status, _) = send("/encrypt", r#"{ "payload": "#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn encrypt_returns_schema_fingerprint_header() {
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+        let expected = state.schema_cache.get("payments-v1").unwrap().fingerprint;
+
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state);
+        let req = Request::builder()
+            .method("POST")
+            .uri("/encrypt")
+            .header("content-type", "application/json")
+            .header("X-Schema-Name", "payments-v1")
+            .body(Body::from(r#"{"payload":{"name":"Alice"}}"#))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[SCHEMA_FINGERPRINT_HEADER], expected.as_ref());
+    }
+
+    #[tokio::test]
+    async fn encrypt_records_field_lengths_not_contents() {
+        use crate::crypto::KEY_LEN;
+        use crate::telemetry::metrics::FieldLengths;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default();
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state.clone());
+        let req = Request::builder()
+            .method("POST")
+            .uri("/encrypt")
+            .header("content-type", "application/json")
+            .header("X-Schema-Name", "customer-v1")
+            .body(Body::from(r#"{"payload":{"ssn":"123-45-6789"}}"#))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let out: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let ciphertext_len = out["payload"]["ssn"].as_str().unwrap().len();
+
+        let lengths = &state.metrics.field_lengths;
+        let mut plaintext = [0; 9];
+        plaintext[FieldLengths::bucket("123-45-6789".len())] = 1;
+        assert_eq!(lengths.plaintext(), plaintext);
+        let mut ciphertext = [0; 9];
+        ciphertext[FieldLengths::bucket(ciphertext_len)] = 1;
+        assert_eq!(lengths.ciphertext(), ciphertext);
     }
 
-    match &segments[0] {
-        PathSegment::Key(key) => {
-            if let serde_json::Value::Object(map) = value {
-                if let Some(child) = map.get_mut(key) {
-                    encrypt_at_path(child, &segments[1..], dek)?;
-                }
-            }
-        }
-        PathSegment::ArrayItem => {
-            if let serde_json::Value::Array(arr) = value {
-                for item in arr.iter_mut() {
-                    encrypt_at_path(item, &segments[1..], dek)?;
-                }
+    #[tokio::test]
+    async fn inline_pii_paths_encrypt_without_schema() {
+        use super::super::identity::SchemaAllowlist;
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+
+        let call = |allow: bool, body: &'static str| async move {
+            let state = AppState::default().with_settings(ServerSettings {
+                allow_inline_schema: allow,
+                ..ServerSettings::default()
+            });
+            state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+            let app = Router::new()
+                .route("/encrypt", post(encrypt))
+                .with_state(state);
+            let req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let resp = app.oneshot(req).await.unwrap();
+            let status = resp.status();
+            let fingerprint = resp.headers().contains_key(SCHEMA_FINGERPRINT_HEADER);
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            (status, fingerprint, body)
+        };
+
+        let body = r#"{"payload":{"ssn":"123-45-6789","orders":[{"card":"4111"}],"name":"Alice"},
+                       "pii_paths":["ssn","orders[].card"]}"#;
+        let (status, fingerprint, resp) = call(true, body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!fingerprint);
+        assert!(resp["payload"]["ssn"].as_str().unwrap().starts_with("v1."));
+        assert!(resp["payload"]["orders"][0]["card"]
+            .as_str()
+            .unwrap()
+            .starts_with("v1."));
+        assert_eq!(resp["payload"]["name"], "Alice");
+
+        // Disabled by default.
+        let (status, _, resp) = call(false, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(resp["message"].as_str().unwrap().contains("not enabled"));
+
+        // Under a client allowlist, only the clients it names may send inline
+        // paths, since there is no schema name to match against their prefixes.
+        let state = AppState::default().with_settings(ServerSettings {
+            allow_inline_schema: true,
+            schema_allowlist: Some(SchemaAllowlist::parse("payments=payments-").unwrap()),
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .with_state(state);
+        for (cn, expected) in [
+            (Some("payments"), StatusCode::OK),
+            (Some("other"), StatusCode::FORBIDDEN),
+            (None, StatusCode::FORBIDDEN),
+        ] {
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
             }
+            let resp = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), expected, "{cn:?}");
         }
     }
-    Ok(())
-}
 
-/// Encrypt all PII string fields in `payload` according to `pii_paths`.
-fn encrypt_pii_fields(
-    payload: &mut serde_json::Value,
-    pii_paths: &PiiFieldPaths,
-    dek: &[u8],
-) -> Result<(), CipherError> {
-    for path in pii_paths {
-        let segments = parse_path(path);
-        encrypt_at_path(payload, &segments, dek)?;
-    }
-    Ok(())
-}
+    #[tokio::test]
+    async fn noop_schema_passes_payloads_through_unchanged() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
 
-/// Recursively navigate `value` following `segments` and decrypt any string
-/// leaf at the end of the path that carries the `v1.` ciphertext prefix.
-/// Leaves that do not start with `v1.` are left unchanged.
-fn decrypt_at_path(
-    value: &mut serde_json::Value,
-    segments: &[PathSegment],
-    dek: &[u8],
-) -> Result<(), CipherError> {
-    if segments.is_empty() {
-        if let serde_json::Value::String(s) = value {
-            if s.starts_with("v1.") {
-                let field = EncryptedField::from_str(s)?;
-                let plaintext = decrypt_field(&field, dek)?;
-                *value = serde_json::Value::String(
-                    String::from_utf8(plaintext).map_err(|_| CipherError::AeadFailure)?,
-                );
+        let state = AppState::default().with_settings(ServerSettings {
+            noop_schema: Some("passthrough".into()),
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state.clone());
+        let send = |uri: &'static str, schema: &'static str| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", schema)
+                .body(Body::from(
+                    r#"{"payload":{"ssn":"123-45-6789","orders":[{"card":"4111"}],"n":1.50}}"#,
+                ))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let fingerprint = resp.headers().get(SCHEMA_FINGERPRINT_HEADER).cloned();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                (status, fingerprint, body)
             }
-            // Non-encrypted strings are left as-is (idempotent path traversal).
-        }
-        return Ok(());
+        };
+        // Parsed rather than built, so `1.50` keeps its exact token.
+        let payload: serde_json::Value =
+            serde_json::from_str(r#"{"ssn":"123-45-6789","orders":[{"card":"4111"}],"n":1.50}"#)
+                .unwrap();
+
+        // Served before any schema has loaded, unlike an unknown name.
+        let (status, fingerprint, body) = send("/encrypt", "passthrough").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            fingerprint,
+            Some(axum::http::HeaderValue::from_static("noop")),
+            "{body}"
+        );
+        assert_eq!(fingerprint.unwrap(), "noop");
+        let (status, _, _) = send("/encrypt", "unknown-v1").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        // A loaded schema of the same name does not take its place, nor does
+        // an empty cache make it unknown.
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    P:
+      type: object
+      properties:
+        ssn: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("passthrough".to_string(), api)]));
+        let (status, _, body) = send("/encrypt", "passthrough").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["payload"], payload);
+        state.schema_cache.replace_all(HashMap::new());
+        let (status, _, body) = send("/decrypt", "passthrough").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["payload"], payload);
+        let (status, _, _) = send("/encrypt", "unknown-v1").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 
-    match &segments[0] {
-        PathSegment::Key(key) => {
-            if let serde_json::Value::Object(map) = value {
-                if let Some(child) = map.get_mut(key) {
-                    decrypt_at_path(child, &segments[1..], dek)?;
-                }
-            }
-        }
-        PathSegment::ArrayItem => {
-            if let serde_json::Value::Array(arr) = value {
-                for item in arr.iter_mut() {
-                    decrypt_at_path(item, &segments[1..], dek)?;
-                }
-            }
+    #[test]
+    fn inline_schema_rejects_malformed_paths() {
+        use super::super::state::ServerSettings;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            allow_inline_schema: true,
+            ..ServerSettings::default()
+        });
+        let check =
+            |paths: &[&str]| inline_schema(&state, paths.iter().map(|p| p.to_string()).collect());
+
+        assert!(check(&["ssn", "user.address.zip", "[]", "[].ssn", "a[].b[]"]).is_ok());
+        assert!(check(&[]).is_err());
+        for bad in ["", "user..zip", ".ssn", "ssn.", "a[]b", "a.[].b"] {
+            let err = check(&[bad]).unwrap_err();
+            assert!(
+                err.message.contains("invalid PII path"),
+                "{bad}: {}",
+                err.message
+            );
         }
+        let too_many: Vec<String> = (0..=MAX_INLINE_PII_PATHS)
+            .map(|i| format!("f{i}"))
+            .collect();
+        assert!(inline_schema(&state, too_many).is_err());
     }
-    Ok(())
-}
 
-/// Decrypt all PII string fields in `payload` according to `pii_paths`.
-fn decrypt_pii_fields(
-    payload: &mut serde_json::Value,
-    pii_paths: &PiiFieldPaths,
-    dek: &[u8],
-) -> Result<(), CipherError> {
-    for path in pii_paths {
-        let segments = parse_path(path);
-        decrypt_at_path(payload, &segments, dek)?;
+    #[tokio::test]
+    async fn error_responses_are_counted_per_code() {
+        use axum::routing::post;
+
+        let state = AppState::default();
+        let errors = state.metrics.error_responses.clone();
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .fallback(not_found)
+            .with_state(state);
+        let send = |uri: &'static str| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"payload":{}}"#))
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+
+        // Missing schema header.
+        assert_eq!(
+            send("/encrypt").await.unwrap().status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(send("/nope").await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(send("/nope").await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        assert_eq!(errors.get(ErrorCode::BadRequest), 1);
+        assert_eq!(errors.get(ErrorCode::NotFound), 2);
+        assert_eq!(errors.get(ErrorCode::ServiceUnavailable), 0);
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::routing::get;
-    use axum::{body::Body, http::Request, Router};
-    use tower::ServiceExt;
+    #[tokio::test]
+    async fn stats_report_counters_without_secrets() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use std::collections::HashMap;
 
-    fn test_router() -> Router {
-        Router::new()
-            .route("/health", get(health))
-            .with_state(AppState::default())
+        let state = AppState::default().with_settings(ServerSettings {
+            admin_identities: ["ops".to_string()].into(),
+            ..ServerSettings::default()
+        });
+        let dek = [0x5au8; KEY_LEN];
+        state.dek_store.store(&dek).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_json::from_str(
+            r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{},
+                "components":{"schemas":{"P":{"type":"object","properties":{
+                    "ssn":{"type":"string","x-pii":true}}}}}}"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+        let _in_progress = state.track_request();
+
+        let app = Router::new()
+            .route("/admin/stats", get(stats))
+            .with_state(state);
+        let call = |cn: Option<&str>| {
+            let mut req = Request::builder()
+                .uri("/admin/stats")
+                .body(Body::empty())
+                .unwrap();
+            if let Some(cn) = cn {
+                req.extensions_mut().insert(ClientIdentity(cn.into()));
+            }
+            app.clone().oneshot(req)
+        };
+        for cn in [None, Some("app")] {
+            assert_eq!(call(cn).await.unwrap().status(), StatusCode::FORBIDDEN);
+        }
+
+        let resp = call(Some("ops")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "schemas_cached": 1,
+                "pii_path_bytes": 3,
+                "active_requests": 1,
+                "dek_generation": 1,
+            })
+        );
+
+        // No rendering of the key appears anywhere in the response.
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let hex: String = dek.iter().map(|b| format!("{b:02x}")).collect();
+        assert!(!text.contains(&hex[..8]));
+        assert!(!text.contains(&STANDARD.encode(dek)[..8]));
+        assert!(!text.contains("ssn"));
     }
 
     #[tokio::test]
-    async fn health_returns_503_when_not_ready() {
-        let app = test_router();
-        let req = Request::builder()
-            .uri("/health")
-            .body(Body::empty())
+    async fn stale_schemas_shed_encrypt_and_degrade_readiness() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+        use std::time::Duration;
+
+        let call = |max_staleness: Duration| async move {
+            let state = AppState::default().with_settings(ServerSettings {
+                max_schema_staleness: Some(max_staleness),
+                ..ServerSettings::default()
+            });
+            state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+            let api: openapiv3::OpenAPI = serde_json::from_str(
+                r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#,
+            )
             .unwrap();
-        let resp = app.oneshot(req).await.unwrap();
-        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+            state
+                .schema_cache
+                .replace_all(HashMap::from([("payments-v1".to_string(), api)]));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+
+            let app = Router::new()
+                .route("/encrypt", post(encrypt))
+                .route("/readyz", get(health))
+                .with_state(state);
+            let encrypt = Request::builder()
+                .method("POST")
+                .uri("/encrypt")
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "payments-v1")
+                .body(Body::from(r#"{"payload":{"name":"Alice"}}"#))
+                .unwrap();
+            let encrypt = app.clone().oneshot(encrypt).await.unwrap();
+            let ready = Request::builder()
+                .uri("/readyz")
+                .body(Body::empty())
+                .unwrap();
+            let ready = app.oneshot(ready).await.unwrap();
+            (encrypt, ready.status())
+        };
+
+        // Fresh: last refresh well within the limit.
+        let (encrypt, ready) = call(Duration::from_secs(3600)).await;
+        assert_eq!(encrypt.status(), StatusCode::OK);
+        assert_eq!(ready, StatusCode::OK);
+
+        // Stale: the refresh is older than the (tiny) limit.
+        let (encrypt, ready) = call(Duration::from_millis(1)).await;
+        assert_eq!(encrypt.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(encrypt.headers().contains_key(RETRY_AFTER));
+        let bytes = axum::body::to_bytes(encrypt.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "schemas_stale");
+        assert_eq!(ready, StatusCode::SERVICE_UNAVAILABLE);
     }
 
     #[test]
@@ -422,6 +6455,90 @@ mod tests {
         assert!(matches!(segs[1], PathSegment::ArrayItem));
     }
 
+    /// The path `segments` were parsed from.
+    fn render_path(segments: &[PathSegment]) -> String {
+        let mut path = String::new();
+        for (i, segment) in segments.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) => {
+                    if i > 0 {
+                        path.push('.');
+                    }
+                    path.push_str(key);
+                }
+                PathSegment::ArrayItem => path.push_str("[]"),
+                PathSegment::RecursiveKey(key) => {
+                    if i > 0 {
+                        path.push('.');
+                    }
+                    path.push_str("**.");
+                    path.push_str(key);
+                }
+            }
+        }
+        path
+    }
+
+    #[test]
+    fn parse_path_edge_cases() {
+        for path in [
+            "",
+            ".",
+            "[]",
+            "[].ssn",
+            "a[][][]",
+            "a..b",
+            "a.[]",
+            "**",
+            "a.**",
+            "**.**",
+            "**[].a",
+            "**.a[].**.b",
+            "é.🦀[].\u{0}",
+        ] {
+            assert_eq!(render_path(&parse_path(path)), path);
+        }
+        let deep = "a[].".repeat(10_000) + "ssn";
+        let segments = parse_path(&deep);
+        assert_eq!(segments.len(), 20_001);
+        let mut payload = serde_json::json!({ "a": [{ "a": [{ "ssn": "x" }] }] });
+        let dek = [0x42u8; crate::crypto::KEY_LEN];
+        encrypt_pii_fields(
+            &mut payload,
+            &[deep].into(),
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        assert_eq!(payload["a"][0]["a"][0]["ssn"], "x");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn parse_path_round_trips(path in "\\PC*") {
+            proptest::prop_assert_eq!(render_path(&parse_path(&path)), path);
+        }
+
+        #[test]
+        fn path_traversal_never_panics(
+            path in "([a-z]{0,2}(\\[\\])*\\.){0,8}[a-z]{0,2}(\\[\\])*",
+        ) {
+            let mut payload = serde_json::json!({
+                "a": [{ "b": "x", "a": [[{ "a": 1 }], "y"] }],
+                "b": { "a": ["z", { "b": "w" }] },
+                "": { "": "v" },
+            });
+            let dek = [0x42u8; crate::crypto::KEY_LEN];
+            let paths = PiiFieldPaths::from([path]);
+            encrypt_pii_fields(&mut payload, &paths, &PiiConditions::new(), &PiiFieldPaths::new(), &PiiFieldPaths::new(), &PiiFieldPaths::new(), &ctx(&dek))
+            .unwrap();
+            decrypt_pii_fields(&mut payload, &paths, &PiiFieldPaths::new(), &PiiFieldPaths::new(), &ctx(&dek)).unwrap();
+        }
+    }
+
     #[test]
     fn encrypt_flat_field() {
         use crate::crypto::KEY_LEN;
@@ -429,7 +6546,16 @@ mod tests {
         let mut val = serde_json::json!({"ssn": "123-45-6789", "name": "Alice"});
         let mut paths = PiiFieldPaths::new();
         paths.insert("ssn".into());
-        encrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         let ssn = val["ssn"].as_str().unwrap();
         assert!(ssn.starts_with("v1."), "expected v1. prefix, got: {ssn}");
         assert_eq!(val["name"].as_str().unwrap(), "Alice");
@@ -442,7 +6568,16 @@ mod tests {
         let mut val = serde_json::json!({"user": {"address": {"zip": "90210"}}});
         let mut paths = PiiFieldPaths::new();
         paths.insert("user.address.zip".into());
-        encrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         let zip = val["user"]["address"]["zip"].as_str().unwrap();
         assert!(zip.starts_with("v1."));
     }
@@ -459,7 +6594,16 @@ mod tests {
         });
         let mut paths = PiiFieldPaths::new();
         paths.insert("orders[].card_number".into());
-        encrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         for order in val["orders"].as_array().unwrap() {
             let cn = order["card_number"].as_str().unwrap();
             assert!(cn.starts_with("v1."), "expected encrypted, got: {cn}");
@@ -473,7 +6617,16 @@ mod tests {
         let mut val = serde_json::json!({"name": "Bob"});
         let mut paths = PiiFieldPaths::new();
         paths.insert("ssn".into());
-        encrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         // no panic, "name" untouched
         assert_eq!(val["name"].as_str().unwrap(), "Bob");
     }
@@ -489,7 +6642,14 @@ mod tests {
         let mut val = serde_json::json!({"ssn": ciphertext_str, "name": "Alice"});
         let mut paths = PiiFieldPaths::new();
         paths.insert("ssn".into());
-        decrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         assert_eq!(val["ssn"].as_str().unwrap(), plaintext);
         assert_eq!(val["name"].as_str().unwrap(), "Alice");
     }
@@ -502,7 +6662,14 @@ mod tests {
         let mut paths = PiiFieldPaths::new();
         paths.insert("ssn".into());
         // A non-v1. string at a PII path should be left unchanged.
-        decrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         assert_eq!(val["ssn"].as_str().unwrap(), "plaintext-already");
     }
 
@@ -518,7 +6685,14 @@ mod tests {
         let mut val = serde_json::json!({"user": {"address": {"zip": ciphertext_str}}});
         let mut paths = PiiFieldPaths::new();
         paths.insert("user.address.zip".into());
-        decrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         assert_eq!(val["user"]["address"]["zip"].as_str().unwrap(), plaintext);
     }
 
@@ -540,12 +6714,298 @@ mod tests {
         });
         let mut paths = PiiFieldPaths::new();
         paths.insert("orders[].card_number".into());
-        decrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         for (i, order) in val["orders"].as_array().unwrap().iter().enumerate() {
             assert_eq!(order["card_number"].as_str().unwrap(), cards[i]);
         }
     }
 
+    #[test]
+    fn recursive_key_matches_leaves_at_any_depth() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let original = serde_json::json!({
+            "accountId": "top",
+            "owner": {"accountId": {"type": "savings", "accountId": "nested"}},
+            "history": [{"entries": [{"accountId": "in-array"}]}, {"accountId": 7}],
+            "accounts": {"accountId": ["a", "b"]}
+        });
+        let paths: PiiFieldPaths = ["**.accountId".to_string()].into();
+        let mut val = original.clone();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        for leaf in [
+            &val["accountId"],
+            &val["owner"]["accountId"]["accountId"],
+            &val["history"][0]["entries"][0]["accountId"],
+        ] {
+            assert!(leaf.as_str().unwrap().starts_with("v1."), "{leaf}");
+        }
+        // Same-named objects and arrays are not leaves and stay as they are.
+        assert_eq!(val["owner"]["accountId"]["type"], "savings");
+        assert_eq!(val["accounts"], original["accounts"]);
+        assert_eq!(val["history"][1]["accountId"], 7);
+
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        assert_eq!(val, original);
+    }
+
+    #[test]
+    fn json_object_ciphertext_round_trips() {
+        use crate::crypto::{cipher::ALGORITHM, KEY_LEN};
+        let dek = vec![0x42u8; KEY_LEN];
+        let object_ctx = CipherContext {
+            encoding: CiphertextEncoding::JsonObject,
+            schema_tag: Some("3f2a9c1be07d"),
+            algorithm: Algorithm::default(),
+            ..ctx(&dek)
+        };
+        let original = serde_json::json!({
+            "ssn": "123-45-6789",
+            "cards": ["4111", "5500"],
+            "owner": {"accountId": "acct-1"},
+            "age": 42
+        });
+        let paths: PiiFieldPaths = ["ssn", "cards[]", "**.accountId", "age"]
+            .map(String::from)
+            .into();
+        let numeric: PiiFieldPaths = ["age".to_string()].into();
+        let mut val = original.clone();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &numeric,
+            &object_ctx,
+        )
+        .unwrap();
+        for leaf in [
+            &val["ssn"],
+            &val["cards"][0],
+            &val["cards"][1],
+            &val["owner"]["accountId"],
+            &val["age"],
+        ] {
+            assert_eq!(leaf["alg"], ALGORITHM, "{leaf}");
+            assert_eq!(leaf["schema_tag"], "3f2a9c1be07d");
+            assert!(leaf["nonce"].is_string() && leaf["ct"].is_string());
+        }
+
+        // Decryption does not depend on the configured encoding, and accepts
+        // both encodings side by side.
+        val["ssn"] = serde_json::Value::String(
+            EncryptedField::from_json(&val["ssn"])
+                .unwrap()
+                .to_string_repr(),
+        );
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &numeric,
+            &ctx(&dek),
+        )
+        .unwrap();
+        assert_eq!(val, original);
+    }
+
+    #[test]
+    fn strings_shorter_than_min_encrypt_len_pass_through() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let paths: PiiFieldPaths = ["ssn", "name"].map(String::from).into();
+        let encrypt = |ctx: &CipherContext| {
+            let mut val = serde_json::json!({"ssn": "", "name": "a"});
+            encrypt_pii_fields(
+                &mut val,
+                &paths,
+                &PiiConditions::new(),
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                ctx,
+            )
+            .unwrap();
+            val
+        };
+        let encrypted = |v: &serde_json::Value| v.as_str().unwrap().starts_with("v1.");
+
+        let skipped = encrypt(&CipherContext {
+            min_encrypt_len: 1,
+            ..ctx(&dek)
+        });
+        assert_eq!(skipped["ssn"], "");
+        assert!(encrypted(&skipped["name"]));
+
+        // The default threshold of 0 encrypts even empty strings.
+        let all = encrypt(&ctx(&dek));
+        assert!(encrypted(&all["ssn"]));
+        assert!(encrypted(&all["name"]));
+    }
+
+    #[test]
+    fn recursive_key_continues_with_the_rest_of_the_path() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let mut val = serde_json::json!({
+            "user": {"cards": ["4111"], "profile": {"cards": ["5500", "3400"]}},
+            "cards": ["outside"]
+        });
+        encrypt_pii_fields(
+            &mut val,
+            &["user.**.cards[]".to_string()].into(),
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        let encrypted = |v: &serde_json::Value| v.as_str().unwrap().starts_with("v1.");
+        assert!(encrypted(&val["user"]["cards"][0]));
+        assert!(encrypted(&val["user"]["profile"]["cards"][0]));
+        assert!(encrypted(&val["user"]["profile"]["cards"][1]));
+        assert_eq!(val["cards"][0], "outside");
+    }
+
+    fn embedded_ssn() -> EmbeddedJsonPaths {
+        let mut inner = crate::schema::resolver::ResolvedSchema::default();
+        inner.pii_paths.insert("ssn".into());
+        let mut embedded = EmbeddedJsonPaths::new();
+        embedded.insert("metadata".into(), inner);
+        embedded
+    }
+
+    #[test]
+    fn encrypt_embedded_json_field() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let inner_doc = r#"{"ssn":"123-45-6789","note":"hello"}"#;
+        let mut val = serde_json::json!({"metadata": inner_doc, "name": "Alice"});
+        encrypt_embedded_json(&mut val, &embedded_ssn(), &ctx(&dek)).unwrap();
+
+        // Still a string, but the embedded PII is now ciphertext.
+        let s = val["metadata"].as_str().expect("metadata remains a string");
+        let doc: serde_json::Value = serde_json::from_str(s).unwrap();
+        assert!(doc["ssn"].as_str().unwrap().starts_with("v1."), "{doc}");
+        assert_eq!(doc["note"], "hello");
+        assert_eq!(val["name"], "Alice");
+    }
+
+    #[test]
+    fn embedded_json_round_trip() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let original = serde_json::json!({"metadata": r#"{"ssn":"123-45-6789"}"#});
+        let mut val = original.clone();
+        encrypt_embedded_json(&mut val, &embedded_ssn(), &ctx(&dek)).unwrap();
+        assert_ne!(val, original);
+        decrypt_embedded_json(&mut val, &embedded_ssn(), &ctx(&dek)).unwrap();
+        assert_eq!(val, original);
+    }
+
+    #[test]
+    fn embedded_json_invalid_document_is_bad_request() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let mut val = serde_json::json!({"metadata": "not json"});
+        let err = encrypt_embedded_json(&mut val, &embedded_ssn(), &ctx(&dek)).unwrap_err();
+        assert!(matches!(err, TraversalError::EmbeddedJson(ref p) if p == "metadata"));
+        let (status, body) = err.into_response_parts("encryption failed");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.message.contains("metadata"));
+    }
+
+    fn ssn_condition() -> (PiiFieldPaths, PiiConditions) {
+        let mut paths = PiiFieldPaths::new();
+        paths.insert("ids[].id_number".into());
+        let mut conditions = PiiConditions::new();
+        conditions.insert(
+            "ids[].id_number".into(),
+            PiiCondition {
+                field: "id_type".into(),
+                equals: serde_json::json!("SSN"),
+            },
+        );
+        (paths, conditions)
+    }
+
+    #[test]
+    fn conditional_field_encrypted_when_sibling_matches() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let (paths, conditions) = ssn_condition();
+        let mut val = serde_json::json!({"ids": [
+            {"id_type": "SSN", "id_number": "123-45-6789"},
+        ]});
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &conditions,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        assert!(val["ids"][0]["id_number"]
+            .as_str()
+            .unwrap()
+            .starts_with("v1."));
+    }
+
+    #[test]
+    fn conditional_field_untouched_when_sibling_differs() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let (paths, conditions) = ssn_condition();
+        let mut val = serde_json::json!({"ids": [
+            {"id_type": "PASSPORT", "id_number": "X1234567"},
+            {"id_number": "no-discriminator"},
+            {"id_type": "SSN", "id_number": "123-45-6789"},
+        ]});
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &conditions,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        assert_eq!(val["ids"][0]["id_number"], "X1234567");
+        assert_eq!(val["ids"][1]["id_number"], "no-discriminator");
+        assert!(val["ids"][2]["id_number"]
+            .as_str()
+            .unwrap()
+            .starts_with("v1."));
+    }
+
     #[test]
     fn encrypt_then_decrypt_idempotent() {
         use crate::crypto::KEY_LEN;
@@ -555,8 +7015,327 @@ mod tests {
         paths.insert("ssn".into());
 
         let mut val = original.clone();
-        encrypt_pii_fields(&mut val, &paths, &dek).unwrap();
-        decrypt_pii_fields(&mut val, &paths, &dek).unwrap();
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
         assert_eq!(val, original);
     }
+
+    #[test]
+    fn hashed_field_is_deterministic_and_survives_decrypt() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let paths = PiiFieldPaths::from(["email".into(), "ssn".into()]);
+        let hashed = PiiFieldPaths::from(["email".into()]);
+        let original = serde_json::json!({"email": "jane@example.com", "ssn": "123-45-6789"});
+
+        let encrypt = |payload: &serde_json::Value| {
+            let mut val = payload.clone();
+            encrypt_pii_fields(
+                &mut val,
+                &paths,
+                &PiiConditions::new(),
+                &hashed,
+                &PiiFieldPaths::new(),
+                &PiiFieldPaths::new(),
+                &ctx(&dek),
+            )
+            .unwrap();
+            val
+        };
+        let mut val = encrypt(&original);
+        let token = val["email"].as_str().unwrap().to_owned();
+        assert!(is_hashed(&token), "{token}");
+        assert!(val["ssn"].as_str().unwrap().starts_with("v1."));
+        // Same input, same token: usable for lookups and deduplication.
+        assert_eq!(encrypt(&original)["email"], token.as_str());
+
+        // Decrypt restores encrypted fields but cannot reverse the hash.
+        decrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiFieldPaths::new(),
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        assert_eq!(val["ssn"], "123-45-6789");
+        assert_eq!(val["email"], token.as_str());
+    }
+
+    #[test]
+    fn tokenized_card_number_keeps_its_format_and_decrypts() {
+        use crate::crypto::KEY_LEN;
+        let dek = vec![0x42u8; KEY_LEN];
+        let paths = PiiFieldPaths::from(["card".into(), "cvv".into()]);
+        let tokenized = PiiFieldPaths::from(["card".into()]);
+        let mut val = serde_json::json!({"card": "4111111111111111", "cvv": "123"});
+
+        encrypt_pii_fields(
+            &mut val,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &tokenized,
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap();
+        let token = val["card"].as_str().unwrap().to_owned();
+        assert_eq!(token.len(), 16);
+        assert!(token.bytes().all(|b| b.is_ascii_digit()), "{token}");
+        assert_ne!(token, "4111111111111111");
+        assert!(val["cvv"].as_str().unwrap().starts_with("v1."));
+        let tag = val["card_fpe"].as_str().unwrap().to_owned();
+        assert!(tag.starts_with("f1."), "{tag}");
+        let encrypted = val.clone();
+
+        let decrypt = |val: &mut serde_json::Value| {
+            decrypt_pii_fields(val, &paths, &tokenized, &PiiFieldPaths::new(), &ctx(&dek))
+        };
+        decrypt(&mut val).unwrap();
+        assert_eq!(
+            val,
+            serde_json::json!({"card": "4111111111111111", "cvv": "123"})
+        );
+
+        // Without its tag a value at the path cannot be told from plaintext
+        // and is left alone, whether it is a token or plaintext.
+        for card in [token.as_str(), "4111111111111111"] {
+            let mut untagged = serde_json::json!({ "card": card });
+            decrypt(&mut untagged).unwrap();
+            assert_eq!(untagged, serde_json::json!({ "card": card }));
+        }
+
+        // A tag that does not match its value is refused.
+        for (card, card_fpe) in [("4111111111111111", tag.as_str()), (&token, "f1.AAAA")] {
+            let mut forged = serde_json::json!({ "card": card, "card_fpe": card_fpe });
+            let err = decrypt(&mut forged).unwrap_err();
+            assert_eq!(
+                err.into_response_parts("decryption failed").0,
+                StatusCode::BAD_REQUEST
+            );
+        }
+
+        // Tokens need the token key; without one they are refused, not
+        // derived from the rotating DEK.
+        let no_token_key = CipherContext {
+            token_key: None,
+            ..ctx(&dek)
+        };
+        let err = decrypt_pii_fields(
+            &mut encrypted.clone(),
+            &paths,
+            &tokenized,
+            &PiiFieldPaths::new(),
+            &no_token_key,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.into_response_parts("decryption failed").0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        // Too few digits for FF1 is the caller's problem, not a server error.
+        let mut short = serde_json::json!({"card": "4111"});
+        let err = encrypt_pii_fields(
+            &mut short,
+            &paths,
+            &PiiConditions::new(),
+            &PiiFieldPaths::new(),
+            &tokenized,
+            &PiiFieldPaths::new(),
+            &ctx(&dek),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.into_response_parts("encryption failed").0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn lookup_mode_adds_deterministic_tag_beside_ciphertext() {
+        use crate::crypto::KEY_LEN;
+        use crate::dek::store::DekBytes;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_token_key(DekBytes(Box::new([0x24u8; KEY_LEN])));
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        email: { type: string, x-pii: true, x-pii-mode: lookup }
+        phone: { type: string, x-pii: true, x-pii-mode: hash }
+        name: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state.clone());
+        let call = |uri: &'static str, body: serde_json::Value| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()["payload"].clone()
+            }
+        };
+
+        let plaintext = serde_json::json!({"payload": {
+            "email": "jane@example.com", "phone": "555-0100", "name": "Jane"
+        }});
+        let first = call("/encrypt", plaintext.clone()).await;
+        let second = call("/encrypt", plaintext.clone()).await;
+
+        let tag = first["email_lookup"].as_str().unwrap();
+        assert!(tag.starts_with("t1."), "{tag}");
+        assert_eq!(second["email_lookup"], tag);
+        assert!(first["email"].as_str().unwrap().starts_with("v1."));
+        assert!(first.get("name_lookup").is_none());
+
+        let decrypted = call("/decrypt", serde_json::json!({ "payload": first })).await;
+        assert_eq!(decrypted["email"], "jane@example.com");
+        assert_eq!(decrypted["email_lookup"], tag);
+
+        // Lookup tags and hash tokens come from the token key, so they still
+        // match after the DEK rotates; the ciphertext does not.
+        state
+            .dek_store
+            .store(&[0x43u8; crate::crypto::KEY_LEN])
+            .await
+            .unwrap();
+        let rotated = call("/encrypt", plaintext).await;
+        assert_eq!(rotated["email_lookup"], tag);
+        assert_eq!(rotated["phone"], first["phone"]);
+        assert_ne!(rotated["email"], first["email"]);
+    }
+
+    #[tokio::test]
+    async fn audit_flags_fields_sharing_a_nonce() {
+        use super::super::state::ServerSettings;
+        use crate::crypto::KEY_LEN;
+        use axum::routing::post;
+        use std::collections::HashMap;
+
+        let state = AppState::default().with_settings(ServerSettings {
+            audit_nonce_reuse: true,
+            ..ServerSettings::default()
+        });
+        state.dek_store.store(&[0x42u8; KEY_LEN]).await.unwrap();
+        let api: openapiv3::OpenAPI = serde_yaml::from_str(
+            r#"
+openapi: "3.0.0"
+info: { title: t, version: "1" }
+paths: {}
+components:
+  schemas:
+    Customer:
+      type: object
+      properties:
+        email: { type: string, x-pii: true }
+        backup_email: { type: string, x-pii: true }
+"#,
+        )
+        .unwrap();
+        state
+            .schema_cache
+            .replace_all(HashMap::from([("customer-v1".to_string(), api)]));
+        let metrics = std::sync::Arc::clone(&state.metrics);
+        let app = Router::new()
+            .route("/encrypt", post(encrypt))
+            .route("/decrypt", post(decrypt))
+            .with_state(state);
+        let call = |uri: &'static str, body: serde_json::Value| {
+            let req = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("X-Schema-Name", "customer-v1")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+                (status, body["payload"].clone())
+            }
+        };
+        let flagged = || metrics.nonce_reuse.load(Ordering::Relaxed);
+
+        // Equal values share nonce and ciphertext by design: not flagged.
+        let (_, same) = call(
+            "/encrypt",
+            serde_json::json!({"payload": {"email": "a@x.io", "backup_email": "a@x.io"}}),
+        )
+        .await;
+        assert_eq!(same["email"], same["backup_email"]);
+        let (status, _) = call("/decrypt", serde_json::json!({ "payload": same })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(flagged(), 0);
+
+        // Splice the first field's nonce onto the second field's ciphertext.
+        let (_, mut doc) = call(
+            "/encrypt",
+            serde_json::json!({"payload": {"email": "a@x.io", "backup_email": "b@x.io"}}),
+        )
+        .await;
+        let nonce = doc["email"]
+            .as_str()
+            .unwrap()
+            .split('.')
+            .nth(1)
+            .unwrap()
+            .to_string();
+        let backup = doc["backup_email"].as_str().unwrap().to_string();
+        let parts: Vec<&str> = backup.split('.').collect();
+        doc["backup_email"] = serde_json::json!(format!("{}.{nonce}.{}", parts[0], parts[2]));
+
+        let (status, _) = call("/decrypt", serde_json::json!({ "payload": doc })).await;
+        // Decryption behaves as without the audit: the spliced field fails.
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(flagged(), 1);
+    }
 }
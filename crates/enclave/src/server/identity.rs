@@ -0,0 +1,121 @@
+//! mTLS client identity and per-identity schema authorization.
+//!
+//! When `TLS_CLIENT_CA_PATH` is set, clients must present a certificate signed by
+//! one of the configured CAs. The TLS accept loop extracts the certificate's
+//! subject Common Name into a [`ClientIdentity`] request extension, and
+//! [`SchemaAllowlist`] restricts which schemas each identity may use.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+/// Identity of an mTLS client: the Common Name of its leaf certificate.
+///
+/// Inserted into request extensions by the TLS accept loop; absent when the
+/// client presented no certificate or the certificate has no CN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity(pub String);
+
+/// Extract the subject Common Name from a DER-encoded X.509 certificate.
+///
+/// Returns `None` if the certificate cannot be parsed or carries no CN.
+pub fn common_name(cert_der: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert_der).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(str::to_owned)
+}
+
+/// Config-driven grants mapping a client CN to the schema-name prefixes it may use.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaAllowlist {
+    grants: HashMap<String, Vec<String>>,
+}
+
+impl SchemaAllowlist {
+    /// Parse an allowlist of the form `cn=prefix[,prefix...][;cn=...]`,
+    /// e.g. `payments=payments-;identity=identity-,kyc-`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry is missing `=`, or has an empty CN or no prefixes.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut grants: HashMap<String, Vec<String>> = HashMap::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (cn, prefixes) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("allowlist entry {entry:?} must be cn=prefix"))?;
+            let cn = cn.trim();
+            if cn.is_empty() {
+                anyhow::bail!("allowlist entry {entry:?} has an empty CN");
+            }
+            let prefixes: Vec<String> = prefixes
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+                .collect();
+            if prefixes.is_empty() {
+                anyhow::bail!("allowlist entry for {cn:?} grants no schema prefixes");
+            }
+            grants.entry(cn.to_owned()).or_default().extend(prefixes);
+        }
+        Ok(Self { grants })
+    }
+
+    /// Whether `identity` may use the schema named `schema`.
+    ///
+    /// Requests without an identity, or from an identity with no grant, are denied.
+    pub fn permits(&self, identity: Option<&ClientIdentity>, schema: &str) -> bool {
+        identity
+            .and_then(|id| self.grants.get(&id.0))
+            .is_some_and(|prefixes| prefixes.iter().any(|p| schema.starts_with(p.as_str())))
+    }
+
+    /// Whether `identity` holds any grant. Schemaless requests (inline
+    /// `pii_paths`, `/encrypt/value`, `/decrypt/value`) are open to exactly
+    /// these clients.
+    pub fn admits(&self, identity: Option<&ClientIdentity>) -> bool {
+        identity.is_some_and(|id| self.grants.contains_key(&id.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(cn: &str) -> ClientIdentity {
+        ClientIdentity(cn.into())
+    }
+
+    #[test]
+    fn parse_and_permit() {
+        let list = SchemaAllowlist::parse("payments=payments-; identity=identity-,kyc-").unwrap();
+        assert!(list.permits(Some(&id("payments")), "payments-v1"));
+        assert!(!list.permits(Some(&id("payments")), "identity-v1"));
+        assert!(list.permits(Some(&id("identity")), "kyc-v2"));
+        assert!(!list.permits(Some(&id("unknown")), "payments-v1"));
+        assert!(!list.permits(None, "payments-v1"));
+        assert!(list.admits(Some(&id("identity"))));
+        assert!(!list.admits(Some(&id("unknown"))));
+        assert!(!list.admits(None));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_entries() {
+        assert!(SchemaAllowlist::parse("payments").is_err());
+        assert!(SchemaAllowlist::parse("=payments-").is_err());
+        assert!(SchemaAllowlist::parse("payments=").is_err());
+    }
+
+    #[test]
+    fn common_name_extracted_from_certificate() {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".into()]).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "payments");
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        assert_eq!(common_name(cert.der()).as_deref(), Some("payments"));
+        assert_eq!(common_name(b"not a certificate"), None);
+    }
+}
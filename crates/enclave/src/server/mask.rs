@@ -0,0 +1,158 @@
+//! Masking of decrypted PII for the admin decrypt preview.
+//!
+//! Support staff can confirm that a value decrypts correctly without seeing
+//! it in full. Which portion stays visible is chosen per `x-pii-category`
+//! (e.g. `FINANCIAL`, `CONTACT`) by a [`MaskPolicy`] parsed from config.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+/// Character substituted for every hidden character.
+const MASK_CHAR: char = '*';
+
+/// How much of a plaintext value remains visible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskRule {
+    /// Hide every character.
+    Full,
+    /// Show only the first `n` characters.
+    First(usize),
+    /// Show only the last `n` characters.
+    Last(usize),
+}
+
+impl MaskRule {
+    /// Parse `full`, `first:N`, or `last:N`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for any other form.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec == "full" {
+            return Ok(MaskRule::Full);
+        }
+        let parse_n = |n: &str| {
+            n.trim()
+                .parse::<usize>()
+                .map_err(|_| anyhow::anyhow!("mask rule {spec:?} has an invalid count"))
+        };
+        match spec.split_once(':') {
+            Some(("first", n)) => Ok(MaskRule::First(parse_n(n)?)),
+            Some(("last", n)) => Ok(MaskRule::Last(parse_n(n)?)),
+            _ => anyhow::bail!("mask rule {spec:?} must be full, first:N, or last:N"),
+        }
+    }
+
+    /// Apply the rule to `value`.
+    ///
+    /// A value no longer than the visible portion is masked completely, so
+    /// short values are never revealed in full.
+    pub fn apply(self, value: &str) -> String {
+        let len = value.chars().count();
+        let visible = match self {
+            MaskRule::Full => 0..0,
+            MaskRule::First(n) if n < len => 0..n,
+            MaskRule::Last(n) if n < len => len - n..len,
+            MaskRule::First(_) | MaskRule::Last(_) => 0..0,
+        };
+        value
+            .chars()
+            .enumerate()
+            .map(|(i, c)| if visible.contains(&i) { c } else { MASK_CHAR })
+            .collect()
+    }
+}
+
+/// Masking rules keyed by `x-pii-category`, with a fallback for fields
+/// without a category or with an unlisted one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskPolicy {
+    default: MaskRule,
+    by_category: HashMap<String, MaskRule>,
+}
+
+impl Default for MaskPolicy {
+    /// Last four characters visible for every field.
+    fn default() -> Self {
+        Self {
+            default: MaskRule::Last(4),
+            by_category: HashMap::new(),
+        }
+    }
+}
+
+impl MaskPolicy {
+    /// Parse a policy of the form `CATEGORY=rule[;CATEGORY=rule...]`, where the
+    /// reserved category `default` replaces the fallback rule, e.g.
+    /// `FINANCIAL=last:4;CONTACT=first:1;default=full`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry is missing `=` or has an invalid rule.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut policy = Self::default();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (category, rule) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("mask entry {entry:?} must be CATEGORY=rule"))?;
+            let rule = MaskRule::parse(rule)?;
+            match category.trim() {
+                "default" => policy.default = rule,
+                category => {
+                    policy.by_category.insert(category.to_owned(), rule);
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    /// The rule for a field with the given `x-pii-category`.
+    pub fn rule_for(&self, category: Option<&str>) -> MaskRule {
+        category
+            .and_then(|c| self.by_category.get(c))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_reveal_only_allowed_portion() {
+        assert_eq!(
+            MaskRule::Last(4).apply("4111111111111111"),
+            "************1111"
+        );
+        assert_eq!(
+            MaskRule::First(1).apply("jane@example.com"),
+            "j***************"
+        );
+        assert_eq!(MaskRule::Full.apply("Jane"), "****");
+        // Values not longer than the visible portion are fully masked.
+        assert_eq!(MaskRule::Last(4).apply("1234"), "****");
+        // Counts characters, not bytes.
+        assert_eq!(MaskRule::Last(2).apply("Zoë Ü"), "*** Ü");
+    }
+
+    #[test]
+    fn policy_selects_rule_by_category() {
+        let policy = MaskPolicy::parse("FINANCIAL=last:4; CONTACT=first:1; default=full").unwrap();
+        assert_eq!(policy.rule_for(Some("FINANCIAL")), MaskRule::Last(4));
+        assert_eq!(policy.rule_for(Some("CONTACT")), MaskRule::First(1));
+        assert_eq!(policy.rule_for(Some("OTHER")), MaskRule::Full);
+        assert_eq!(policy.rule_for(None), MaskRule::Full);
+        assert_eq!(MaskPolicy::default().rule_for(None), MaskRule::Last(4));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_entries() {
+        assert!(MaskPolicy::parse("FINANCIAL").is_err());
+        assert!(MaskPolicy::parse("FINANCIAL=last").is_err());
+        assert!(MaskPolicy::parse("FINANCIAL=last:x").is_err());
+        assert!(MaskPolicy::parse("FINANCIAL=middle:2").is_err());
+    }
+}
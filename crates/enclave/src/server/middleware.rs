@@ -1,8 +1,348 @@
 //! Axum middleware layers applied to the router.
 //!
-//! Includes request tracing, timeout enforcement, and response compression.
+//! Includes request tracing, timeout enforcement, caller-supplied deadlines,
+//! request body checksums, response compression, and redaction of sensitive
+//! header values.
 
 use std::time::Duration;
 
+use anyhow::{Context, Result};
+use axum::body::{Body, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::protocol::{ErrorCode, ErrorResponse};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use tokio::time::Instant;
+
+use super::handlers::{error_response, TENANT_HEADER};
+use super::state::AppState;
+
 /// Default per-request timeout applied to all routes.
 pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request header carrying the caller's time budget, in milliseconds from
+/// when the request is received.
+pub const DEADLINE_HEADER: &str = "x-deadline-ms";
+
+/// gRPC-style time budget header (`<digits><unit>`, e.g. `250m`), honoured
+/// when [`DEADLINE_HEADER`] is absent.
+pub const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// The point in time after which the caller no longer wants a response.
+///
+/// Set by [`enforce_deadline`] as a request extension so handlers can stop
+/// long-running work once it passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// The deadline `budget` from now.
+    pub fn after(budget: Duration) -> Self {
+        Self(Instant::now() + budget)
+    }
+
+    /// Whether the deadline has passed.
+    pub fn expired(self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+/// The caller's time budget from [`DEADLINE_HEADER`] or, failing that,
+/// [`GRPC_TIMEOUT_HEADER`]; `None` when neither is sent.
+///
+/// # Errors
+///
+/// Returns a message naming the header when its value is malformed.
+pub fn requested_budget(headers: &HeaderMap) -> Result<Option<Duration>, String> {
+    if let Some(value) = headers.get(DEADLINE_HEADER) {
+        return value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|ms| Some(Duration::from_millis(ms)))
+            .ok_or_else(|| format!("invalid {DEADLINE_HEADER} header: expected milliseconds"));
+    }
+    match headers.get(GRPC_TIMEOUT_HEADER) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(parse_grpc_timeout)
+            .map(Some)
+            .ok_or_else(|| format!("invalid {GRPC_TIMEOUT_HEADER} header")),
+        None => Ok(None),
+    }
+}
+
+/// Parse a gRPC `TimeoutValue TimeoutUnit`: at most 8 digits followed by one
+/// of `H`, `M`, `S`, `m`, `u` or `n`.
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let digits = value.get(..value.len().checked_sub(1)?)?;
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    Some(match value.as_bytes().last()? {
+        b'H' => Duration::from_secs(amount * 3600),
+        b'M' => Duration::from_secs(amount * 60),
+        b'S' => Duration::from_secs(amount),
+        b'm' => Duration::from_millis(amount),
+        b'u' => Duration::from_micros(amount),
+        b'n' => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+/// The `504 Gateway Timeout` response for a request past its [`Deadline`].
+pub fn deadline_exceeded(state: &AppState) -> Response {
+    let err = ErrorResponse::new(ErrorCode::DeadlineExceeded, "request deadline exceeded");
+    error_response(state, StatusCode::GATEWAY_TIMEOUT, err)
+}
+
+/// Read the caller's deadline, if any, into the request extensions and answer
+/// `504` once it passes while the handler is still waiting.
+///
+/// Handlers check the [`Deadline`] themselves between units of synchronous
+/// work (batch items, PII paths), which this layer cannot interrupt. A
+/// malformed header is rejected with `400`.
+pub async fn enforce_deadline(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let budget = match requested_budget(req.headers()) {
+        Ok(Some(budget)) => budget,
+        Ok(None) => return next.run(req).await,
+        Err(message) => {
+            let err = ErrorResponse::new(ErrorCode::BadRequest, message);
+            return error_response(&state, StatusCode::BAD_REQUEST, err);
+        }
+    };
+    let deadline = Deadline::after(budget);
+    req.extensions_mut().insert(deadline);
+    match tokio::time::timeout_at(deadline.0, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => deadline_exceeded(&state),
+    }
+}
+
+/// Record the size of each response body whose length is known in
+/// `enclave_response_bytes`. Runs inside compression, so sizes are
+/// uncompressed.
+pub async fn record_response_size(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(req).await;
+    if let Some(bytes) = response.body().size_hint().exact() {
+        state.metrics.response_bytes.record(bytes, &[]);
+    }
+    response
+}
+
+/// Request header carrying the base64 MD5 digest of the body (RFC 1864).
+pub const CONTENT_MD5_HEADER: &str = "content-md5";
+
+/// Request header carrying the hex SHA-256 digest of the body.
+pub const CONTENT_SHA256_HEADER: &str = "x-content-sha256";
+
+/// Largest body [`verify_body_checksum`] buffers to check.
+pub const MAX_CHECKSUMMED_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Check the body of a request that carries [`CONTENT_MD5_HEADER`] or
+/// [`CONTENT_SHA256_HEADER`] against it, catching corruption introduced on
+/// the way to the enclave. The body is buffered, checked against every
+/// checksum sent and handed on unchanged; a mismatch is rejected with `400`
+/// and a body over [`MAX_CHECKSUMMED_BODY_BYTES`] with `413`. Requests
+/// without either header pass straight through.
+pub async fn verify_body_checksum(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let md5 = req.headers().get(CONTENT_MD5_HEADER).cloned();
+    let sha256 = req.headers().get(CONTENT_SHA256_HEADER).cloned();
+    if md5.is_none() && sha256.is_none() {
+        return next.run(req).await;
+    }
+    let (parts, body) = req.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_CHECKSUMMED_BODY_BYTES).await else {
+        let err = ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("checksummed request body exceeds {MAX_CHECKSUMMED_BODY_BYTES} bytes"),
+        );
+        return error_response(&state, StatusCode::PAYLOAD_TOO_LARGE, err);
+    };
+    let checks = [
+        md5.map(|sent| {
+            let actual = STANDARD.encode(Md5::digest(&bytes));
+            (CONTENT_MD5_HEADER, sent.as_bytes() == actual.as_bytes())
+        }),
+        sha256.map(|sent| {
+            let actual: String = Sha256::digest(&bytes)
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            let matches = sent
+                .to_str()
+                .is_ok_and(|sent| sent.trim().eq_ignore_ascii_case(&actual));
+            (CONTENT_SHA256_HEADER, matches)
+        }),
+    ];
+    if let Some((header, _)) = checks.into_iter().flatten().find(|(_, ok)| !ok) {
+        let err = ErrorResponse::new(
+            ErrorCode::BadRequest,
+            format!("request body does not match its {header} checksum"),
+        );
+        return error_response(&state, StatusCode::BAD_REQUEST, err);
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Headers whose values are always redacted from spans and logs.
+pub const DEFAULT_REDACTED_HEADERS: [&str; 5] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    TENANT_HEADER,
+];
+
+/// The header names to redact: [`DEFAULT_REDACTED_HEADERS`] plus the
+/// comma-separated names in `extra`.
+///
+/// Values of these headers are marked sensitive before the trace layer sees
+/// them, so spans and logs print `Sensitive` instead of the value.
+///
+/// # Errors
+///
+/// Returns an error if a name in `extra` is not a valid header name.
+pub fn redacted_headers(extra: Option<&str>) -> Result<Vec<HeaderName>> {
+    let mut names: Vec<HeaderName> = DEFAULT_REDACTED_HEADERS
+        .iter()
+        .map(|name| HeaderName::from_static(name))
+        .collect();
+    for name in extra.unwrap_or_default().split(',').map(str::trim) {
+        if name.is_empty() {
+            continue;
+        }
+        let name = HeaderName::try_from(name)
+            .with_context(|| format!("invalid header name in REDACTED_HEADERS: {name}"))?;
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_headers_extend_defaults() {
+        let names = redacted_headers(Some(" X-Customer-Ref, authorization ,")).unwrap();
+        assert_eq!(names.len(), DEFAULT_REDACTED_HEADERS.len() + 1);
+        assert!(names.contains(&HeaderName::from_static("x-customer-ref")));
+        assert!(names.contains(&HeaderName::from_static("x-tenant-id")));
+        assert!(redacted_headers(Some("bad header")).is_err());
+    }
+
+    #[test]
+    fn deadline_headers_parse() {
+        let budget = |name: &'static str, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, value.parse().unwrap());
+            requested_budget(&headers)
+        };
+        assert_eq!(requested_budget(&HeaderMap::new()), Ok(None));
+        assert_eq!(
+            budget(DEADLINE_HEADER, "250"),
+            Ok(Some(Duration::from_millis(250)))
+        );
+        assert_eq!(
+            budget(GRPC_TIMEOUT_HEADER, "2S"),
+            Ok(Some(Duration::from_secs(2)))
+        );
+        assert_eq!(
+            budget(GRPC_TIMEOUT_HEADER, "1500u"),
+            Ok(Some(Duration::from_micros(1500)))
+        );
+        for (name, value) in [
+            (DEADLINE_HEADER, "-1"),
+            (DEADLINE_HEADER, "soon"),
+            (GRPC_TIMEOUT_HEADER, "S"),
+            (GRPC_TIMEOUT_HEADER, "100"),
+            (GRPC_TIMEOUT_HEADER, "123456789m"),
+        ] {
+            assert!(budget(name, value).is_err(), "{name}: {value}");
+        }
+    }
+
+    #[tokio::test]
+    async fn body_checksums_are_verified_when_sent() {
+        use axum::routing::post;
+        use axum::Router;
+        use tower::ServiceExt;
+
+        let state = AppState::default();
+        let app = Router::new()
+            .route("/", post(|body: String| async move { body }))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                verify_body_checksum,
+            ))
+            .with_state(state);
+        let send = |headers: &[(&'static str, &str)]| {
+            let mut req = Request::builder().method("POST").uri("/");
+            for (name, value) in headers {
+                req = req.header(*name, *value);
+            }
+            let req = req.body(Body::from(r#"{"payload":{}}"#)).unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        let md5 = STANDARD.encode(Md5::digest(br#"{"payload":{}}"#));
+        let sha256: String = Sha256::digest(br#"{"payload":{}}"#)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        // Matching checksums hand the body on intact; none is not checked.
+        for headers in [
+            vec![(CONTENT_MD5_HEADER, md5.as_str())],
+            vec![(CONTENT_SHA256_HEADER, &sha256.to_uppercase())],
+            vec![(CONTENT_MD5_HEADER, &md5), (CONTENT_SHA256_HEADER, &sha256)],
+            vec![],
+        ] {
+            let (status, body) = send(&headers).await;
+            assert_eq!(status, StatusCode::OK, "{headers:?}");
+            assert_eq!(body, r#"{"payload":{}}"#);
+        }
+
+        // Any mismatch is a 400 naming the header.
+        let wrong = STANDARD.encode(Md5::digest(b"{}"));
+        let (status, body) = send(&[(CONTENT_MD5_HEADER, &wrong)]).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("content-md5"), "{body}");
+        let (status, body) = send(&[
+            (CONTENT_MD5_HEADER, &md5),
+            (CONTENT_SHA256_HEADER, &"0".repeat(64)),
+        ])
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("x-content-sha256"), "{body}");
+    }
+}
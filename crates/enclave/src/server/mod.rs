@@ -7,7 +7,12 @@
 
 // Sub-modules added as the server layer is implemented.
 pub mod handlers;
+pub mod identity;
+pub mod mask;
 pub mod middleware;
+pub mod patch;
+pub mod pkcs8;
 pub mod router;
 pub mod state;
+pub mod stream;
 pub mod tls;
@@ -0,0 +1,123 @@
+//! JSON Merge Patch (RFC 7386) responses for `/encrypt`.
+//!
+//! A caller that sends `Accept: application/merge-patch+json` receives, instead
+//! of the whole encrypted payload, a merge patch that turns the payload it sent
+//! into the encrypted one. Only the changed members appear. Merge patches cannot
+//! address array elements, so an array containing an encrypted leaf is sent
+//! whole.
+
+use serde_json::{Map, Value};
+
+/// Media type of a JSON Merge Patch document.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// Whether an `Accept` header value asks for a merge patch.
+pub fn accepts_merge_patch(accept: &str) -> bool {
+    accept.split(',').any(|range| {
+        range
+            .split(';')
+            .next()
+            .is_some_and(|media| media.trim().eq_ignore_ascii_case(MERGE_PATCH_CONTENT_TYPE))
+    })
+}
+
+/// The merge patch that turns `original` into `updated`.
+///
+/// Members that are equal in both are omitted and members missing from
+/// `updated` become `null`. When either side is not an object, the patch is
+/// `updated` itself, which replaces the target wholesale.
+pub fn diff(original: &Value, updated: &Value) -> Value {
+    match (original, updated) {
+        (Value::Object(original), Value::Object(updated)) => {
+            let mut patch = Map::new();
+            for (key, new) in updated {
+                match original.get(key) {
+                    Some(old) if old == new => {}
+                    Some(old) => {
+                        patch.insert(key.clone(), diff(old, new));
+                    }
+                    None => {
+                        patch.insert(key.clone(), new.clone());
+                    }
+                }
+            }
+            for key in original.keys() {
+                if !updated.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            Value::Object(patch)
+        }
+        _ => updated.clone(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// RFC 7386 §2 `MergePatch(Target, Patch)`.
+    pub(crate) fn apply(target: &Value, patch: &Value) -> Value {
+        let Value::Object(patch) = patch else {
+            return patch.clone();
+        };
+        let mut target = match target {
+            Value::Object(target) => target.clone(),
+            _ => Map::new(),
+        };
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                let merged = apply(target.get(key).unwrap_or(&Value::Null), value);
+                target.insert(key.clone(), merged);
+            }
+        }
+        Value::Object(target)
+    }
+
+    #[test]
+    fn diff_contains_only_changed_members() {
+        let original = json!({
+            "id": 7,
+            "user": {"name": "Jane", "ssn": "123", "tags": ["a"]},
+            "cards": [{"pan": "4111"}, {"pan": "5500"}],
+            "gone": true
+        });
+        let updated = json!({
+            "id": 7,
+            "user": {"name": "Jane", "ssn": "enc:v1.abc", "tags": ["a"], "ssn_tag": "t1.x"},
+            "cards": [{"pan": "enc:v1.1"}, {"pan": "enc:v1.2"}]
+        });
+        let patch = diff(&original, &updated);
+        assert_eq!(
+            patch,
+            json!({
+                "user": {"ssn": "enc:v1.abc", "ssn_tag": "t1.x"},
+                "cards": [{"pan": "enc:v1.1"}, {"pan": "enc:v1.2"}],
+                "gone": null
+            })
+        );
+        assert_eq!(apply(&original, &patch), updated);
+    }
+
+    #[test]
+    fn diff_of_non_objects_replaces_the_target() {
+        let original = json!([{"pan": "4111"}]);
+        let updated = json!([{"pan": "enc:v1.1"}]);
+        assert_eq!(diff(&original, &updated), updated);
+        assert_eq!(apply(&original, &diff(&original, &updated)), updated);
+        assert_eq!(diff(&json!({"a": 1}), &json!({"a": 1})), json!({}));
+    }
+
+    #[test]
+    fn accept_header_matching() {
+        assert!(accepts_merge_patch("application/merge-patch+json"));
+        assert!(accepts_merge_patch(
+            "application/json;q=0.5, Application/Merge-Patch+JSON; q=1"
+        ));
+        assert!(!accepts_merge_patch("application/json"));
+        assert!(!accepts_merge_patch("*/*"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Decryption of password-protected PKCS#8 private keys (RFC 5958 / RFC 8018).
+//!
+//! Only the PBES2 scheme is supported, with PBKDF2-HMAC-SHA2 key derivation
+//! and AES-CBC encryption — what `openssl pkcs8 -topk8` produces by default.
+//! Older PBES1 schemes, SHA-1 PRFs and scrypt are rejected as unsupported
+//! rather than silently misread. The cryptography itself is the RustCrypto
+//! `pkcs8` crate's; this module only bounds the work a key file can demand.
+
+use pkcs8::pkcs5::{self, pbes2};
+use pkcs8::EncryptedPrivateKeyInfo;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Upper bound on PBKDF2 iterations, so a hostile key file cannot stall startup.
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+/// Errors produced while decrypting an encrypted PKCS#8 key.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Pkcs8Error {
+    /// The DER structure is not an `EncryptedPrivateKeyInfo`.
+    #[error("malformed encrypted private key")]
+    Malformed,
+    /// The key uses an encryption or key-derivation scheme not supported here.
+    #[error("unsupported key encryption scheme: {0}")]
+    Unsupported(String),
+    /// Decryption produced garbage: the passphrase is wrong or the key corrupt.
+    #[error("wrong passphrase or corrupt private key")]
+    Decrypt,
+}
+
+/// Decrypt a DER-encoded `EncryptedPrivateKeyInfo` with `passphrase`,
+/// returning the DER of the inner (unencrypted) PKCS#8 `PrivateKeyInfo`.
+///
+/// # Errors
+///
+/// See [`Pkcs8Error`].
+pub fn decrypt(der: &[u8], passphrase: &[u8]) -> Result<Zeroizing<Vec<u8>>, Pkcs8Error> {
+    let info = EncryptedPrivateKeyInfo::try_from(der).map_err(|_| Pkcs8Error::Malformed)?;
+    let pkcs5::EncryptionScheme::Pbes2(params) = &info.encryption_algorithm else {
+        let oid = info.encryption_algorithm.oid();
+        return Err(Pkcs8Error::Unsupported(oid.to_string()));
+    };
+    let Some(kdf) = params.kdf.pbkdf2() else {
+        return Err(Pkcs8Error::Unsupported(params.kdf.oid().to_string()));
+    };
+    if kdf.prf == pbes2::Pbkdf2Prf::HmacWithSha1 {
+        return Err(Pkcs8Error::Unsupported(format!(
+            "PBKDF2 PRF {}",
+            kdf.prf.oid()
+        )));
+    }
+    if kdf.iteration_count == 0 || kdf.iteration_count > MAX_ITERATIONS {
+        return Err(Pkcs8Error::Unsupported(format!(
+            "{} PBKDF2 iterations",
+            kdf.iteration_count
+        )));
+    }
+    // A wrong passphrase usually fails the padding check; the result must
+    // also parse as one DER document for the rare case where it does not.
+    match info.decrypt(passphrase) {
+        Ok(plain) => Ok(plain.to_bytes()),
+        Err(pkcs8::Error::EncryptedPrivateKey(pkcs5::Error::UnsupportedAlgorithm { oid })) => {
+            Err(Pkcs8Error::Unsupported(oid.to_string()))
+        }
+        Err(_) => Err(Pkcs8Error::Decrypt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pkcs8::der::Encode;
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert_eq!(decrypt(b"", b"pw"), Err(Pkcs8Error::Malformed));
+        assert_eq!(decrypt(&[0x30, 0x00], b"pw"), Err(Pkcs8Error::Malformed));
+    }
+
+    #[test]
+    fn excessive_iterations_are_rejected_before_deriving() {
+        let (salt, iv) = ([7u8; 16], [9u8; 16]);
+        let params =
+            pbes2::Parameters::pbkdf2_sha256_aes256cbc(MAX_ITERATIONS + 1, &salt, &iv).unwrap();
+        let der = EncryptedPrivateKeyInfo {
+            encryption_algorithm: params.into(),
+            encrypted_data: &[0u8; 16],
+        }
+        .to_der()
+        .unwrap();
+        assert_eq!(
+            decrypt(&der, b"pw"),
+            Err(Pkcs8Error::Unsupported(format!(
+                "{} PBKDF2 iterations",
+                MAX_ITERATIONS + 1
+            )))
+        );
+    }
+}
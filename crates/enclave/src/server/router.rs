@@ -1,29 +1,168 @@
 //! Axum router construction.
 
 use axum::{
+    body::HttpBody,
+    http::{header::CONTENT_LENGTH, Response},
     routing::{get, post},
     Router,
 };
-use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
+use tower_http::{
+    compression::{
+        predicate::{And, DefaultPredicate, NotForContentType},
+        CompressionLayer, Predicate,
+    },
+    sensitive_headers::{SetSensitiveRequestHeadersLayer, SetSensitiveResponseHeadersLayer},
+    timeout::TimeoutLayer,
+    trace::{DefaultMakeSpan, TraceLayer},
+};
 
 use super::{handlers, middleware, state::AppState};
 
 /// Build the application [`Router`] with all routes and middleware attached.
+///
+/// Request spans record headers, but values of the configured redacted
+/// headers are marked sensitive first and appear only as `Sensitive`.
 pub fn build(state: AppState) -> Router {
+    finish(
+        primary_routes()
+            .merge(read_only_routes())
+            .merge(admin_routes()),
+        state,
+    )
+}
+
+/// Build the [`Router`] for the main port when `READ_ONLY_PORT` is set: the
+/// routes that encrypt, decrypt or redact payloads, without the read-only and
+/// admin ones served by [`build_read_only`].
+pub fn build_primary(state: AppState) -> Router {
+    finish(primary_routes(), state)
+}
+
+/// Build the [`Router`] for `READ_ONLY_PORT`: health, previews, stats and
+/// schema lookups, plus the admin routes that change service state, sharing
+/// `state` with [`build_primary`]. The admin routes live here because this
+/// is the port that carries client certificates when mTLS is configured for
+/// either port.
+pub fn build_read_only(state: AppState) -> Router {
+    finish(read_only_routes().merge(admin_routes()), state)
+}
+
+/// Routes that transform payloads.
+fn primary_routes() -> Router<AppState> {
     Router::new()
         .route("/encrypt", post(handlers::encrypt))
+        .route("/encrypt/batch", post(handlers::encrypt_batch))
+        .route("/encrypt/stream", post(handlers::encrypt_stream))
+        .route("/encrypt/value", post(handlers::encrypt_value))
         .route("/decrypt", post(handlers::decrypt))
+        .route("/decrypt/value", post(handlers::decrypt_value))
+        .route("/redact", post(handlers::redact))
+        .route("/verify", post(handlers::verify))
+}
+
+/// Routes that only report on the service.
+fn read_only_routes() -> Router<AppState> {
+    Router::new()
         .route("/health", get(handlers::health))
-        .fallback(handlers::not_found)
-        .layer(TraceLayer::new_for_http())
+        .route("/readyz", get(handlers::health))
+        .route("/admin/schemas", get(handlers::list_schemas))
+        .route("/admin/schemas/by-path", get(handlers::schemas_with_path))
+        .route("/admin/decrypt/preview", post(handlers::decrypt_preview))
+        .route("/admin/stats", get(handlers::stats))
+}
+
+/// Admin routes that change service state. Every handler requires a client
+/// certificate CN listed in `ADMIN_CLIENT_CNS`.
+fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/admin/drain",
+            post(handlers::start_drain).delete(handlers::stop_drain),
+        )
+        .route("/admin/reload-schemas", post(handlers::reload_schemas))
+        .route(
+            "/admin/encryption",
+            get(handlers::encryption_settings).put(handlers::update_encryption_settings),
+        )
+}
+
+/// Attach the fallback and middleware shared by every router to `routes`.
+fn finish(routes: Router<AppState>, state: AppState) -> Router {
+    let redacted = state.settings.redacted_headers.clone();
+    let compression_max_bytes = state.settings.compression_max_bytes;
+    let routes = routes.fallback(handlers::not_found);
+    // Only pay for buffering bodies when checksums are to be verified.
+    let routes = if state.settings.verify_body_checksums {
+        routes.layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::verify_body_checksum,
+        ))
+    } else {
+        routes
+    };
+    routes
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::enforce_deadline,
+        ))
+        .layer(SetSensitiveResponseHeadersLayer::from_shared(
+            redacted.clone(),
+        ))
+        .layer(
+            TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::new().include_headers(true)),
+        )
+        .layer(SetSensitiveRequestHeadersLayer::from_shared(redacted))
         .layer(TimeoutLayer::new(middleware::REQUEST_TIMEOUT))
-        // No CompressionLayer: callers are internal services, not browsers.
-        // Compression forces Transfer-Encoding: chunked (no Content-Length),
-        // which prevents ab-style load testers from using keep-alive cleanly
-        // and adds CPU overhead for no gain on already-small JSON payloads.
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::record_response_size,
+        ))
+        .layer(compression_layer(compression_max_bytes))
         .with_state(state)
 }
 
+/// Predicate deciding which responses [`compression_layer`] compresses.
+type CompressionPredicate =
+    And<And<And<DefaultPredicate, NotForContentType>, NotForContentType>, NotAbove>;
+
+/// Refuses compression for bodies known to exceed a byte limit (`0`: none).
+/// Bodies of unknown length, such as streams, are not refused.
+#[derive(Debug, Clone, Copy)]
+struct NotAbove(u64);
+
+impl Predicate for NotAbove {
+    fn should_compress<B: HttpBody>(&self, response: &Response<B>) -> bool {
+        let size = response.body().size_hint().exact().or_else(|| {
+            response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok()?.parse().ok())
+        });
+        self.0 == 0 || size.is_none_or(|size| size <= self.0)
+    }
+}
+
+/// Response compression, negotiated via the request's `Accept-Encoding`.
+///
+/// Compression forces `Transfer-Encoding: chunked` (no `Content-Length`) and
+/// costs CPU, so it only applies when the caller asks for it; internal
+/// services and ab-style load testers that send no `Accept-Encoding` get
+/// identity responses with keep-alive intact. Binary content types that are
+/// already compact or encrypted (CBOR attestation documents, octet streams)
+/// are never re-compressed, on top of the tower-http defaults (tiny bodies,
+/// images, gRPC, SSE). An encoding the caller refuses with `q=0` is never
+/// chosen; when nothing acceptable remains the response is sent as identity.
+/// Bodies over `max_bytes` are sent uncompressed to bound the memory
+/// compression takes.
+fn compression_layer(max_bytes: u64) -> CompressionLayer<CompressionPredicate> {
+    CompressionLayer::new().compress_when(
+        DefaultPredicate::new()
+            .and(NotForContentType::const_new("application/cbor"))
+            .and(NotForContentType::const_new("application/octet-stream"))
+            .and(NotAbove(max_bytes)),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,6 +194,20 @@ mod tests {
         assert_eq!(resp.status(), 400);
     }
 
+    #[tokio::test]
+    async fn redact_route_exists() {
+        let app = build(AppState::default());
+        let req = Request::builder()
+            .method("POST")
+            .uri("/redact")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"payload":{}}"#))
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        // 400 because the X-Schema-Name header is absent.
+        assert_eq!(resp.status(), 400);
+    }
+
     #[tokio::test]
     async fn health_route_exists() {
         let app = build(AppState::default());
@@ -66,4 +219,250 @@ mod tests {
         // 503 because DEK and schemas are not loaded in the test state.
         assert_eq!(resp.status(), 503);
     }
+
+    #[tokio::test]
+    async fn admin_schemas_by_path_route_exists() {
+        let app = build(AppState::default());
+        let req = Request::builder()
+            .uri("/admin/schemas/by-path?path=account.iban")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        // 403 because the request carries no admin client certificate.
+        assert_eq!(resp.status(), 403);
+    }
+
+    #[tokio::test]
+    async fn primary_and_read_only_routers_are_disjoint() {
+        let primary = build_primary(AppState::default());
+        let read_only = build_read_only(AppState::default());
+        let all = build(AppState::default());
+        let routed = |router: &Router, method: &'static str, uri: &'static str| {
+            let router = router.clone();
+            async move {
+                let req = Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap();
+                let status = router.oneshot(req).await.unwrap().status();
+                status != 404 && status != 405
+            }
+        };
+
+        let primary_routes = [
+            ("POST", "/encrypt"),
+            ("POST", "/encrypt/batch"),
+            ("POST", "/decrypt"),
+            ("POST", "/redact"),
+            ("POST", "/verify"),
+        ];
+        // Admin routes need client certificates, so they share the port
+        // that has mTLS with the read-only ones.
+        let read_only_routes = [
+            ("GET", "/health"),
+            ("GET", "/readyz"),
+            ("GET", "/admin/stats"),
+            ("GET", "/admin/schemas"),
+            ("GET", "/admin/schemas/by-path?path=a"),
+            ("POST", "/admin/decrypt/preview"),
+            ("GET", "/admin/encryption"),
+            ("PUT", "/admin/encryption"),
+            ("POST", "/admin/drain"),
+            ("DELETE", "/admin/drain"),
+            ("POST", "/admin/reload-schemas"),
+        ];
+        for (method, uri) in primary_routes {
+            assert!(routed(&primary, method, uri).await, "{method} {uri}");
+            assert!(!routed(&read_only, method, uri).await, "{method} {uri}");
+            assert!(routed(&all, method, uri).await, "{method} {uri}");
+        }
+        for (method, uri) in read_only_routes {
+            assert!(routed(&read_only, method, uri).await, "{method} {uri}");
+            assert!(!routed(&primary, method, uri).await, "{method} {uri}");
+            assert!(routed(&all, method, uri).await, "{method} {uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn body_checksums_are_verified_only_when_enabled() {
+        use super::super::state::ServerSettings;
+
+        let send = |verify: bool| {
+            let app = build(AppState::default().with_settings(ServerSettings {
+                verify_body_checksums: verify,
+                ..ServerSettings::default()
+            }));
+            let req = Request::builder()
+                .uri("/health")
+                .header(middleware::CONTENT_MD5_HEADER, "AAAAAAAAAAAAAAAAAAAAAA==")
+                .body(Body::empty())
+                .unwrap();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        assert_ne!(send(false).await, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(send(true).await, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn redacted_header_values_never_reach_spans() {
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<u8>>>);
+        impl Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = build(AppState::default());
+        let req = Request::builder()
+            .uri("/health")
+            .header("authorization", "Bearer top-secret-token")
+            .header("x-tenant-id", "secret-tenant")
+            .header("x-schema-name", "visible-schema")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(req).await.unwrap();
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("visible-schema"), "{logs}");
+        assert!(!logs.contains("top-secret-token"), "{logs}");
+        assert!(!logs.contains("secret-tenant"), "{logs}");
+        assert!(logs.contains("Sensitive"), "{logs}");
+    }
+
+    #[tokio::test]
+    async fn compression_skips_binary_content_types() {
+        use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
+
+        let body = "x".repeat(1024);
+        let json = body.clone();
+        let app = Router::new()
+            .route(
+                "/cbor",
+                get(move || async move { ([(CONTENT_TYPE, "application/cbor")], body) }),
+            )
+            .route(
+                "/json",
+                get(move || async move { ([(CONTENT_TYPE, "application/json")], json) }),
+            )
+            .layer(compression_layer(0));
+        let encoding_of = |uri: &'static str| {
+            let app = app.clone();
+            let req = Request::builder()
+                .uri(uri)
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                resp.headers()
+                    .get(CONTENT_ENCODING)
+                    .map(|v| v.to_str().unwrap().to_owned())
+            }
+        };
+
+        assert_eq!(encoding_of("/cbor").await, None);
+        assert_eq!(encoding_of("/json").await.as_deref(), Some("gzip"));
+    }
+
+    #[tokio::test]
+    async fn compression_honours_zero_quality() {
+        use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
+
+        let body = "x".repeat(1024);
+        let app = Router::new()
+            .route(
+                "/json",
+                get(move || async move { ([(CONTENT_TYPE, "application/json")], body) }),
+            )
+            .layer(compression_layer(0));
+        let encoding_for = |accept: &'static str| {
+            let app = app.clone();
+            let req = Request::builder()
+                .uri("/json")
+                .header(ACCEPT_ENCODING, accept)
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let encoding = resp
+                    .headers()
+                    .get(CONTENT_ENCODING)
+                    .map(|v| v.to_str().unwrap().to_owned());
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (encoding, body.len())
+            }
+        };
+
+        assert_eq!(encoding_for("gzip;q=0").await, (None, 1024));
+        assert_eq!(encoding_for("gzip; q=0.000").await, (None, 1024));
+        assert_eq!(
+            encoding_for("gzip;q=0, deflate;q=0, br;q=0, zstd;q=0").await,
+            (None, 1024)
+        );
+        let (encoding, _) = encoding_for("gzip;q=0, deflate").await;
+        assert_eq!(encoding.as_deref(), Some("deflate"));
+    }
+
+    #[tokio::test]
+    async fn compression_skips_oversized_responses() {
+        use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
+
+        let large = "x".repeat(4096);
+        let small = "x".repeat(1024);
+        let app = Router::new()
+            .route(
+                "/large",
+                get(move || async move { ([(CONTENT_TYPE, "application/json")], large) }),
+            )
+            .route(
+                "/small",
+                get(move || async move { ([(CONTENT_TYPE, "application/json")], small) }),
+            )
+            .layer(compression_layer(2048));
+        let fetch = |uri: &'static str| {
+            let app = app.clone();
+            let req = Request::builder()
+                .uri(uri)
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let encoding = resp
+                    .headers()
+                    .get(CONTENT_ENCODING)
+                    .map(|v| v.to_str().unwrap().to_owned());
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (encoding, body.len())
+            }
+        };
+
+        assert_eq!(fetch("/large").await, (None, 4096));
+        let (encoding, len) = fetch("/small").await;
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        assert!(len < 1024);
+    }
 }
@@ -1,9 +1,22 @@
 //! Shared application state injected into every Axum handler.
 
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::dek::DekStore;
-use crate::schema::SchemaCache;
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use axum::http::{HeaderName, StatusCode};
+use common::protocol::EncryptionSettings;
+
+use super::identity::{ClientIdentity, SchemaAllowlist};
+use super::mask::MaskPolicy;
+use super::middleware::{redacted_headers, DEFAULT_REDACTED_HEADERS};
+use crate::config::Config;
+use crate::dek::{store::DekBytes, DekStore};
+use crate::schema::{SchemaCache, SchemaLoader};
+use crate::telemetry::audit::AuditLog;
 use crate::telemetry::Metrics;
 
 /// Application state shared across all request handlers.
@@ -14,12 +27,223 @@ use crate::telemetry::Metrics;
 pub struct AppState {
     /// Thread-safe store for the current Data Encryption Key.
     pub dek_store: DekStore,
+    /// Non-rotating key hash, lookup and format-preserving tokens are derived
+    /// from; `None` without `TOKEN_KEY_SECRET_ARN`.
+    pub token_key: Option<Arc<DekBytes>>,
     /// Lock-free cache of parsed OpenAPI schemas.
     pub schema_cache: SchemaCache,
     /// Name of the HTTP header used to identify the schema for each request.
     pub schema_header_name: Arc<String>,
     /// OTEL metric instruments recorded by request handlers.
     pub metrics: Arc<Metrics>,
+    /// Handler behaviour knobs derived from [`Config`].
+    pub settings: Arc<ServerSettings>,
+    /// Set by `POST /admin/drain`: readiness reports degraded so the load
+    /// balancer stops routing new traffic, while in-flight and new requests
+    /// continue to be served.
+    pub draining: Arc<AtomicBool>,
+    /// Encrypt/decrypt requests currently in progress; see
+    /// [`AppState::track_request`].
+    pub active_requests: Arc<AtomicUsize>,
+    /// Settings for newly encrypted data, swappable at runtime through
+    /// `PUT /admin/encryption`. Handlers load one snapshot per request so a
+    /// concurrent swap never mixes settings within a payload.
+    pub encryption: Arc<ArcSwap<EncryptionSettings>>,
+    /// Outcome of the latest vsock proxy probe; stays `true` when probing is
+    /// disabled.
+    pub proxy_reachable: Arc<AtomicBool>,
+    /// Queue of audit events for successful PII-touching requests;
+    /// disabled unless `AUDIT_QUEUE_CAPACITY` is set.
+    pub audit: AuditLog,
+    /// Reloads individual schema sources for `POST /admin/reload-schemas`;
+    /// `None` when no AWS clients are available (tests).
+    pub schema_loader: Option<SchemaLoader>,
+}
+
+/// Counts one in-progress request in [`AppState::active_requests`] until dropped.
+#[derive(Debug)]
+pub struct ActiveRequest(Arc<AtomicUsize>);
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Request-handling settings derived from the service [`Config`].
+///
+/// Kept separate from [`Config`] so handlers (and tests) do not need the
+/// AWS/TLS fields that are irrelevant to request processing.
+#[derive(Debug, Clone)]
+pub struct ServerSettings {
+    /// Minimum number of cached schemas required for readiness.
+    pub min_schemas_for_ready: usize,
+    /// Whether readiness requires a loaded DEK.
+    pub require_dek_for_ready: bool,
+    /// Per-client-identity schema grants; `None` allows every client every schema.
+    pub schema_allowlist: Option<SchemaAllowlist>,
+    /// `Retry-After` seconds sent with not-ready 503 responses.
+    pub retry_after_secs: u64,
+    /// Status for requests naming an unknown schema (`400` or `404`).
+    pub unknown_schema_status: StatusCode,
+    /// Whether requests must carry an `X-Tenant-Id` header.
+    pub require_tenant: bool,
+    /// Whether payloads must match the schema's root kind.
+    pub enforce_payload_root: bool,
+    /// Whether payloads may only carry the schema's declared top-level keys.
+    pub reject_unknown_top_level_keys: bool,
+    /// Whether `/decrypt` audits payloads for reused nonces.
+    pub audit_nonce_reuse: bool,
+    /// Byte limit for PII string values without a schema `maxLength`.
+    pub max_field_bytes: usize,
+    /// Replacement for PII values in `/redact` responses.
+    pub redaction_marker: String,
+    /// PII leaves one payload may carry (`0` for no limit).
+    pub max_encrypted_fields: usize,
+    /// Items an array on a PII path may hold (`0` for no limit).
+    pub max_array_items: usize,
+    /// Client CNs holding the admin role (`/admin` routes).
+    pub admin_identities: HashSet<String>,
+    /// Masking applied by the decrypt preview.
+    pub mask_policy: MaskPolicy,
+    /// Whether `/encrypt` accepts inline `pii_paths`.
+    pub allow_inline_schema: bool,
+    /// Whether `/encrypt/value` and `/decrypt/value` are served.
+    pub allow_value_endpoint: bool,
+    /// Age of the last successful schema refresh beyond which schemas count
+    /// as stale; `None` disables the check.
+    pub max_schema_staleness: Option<Duration>,
+    /// Whether ciphertexts embed a prefix of the schema fingerprint.
+    pub schema_tag_ciphertext: bool,
+    /// PII strings shorter than this (in characters) are not encrypted.
+    pub min_encrypt_len: usize,
+    /// Whether a PII path holding a non-scalar value fails the request.
+    pub strict_leaf_types: bool,
+    /// Reserved schema name served as an always-available pass-through.
+    pub noop_schema: Option<String>,
+    /// Request and response headers whose values are redacted from spans.
+    pub redacted_headers: Arc<[HeaderName]>,
+    /// Body size above which responses are not compressed (`0` for no limit).
+    pub compression_max_bytes: u64,
+    /// Whether request bodies sent with a checksum header are verified.
+    pub verify_body_checksums: bool,
+    /// How long `/encrypt` waits for readiness before answering `503`.
+    pub encrypt_ready_wait: Option<Duration>,
+}
+
+impl ServerSettings {
+    /// Extract the handler settings from the service configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client schema allowlist, mask rules or redacted
+    /// header names cannot be parsed.
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let schema_allowlist = cfg
+            .client_schema_allowlist
+            .as_deref()
+            .map(SchemaAllowlist::parse)
+            .transpose()?;
+        let admin_identities = cfg
+            .admin_client_cns
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|cn| !cn.is_empty())
+            .map(str::to_owned)
+            .collect();
+        let mask_policy = cfg
+            .mask_rules
+            .as_deref()
+            .map(MaskPolicy::parse)
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Self {
+            min_schemas_for_ready: cfg.min_schemas_for_ready,
+            require_dek_for_ready: cfg.require_dek_for_ready,
+            schema_allowlist,
+            retry_after_secs: cfg.retry_after_secs,
+            unknown_schema_status: StatusCode::from_u16(cfg.unknown_schema_status)?,
+            require_tenant: cfg.require_tenant,
+            enforce_payload_root: cfg.enforce_payload_root,
+            reject_unknown_top_level_keys: cfg.reject_unknown_top_level_keys,
+            audit_nonce_reuse: cfg.audit_nonce_reuse,
+            max_field_bytes: cfg.max_field_bytes,
+            redaction_marker: cfg.redaction_marker.clone(),
+            max_encrypted_fields: cfg.max_encrypted_fields,
+            max_array_items: cfg.max_array_items,
+            admin_identities,
+            mask_policy,
+            allow_inline_schema: cfg.allow_inline_schema,
+            allow_value_endpoint: cfg.allow_value_endpoint,
+            max_schema_staleness: (cfg.max_schema_staleness_secs > 0)
+                .then(|| Duration::from_secs(cfg.max_schema_staleness_secs)),
+            schema_tag_ciphertext: cfg.schema_tag_ciphertext,
+            min_encrypt_len: cfg.min_encrypt_len,
+            strict_leaf_types: cfg.strict_pii_leaf_types,
+            noop_schema: cfg.noop_schema_name.clone(),
+            redacted_headers: redacted_headers(cfg.redacted_headers.as_deref())?.into(),
+            compression_max_bytes: cfg.compression_max_response_bytes,
+            verify_body_checksums: cfg.verify_body_checksums,
+            encrypt_ready_wait: (cfg.encrypt_ready_wait_ms > 0)
+                .then(|| Duration::from_millis(cfg.encrypt_ready_wait_ms)),
+        })
+    }
+
+    /// Whether `identity` holds the admin role.
+    pub fn is_admin(&self, identity: Option<&ClientIdentity>) -> bool {
+        identity.is_some_and(|id| self.admin_identities.contains(&id.0))
+    }
+
+    /// Whether schemas last refreshed `staleness` ago (`None`: never) are too
+    /// old to serve. Never-loaded schemas are handled by readiness instead.
+    pub fn schemas_stale(&self, staleness: Option<Duration>) -> bool {
+        matches!((self.max_schema_staleness, staleness), (Some(max), Some(age)) if age > max)
+    }
+
+    /// Whether the service should report ready given the current DEK and
+    /// schema cache state.
+    pub fn is_ready(&self, dek_ready: bool, schemas_loaded: usize) -> bool {
+        (dek_ready || !self.require_dek_for_ready) && schemas_loaded >= self.min_schemas_for_ready
+    }
+}
+
+impl Default for ServerSettings {
+    /// Matches the [`Config`] defaults: a DEK and at least one schema are required.
+    fn default() -> Self {
+        Self {
+            min_schemas_for_ready: 1,
+            require_dek_for_ready: true,
+            schema_allowlist: None,
+            retry_after_secs: 5,
+            unknown_schema_status: StatusCode::BAD_REQUEST,
+            require_tenant: false,
+            enforce_payload_root: true,
+            reject_unknown_top_level_keys: false,
+            audit_nonce_reuse: false,
+            max_field_bytes: 64 * 1024,
+            redaction_marker: "[REDACTED]".into(),
+            max_encrypted_fields: 0,
+            max_array_items: 100_000,
+            admin_identities: HashSet::new(),
+            mask_policy: MaskPolicy::default(),
+            allow_inline_schema: false,
+            allow_value_endpoint: false,
+            max_schema_staleness: None,
+            schema_tag_ciphertext: false,
+            min_encrypt_len: 0,
+            strict_leaf_types: false,
+            noop_schema: None,
+            redacted_headers: DEFAULT_REDACTED_HEADERS
+                .iter()
+                .map(|name| HeaderName::from_static(name))
+                .collect(),
+            compression_max_bytes: 8 * 1024 * 1024,
+            verify_body_checksums: false,
+            encrypt_ready_wait: None,
+        }
+    }
 }
 
 impl AppState {
@@ -32,11 +256,56 @@ impl AppState {
     ) -> Self {
         Self {
             dek_store,
+            token_key: None,
             schema_cache,
             schema_header_name: Arc::new(schema_header_name),
             metrics,
+            settings: Arc::new(ServerSettings::default()),
+            draining: Arc::new(AtomicBool::new(false)),
+            active_requests: Arc::new(AtomicUsize::new(0)),
+            encryption: Arc::new(ArcSwap::from_pointee(EncryptionSettings::default())),
+            proxy_reachable: Arc::new(AtomicBool::new(true)),
+            audit: AuditLog::default(),
+            schema_loader: None,
         }
     }
+
+    /// Count a request as active for as long as the returned guard lives.
+    pub fn track_request(&self) -> ActiveRequest {
+        self.active_requests.fetch_add(1, Ordering::Relaxed);
+        ActiveRequest(Arc::clone(&self.active_requests))
+    }
+
+    /// Replace the handler settings (defaults to [`ServerSettings::default`]).
+    pub fn with_settings(mut self, settings: ServerSettings) -> Self {
+        self.settings = Arc::new(settings);
+        self
+    }
+
+    /// Derive hash, lookup and format-preserving tokens from `key`.
+    pub fn with_token_key(mut self, key: DekBytes) -> Self {
+        self.token_key = Some(Arc::new(key));
+        self
+    }
+
+    /// Enable on-demand schema source reloads through `loader`.
+    pub fn with_schema_loader(mut self, loader: SchemaLoader) -> Self {
+        self.schema_loader = Some(loader);
+        self
+    }
+
+    /// Record audit events through `audit`.
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Replace the initial encryption settings (defaults to
+    /// [`EncryptionSettings::default`]).
+    pub fn with_encryption(self, encryption: EncryptionSettings) -> Self {
+        self.encryption.store(Arc::new(encryption));
+        self
+    }
 }
 
 impl Default for AppState {
@@ -51,3 +320,62 @@ impl Default for AppState {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_requests_tracked_until_guard_dropped() {
+        let state = AppState::default();
+        let first = state.track_request();
+        let second = state.track_request();
+        assert_eq!(state.active_requests.load(Ordering::Relaxed), 2);
+        drop(first);
+        assert_eq!(state.active_requests.load(Ordering::Relaxed), 1);
+        drop(second);
+        assert_eq!(state.active_requests.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn default_readiness_requires_dek_and_one_schema() {
+        let s = ServerSettings::default();
+        assert!(s.is_ready(true, 1));
+        assert!(!s.is_ready(false, 1));
+        assert!(!s.is_ready(true, 0));
+    }
+
+    #[test]
+    fn schemas_stale_only_beyond_threshold() {
+        let s = ServerSettings {
+            max_schema_staleness: Some(Duration::from_secs(600)),
+            ..ServerSettings::default()
+        };
+        assert!(!s.schemas_stale(Some(Duration::from_secs(599))));
+        assert!(s.schemas_stale(Some(Duration::from_secs(601))));
+        assert!(!s.schemas_stale(None));
+        assert!(!ServerSettings::default().schemas_stale(Some(Duration::MAX)));
+    }
+
+    #[test]
+    fn readiness_without_schemas() {
+        let s = ServerSettings {
+            min_schemas_for_ready: 0,
+            ..ServerSettings::default()
+        };
+        assert!(s.is_ready(true, 0));
+        assert!(!s.is_ready(false, 0));
+    }
+
+    #[test]
+    fn readiness_without_dek() {
+        let s = ServerSettings {
+            require_dek_for_ready: false,
+            min_schemas_for_ready: 3,
+            ..ServerSettings::default()
+        };
+        assert!(s.is_ready(false, 3));
+        assert!(!s.is_ready(false, 2));
+        assert!(s.is_ready(true, 5));
+    }
+}
@@ -0,0 +1,447 @@
+//! Streaming transformation of JSON payloads.
+//!
+//! [`transform`] copies a JSON document from input to output in a single pass,
+//! rewriting only the scalar leaves that sit at a PII path. No
+//! `serde_json::Value` tree is built: keys are decoded only where a PII path
+//! could still descend, and subtrees outside every path are validated and
+//! copied through verbatim. Peak memory is the output buffer plus one leaf.
+//!
+//! Whitespace between tokens is dropped, so the output is compact JSON that
+//! parses to the same value the buffered path produces.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+/// Maximum nesting depth, matching `serde_json`'s recursion limit.
+const MAX_DEPTH: usize = 128;
+
+/// The PII paths of a schema, arranged for matching during a single pass.
+///
+/// Paths use the same dot notation as the rest of the service, with `[]`
+/// marking array items (`"orders[].card_number"`).
+#[derive(Debug, Default)]
+pub struct PathTrie {
+    root: Node,
+    /// Items an array on a path may hold (`0` for no limit).
+    max_array_items: usize,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    /// Children reached through an object key.
+    keys: HashMap<String, Node>,
+    /// Child reached through any array item.
+    items: Option<Box<Node>>,
+    /// The full path when a PII path ends here.
+    leaf: Option<String>,
+}
+
+impl PathTrie {
+    /// Build a trie from dot-notation PII paths.
+    pub fn new<'a>(paths: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut root = Node::default();
+        for path in paths {
+            let mut node = &mut root;
+            for part in path.split('.') {
+                let (key, item) = match part.strip_suffix("[]") {
+                    Some(key) => (key, true),
+                    None => (part, false),
+                };
+                node = node.keys.entry(key.to_owned()).or_default();
+                if item {
+                    node = node.items.get_or_insert_with(Box::default);
+                }
+            }
+            node.leaf = Some(path.clone());
+        }
+        Self {
+            root,
+            max_array_items: 0,
+        }
+    }
+
+    /// Fail [`transform`] on any array on a path holding more than `max`
+    /// items (`0` for no limit). Arrays outside every path are not counted.
+    pub fn with_max_array_items(mut self, max: usize) -> Self {
+        self.max_array_items = max;
+        self
+    }
+}
+
+/// A scalar found at a PII path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Leaf<'a> {
+    /// A string value, unescaped.
+    String(&'a str),
+    /// A number value, as its exact JSON token.
+    Number(&'a str),
+}
+
+/// Errors produced by [`transform`].
+#[derive(Debug, Error)]
+pub enum StreamError<E> {
+    /// The input is not a single well-formed JSON document.
+    #[error("invalid JSON at byte {0}")]
+    Syntax(usize),
+    /// The input nests deeper than the recursion limit.
+    #[error("JSON nested more than {MAX_DEPTH} levels deep")]
+    TooDeep,
+    /// An array on a path holds more items than the trie allows.
+    #[error("array has more than {0} items")]
+    ArrayTooLarge(usize),
+    /// The leaf callback failed.
+    #[error(transparent)]
+    Leaf(E),
+}
+
+/// The JSON kind (`"object"`, `"array"`, `"string"`, `"number"`, `"boolean"`
+/// or `"null"`) of the document in `input`, judged from its first token.
+pub fn root_kind(input: &[u8]) -> Option<&'static str> {
+    let first = input.iter().find(|b| !b.is_ascii_whitespace())?;
+    Some(match first {
+        b'{' => "object",
+        b'[' => "array",
+        b'"' => "string",
+        b't' | b'f' => "boolean",
+        b'n' => "null",
+        _ => "number",
+    })
+}
+
+/// Copy the JSON document in `input`, passing every string or number leaf at
+/// a path in `trie` to `on_leaf`. When it returns a replacement, the leaf is
+/// written as that JSON string; otherwise the leaf is copied unchanged.
+///
+/// # Errors
+///
+/// Returns [`StreamError::Syntax`] or [`StreamError::TooDeep`] for malformed
+/// input, [`StreamError::ArrayTooLarge`] for an array over the trie's limit,
+/// and [`StreamError::Leaf`] with the first error from `on_leaf`.
+pub fn transform<E, F>(input: &[u8], trie: &PathTrie, on_leaf: F) -> Result<Vec<u8>, StreamError<E>>
+where
+    F: FnMut(&str, Leaf<'_>) -> Result<Option<String>, E>,
+{
+    let text = std::str::from_utf8(input).map_err(|e| StreamError::Syntax(e.valid_up_to()))?;
+    let mut t = Transcoder {
+        input: text,
+        pos: 0,
+        out: Vec::with_capacity(input.len() + input.len() / 4),
+        max_array_items: trie.max_array_items,
+        on_leaf,
+    };
+    t.value(Some(&trie.root), 0)?;
+    t.skip_whitespace();
+    if t.pos != input.len() {
+        return Err(StreamError::Syntax(t.pos));
+    }
+    Ok(t.out)
+}
+
+struct Transcoder<'a, F> {
+    input: &'a str,
+    pos: usize,
+    out: Vec<u8>,
+    max_array_items: usize,
+    on_leaf: F,
+}
+
+impl<'a, E, F> Transcoder<'a, F>
+where
+    F: FnMut(&str, Leaf<'_>) -> Result<Option<String>, E>,
+{
+    fn bytes(&self) -> &'a [u8] {
+        self.input.as_bytes()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), StreamError<E>> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(StreamError::Syntax(self.pos));
+        }
+        self.pos += 1;
+        self.out.push(byte);
+        Ok(())
+    }
+
+    /// Copy one value; `node` is the trie position, `None` outside every path.
+    fn value(&mut self, node: Option<&Node>, depth: usize) -> Result<(), StreamError<E>> {
+        if depth > MAX_DEPTH {
+            return Err(StreamError::TooDeep);
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(node, depth),
+            Some(b'[') => self.array(node, depth),
+            Some(b'"') => {
+                let (start, end) = self.string()?;
+                self.scalar(node, start, end, true)
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let (start, end) = self.number()?;
+                self.scalar(node, start, end, false)
+            }
+            Some(b't') => self.literal("true"),
+            Some(b'f') => self.literal("false"),
+            Some(b'n') => self.literal("null"),
+            _ => Err(StreamError::Syntax(self.pos)),
+        }
+    }
+
+    fn object(&mut self, node: Option<&Node>, depth: usize) -> Result<(), StreamError<E>> {
+        self.expect(b'{')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            return self.expect(b'}');
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(StreamError::Syntax(self.pos));
+            }
+            let (start, end) = self.string()?;
+            let child = match node {
+                Some(node) if !node.keys.is_empty() => {
+                    let key = self.decode(start, end)?;
+                    node.keys.get(&key)
+                }
+                _ => None,
+            };
+            self.out.extend_from_slice(&self.bytes()[start..end]);
+            self.expect(b':')?;
+            self.value(child, depth + 1)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.expect(b',')?,
+                Some(b'}') => return self.expect(b'}'),
+                _ => return Err(StreamError::Syntax(self.pos)),
+            }
+        }
+    }
+
+    fn array(&mut self, node: Option<&Node>, depth: usize) -> Result<(), StreamError<E>> {
+        self.expect(b'[')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            return self.expect(b']');
+        }
+        let child = node.and_then(|n| n.items.as_deref());
+        let mut items = 0usize;
+        loop {
+            items += 1;
+            if child.is_some() && self.max_array_items != 0 && items > self.max_array_items {
+                return Err(StreamError::ArrayTooLarge(self.max_array_items));
+            }
+            self.value(child, depth + 1)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.expect(b',')?,
+                Some(b']') => return self.expect(b']'),
+                _ => return Err(StreamError::Syntax(self.pos)),
+            }
+        }
+    }
+
+    /// Emit the scalar token at `start..end`, replacing it if it is a leaf.
+    fn scalar(
+        &mut self,
+        node: Option<&Node>,
+        start: usize,
+        end: usize,
+        string: bool,
+    ) -> Result<(), StreamError<E>> {
+        if let Some(path) = node.and_then(|n| n.leaf.as_deref()) {
+            let replacement = if string {
+                let decoded = self.decode(start, end)?;
+                (self.on_leaf)(path, Leaf::String(&decoded))
+            } else {
+                (self.on_leaf)(path, Leaf::Number(&self.input[start..end]))
+            }
+            .map_err(StreamError::Leaf)?;
+            if let Some(replacement) = replacement {
+                let encoded =
+                    serde_json::to_vec(&replacement).map_err(|_| StreamError::Syntax(start))?;
+                self.out.extend_from_slice(&encoded);
+                return Ok(());
+            }
+        }
+        self.out.extend_from_slice(&self.bytes()[start..end]);
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str) -> Result<(), StreamError<E>> {
+        if !self.input[self.pos..].starts_with(word) {
+            return Err(StreamError::Syntax(self.pos));
+        }
+        self.out.extend_from_slice(word.as_bytes());
+        self.pos += word.len();
+        Ok(())
+    }
+
+    /// Scan a string token, returning its span including the quotes.
+    fn string(&mut self) -> Result<(usize, usize), StreamError<E>> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok((start, self.pos));
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => {
+                            self.pos += 1;
+                        }
+                        Some(b'u') => {
+                            let hex = self.bytes().get(self.pos + 1..self.pos + 5);
+                            if !hex.is_some_and(|h| h.iter().all(u8::is_ascii_hexdigit)) {
+                                return Err(StreamError::Syntax(self.pos));
+                            }
+                            self.pos += 5;
+                        }
+                        _ => return Err(StreamError::Syntax(self.pos)),
+                    }
+                }
+                Some(0x00..=0x1f) | None => return Err(StreamError::Syntax(self.pos)),
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Scan a number token per the JSON grammar, returning its span.
+    fn number(&mut self) -> Result<(usize, usize), StreamError<E>> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(StreamError::Syntax(self.pos)),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.required_digits()?;
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            self.required_digits()?;
+        }
+        Ok((start, self.pos))
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+    }
+
+    fn required_digits(&mut self) -> Result<(), StreamError<E>> {
+        if !self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            return Err(StreamError::Syntax(self.pos));
+        }
+        self.digits();
+        Ok(())
+    }
+
+    /// Unescape the string token at `start..end`.
+    fn decode(&self, start: usize, end: usize) -> Result<String, StreamError<E>> {
+        let token = &self.input[start..end];
+        if !token.contains('\\') {
+            return Ok(token[1..token.len() - 1].to_owned());
+        }
+        serde_json::from_str(token).map_err(|_| StreamError::Syntax(start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    fn upper(input: &str, paths: &[&str]) -> Result<serde_json::Value, StreamError<Infallible>> {
+        let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+        let trie = PathTrie::new(&paths);
+        let out = transform(input.as_bytes(), &trie, |_, leaf| {
+            Ok(Some(match leaf {
+                Leaf::String(s) => s.to_uppercase(),
+                Leaf::Number(n) => format!("#{n}"),
+            }))
+        })?;
+        Ok(serde_json::from_slice(&out).unwrap())
+    }
+
+    #[test]
+    fn rewrites_only_leaves_on_paths() {
+        let out = upper(
+            r#"{ "name": "ann", "ab": "esc", "orders": [ {"card": "x", "n": 1.5e3},
+               {"card": "y"} ], "other": {"card": "z"}, "tags": ["p", "q"] }"#,
+            &["name", "ab", "orders[].card", "orders[].n", "tags[]"],
+        )
+        .unwrap();
+        assert_eq!(
+            out,
+            serde_json::json!({
+                "name": "ANN", "ab": "ESC",
+                "orders": [{"card": "X", "n": "#1.5e3"}, {"card": "Y"}],
+                "other": {"card": "z"}, "tags": ["P", "Q"]
+            })
+        );
+    }
+
+    #[test]
+    fn dotted_keys_do_not_match_nested_paths() {
+        let out = upper(r#"{"a.b":"flat","a":{"b":"nested"}}"#, &["a.b"]).unwrap();
+        assert_eq!(
+            out,
+            serde_json::json!({"a.b": "flat", "a": {"b": "NESTED"}})
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for bad in [
+            "",
+            "{",
+            r#"{"a" 1}"#,
+            r#"{"a":1,}"#,
+            "[1 2]",
+            "01",
+            "1.",
+            r#""\x""#,
+            "tru",
+            "{} {}",
+            "\"a\u{1}\"",
+        ] {
+            assert!(
+                matches!(upper(bad, &["a"]), Err(StreamError::Syntax(_))),
+                "{bad:?}"
+            );
+        }
+        let deep = "[".repeat(MAX_DEPTH + 2) + &"]".repeat(MAX_DEPTH + 2);
+        assert!(matches!(upper(&deep, &[]), Err(StreamError::TooDeep)));
+    }
+
+    #[test]
+    fn root_kind_reads_first_token() {
+        assert_eq!(root_kind(b"  {}"), Some("object"));
+        assert_eq!(root_kind(b"[1]"), Some("array"));
+        assert_eq!(root_kind(b"-1"), Some("number"));
+        assert_eq!(root_kind(b" "), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Audit events for successful `/encrypt` and `/decrypt` requests.
+//!
+//! Handlers hand each event to an [`AuditLog`], which queues it on a bounded
+//! channel. A dedicated task ([`writer_task`]) drains the queue and emits each
+//! event as an `audit` log record. Writing a log record can block on a slow
+//! sink, so it never happens on the request path. When the queue is full the
+//! event is dropped and counted in `enclave_audit_events_dropped`; the request
+//! does not wait.
+//!
+//! Events carry schema names and identities only, never payload contents.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// One audited request.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// The endpoint: `"encrypt"`, `"encrypt_batch"`, `"encrypt_stream"`,
+    /// `"encrypt_value"`, `"decrypt"`, `"decrypt_value"` or `"decrypt_preview"`.
+    pub action: &'static str,
+    /// Schema named by the request; `None` for inline PII paths and the
+    /// value endpoints.
+    pub schema: Option<String>,
+    /// Client certificate CN, when the client presented one.
+    pub client: Option<String>,
+    /// `X-Tenant-Id` of the request, if any.
+    pub tenant: Option<String>,
+}
+
+impl AuditEvent {
+    /// Emit the event as a single `audit` log record.
+    pub fn emit(&self) {
+        let event = serde_json::to_string(self).unwrap_or_default();
+        info!(event = "audit", audit = %event, "audit event");
+    }
+}
+
+/// Producer side of the audit queue. Clones share the queue and the drop
+/// counter; the default log is disabled and records nothing.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    tx: Option<mpsc::Sender<AuditEvent>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+    /// A log queueing up to `capacity` events (at least one), counting
+    /// dropped events in `dropped`, and the receiver for [`writer_task`].
+    pub fn channel(capacity: usize, dropped: Arc<AtomicU64>) -> (Self, mpsc::Receiver<AuditEvent>) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        (
+            Self {
+                tx: Some(tx),
+                dropped,
+            },
+            rx,
+        )
+    }
+
+    /// Queue `event` without waiting. It is dropped and counted when the
+    /// queue is full or the writer has stopped.
+    pub fn record(&self, event: AuditEvent) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if tx.try_send(event).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Spawn the task that emits every event queued on `rx`, until all
+/// [`AuditLog`] handles are gone.
+pub fn writer_task(mut rx: mpsc::Receiver<AuditEvent>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            event.emit();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(n: usize) -> AuditEvent {
+        AuditEvent {
+            action: "encrypt",
+            schema: Some(format!("payments-v{n}")),
+            client: Some("billing".into()),
+            tenant: None,
+        }
+    }
+
+    #[test]
+    fn full_queue_drops_and_counts_without_blocking() {
+        let dropped = Arc::new(AtomicU64::new(0));
+        let (log, mut rx) = AuditLog::channel(2, Arc::clone(&dropped));
+        // No writer is draining the queue; `record` must still return.
+        for n in 0..5 {
+            log.record(event(n));
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(rx.try_recv().unwrap(), event(0));
+        assert_eq!(rx.try_recv().unwrap(), event(1));
+        assert!(rx.try_recv().is_err());
+
+        // Room again once the writer catches up; a stopped writer drops.
+        log.record(event(5));
+        assert_eq!(rx.try_recv().unwrap(), event(5));
+        drop(rx);
+        log.record(event(6));
+        assert_eq!(dropped.load(Ordering::Relaxed), 4);
+
+        let disabled = AuditLog::default();
+        disabled.record(event(7));
+        assert_eq!(disabled.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn writer_drains_until_every_log_is_dropped() {
+        let (log, rx) = AuditLog::channel(8, Arc::default());
+        let writer = writer_task(rx);
+        log.record(event(0));
+        drop(log);
+        writer.await.unwrap();
+    }
+}
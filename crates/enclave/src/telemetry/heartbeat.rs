@@ -0,0 +1,117 @@
+//! Liveness of background tasks.
+//!
+//! A task that panics is dropped without a trace and never runs again, while
+//! the service keeps serving. Each background task therefore holds a
+//! [`Heartbeat`] and beats it once per tick, announcing when the next beat is
+//! due. A task that misses its beat by a full period is reported as stalled by
+//! `/health` and the `enclave_background_task_stalled` gauge.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Heartbeat of one background task. Clones share the same state.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    name: &'static str,
+    origin: Instant,
+    /// Milliseconds after `origin` beyond which the task counts as stalled;
+    /// `u64::MAX` until the first beat.
+    deadline_ms: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    /// A heartbeat for the task `name` that has not beaten yet.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            origin: Instant::now(),
+            deadline_ms: Arc::new(AtomicU64::new(u64::MAX)),
+        }
+    }
+
+    /// Record that the task is alive and will beat again within `next_in`.
+    ///
+    /// The task is stalled once `next_in` has passed twice over without a
+    /// beat, leaving a whole period for the work done between beats.
+    pub fn beat(&self, next_in: Duration) {
+        let deadline = self
+            .elapsed_ms(Instant::now())
+            .saturating_add(millis(next_in).saturating_mul(2));
+        self.deadline_ms.store(deadline, Ordering::Relaxed);
+    }
+
+    /// Whether the task has missed its beat as of `now`.
+    pub fn is_stalled_at(&self, now: Instant) -> bool {
+        self.elapsed_ms(now) > self.deadline_ms.load(Ordering::Relaxed)
+    }
+
+    fn elapsed_ms(&self, now: Instant) -> u64 {
+        millis(now.saturating_duration_since(self.origin))
+    }
+}
+
+fn millis(d: Duration) -> u64 {
+    u64::try_from(d.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// The heartbeats of every registered background task.
+#[derive(Debug, Default)]
+pub struct TaskHeartbeats(Mutex<Vec<Heartbeat>>);
+
+impl TaskHeartbeats {
+    /// Register the task `name` and return the heartbeat it should beat.
+    pub fn register(&self, name: &'static str) -> Heartbeat {
+        let heartbeat = Heartbeat::new(name);
+        if let Ok(mut tasks) = self.0.lock() {
+            tasks.push(heartbeat.clone());
+        }
+        heartbeat
+    }
+
+    /// Every registered task with whether it is stalled as of `now`.
+    pub fn statuses(&self, now: Instant) -> Vec<(&'static str, bool)> {
+        self.0
+            .lock()
+            .map(|tasks| {
+                tasks
+                    .iter()
+                    .map(|task| (task.name, task.is_stalled_at(now)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Names of the tasks that are stalled as of `now`.
+    pub fn stalled(&self, now: Instant) -> Vec<&'static str> {
+        self.statuses(now)
+            .into_iter()
+            .filter_map(|(name, stalled)| stalled.then_some(name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn task_stalls_once_a_full_period_late() {
+        let tasks = TaskHeartbeats::default();
+        let heartbeat = tasks.register("schema_refresh");
+        // Not stalled before the task has started.
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        assert!(tasks.stalled(Instant::now()).is_empty());
+
+        heartbeat.beat(Duration::from_secs(60));
+        tokio::time::advance(Duration::from_secs(119)).await;
+        assert!(tasks.stalled(Instant::now()).is_empty());
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(tasks.stalled(Instant::now()), ["schema_refresh"]);
+
+        heartbeat.beat(Duration::from_secs(60));
+        assert_eq!(tasks.statuses(Instant::now()), [("schema_refresh", false)]);
+    }
+}
@@ -1,12 +1,15 @@
-//! OTEL SDK initialisation: tracing subscriber + OTLP exporter via vsock.
+//! OTEL SDK initialisation: tracing subscriber + OTLP exporters via vsock.
 
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use opentelemetry::KeyValue;
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
 use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::{runtime, Resource};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use opentelemetry_sdk::{logs::LoggerProvider, runtime, Resource};
+use tracing_subscriber::{
+    filter::filter_fn, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
 
 use super::log_writer::SharedTcpWriter;
 
@@ -20,6 +23,8 @@ use super::log_writer::SharedTcpWriter;
 /// - A [`tracing_opentelemetry`] layer exporting spans via OTLP to the collector.
 /// - An OTLP metrics pipeline exporting to the same endpoint every 15 s (registered
 ///   as the global [`opentelemetry::global`] meter provider).
+/// - When `logs_enabled`, an OTLP logs pipeline bridging every log record to the
+///   same endpoint (see [`logs_provider`]).
 ///
 /// The `log_writer` is `None` when the log bridge TCP socket could not be connected
 /// (e.g., ADOT Collector not yet running on parent); in that case only stderr is used.
@@ -30,6 +35,7 @@ use super::log_writer::SharedTcpWriter;
 pub fn init_telemetry(
     otlp_endpoint: &str,
     log_level: &str,
+    logs_enabled: bool,
     log_writer: Option<SharedTcpWriter>,
 ) -> Result<()> {
     // --- Metrics pipeline ---
@@ -62,6 +68,22 @@ pub fn init_telemetry(
 
     let otel_layer = tracing_opentelemetry::layer().with_tracer(tracer);
 
+    // --- Logs pipeline ---
+    // Records emitted by the exporter's own transport are dropped so a failing
+    // export cannot feed back into itself.
+    let logs_layer = if logs_enabled {
+        let provider = logs_provider(otlp_endpoint)?;
+        Some(
+            OpenTelemetryTracingBridge::new(&provider).with_filter(filter_fn(|meta| {
+                !["h2", "hyper", "tonic", "tower"]
+                    .iter()
+                    .any(|prefix| meta.target().starts_with(prefix))
+            })),
+        )
+    } else {
+        None
+    };
+
     // --- Subscriber ---
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
@@ -69,7 +91,8 @@ pub fn init_telemetry(
     let registry = tracing_subscriber::registry()
         .with(env_filter)
         .with(tracing_subscriber::fmt::layer().json())
-        .with(otel_layer);
+        .with(otel_layer)
+        .with(logs_layer);
 
     if let Some(writer) = log_writer {
         // Second JSON layer forwarding log records to the ADOT Collector tcplog receiver.
@@ -82,6 +105,26 @@ pub fn init_telemetry(
     .context("failed to initialise tracing subscriber")
 }
 
+/// Build the OTLP logs pipeline exporting batched log records to `otlp_endpoint`.
+///
+/// The returned provider is kept alive by the tracing bridge that holds it.
+///
+/// # Errors
+///
+/// Returns an error if the OTLP log exporter cannot be built.
+pub fn logs_provider(otlp_endpoint: &str) -> Result<LoggerProvider> {
+    opentelemetry_otlp::new_pipeline()
+        .logging()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(otlp_endpoint),
+        )
+        .with_log_config(opentelemetry_sdk::logs::config().with_resource(service_resource()))
+        .install_batch(runtime::Tokio)
+        .context("failed to install OTLP logs pipeline")
+}
+
 fn service_resource() -> Resource {
     Resource::new(vec![
         KeyValue::new(
@@ -94,3 +137,18 @@ fn service_resource() -> Resource {
         ),
     ])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shutting down a batch provider blocks on its export task, which needs a
+    // second worker thread to make progress.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_pipeline_initialises_for_endpoint() {
+        // The tonic channel connects lazily, so no collector needs to be listening.
+        let provider = logs_provider("http://127.0.0.1:4317");
+        assert!(provider.is_ok(), "{:?}", provider.err());
+        let _ = provider.unwrap().shutdown();
+    }
+}
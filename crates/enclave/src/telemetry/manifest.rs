@@ -0,0 +1,139 @@
+//! The boot manifest: one structured event emitted once startup completes.
+//!
+//! It records what the enclave is about to serve with — crate version, field
+//! encryption algorithm, DEK generation, every loaded schema with its
+//! fingerprint, and the NSM PCR values when they are available — so an audit
+//! trail can tie later ciphertext to a known configuration. A SHA-256 digest
+//! over the canonical JSON of those fields identifies the configuration
+//! compactly; it is unkeyed and unattested, so it does not protect the logged
+//! event against edits.
+//!
+//! The manifest never carries key material: the DEK appears only as its store
+//! generation.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::crypto::cipher::ALGORITHM;
+use crate::dek::DekStore;
+use crate::schema::SchemaCache;
+
+/// Contents of the boot manifest event.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct BootManifest {
+    /// Crate version of the running binary.
+    pub version: &'static str,
+    /// Field encryption algorithm.
+    pub algorithm: &'static str,
+    /// Generation of the DEK in the store (never the key itself).
+    pub dek_generation: u64,
+    /// Loaded schema names and their fingerprints, sorted by name.
+    pub schemas: BTreeMap<String, String>,
+    /// Hex-encoded PCR values by index, when the NSM reported them.
+    pub pcrs: Option<BTreeMap<u8, String>>,
+}
+
+impl BootManifest {
+    /// Build the manifest from the started service's `cache` and `dek`.
+    pub fn build(cache: &SchemaCache, dek: &DekStore, pcrs: Option<BTreeMap<u8, String>>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            algorithm: ALGORITHM,
+            dek_generation: dek.generation(),
+            schemas: cache.fingerprints(),
+            pcrs,
+        }
+    }
+
+    /// Hex-encoded SHA-256 of the manifest's canonical JSON serialisation:
+    /// equal configurations share a digest. Anyone can recompute it, so it
+    /// is an identifier, not an integrity check.
+    ///
+    /// # Errors
+    ///
+    /// Returns the serialisation error if the manifest cannot be written as
+    /// JSON.
+    pub fn digest(&self) -> Result<String, serde_json::Error> {
+        let mut hasher = Sha256::new();
+        serde_json::to_writer(&mut hasher, self)?;
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect())
+    }
+
+    /// Emit the manifest as a single `boot_manifest` log event, or an error
+    /// event if it cannot be serialised.
+    pub fn emit(&self) {
+        match serde_json::to_string(self).and_then(|manifest| Ok((manifest, self.digest()?))) {
+            Ok((manifest, digest)) => info!(
+                event = "boot_manifest",
+                manifest = %manifest,
+                digest = %digest,
+                "boot manifest"
+            ),
+            Err(e) => error!(error = %e, "failed to serialise boot manifest"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KEY_LEN;
+    use std::collections::HashMap;
+
+    const SCHEMA: &str = r#"{"openapi":"3.0.0","info":{"title":"t","version":"1"},"paths":{}}"#;
+
+    #[tokio::test]
+    async fn manifest_reflects_state_without_key_material() {
+        let cache = SchemaCache::new();
+        let api: openapiv3::OpenAPI = serde_json::from_str(SCHEMA).unwrap();
+        cache.replace_all(HashMap::from([
+            ("payments-v1".to_string(), api.clone()),
+            ("customers-v2".to_string(), api.clone()),
+        ]));
+        let dek = DekStore::new();
+        let key = [0x5Au8; KEY_LEN];
+        dek.store(&key).await.unwrap();
+        let pcrs = BTreeMap::from([(0u8, "ab".repeat(48))]);
+
+        let manifest = BootManifest::build(&cache, &dek, Some(pcrs.clone()));
+        assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest.algorithm, "AES-256-GCM-SIV");
+        assert_eq!(manifest.dek_generation, 1);
+        assert_eq!(manifest.pcrs, Some(pcrs));
+        let fingerprint = crate::schema::cache::fingerprint(&api).unwrap();
+        assert_eq!(
+            manifest.schemas.keys().collect::<Vec<_>>(),
+            ["customers-v2", "payments-v1"]
+        );
+        assert_eq!(manifest.schemas["payments-v1"], fingerprint);
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        let key_hex: String = key.iter().map(|b| format!("{b:02x}")).collect();
+        assert!(!json.contains(&key_hex));
+        assert!(!json.contains("WlpaWlpa"), "no base64 of the key either");
+    }
+
+    #[tokio::test]
+    async fn digest_changes_with_any_field() {
+        let cache = SchemaCache::new();
+        let dek = DekStore::new();
+        let manifest = BootManifest::build(&cache, &dek, None);
+        let digest = manifest.digest().unwrap();
+        assert_eq!(digest, manifest.clone().digest().unwrap());
+        assert_eq!(digest.len(), 64);
+
+        let mut tampered = manifest.clone();
+        tampered.dek_generation = 2;
+        assert_ne!(tampered.digest().unwrap(), digest);
+        let mut tampered = manifest.clone();
+        tampered.schemas.insert("extra".into(), "00".into());
+        assert_ne!(tampered.digest().unwrap(), digest);
+    }
+}
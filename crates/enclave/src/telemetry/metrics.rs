@@ -9,8 +9,14 @@
 //!
 //! No PII or key material must appear in any metric label or attribute.
 
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use common::protocol::ErrorCode;
+
+use super::heartbeat::TaskHeartbeats;
 use opentelemetry::{
-    metrics::{Counter, Histogram, Meter, Unit},
+    metrics::{Counter, Histogram, Meter, ObservableCounter, ObservableGauge, Unit},
     KeyValue,
 };
 
@@ -24,8 +30,120 @@ pub struct Metrics {
     pub decrypt_requests: Counter<u64>,
     /// Latency of `/decrypt` requests in milliseconds. Label: `status` = `"success"` | `"error"`.
     pub decrypt_latency_ms: Histogram<f64>,
+    /// Duration of each startup phase in milliseconds, recorded once per
+    /// boot. Label: `phase`.
+    pub startup_phase_ms: Histogram<f64>,
     /// Count of successful DEK rotations (background task).
     pub dek_rotations: Counter<u64>,
+    /// Count of failed KMS decrypts of the DEK during rotation. Label:
+    /// `reason` = `"access_denied"` | `"invalid_state"` |
+    /// `"invalid_ciphertext"` | `"other"`.
+    pub kms_decrypt_failures: Counter<u64>,
+    /// Count of accepted TLS connections. Label: `version` = `"TLSv1.3"` |
+    /// `"TLSv1.2"`.
+    pub tls_handshakes: Counter<u64>,
+    /// Body size in bytes of responses whose length is known, before
+    /// compression.
+    pub response_bytes: Histogram<u64>,
+    /// Current KMS circuit-breaker state (`0` closed, `1` half-open, `2` open),
+    /// exported through the `enclave_kms_breaker_state` observable gauge.
+    pub kms_breaker_state: Arc<AtomicU64>,
+    /// Keeps the breaker-state gauge (and its callback) registered.
+    _kms_breaker_gauge: ObservableGauge<u64>,
+    /// Error responses sent, per [`ErrorCode`], exported through the
+    /// `enclave_error_responses` observable counter. Label: `code`.
+    pub error_responses: Arc<ErrorCounts>,
+    /// Keeps the error-response counter (and its callback) registered.
+    _error_responses_counter: ObservableCounter<u64>,
+    /// Plaintext and ciphertext sizes of protected fields, bucketed by
+    /// length, exported through the `enclave_field_bytes` observable counter.
+    /// Labels: `kind` = `"plaintext"` | `"ciphertext"`, `le` = bucket bound.
+    pub field_lengths: Arc<FieldLengths>,
+    /// Keeps the field-length counter (and its callback) registered.
+    _field_lengths_counter: ObservableCounter<u64>,
+    /// `/decrypt` payloads flagged by the nonce-reuse audit, exported through
+    /// the `enclave_nonce_reuse_detected` observable counter.
+    pub nonce_reuse: Arc<AtomicU64>,
+    /// Keeps the nonce-reuse counter (and its callback) registered.
+    _nonce_reuse_counter: ObservableCounter<u64>,
+    /// Audit events dropped because the audit queue was full, exported
+    /// through the `enclave_audit_events_dropped` observable counter.
+    pub audit_events_dropped: Arc<AtomicU64>,
+    /// Keeps the dropped-audit-events counter (and its callback) registered.
+    _audit_dropped_counter: ObservableCounter<u64>,
+    /// Heartbeats of the background tasks, exported through the
+    /// `enclave_background_task_stalled` observable gauge (`1` stalled, `0`
+    /// alive). Label: `task`.
+    pub task_heartbeats: Arc<TaskHeartbeats>,
+    /// Keeps the task-liveness gauge (and its callback) registered.
+    _task_stalled_gauge: ObservableGauge<u64>,
+}
+
+/// Inclusive upper bounds, in bytes, of the field-length buckets. Longer
+/// fields fall into a final `+Inf` bucket.
+pub const FIELD_LENGTH_BUCKETS: [usize; 8] = [8, 16, 32, 64, 128, 256, 1024, 4096];
+
+type LengthBuckets = [AtomicU64; FIELD_LENGTH_BUCKETS.len() + 1];
+
+/// Per-bucket counts of protected field sizes. Only lengths are recorded,
+/// never field contents.
+#[derive(Debug, Default)]
+pub struct FieldLengths {
+    plaintext: LengthBuckets,
+    ciphertext: LengthBuckets,
+}
+
+impl FieldLengths {
+    /// Count one protected field of `plaintext` bytes stored as `ciphertext` bytes.
+    pub fn record(&self, plaintext: usize, ciphertext: usize) {
+        self.plaintext[Self::bucket(plaintext)].fetch_add(1, Ordering::Relaxed);
+        self.ciphertext[Self::bucket(ciphertext)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Index of the bucket a field of `len` bytes is counted in.
+    pub fn bucket(len: usize) -> usize {
+        FIELD_LENGTH_BUCKETS.partition_point(|&bound| bound < len)
+    }
+
+    /// Current plaintext counts, one per bucket.
+    pub fn plaintext(&self) -> [u64; FIELD_LENGTH_BUCKETS.len() + 1] {
+        self.plaintext.each_ref().map(|c| c.load(Ordering::Relaxed))
+    }
+
+    /// Current ciphertext counts, one per bucket.
+    pub fn ciphertext(&self) -> [u64; FIELD_LENGTH_BUCKETS.len() + 1] {
+        self.ciphertext
+            .each_ref()
+            .map(|c| c.load(Ordering::Relaxed))
+    }
+}
+
+/// Label value for bucket `index`: its upper bound, or `+Inf` for the last.
+fn bucket_label(index: usize) -> String {
+    FIELD_LENGTH_BUCKETS
+        .get(index)
+        .map_or_else(|| "+Inf".to_string(), usize::to_string)
+}
+
+/// Running totals of error responses, one per [`ErrorCode`].
+#[derive(Debug, Default)]
+pub struct ErrorCounts([AtomicU64; ErrorCode::ALL.len()]);
+
+impl ErrorCounts {
+    /// Count one error response with `code`.
+    pub fn record(&self, code: ErrorCode) {
+        self.slot(code).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Error responses sent so far with `code`.
+    pub fn get(&self, code: ErrorCode) -> u64 {
+        self.slot(code).load(Ordering::Relaxed)
+    }
+
+    fn slot(&self, code: ErrorCode) -> &AtomicU64 {
+        // Unit variants are numbered in declaration order, matching `ALL`.
+        &self.0[code as usize]
+    }
 }
 
 impl Metrics {
@@ -34,6 +152,18 @@ impl Metrics {
     /// Must be called after [`super::init_telemetry`] so that the global
     /// meter provider is set.
     pub fn new(meter: &Meter) -> Self {
+        let kms_breaker_state = Arc::new(AtomicU64::new(0));
+        let observed = Arc::clone(&kms_breaker_state);
+        let error_responses = Arc::new(ErrorCounts::default());
+        let observed_errors = Arc::clone(&error_responses);
+        let field_lengths = Arc::new(FieldLengths::default());
+        let observed_lengths = Arc::clone(&field_lengths);
+        let nonce_reuse = Arc::new(AtomicU64::new(0));
+        let observed_reuse = Arc::clone(&nonce_reuse);
+        let audit_events_dropped = Arc::new(AtomicU64::new(0));
+        let observed_audit = Arc::clone(&audit_events_dropped);
+        let task_heartbeats = Arc::new(TaskHeartbeats::default());
+        let observed_tasks = Arc::clone(&task_heartbeats);
         Self {
             encrypt_requests: meter
                 .u64_counter("enclave_encrypt_requests")
@@ -53,10 +183,96 @@ impl Metrics {
                 .with_description("Latency of /decrypt requests in milliseconds")
                 .with_unit(Unit::new("ms"))
                 .init(),
+            startup_phase_ms: meter
+                .f64_histogram("enclave_startup_phase_ms")
+                .with_description("Duration of each startup phase in milliseconds")
+                .with_unit(Unit::new("ms"))
+                .init(),
             dek_rotations: meter
                 .u64_counter("enclave_dek_rotations")
                 .with_description("Number of successful DEK background rotations")
                 .init(),
+            kms_decrypt_failures: meter
+                .u64_counter("enclave_kms_decrypt_failures")
+                .with_description("Failed KMS decrypts of the DEK, by reason")
+                .init(),
+            tls_handshakes: meter
+                .u64_counter("enclave_tls_handshakes")
+                .with_description("Accepted TLS connections, by negotiated protocol version")
+                .init(),
+            response_bytes: meter
+                .u64_histogram("enclave_response_bytes")
+                .with_description("Size of response bodies before compression, in bytes")
+                .with_unit(Unit::new("By"))
+                .init(),
+            _kms_breaker_gauge: meter
+                .u64_observable_gauge("enclave_kms_breaker_state")
+                .with_description("KMS circuit-breaker state: 0 closed, 1 half-open, 2 open")
+                .with_callback(move |obs| obs.observe(observed.load(Ordering::Relaxed), &[]))
+                .init(),
+            kms_breaker_state,
+            _error_responses_counter: meter
+                .u64_observable_counter("enclave_error_responses")
+                .with_description("Error responses sent, by error code")
+                .with_callback(move |obs| {
+                    for code in ErrorCode::ALL {
+                        obs.observe(
+                            observed_errors.get(code),
+                            &[KeyValue::new("code", code.as_str())],
+                        );
+                    }
+                })
+                .init(),
+            error_responses,
+            _field_lengths_counter: meter
+                .u64_observable_counter("enclave_field_bytes")
+                .with_description("Protected PII fields, by plaintext or ciphertext length bucket")
+                .with_unit(Unit::new("By"))
+                .with_callback(move |obs| {
+                    // Exported cumulatively, Prometheus-style: `le` counts every
+                    // field no longer than the bound.
+                    for (kind, counts) in [
+                        ("plaintext", observed_lengths.plaintext()),
+                        ("ciphertext", observed_lengths.ciphertext()),
+                    ] {
+                        let mut total = 0;
+                        for (index, count) in counts.into_iter().enumerate() {
+                            total += count;
+                            obs.observe(
+                                total,
+                                &[
+                                    KeyValue::new("kind", kind),
+                                    KeyValue::new("le", bucket_label(index)),
+                                ],
+                            );
+                        }
+                    }
+                })
+                .init(),
+            field_lengths,
+            _nonce_reuse_counter: meter
+                .u64_observable_counter("enclave_nonce_reuse_detected")
+                .with_description("Decrypt payloads whose ciphertext reuses a nonce inconsistently")
+                .with_callback(move |obs| obs.observe(observed_reuse.load(Ordering::Relaxed), &[]))
+                .init(),
+            nonce_reuse,
+            _audit_dropped_counter: meter
+                .u64_observable_counter("enclave_audit_events_dropped")
+                .with_description("Audit events dropped because the audit queue was full")
+                .with_callback(move |obs| obs.observe(observed_audit.load(Ordering::Relaxed), &[]))
+                .init(),
+            audit_events_dropped,
+            _task_stalled_gauge: meter
+                .u64_observable_gauge("enclave_background_task_stalled")
+                .with_description("Whether a background task has missed its heartbeat")
+                .with_callback(move |obs| {
+                    let now = tokio::time::Instant::now();
+                    for (task, stalled) in observed_tasks.statuses(now) {
+                        obs.observe(u64::from(stalled), &[KeyValue::new("task", task)]);
+                    }
+                })
+                .init(),
+            task_heartbeats,
         }
     }
 
@@ -85,4 +301,25 @@ mod tests {
         let meter = global::meter("test");
         let _m = Metrics::new(&meter);
     }
+
+    #[test]
+    fn error_counts_are_tracked_per_code() {
+        let counts = ErrorCounts::default();
+        counts.record(ErrorCode::BadRequest);
+        counts.record(ErrorCode::BadRequest);
+        counts.record(ErrorCode::ServiceUnavailable);
+        assert_eq!(counts.get(ErrorCode::BadRequest), 2);
+        assert_eq!(counts.get(ErrorCode::ServiceUnavailable), 1);
+        assert_eq!(counts.get(ErrorCode::InternalError), 0);
+    }
+
+    #[test]
+    fn field_lengths_are_bucketed_by_upper_bound() {
+        assert_eq!(FieldLengths::bucket(0), 0);
+        assert_eq!(FieldLengths::bucket(8), 0);
+        assert_eq!(FieldLengths::bucket(9), 1);
+        assert_eq!(FieldLengths::bucket(4096), FIELD_LENGTH_BUCKETS.len() - 1);
+        assert_eq!(FieldLengths::bucket(4097), FIELD_LENGTH_BUCKETS.len());
+        assert_eq!(bucket_label(FIELD_LENGTH_BUCKETS.len()), "+Inf");
+    }
 }
@@ -9,9 +9,13 @@
 //!   or log field.
 //! - Log level is configurable via `LOG_LEVEL` (default: `info`).
 
+pub mod audit;
+pub mod heartbeat;
 pub mod init;
 pub mod log_writer;
+pub mod manifest;
 pub mod metrics;
+pub mod startup;
 
 pub use init::init_telemetry;
 pub use metrics::Metrics;
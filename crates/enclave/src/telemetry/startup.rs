@@ -0,0 +1,111 @@
+//! Timings of the startup phases, for tuning enclave cold start.
+//!
+//! Each phase in `main` (AWS client init, DEK fetch, schema load, TLS build)
+//! runs under [`StartupTimings::time`] or [`StartupTimings::time_sync`], which
+//! logs its duration as it finishes. Once the metrics instruments exist, the
+//! collected durations are exported through the `enclave_startup_phase_ms`
+//! histogram (label `phase`) by [`StartupTimings::report`].
+
+use std::future::Future;
+use std::time::Duration;
+
+use opentelemetry::{metrics::Histogram, KeyValue};
+use tokio::time::Instant;
+use tracing::info;
+
+/// Durations of the startup phases run so far, in order.
+#[derive(Debug, Default)]
+pub struct StartupTimings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl StartupTimings {
+    /// Await `fut` as the phase `phase`, recording how long it took whether
+    /// or not it succeeded.
+    pub async fn time<F: Future>(&mut self, phase: &'static str, fut: F) -> F::Output {
+        let start = Instant::now();
+        let output = fut.await;
+        self.record(phase, start.elapsed());
+        output
+    }
+
+    /// Run `f` as the phase `phase`; see [`time`](Self::time).
+    pub fn time_sync<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let output = f();
+        self.record(phase, start.elapsed());
+        output
+    }
+
+    /// Add `elapsed` to `phase`, logging the phase's new duration. A phase
+    /// run more than once accumulates.
+    pub fn record(&mut self, phase: &'static str, elapsed: Duration) {
+        let total = match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, duration)) => {
+                *duration += elapsed;
+                *duration
+            }
+            None => {
+                self.phases.push((phase, elapsed));
+                elapsed
+            }
+        };
+        info!(phase, duration_ms = millis(total), "startup phase finished");
+    }
+
+    /// Every recorded phase with its accumulated duration, in first-run order.
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    /// Sum of every phase's duration.
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// Export each phase to `histogram` and log the total.
+    pub fn report(&self, histogram: &Histogram<f64>) {
+        for (phase, duration) in self.phases() {
+            histogram.record(
+                duration.as_secs_f64() * 1000.0,
+                &[KeyValue::new("phase", *phase)],
+            );
+        }
+        info!(total_ms = millis(self.total()), "startup phases timed");
+    }
+}
+
+fn millis(d: Duration) -> u64 {
+    u64::try_from(d.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn phases_accumulate_in_order() {
+        let mut timings = StartupTimings::default();
+        timings
+            .time("dek_fetch", tokio::time::sleep(Duration::from_millis(250)))
+            .await;
+        // A failing phase is timed too, and its output passed through.
+        let schemas: Result<(), &str> = timings.time("schema_load", async { Err("boom") }).await;
+        assert_eq!(schemas, Err("boom"));
+        timings.time_sync("tls_config", || ());
+        timings.record("dek_fetch", Duration::from_millis(50));
+
+        assert_eq!(
+            timings.phases(),
+            [
+                ("dek_fetch", Duration::from_millis(300)),
+                ("schema_load", Duration::ZERO),
+                ("tls_config", Duration::ZERO),
+            ]
+        );
+        assert_eq!(timings.total(), Duration::from_millis(300));
+
+        let meter = opentelemetry::global::meter("test");
+        timings.report(&meter.f64_histogram("test_startup_phase_ms").init());
+    }
+}
@@ -10,6 +10,16 @@ pub struct Config {
     #[serde(default = "default_listen_port")]
     pub listen_port: u16,
 
+    /// Maximum number of pending connections queued by the kernel on the TCP
+    /// listener before new SYNs are dropped.
+    #[serde(default = "default_listen_backlog")]
+    pub listen_backlog: u32,
+
+    /// When set, accept connections on a Unix domain socket at this path
+    /// instead of the TCP port, so only processes in the pod can connect.
+    #[serde(default)]
+    pub listen_uds: Option<String>,
+
     /// Vsock CID of the Nitro Enclave on this node. **Required.**
     pub enclave_cid: u32,
 
@@ -24,17 +34,65 @@ pub struct Config {
     /// Tracing log level.
     #[serde(default = "default_log_level")]
     pub log_level: String,
+
+    /// Connections open longer than this (seconds) are counted as long-lived
+    /// in the periodic connection-age report.
+    #[serde(default = "default_connection_age_threshold")]
+    pub connection_age_threshold_secs: u64,
+
+    /// Interval (seconds) between connection-age reports.
+    #[serde(default = "default_connection_age_report_interval")]
+    pub connection_age_report_interval_secs: u64,
+
+    /// Idle time (seconds) before TCP keepalive probes start on accepted
+    /// client connections; `0` disables keepalive.
+    #[serde(default = "default_tcp_keepalive")]
+    pub tcp_keepalive_secs: u64,
+
+    /// Interval (seconds) between unanswered TCP keepalive probes.
+    #[serde(default = "default_tcp_keepalive_interval")]
+    pub tcp_keepalive_interval_secs: u64,
+
+    /// Unanswered TCP keepalive probes before the connection is dropped.
+    #[serde(default = "default_tcp_keepalive_retries")]
+    pub tcp_keepalive_retries: u32,
+
+    /// Seconds to let open connections finish after SIGTERM before exiting;
+    /// `0` exits without waiting.
+    #[serde(default = "default_shutdown_grace")]
+    pub shutdown_grace_secs: u64,
 }
 
 fn default_listen_port() -> u16 {
     8443
 }
+fn default_listen_backlog() -> u32 {
+    1024
+}
 fn default_enclave_port() -> u32 {
     443
 }
 fn default_log_level() -> String {
     "info".into()
 }
+fn default_connection_age_threshold() -> u64 {
+    300
+}
+fn default_connection_age_report_interval() -> u64 {
+    60
+}
+fn default_tcp_keepalive() -> u64 {
+    60
+}
+fn default_tcp_keepalive_interval() -> u64 {
+    15
+}
+fn default_tcp_keepalive_retries() -> u32 {
+    4
+}
+fn default_shutdown_grace() -> u64 {
+    30
+}
 
 impl Config {
     /// Load and validate configuration from environment variables.
@@ -59,29 +117,75 @@ impl Config {
         if self.main_app_addr.trim().is_empty() {
             anyhow::bail!("MAIN_APP_ADDR is required and must not be empty");
         }
+        if self.listen_backlog == 0 || self.listen_backlog > i32::MAX as u32 {
+            anyhow::bail!("LISTEN_BACKLOG must be between 1 and {}", i32::MAX);
+        }
+        if self
+            .listen_uds
+            .as_deref()
+            .is_some_and(|p| p.trim().is_empty())
+        {
+            anyhow::bail!("LISTEN_UDS must not be empty when set");
+        }
+        if self.connection_age_threshold_secs == 0 {
+            anyhow::bail!("CONNECTION_AGE_THRESHOLD_SECS must be > 0");
+        }
+        if self.connection_age_report_interval_secs == 0 {
+            anyhow::bail!("CONNECTION_AGE_REPORT_INTERVAL_SECS must be > 0");
+        }
+        if self.tcp_keepalive_secs > 0 {
+            if self.tcp_keepalive_interval_secs == 0 {
+                anyhow::bail!("TCP_KEEPALIVE_INTERVAL_SECS must be > 0 when keepalive is enabled");
+            }
+            if self.tcp_keepalive_retries == 0 {
+                anyhow::bail!("TCP_KEEPALIVE_RETRIES must be > 0 when keepalive is enabled");
+            }
+        }
         Ok(())
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    pub(crate) fn valid_config() -> Config {
+        Config {
+            listen_port: 8443,
+            listen_backlog: default_listen_backlog(),
+            listen_uds: None,
+            enclave_cid: 16,
+            enclave_port: 443,
+            main_app_addr: "127.0.0.1:8080".into(),
+            log_level: "info".into(),
+            connection_age_threshold_secs: default_connection_age_threshold(),
+            connection_age_report_interval_secs: default_connection_age_report_interval(),
+            tcp_keepalive_secs: default_tcp_keepalive(),
+            tcp_keepalive_interval_secs: default_tcp_keepalive_interval(),
+            tcp_keepalive_retries: default_tcp_keepalive_retries(),
+            shutdown_grace_secs: default_shutdown_grace(),
+        }
+    }
+
     #[test]
     fn defaults() {
         assert_eq!(default_listen_port(), 8443);
+        assert_eq!(default_listen_backlog(), 1024);
         assert_eq!(default_enclave_port(), 443);
         assert_eq!(default_log_level(), "info");
+        assert_eq!(default_connection_age_threshold(), 300);
+        assert_eq!(default_connection_age_report_interval(), 60);
+        assert_eq!(default_tcp_keepalive(), 60);
+        assert_eq!(default_tcp_keepalive_interval(), 15);
+        assert_eq!(default_tcp_keepalive_retries(), 4);
+        assert_eq!(default_shutdown_grace(), 30);
     }
 
     #[test]
     fn validate_rejects_zero_cid() {
         let cfg = Config {
-            listen_port: 8443,
             enclave_cid: 0,
-            enclave_port: 443,
-            main_app_addr: "127.0.0.1:8080".into(),
-            log_level: "info".into(),
+            ..valid_config()
         };
         assert!(cfg.validate().is_err());
     }
@@ -89,24 +193,68 @@ mod tests {
     #[test]
     fn validate_rejects_empty_main_app_addr() {
         let cfg = Config {
-            listen_port: 8443,
-            enclave_cid: 16,
-            enclave_port: 443,
             main_app_addr: "  ".into(),
-            log_level: "info".into(),
+            ..valid_config()
         };
         assert!(cfg.validate().is_err());
     }
 
     #[test]
-    fn validate_accepts_valid_config() {
+    fn validate_rejects_zero_backlog() {
         let cfg = Config {
-            listen_port: 8443,
-            enclave_cid: 16,
-            enclave_port: 443,
-            main_app_addr: "127.0.0.1:8080".into(),
-            log_level: "info".into(),
+            listen_backlog: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_zero_connection_age_settings() {
+        let cfg = Config {
+            connection_age_threshold_secs: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        let cfg = Config {
+            connection_age_report_interval_secs: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_keepalive_settings() {
+        let cfg = Config {
+            tcp_keepalive_interval_secs: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        let cfg = Config {
+            tcp_keepalive_retries: 0,
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+        // Probe settings are irrelevant once keepalive is disabled.
+        let cfg = Config {
+            tcp_keepalive_secs: 0,
+            tcp_keepalive_interval_secs: 0,
+            tcp_keepalive_retries: 0,
+            ..valid_config()
         };
         assert!(cfg.validate().is_ok());
     }
+
+    #[test]
+    fn validate_rejects_empty_listen_uds() {
+        let cfg = Config {
+            listen_uds: Some(" ".into()),
+            ..valid_config()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_accepts_valid_config() {
+        assert!(valid_config().validate().is_ok());
+    }
 }
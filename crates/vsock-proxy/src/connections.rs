@@ -0,0 +1,257 @@
+//! Bookkeeping for active proxied connections and their ages.
+//!
+//! Long-lived stuck connections are an early symptom of enclave problems. Each
+//! connection registers its start time with a [`ConnectionTracker`] for as long
+//! as it is open, and a background task periodically logs the age of the
+//! oldest connection and how many exceed a threshold.
+//!
+//! On shutdown, [`drain`] waits for the open connections to finish, logging
+//! how many are still draining each second so operators can decide whether to
+//! wait or force-kill.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// Interval between "still draining" log lines during shutdown.
+const DRAIN_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Registry of open connections keyed by an internal id.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionTracker {
+    next_id: Arc<AtomicU64>,
+    started: Arc<Mutex<HashMap<u64, Instant>>>,
+    active: Arc<AtomicUsize>,
+    idle: Arc<Notify>,
+}
+
+/// How a shutdown drain ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainOutcome {
+    /// Every connection finished within the grace period.
+    Drained,
+    /// The grace period ran out with `remaining` connections still open.
+    TimedOut {
+        /// Connections still open at the deadline.
+        remaining: usize,
+    },
+}
+
+/// Point-in-time summary of active connection ages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionAges {
+    /// Number of open connections.
+    pub active: usize,
+    /// Age of the oldest open connection; zero when none are open.
+    pub oldest: Duration,
+    /// Number of open connections older than the threshold.
+    pub older_than_threshold: usize,
+}
+
+/// Registration of one open connection; deregisters it when dropped.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    id: u64,
+    tracker: ConnectionTracker,
+}
+
+impl ConnectionTracker {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a connection that started at `now`.
+    pub fn register(&self, now: Instant) -> ConnectionGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock().insert(id, now);
+        self.active.fetch_add(1, Ordering::AcqRel);
+        ConnectionGuard {
+            id,
+            tracker: self.clone(),
+        }
+    }
+
+    /// Number of open connections.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Summarise connection ages as of `now`, counting those older than `threshold`.
+    pub fn snapshot(&self, now: Instant, threshold: Duration) -> ConnectionAges {
+        let started = self.lock();
+        let ages = started
+            .values()
+            .map(|start| now.saturating_duration_since(*start));
+        let mut oldest = Duration::ZERO;
+        let mut older_than_threshold = 0;
+        for age in ages {
+            oldest = oldest.max(age);
+            if age > threshold {
+                older_than_threshold += 1;
+            }
+        }
+        ConnectionAges {
+            active: started.len(),
+            oldest,
+            older_than_threshold,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Instant>> {
+        // The map holds plain data; a panic mid-update cannot leave it inconsistent.
+        self.started.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.tracker.lock().remove(&self.id);
+        if self.tracker.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tracker.idle.notify_waiters();
+        }
+    }
+}
+
+/// Wait up to `grace` for every connection in `tracker` to close.
+///
+/// Logs the number still draining every second, then a final "drained
+/// cleanly" or "forced after timeout" line.
+pub async fn drain(tracker: &ConnectionTracker, grace: Duration) -> DrainOutcome {
+    let deadline = tokio::time::Instant::now() + grace;
+    let mut ticker = tokio::time::interval_at(
+        tokio::time::Instant::now() + DRAIN_LOG_INTERVAL,
+        DRAIN_LOG_INTERVAL,
+    );
+    loop {
+        let idle = tracker.idle.notified();
+        let remaining = tracker.active();
+        if remaining == 0 {
+            info!("connections drained cleanly");
+            return DrainOutcome::Drained;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            warn!(
+                remaining,
+                grace_secs = grace.as_secs(),
+                "shutdown forced after timeout"
+            );
+            return DrainOutcome::TimedOut { remaining };
+        }
+        info!(remaining, "connections still draining");
+        tokio::select! {
+            _ = idle => {}
+            _ = ticker.tick() => {}
+            _ = tokio::time::sleep_until(deadline) => {}
+        }
+    }
+}
+
+/// Log a [`ConnectionAges`] summary every `interval` until the process exits.
+///
+/// Emits at `warn` when any connection is older than `threshold`, otherwise at
+/// `info`, so stuck connections surface before any timeout fires.
+pub async fn report_task(tracker: ConnectionTracker, interval: Duration, threshold: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let ages = tracker.snapshot(Instant::now(), threshold);
+        if ages.older_than_threshold > 0 {
+            warn!(
+                active = ages.active,
+                oldest_secs = ages.oldest.as_secs(),
+                older_than_threshold = ages.older_than_threshold,
+                threshold_secs = threshold.as_secs(),
+                "connection ages"
+            );
+        } else {
+            info!(
+                active = ages.active,
+                oldest_secs = ages.oldest.as_secs(),
+                older_than_threshold = 0,
+                threshold_secs = threshold.as_secs(),
+                "connection ages"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_tracker_reports_nothing() {
+        let tracker = ConnectionTracker::new();
+        let ages = tracker.snapshot(Instant::now(), Duration::from_secs(1));
+        assert_eq!(
+            ages,
+            ConnectionAges {
+                active: 0,
+                oldest: Duration::ZERO,
+                older_than_threshold: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn tracks_oldest_and_threshold_until_dropped() {
+        let tracker = ConnectionTracker::new();
+        let t0 = Instant::now();
+        let old = tracker.register(t0);
+        let _mid = tracker.register(t0 + Duration::from_secs(50));
+        let _new = tracker.register(t0 + Duration::from_secs(90));
+
+        let now = t0 + Duration::from_secs(100);
+        let ages = tracker.snapshot(now, Duration::from_secs(30));
+        assert_eq!(ages.active, 3);
+        assert_eq!(ages.oldest, Duration::from_secs(100));
+        assert_eq!(ages.older_than_threshold, 2);
+
+        drop(old);
+        let ages = tracker.snapshot(now, Duration::from_secs(30));
+        assert_eq!(ages.active, 2);
+        assert_eq!(ages.oldest, Duration::from_secs(50));
+        assert_eq!(ages.older_than_threshold, 1);
+    }
+
+    #[test]
+    fn active_count_decrements_as_connections_complete() {
+        let tracker = ConnectionTracker::new();
+        let now = Instant::now();
+        let first = tracker.register(now);
+        let second = tracker.register(now);
+        assert_eq!(tracker.active(), 2);
+
+        drop(first);
+        assert_eq!(tracker.active(), 1);
+        drop(second);
+        assert_eq!(tracker.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_open_connections() {
+        let tracker = ConnectionTracker::new();
+        assert_eq!(drain(&tracker, Duration::ZERO).await, DrainOutcome::Drained);
+
+        let guard = tracker.register(Instant::now());
+        assert_eq!(
+            drain(&tracker, Duration::ZERO).await,
+            DrainOutcome::TimedOut { remaining: 1 }
+        );
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+        assert_eq!(
+            drain(&tracker, Duration::from_secs(30)).await,
+            DrainOutcome::Drained
+        );
+    }
+}
@@ -3,9 +3,12 @@
 //! Startup sequence:
 //! 1. Load and validate [`Config`] from environment variables.
 //! 2. Initialise structured JSON logging.
-//! 3. Start the TCP accept loop, proxying each connection to the enclave vsock port.
+//! 3. Start the TCP accept loop, proxying each connection to the enclave vsock port,
+//!    and a background task reporting the ages of open connections.
+//! 4. On SIGTERM or Ctrl-C, stop accepting and drain open connections.
 
 mod config;
+mod connections;
 mod proxy;
 mod telemetry;
 
@@ -29,5 +32,5 @@ async fn main() -> Result<()> {
     // -----------------------------------------------------------------------
     // 3. Proxy
     // -----------------------------------------------------------------------
-    proxy::run(&cfg).await
+    proxy::run(&cfg, proxy::shutdown_signal()).await
 }
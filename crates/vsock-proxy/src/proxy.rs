@@ -1,92 +1,409 @@
 //! Bidirectional TCP ↔ vsock forwarding.
 //!
-//! For each incoming TCP connection the proxy:
+//! The proxy accepts on a TCP port or, when `LISTEN_UDS` is set, on a Unix
+//! domain socket so that only processes in the pod can connect.
+//!
+//! For each incoming connection the proxy:
 //! 1. Opens a new vsock stream to the enclave.
-//! 2. Spawns two Tokio tasks: one copying bytes TCP→vsock, the other vsock→TCP.
-//! 3. When either half closes, both tasks shut down.
+//! 2. Copies bytes in both directions concurrently: client→vsock and vsock→client.
+//! 3. When one side finishes sending, the write half of the other side is shut
+//!    down (TCP half-close) while the reverse direction keeps flowing; the
+//!    connection ends once both directions are done, or either fails.
+//!
+//! Accepted TCP connections get TCP keepalive (see [`keepalive`]) so that
+//! idle connections silently dropped by a NAT or load balancer are detected.
+//! The vsock leg has no such intermediaries and the vsock transport does not
+//! implement keepalive probes, so it relies on the enclave's own timeouts.
+//!
+//! On SIGTERM or Ctrl-C the proxy stops accepting and gives open connections
+//! up to `SHUTDOWN_GRACE_SECS` to finish (see [`connections::drain`]).
 //!
 //! TLS bytes are forwarded **opaquely** — TLS terminates inside the enclave,
 //! not in this sidecar. The sidecar has no visibility into plaintext.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::future::Future;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
 use tokio::{
-    io::{self, AsyncRead, AsyncWrite},
-    net::{TcpListener, TcpStream},
+    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UnixListener},
 };
 use tokio_vsock::{VsockAddr, VsockStream};
 use tracing::{debug, error, info, warn};
 
 use crate::config::Config;
+use crate::connections::{self, ConnectionTracker};
 
-/// Accept loop: listen on TCP and proxy each connection to the enclave vsock port.
+/// Accept loop: listen on TCP (or the configured Unix domain socket) and proxy
+/// each connection to the enclave vsock port.
 ///
-/// Runs until the process is killed.
+/// Runs until `shutdown` resolves, then stops accepting and drains open
+/// connections for up to `SHUTDOWN_GRACE_SECS` before returning.
 ///
 /// # Errors
 ///
-/// Returns an error if the TCP listener cannot be bound.
-pub async fn run(cfg: &Config) -> Result<()> {
-    let addr: SocketAddr = ([0u8, 0, 0, 0], cfg.listen_port).into();
-    let listener = TcpListener::bind(addr).await?;
-    info!(addr = %addr, enclave_cid = cfg.enclave_cid, enclave_port = cfg.enclave_port, "vsock-proxy listening");
-
-    loop {
-        match listener.accept().await {
-            Ok((tcp_stream, peer_addr)) => {
-                debug!(%peer_addr, "accepted TCP connection");
-                let cid = cfg.enclave_cid;
-                let port = cfg.enclave_port;
-                tokio::spawn(async move {
-                    if let Err(e) = handle_connection(tcp_stream, cid, port).await {
-                        warn!(%peer_addr, error = %e, "connection error");
+/// Returns an error if the listener cannot be bound.
+pub async fn run(cfg: &Config, shutdown: impl Future<Output = ()>) -> Result<()> {
+    let tracker = ConnectionTracker::new();
+    tokio::spawn(connections::report_task(
+        tracker.clone(),
+        Duration::from_secs(cfg.connection_age_report_interval_secs),
+        Duration::from_secs(cfg.connection_age_threshold_secs),
+    ));
+    tokio::pin!(shutdown);
+
+    if let Some(path) = &cfg.listen_uds {
+        let listener = bind_uds_listener(Path::new(path))?;
+        info!(path = %path, enclave_cid = cfg.enclave_cid, enclave_port = cfg.enclave_port, "vsock-proxy listening on unix socket");
+        loop {
+            let accepted = tokio::select! {
+                () = &mut shutdown => break,
+                accepted = listener.accept() => accepted,
+            };
+            match accepted {
+                Ok((stream, _)) => {
+                    debug!("accepted unix socket connection");
+                    spawn_connection(stream, "unix".into(), cfg, &tracker);
+                }
+                Err(e) => {
+                    error!(error = %e, "accept error");
+                }
+            }
+        }
+    } else {
+        let addr: SocketAddr = ([0u8, 0, 0, 0], cfg.listen_port).into();
+        let listener = bind_listener(addr, cfg.listen_backlog)?;
+        let keepalive = keepalive(cfg);
+        info!(addr = %addr, backlog = cfg.listen_backlog, keepalive = keepalive.is_some(), enclave_cid = cfg.enclave_cid, enclave_port = cfg.enclave_port, "vsock-proxy listening");
+
+        loop {
+            let accepted = tokio::select! {
+                () = &mut shutdown => break,
+                accepted = listener.accept() => accepted,
+            };
+            match accepted {
+                Ok((tcp_stream, peer_addr)) => {
+                    debug!(%peer_addr, "accepted TCP connection");
+                    if let Some(keepalive) = &keepalive {
+                        if let Err(e) = apply_keepalive(&tcp_stream, keepalive) {
+                            warn!(%peer_addr, error = %e, "failed to enable TCP keepalive");
+                        }
                     }
-                });
+                    spawn_connection(tcp_stream, peer_addr.to_string(), cfg, &tracker);
+                }
+                Err(e) => {
+                    error!(error = %e, "accept error");
+                }
+            }
+        }
+    }
+
+    info!(
+        active = tracker.active(),
+        grace_secs = cfg.shutdown_grace_secs,
+        "shutdown requested; no longer accepting connections"
+    );
+    connections::drain(&tracker, Duration::from_secs(cfg.shutdown_grace_secs)).await;
+    Ok(())
+}
+
+/// Resolve on SIGTERM (how Kubernetes stops a pod) or Ctrl-C.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(error = %e, "failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
             }
             Err(e) => {
-                error!(error = %e, "accept error");
+                error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
             }
         }
+    };
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+/// Proxy an accepted client stream to the enclave on a new task.
+fn spawn_connection<S>(stream: S, peer: String, cfg: &Config, tracker: &ConnectionTracker)
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let cid = cfg.enclave_cid;
+    let port = cfg.enclave_port;
+    let tracker = tracker.clone();
+    tokio::spawn(async move {
+        if let Err(e) = handle_connection(stream, cid, port, &tracker).await {
+            warn!(peer = %peer, error = %e, "connection error");
+        }
+    });
+}
+
+/// TCP keepalive parameters from `cfg`, or `None` when disabled.
+pub fn keepalive(cfg: &Config) -> Option<TcpKeepalive> {
+    (cfg.tcp_keepalive_secs > 0).then(|| {
+        TcpKeepalive::new()
+            .with_time(Duration::from_secs(cfg.tcp_keepalive_secs))
+            .with_interval(Duration::from_secs(cfg.tcp_keepalive_interval_secs))
+            .with_retries(cfg.tcp_keepalive_retries)
+    })
+}
+
+/// Enable TCP keepalive with `keepalive` parameters on an accepted stream.
+///
+/// # Errors
+///
+/// Returns an error if the socket options cannot be set.
+pub fn apply_keepalive(stream: &TcpStream, keepalive: &TcpKeepalive) -> Result<()> {
+    SockRef::from(stream)
+        .set_tcp_keepalive(keepalive)
+        .context("failed to set TCP keepalive")
+}
+
+/// Bind a Unix domain socket listener at `path`.
+///
+/// A stale socket left by a previous run is removed first; any other existing
+/// file at `path` is an error rather than being overwritten.
+///
+/// # Errors
+///
+/// Returns an error if `path` exists and is not a socket, or cannot be bound.
+pub fn bind_uds_listener(path: &Path) -> Result<UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(_) => {}
     }
+    UnixListener::bind(path)
+        .with_context(|| format!("failed to bind unix socket listener on {}", path.display()))
 }
 
-/// Handle a single TCP ↔ vsock connection.
-async fn handle_connection(tcp: TcpStream, enclave_cid: u32, enclave_port: u32) -> Result<()> {
+/// Bind a non-blocking TCP listener on `addr` with `SO_REUSEADDR` set and an
+/// explicit accept `backlog`.
+///
+/// `SO_REUSEADDR` lets a restarted sidecar rebind while old sockets linger in
+/// `TIME_WAIT`; the backlog bounds how many pending connections the kernel
+/// queues during bursts before dropping SYNs.
+///
+/// # Errors
+///
+/// Returns an error if the socket cannot be created, configured, or bound.
+pub fn bind_listener(addr: SocketAddr, backlog: u32) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+        .context("failed to create TCP socket")?;
+    socket
+        .set_reuse_address(true)
+        .context("failed to set SO_REUSEADDR")?;
+    socket
+        .set_nonblocking(true)
+        .context("failed to set TCP socket non-blocking")?;
+    socket
+        .bind(&addr.into())
+        .with_context(|| format!("failed to bind TCP listener on {addr}"))?;
+    let backlog = i32::try_from(backlog).context("listen backlog out of range")?;
+    socket
+        .listen(backlog)
+        .with_context(|| format!("failed to listen on {addr}"))?;
+    TcpListener::from_std(socket.into()).context("failed to register TCP listener with tokio")
+}
+
+/// Handle a single client ↔ vsock connection.
+///
+/// `client` is any accepted stream (TCP or Unix domain socket). The connection
+/// is registered with `tracker` from accept until it closes.
+async fn handle_connection<S>(
+    client: S,
+    enclave_cid: u32,
+    enclave_port: u32,
+    tracker: &ConnectionTracker,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let _registration = tracker.register(Instant::now());
     let vsock = VsockStream::connect(VsockAddr::new(enclave_cid, enclave_port)).await?;
     debug!(enclave_cid, enclave_port, "vsock connection established");
 
-    let (tcp_read, tcp_write) = io::split(tcp);
-    let (vsock_read, vsock_write) = io::split(vsock);
+    forward(client, vsock).await
+}
 
-    let tcp_to_vsock = copy_half(tcp_read, vsock_write, "tcp→vsock");
-    let vsock_to_tcp = copy_half(vsock_read, tcp_write, "vsock→tcp");
+/// Copy bytes between `client` and `upstream` in both directions until both
+/// are done.
+///
+/// A client that half-closes (finishes sending but still awaits the response)
+/// keeps receiving until the upstream closes too. An error in either
+/// direction aborts both.
+async fn forward<C, U>(client: C, upstream: U) -> Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let (client_read, client_write) = io::split(client);
+    let (upstream_read, upstream_write) = io::split(upstream);
 
-    // Run both directions concurrently; stop when either half finishes.
-    tokio::select! {
-        res = tcp_to_vsock => { res? }
-        res = vsock_to_tcp => { res? }
-    }
+    let client_to_vsock = copy_half(client_read, upstream_write, "client→vsock");
+    let vsock_to_client = copy_half(upstream_read, client_write, "vsock→client");
 
+    tokio::try_join!(client_to_vsock, vsock_to_client)?;
     Ok(())
 }
 
-/// Copy bytes from `reader` to `writer`, logging the direction on completion.
+/// Copy bytes from `reader` to `writer` until EOF, then shut down `writer` so
+/// the peer sees the half-close. Logs the direction on completion.
 async fn copy_half<R, W>(mut reader: R, mut writer: W, label: &'static str) -> Result<()>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
 {
     let bytes = io::copy(&mut reader, &mut writer).await?;
+    writer.shutdown().await?;
     debug!(label, bytes, "half-close");
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    /// The forwarding logic is exercised by integration tests that spin up a
-    /// mock enclave. Unit tests here cover configuration-level checks only.
+    //! The vsock leg is exercised by integration tests that spin up a mock
+    //! enclave. Unit tests here cover listener setup and stream forwarding.
+
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn bind_listener_sets_reuseaddr() {
+        let listener = bind_listener(([127u8, 0, 0, 1], 0).into(), 16).unwrap();
+        let sock = SockRef::from(&listener);
+        assert!(sock.reuse_address().unwrap());
+        assert_ne!(listener.local_addr().unwrap().port(), 0);
+    }
+
+    #[tokio::test]
+    async fn bind_listener_accepts_connections() {
+        let listener = bind_listener(([127u8, 0, 0, 1], 0).into(), 1).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connect = TcpStream::connect(addr);
+        let (accepted, connected) = tokio::join!(listener.accept(), connect);
+        assert!(accepted.is_ok());
+        assert!(connected.is_ok());
+    }
+
+    #[tokio::test]
+    async fn keepalive_applied_to_accepted_socket() {
+        let cfg = crate::config::tests::valid_config();
+        let keepalive = keepalive(&cfg).unwrap();
+        let listener = bind_listener(([127u8, 0, 0, 1], 0).into(), 1).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (accepted, connected) = tokio::join!(listener.accept(), TcpStream::connect(addr));
+        let (accepted, _) = accepted.unwrap();
+        let _client = connected.unwrap();
+
+        apply_keepalive(&accepted, &keepalive).unwrap();
+        let sock = SockRef::from(&accepted);
+        assert!(sock.keepalive().unwrap());
+        assert_eq!(sock.keepalive_time().unwrap(), Duration::from_secs(60));
+        assert_eq!(sock.keepalive_interval().unwrap(), Duration::from_secs(15));
+        assert_eq!(sock.keepalive_retries().unwrap(), 4);
+
+        let disabled = Config {
+            tcp_keepalive_secs: 0,
+            ..cfg
+        };
+        assert!(super::keepalive(&disabled).is_none());
+    }
+
+    #[test]
+    fn bind_listener_rejects_out_of_range_backlog() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let _guard = rt.enter();
+        assert!(bind_listener(([127u8, 0, 0, 1], 0).into(), u32::MAX).is_err());
+    }
+
+    #[tokio::test]
+    async fn uds_accepted_stream_is_forwarded() {
+        let dir = std::env::temp_dir().join(format!("vsock-proxy-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("proxy.sock");
+        // A stale socket from a previous run must not prevent binding.
+        drop(bind_uds_listener(&path).unwrap());
+        let listener = bind_uds_listener(&path).unwrap();
+
+        let (accepted, client) = tokio::join!(listener.accept(), UnixStream::connect(&path));
+        let (accepted, _) = accepted.unwrap();
+        let mut client = client.unwrap();
+
+        // Stand-in for the enclave: echo whatever arrives.
+        let (upstream, mut enclave) = io::duplex(64);
+        tokio::spawn(async move {
+            let mut buf = [0u8; 5];
+            enclave.read_exact(&mut buf).await.unwrap();
+            enclave.write_all(&buf).await.unwrap();
+        });
+        let proxied = tokio::spawn(forward(accepted, upstream));
+
+        client.write_all(b"hello").await.unwrap();
+        let mut echoed = [0u8; 5];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+
+        drop(client);
+        proxied.await.unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn half_closed_client_still_receives_full_response() {
+        let (mut client, accepted) = io::duplex(16);
+        let (upstream, mut enclave) = io::duplex(16);
+
+        // The enclave answers only after the request is complete, with a
+        // response larger than the duplex buffers so it arrives in pieces.
+        let response = vec![b'r'; 1024];
+        let expected = response.clone();
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            enclave.read_to_end(&mut request).await.unwrap();
+            assert_eq!(request, b"request");
+            enclave.write_all(&response).await.unwrap();
+        });
+        let proxied = tokio::spawn(forward(accepted, upstream));
+
+        client.write_all(b"request").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        proxied.await.unwrap().unwrap();
+    }
+
     #[test]
-    fn placeholder() {
-        // Real proxy tests live in tests/ and require a vsock-capable host.
+    fn bind_uds_listener_refuses_to_replace_regular_file() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let _guard = rt.enter();
+        let path = std::env::temp_dir().join(format!("vsock-proxy-file-{}", std::process::id()));
+        std::fs::write(&path, b"not a socket").unwrap();
+        assert!(bind_uds_listener(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
     }
 }
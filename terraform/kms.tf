@@ -34,10 +34,6 @@ resource "aws_kms_key" "enclave_dek" {
           Resource = "*"
         },
       ],
-      # DEV: standard Decrypt allowed from the enclave node IAM role.
-      # TODO: Once NSM attested decrypt is implemented in dek/mod.rs, change
-      # this back to a RecipientAttestation:PCR0 condition so that only a
-      # genuine enclave with the correct image measurement can decrypt the DEK.
       # When kms_enclave_pcr0 is set: enforce NSM attestation on Decrypt so only
       # an enclave whose EIF produces the expected PCR0 measurement can decrypt
       # the DEK.  PCR0 is a SHA-384 hash of the enclave image file contents and
@@ -45,8 +41,9 @@ resource "aws_kms_key" "enclave_dek" {
       #
       # The KMS key policy condition kms:RecipientAttestation:PCR0 is evaluated
       # when the KMS Decrypt call includes a RecipientAttestation parameter
-      # containing a valid NSM attestation document (see dek/mod.rs for the
-      # aws-nitro-enclaves-sdk-rust integration).
+      # containing a valid NSM attestation document. The enclave only sends one
+      # with KMS_RECIPIENT_ATTESTATION=true (see aws/recipient.rs), so set that
+      # flag before setting kms_enclave_pcr0.
       #
       # When kms_enclave_pcr0 is empty (dev mode): standard IAM Decrypt is
       # allowed without attestation — the DEK can be fetched by any process